@@ -0,0 +1,30 @@
+// Times the parse, plan and execute phases over generated trees of a few
+// sizes, with std::time::Instant, so a change to the planner shows up as a
+// change in time per entry. Sizes can be given instead:
+//
+//     cargo bench --bench planner -- 50000
+//
+// Each tree is made under the temporary directory and removed afterwards.
+
+use std::env;
+use std::io;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 50_000];
+
+fn main() -> io::Result<()> {
+    // cargo bench passes --bench along with what follows --
+    let sizes: Vec<usize> = env::args()
+        .skip(1)
+        .filter_map(|arg| arg.parse().ok())
+        .collect();
+    let sizes = if sizes.is_empty() {
+        SIZES.to_vec()
+    } else {
+        sizes
+    };
+    for entries in sizes {
+        neostow::bench::bench(entries)?;
+        println!();
+    }
+    Ok(())
+}
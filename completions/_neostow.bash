@@ -11,7 +11,7 @@ _neostow() {
   prev="${COMP_WORDS[COMP_CWORD - 1]}"
   case "${cur}" in
   -*)
-    mapfile -t COMPREPLY < <(compgen -W " --file -f --debug -D --dry -d --overwrite -o --help -h --force -F --verbose -V --version -v" -- "${cur}")
+    mapfile -t COMPREPLY < <(compgen -W " --file -f --debug -D --dry -d --overwrite -o --help -h --force -F --verbose -V --skip-unavailable -s --root -r --plan-diff --gc-broken --convert-shadows --no-folding --backup --atomic --output --mode --target -t --preserve --interactive -i --open-report --from-links --include-manual --pause-between-roots --jobs --strict --all --fix --provenance --color --no-color --quiet -q --log-level --log-file --interval --notify --group-by --remove-links --where --rev --allow-outside-home --allow-root --fail-fast --format --dry-run-hooks --sandbox --trace --assume-yes --assume-no --no-diff --wait --no-wait --override-home --override-hostname --copy-budget --json --fail-on --set --review --since --until --failed --version -v" -- "${cur}")
     return 0
     ;;
   esac
@@ -21,7 +21,7 @@ _neostow() {
     return 0
     ;;
   esac
-  mapfile -t COMPREPLY < <(compgen -W "edit delete" -- "${cur}")
+  mapfile -t COMPREPLY < <(compgen -W "edit delete retry export conflicts path edit-file status adopt restore setup suggest undo list init stats env check restore-original prune decommission doctor guard service watch import-stow plan-diff chown-fix bootstrap selftest capabilities snapshot resolve disable enable new-host log" -- "${cur}")
   return 0
 }
 complete -F _neostow neostow
@@ -0,0 +1,41 @@
+/* C interface to the neostow engine. Build the shared library,
+ * target/release/libneostow.so, with:
+ *
+ *     cargo build --release --features ffi
+ *
+ * Functions returning pointers return NULL on failure; neostow_last_error
+ * then describes why. Strings returned by the library are JSON and must be
+ * released with neostow_string_free.
+ */
+#ifndef NEOSTOW_H
+#define NEOSTOW_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct neostow_plan neostow_plan;
+
+#define NEOSTOW_CREATE 0
+#define NEOSTOW_OVERWRITE 1
+#define NEOSTOW_DELETE 2
+
+#define NEOSTOW_DRY 1
+#define NEOSTOW_FORCE 2
+#define NEOSTOW_ATOMIC 4
+
+const char *neostow_last_error(void);
+
+neostow_plan *neostow_plan(const char *path, int mode);
+char *neostow_plan_json(const neostow_plan *plan);
+char *neostow_execute(const neostow_plan *plan, int flags);
+char *neostow_status(const neostow_plan *plan);
+
+void neostow_plan_free(neostow_plan *plan);
+void neostow_string_free(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,100 @@
+// `neostow disable PACKAGE` keeps a package of the neostow file out of the
+// runs on this machine that name no packages, and `neostow enable PACKAGE`
+// lets it back in, so packages only used now and then can stay in the
+// repository without naming all the others on every run. Every package is
+// enabled until it is disabled, and a package named on the command line is
+// applied either way. What is disabled is kept per machine in the state
+// directory, by the neostow file it is a package of. Disabling a package
+// leaves its links where they are; `neostow delete PACKAGE` removes them.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Config, LogLevel, bytes, state};
+
+const DISABLED_FILE: &str = "disabled";
+
+// The same file however it was given
+fn key(file: &Path) -> PathBuf {
+    std::fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf())
+}
+
+// Each package disabled, with the neostow file it is a package of
+fn load() -> io::Result<Vec<(PathBuf, String)>> {
+    let Some(content) = state::read_file(&state::host_dir().join(DISABLED_FILE))? else {
+        return Ok(Vec::new());
+    };
+    let mut disabled = Vec::new();
+    for line in bytes::lines(&content) {
+        let Some((file, package)) = bytes::split_once(line, b'\t') else {
+            continue;
+        };
+        disabled.push((
+            PathBuf::from(bytes::to_os(file).into_owned()),
+            String::from_utf8_lossy(package).into_owned(),
+        ));
+    }
+    Ok(disabled)
+}
+
+// None left disabled removes the file
+fn save(disabled: &[(PathBuf, String)]) -> io::Result<()> {
+    let path = state::host_dir().join(DISABLED_FILE);
+    if disabled.is_empty() {
+        return state::remove_file(&path);
+    }
+    let mut out = Vec::new();
+    for (file, package) in disabled {
+        out.extend_from_slice(&bytes::from_os(file.as_os_str()));
+        out.push(b'\t');
+        out.extend_from_slice(package.as_bytes());
+        out.push(b'\n');
+    }
+    state::write_file(&path, &out)
+}
+
+/// The packages of file disabled on this machine.
+pub fn disabled(file: &Path) -> io::Result<Vec<String>> {
+    let file = key(file);
+    Ok(load()?
+        .into_iter()
+        .filter(|(from, _)| *from == file)
+        .map(|(_, package)| package)
+        .collect())
+}
+
+/// Enables or disables each package cfg names, for the runs of its file
+/// that name none.
+pub fn set(cfg: &Config, enable: bool) -> io::Result<()> {
+    let file = key(&cfg.file);
+    let before = load()?;
+    let mut disabled = before.clone();
+    for package in &cfg.packages {
+        let at = disabled
+            .iter()
+            .position(|(from, name)| *from == file && name == package);
+        match (at, enable) {
+            (Some(at), true) => {
+                disabled.remove(at);
+                printfc!(LogLevel::Info, "Enabled {package}");
+            }
+            (None, false) => {
+                disabled.push((file.clone(), package.clone()));
+                printfc!(
+                    LogLevel::Info,
+                    "Disabled {package}, runs that name no packages leave it out"
+                );
+            }
+            (Some(_), false) => {
+                printfc!(LogLevel::Info, "{package} is already disabled");
+            }
+            (None, true) => {
+                printfc!(LogLevel::Info, "{package} is already enabled");
+            }
+        }
+    }
+    if disabled != before {
+        save(&disabled)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::expand::normalize;
+use crate::glyphs::{self, Glyph};
+use crate::vfs::Fs;
+use crate::{
+    Config, LogLevel, Mode, Operation, diff, execute_op, fsops, init, overlay, parse_line, plan,
+    platform, prompt_user, report,
+};
+
+// Like GNU stow's --adopt, an existing source is replaced, leaving the
+// difference for version control to show
+fn move_into_repo(fs: &dyn Fs, from: &Path, to: &Path) -> io::Result<()> {
+    if fs.exists(to) {
+        fs.remove(to)?;
+    }
+    fsops::move_path(fs, from, to)
+}
+
+// Whether a real file or directory is at path, rather than a link or nothing
+fn is_real(path: &Path) -> bool {
+    path.symlink_metadata()
+        .is_ok_and(|meta| !meta.file_type().is_symlink())
+}
+
+// Moves a real file or directory at the destination into the repo, returning
+// whether it did; dry runs only say so
+pub(crate) fn take_over(op: &Operation, cfg: &Config) -> io::Result<bool> {
+    if !is_real(&op.dest) {
+        return Ok(false);
+    }
+    if cfg.dry {
+        printfc!(
+            LogLevel::Info,
+            "Would adopt {} into {}",
+            op.dest.display(),
+            op.src.display()
+        );
+        return Ok(false);
+    }
+    move_into_repo(&*cfg.fs, &op.dest, &op.src)?;
+    if cfg.verbose {
+        printfc!(
+            LogLevel::Info,
+            "Adopted {} into {}",
+            op.dest.display(),
+            op.src.display()
+        );
+    }
+    Ok(true)
+}
+
+fn adopt_one(op: &Operation, cfg: &Config, operations: &mut i32) -> io::Result<()> {
+    // These destinations are real directories by design
+    if op.managed || (op.is_dir && cfg.no_folding) {
+        execute_op(op, cfg, operations)?;
+        return Ok(());
+    }
+    if is_real(&op.dest) {
+        if !take_over(op, cfg)? {
+            return Ok(());
+        }
+    } else if op.dest.symlink_metadata().is_ok() || !op.src.exists() {
+        // Already a link, or nothing to adopt and nothing to link
+        return Ok(());
+    }
+    execute_op(op, cfg, operations)?;
+    Ok(())
+}
+
+// Whether adopt moves what is at the destination of op into the repo
+fn moves(op: &Operation, cfg: &Config) -> bool {
+    !(op.managed || op.is_dir && cfg.no_folding) && is_real(&op.dest)
+}
+
+/// The operations planned from a neostow file, by line.
+pub type Planned = Vec<(usize, Operation)>;
+
+/// A file given to adopt that no entry has, with the entry it gets.
+pub struct NewEntry {
+    line: String,
+    ops: Vec<Operation>,
+}
+
+// The entry of a file adopted from path: its name without the leading dot
+// in the repo, linked back to where it is
+fn entry_for(path: &Path) -> String {
+    let home = platform::home();
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let src = name.strip_prefix('.').filter(|src| !src.is_empty());
+    match src {
+        Some(src) => format!("{src} -> {}", init::portable(path, &home)),
+        None => {
+            let parent = path.parent().unwrap_or(Path::new("/"));
+            format!("{name}={}/", init::portable(parent, &home))
+        }
+    }
+}
+
+/// What adopting the files at paths takes: the operations of ops that have
+/// them as destinations, and a new entry for each of the others.
+pub fn given(
+    paths: &[PathBuf],
+    mut ops: Planned,
+    cfg: &Config,
+) -> io::Result<(Planned, Vec<NewEntry>)> {
+    let mut dests = Vec::new();
+    let mut entries = Vec::new();
+    for path in paths {
+        let path = normalize(&std::path::absolute(path)?);
+        if ops.iter().any(|(_, op)| op.dest == path) {
+            dests.push(path);
+            continue;
+        }
+        if !is_real(&path) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a file or directory to adopt", path.display()),
+            ));
+        }
+        overlay::writable(cfg, "adopt")?;
+        let line = entry_for(&path);
+        let entry = parse_line(line.as_bytes()).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} can't be written as an entry", path.display()),
+            )
+        })?;
+        let planned = plan(&entry, cfg)?;
+        entries.push(NewEntry { line, ops: planned });
+    }
+    ops.retain(|(_, op)| dests.contains(&op.dest));
+    Ok((ops, entries))
+}
+
+// Lists what adopt would move into the repo, each with how the source there
+// already differs, and the entries it would add, then asks once for all of it
+fn review(ops: &[(usize, Operation)], new: &[NewEntry], cfg: &Config) -> io::Result<bool> {
+    let batch: Vec<&Operation> = ops
+        .iter()
+        .map(|(_, op)| op)
+        .chain(new.iter().flat_map(|entry| &entry.ops))
+        .filter(|op| moves(op, cfg))
+        .collect();
+    if batch.is_empty() {
+        printfc!(LogLevel::Info, "Nothing to adopt");
+        return Ok(false);
+    }
+    let mut out = Vec::new();
+    for op in &batch {
+        writeln!(
+            out,
+            "{} {} {}",
+            op.dest.display(),
+            glyphs::get(Glyph::Arrow),
+            op.src.display()
+        )?;
+        if op.src.symlink_metadata().is_ok() {
+            diff::paths(&op.src, &op.dest, &mut out)?;
+        }
+    }
+    for entry in new {
+        writeln!(out, "New entry: {}", entry.line)?;
+    }
+    // The listing goes to stderr when stdout carries JSON
+    if report::json() {
+        io::stderr().write_all(&out)?;
+    } else {
+        report::page(&out)?;
+    }
+    if cfg.dry {
+        return Ok(false);
+    }
+    prompt_user(&format!("Adopt these {}?", batch.len()))
+}
+
+/// Moves real files found at destinations into the repo, then links them
+/// back, and gives the files of new their entries in the neostow file. With
+/// review, the whole batch is shown and confirmed first.
+pub fn adopt(
+    ops: &[(usize, Operation)],
+    new: &[NewEntry],
+    cfg: &Config,
+    review: bool,
+    operations: &mut i32,
+) -> io::Result<()> {
+    report::plan_start(&cfg.file, "adopt", cfg.dry);
+    let cfg = Config {
+        mode: Mode::Create,
+        ..cfg.clone()
+    };
+    if review && !self::review(ops, new, &cfg)? {
+        return Ok(());
+    }
+    for (linenum, op) in ops {
+        if let Err(err) = adopt_one(op, &cfg, operations) {
+            printfc!(
+                LogLevel::Error,
+                "{}:{}: {err}",
+                op.file(&cfg).display(),
+                linenum
+            );
+        }
+    }
+
+    // A new entry is only written once everything it plans is adopted
+    let mut lines = Vec::new();
+    for entry in new {
+        let mut adopted = true;
+        for op in &entry.ops {
+            if let Err(err) = adopt_one(op, &cfg, operations) {
+                printfc!(LogLevel::Error, "{}: {err}", op.dest.display());
+                adopted = false;
+            }
+        }
+        if adopted {
+            lines.push(entry.line.as_str());
+        }
+    }
+    if lines.is_empty() {
+        return Ok(());
+    }
+    if cfg.dry {
+        for line in lines {
+            printfc!(LogLevel::Info, "Would add {line} to {}", cfg.file.display());
+        }
+        return Ok(());
+    }
+    overlay::add_lines(&cfg.file, &lines.join("\n"))?;
+    printfc!(
+        LogLevel::Info,
+        "Added {} entries to {}",
+        lines.len(),
+        cfg.file.display()
+    );
+    Ok(())
+}
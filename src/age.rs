@@ -0,0 +1,69 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
+
+use crate::format;
+
+// A config ending in .age is encrypted with age(1). It is only ever decrypted
+// in memory, and only once per run, so a passphrase is asked for a single time.
+pub fn is_encrypted(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "age")
+}
+
+fn decrypt(path: &Path) -> io::Result<Vec<u8>> {
+    let mut cmd = Command::new("age");
+    cmd.arg("--decrypt");
+    if let Some(identity) = env::var_os("NEOSTOW_AGE_IDENTITY") {
+        cmd.arg("--identity").arg(identity);
+    }
+    // stdin and stderr stay on the terminal for age's passphrase prompt
+    let output = cmd
+        .arg(path)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "could not decrypt {}: age exited with {}",
+            path.display(),
+            output.status
+        )));
+    }
+    Ok(output.stdout)
+}
+
+// The file as written, decrypted if need be
+fn contents(path: &Path) -> io::Result<Vec<u8>> {
+    if !is_encrypted(path) {
+        return fs::read(path);
+    }
+
+    static DECRYPTED: OnceLock<Mutex<HashMap<PathBuf, Vec<u8>>>> = OnceLock::new();
+    let mut cache = DECRYPTED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(content) = cache.get(path) {
+        return Ok(content.clone());
+    }
+    let content = decrypt(path)?;
+    cache.insert(path.to_path_buf(), content.clone());
+    Ok(content)
+}
+
+// The neostow file at path in the line format, whatever it is written in
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    format::of(path).lines(contents(path)?, path)
+}
+
+// Plain configs are streamed, encrypted and TOML ones come from memory
+pub fn open(path: &Path) -> io::Result<Box<dyn BufRead>> {
+    if is_encrypted(path) || !format::is_plain(path) {
+        return Ok(Box::new(io::Cursor::new(read(path)?)));
+    }
+    Ok(Box::new(io::BufReader::new(fs::File::open(path)?)))
+}
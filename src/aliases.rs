@@ -0,0 +1,59 @@
+// An [aliases] section names command lines used often:
+//   [aliases]
+//   refresh = "-o --jobs 4 nvim"
+// so that `neostow refresh` runs `neostow -o --jobs 4 nvim`, with any
+// further arguments after the alias's. A command of the same name wins, and
+// an alias isn't expanded again inside another.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::reload::field;
+use crate::{include, section_lines};
+
+// A name and the arguments it stands for
+type Alias<'a> = (&'a [u8], Vec<Vec<u8>>);
+
+pub(crate) fn parse_alias(line: &[u8]) -> io::Result<Option<Alias<'_>>> {
+    let line = line.trim_ascii();
+    if line.is_empty() || line.starts_with(b"#") {
+        return Ok(None);
+    }
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "expected name = \"arguments\"");
+    let (name, rest) = field(line, b'=').ok_or_else(invalid)?;
+    let rest = rest
+        .trim_ascii_start()
+        .strip_prefix(b"=")
+        .ok_or_else(invalid)?;
+    let (value, _) = field(rest, b'#').ok_or_else(invalid)?;
+    let words = include::words(value)?;
+    if name.is_empty() || words.is_empty() {
+        return Err(invalid());
+    }
+    Ok(Some((name, words)))
+}
+
+/// The arguments the alias `name` stands for in the `[aliases]` section of
+/// the neostow file at path, if it has one.
+pub fn find(path: &Path, name: &str) -> io::Result<Option<Vec<String>>> {
+    // An encrypted file would ask for its passphrase just to look
+    let Ok(content) = fs::read(path) else {
+        return Ok(None);
+    };
+    let mut found = None;
+    section_lines(&content, path, b"aliases", |line| {
+        if let Some((alias, words)) = parse_alias(line)?
+            && alias == name.as_bytes()
+        {
+            found = Some(
+                words
+                    .into_iter()
+                    .map(|word| String::from_utf8_lossy(&word).into_owned())
+                    .collect(),
+            );
+        }
+        Ok(())
+    })?;
+    Ok(found)
+}
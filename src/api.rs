@@ -0,0 +1,310 @@
+// The stable entry points for front-ends embedding neostow: plan a config
+// file, inspect the plan, then execute it. The option and report types are
+// non-exhaustive, so fields and outcomes can be added without breaking callers.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::backup::Backup;
+use crate::meta::Preserve;
+use crate::vars::Vars;
+use crate::vfs::{Fs, RealFs};
+use crate::{
+    Config, LinkMode, Mode, Operation, Session, age, bytes, conflicts, plan_lines, report, state,
+};
+
+/// What to plan: the mode of the run and which part of the file it covers.
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct PlanOptions {
+    pub mode: Mode,
+    /// Packages ([name] sections) to plan; empty plans the whole file
+    pub packages: Vec<String>,
+    /// Refuse destinations outside this directory
+    pub root: Option<PathBuf>,
+    /// Put destinations under this directory, which `~` and `$HOME` also
+    /// expand to, like DESTDIR
+    pub target: Option<PathBuf>,
+    /// Link the files of directory sources instead of the directory
+    pub no_folding: bool,
+    /// What copies take on from their source, on top of each entry's
+    /// !preserve
+    pub preserve: Preserve,
+    /// Values taking precedence over the environment, such as HOME when
+    /// planning for another machine
+    pub env: Vars,
+    /// Plan destinations outside HOME, or the target, without `!system`
+    pub allow_outside_home: bool,
+}
+
+/// How to carry out a plan.
+#[derive(Clone, Default)]
+#[non_exhaustive]
+pub struct ExecuteOptions {
+    pub dry: bool,
+    /// Make destinations as symlinks, copies or hard links
+    pub link_mode: LinkMode,
+    /// Overwrite without asking, and past two-way conflicts
+    pub force: bool,
+    /// Roll back every change if an operation fails
+    pub atomic: bool,
+    /// With overwrite, back up replaced files instead of deleting them
+    pub backup: Option<Backup>,
+    pub skip_unavailable: bool,
+    pub gc_broken: bool,
+    pub verbose: bool,
+    /// Apply `!manual` entries too
+    pub include_manual: bool,
+}
+
+/// The operations a config file calls for, and the entries that couldn't
+/// be planned.
+pub struct Plan {
+    config: Config,
+    pub operations: Vec<(usize, Operation)>,
+    pub errors: Vec<(usize, String)>,
+    // The file each error's line is in
+    error_files: Vec<PathBuf>,
+}
+
+impl Plan {
+    pub fn file(&self) -> &Path {
+        &self.config.file
+    }
+
+    /// The file an error's line is in, which differs from `file` for
+    /// entries of an included file.
+    pub fn error_file(&self, index: usize) -> Option<&Path> {
+        self.error_files.get(index).map(PathBuf::as_path)
+    }
+
+    #[cfg(feature = "ffi")]
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn to_json(&self) -> String {
+        let operations: Vec<String> = self
+            .operations
+            .iter()
+            .map(|(line, op)| {
+                format!(
+                    "{{\"file\":{},\"line\":{line},\"source\":{},\"dest\":{},\"is_dir\":{},\"manual\":{}}}",
+                    report::path(Some(op.file(&self.config))),
+                    report::path(Some(&op.src)),
+                    report::path(Some(&op.dest)),
+                    op.is_dir,
+                    op.manual
+                )
+            })
+            .collect();
+        let errors: Vec<String> = self
+            .errors
+            .iter()
+            .zip(&self.error_files)
+            .map(|((line, err), file)| {
+                format!(
+                    "{{\"file\":{},\"line\":{line},\"error\":{}}}",
+                    report::path(Some(file)),
+                    report::string(err)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"file\":{},\"mode\":{},\"operations\":[{}],\"errors\":[{}]}}",
+            report::path(Some(&self.config.file)),
+            report::string(self.config.mode.name()),
+            operations.join(","),
+            errors.join(",")
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum Outcome {
+    Changed,
+    Unchanged,
+    Failed(String),
+}
+
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct OperationResult {
+    /// The file the operation's entry is in, and its line there
+    pub file: PathBuf,
+    pub line: usize,
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    pub outcome: Outcome,
+}
+
+/// What executing a plan did, operation by operation.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct RunReport {
+    /// Links that changed, or 0 after a rollback
+    pub operations: i32,
+    pub results: Vec<OperationResult>,
+    pub rolled_back: bool,
+}
+
+impl RunReport {
+    pub fn to_json(&self) -> String {
+        let results: Vec<String> = self
+            .results
+            .iter()
+            .map(|r| {
+                let (result, error) = match &r.outcome {
+                    Outcome::Changed => ("changed", "null".to_string()),
+                    Outcome::Unchanged => ("unchanged", "null".to_string()),
+                    Outcome::Failed(err) => ("failed", report::string(err)),
+                };
+                format!(
+                    "{{\"file\":{},\"line\":{},\"source\":{},\"dest\":{},\"result\":\"{result}\",\"error\":{error}}}",
+                    report::path(Some(&r.file)),
+                    r.line,
+                    report::path(Some(&r.src)),
+                    report::path(Some(&r.dest)),
+                )
+            })
+            .collect();
+        format!(
+            "{{\"operations\":{},\"rolled_back\":{},\"results\":[{}]}}",
+            self.operations,
+            self.rolled_back,
+            results.join(",")
+        )
+    }
+}
+
+/// Loads the config file at path, with its variables and reload rules, and
+/// plans it. Entries that can't be planned end up in `Plan::errors`.
+pub fn plan_from_config(path: &Path, options: &PlanOptions) -> io::Result<Plan> {
+    plan_from_source(&age::read(path)?, path, options, Arc::new(RealFs))
+}
+
+/// Plans source as if it were the config file at path, on a machine whose
+/// files fs describes. Apart from `let` commands, nothing runs and nothing
+/// local is read, so this also works where there is no filesystem (wasm32).
+pub fn plan_from_source(
+    source: &[u8],
+    path: &Path,
+    options: &PlanOptions,
+    fs: Arc<dyn Fs>,
+) -> io::Result<Plan> {
+    let mut config = Config {
+        mode: options.mode.clone(),
+        packages: options.packages.clone(),
+        root: options.root.clone(),
+        target: options.target.clone(),
+        no_folding: options.no_folding,
+        allow_outside_home: options.allow_outside_home,
+        preserve: options.preserve,
+        vars: options.env.clone(),
+        fs,
+        ..Config::new(path.to_path_buf())
+    };
+    config.load_from(source)?;
+    let mut errors = Vec::new();
+    let mut error_files = Vec::new();
+    let mut operations = plan_lines(&config, source, |file_cfg, line, err: io::Error| {
+        errors.push((line, err.to_string()));
+        error_files.push(file_cfg.file.clone());
+    })?;
+    let mut claims = conflicts::Claims::new();
+    operations.retain(
+        |(line, op)| match conflicts::claim(&mut claims, op, *line, &config) {
+            Ok(()) => true,
+            Err(err) => {
+                errors.push((*line, err.to_string()));
+                error_files.push(op.file(&config).to_path_buf());
+                false
+            }
+        },
+    );
+    Ok(Plan {
+        config,
+        operations,
+        errors,
+        error_files,
+    })
+}
+
+/// Executes a plan, recording failures for `neostow retry` and the changes
+/// for `neostow undo` like a run from the command line. Destinations are
+/// made and removed through the `Fs` the plan was made against, which a dry
+/// run leaves as it is.
+pub fn execute(plan: &Plan, options: &ExecuteOptions) -> io::Result<RunReport> {
+    let cfg = Config {
+        dry: options.dry,
+        link_mode: options.link_mode,
+        force: options.force,
+        atomic: options.atomic,
+        backup: options.backup.clone(),
+        skip_unavailable: options.skip_unavailable,
+        gc_broken: options.gc_broken,
+        verbose: options.verbose,
+        include_manual: options.include_manual,
+        ..plan.config.clone()
+    };
+    let mut session = Session::new(&cfg)?;
+    let mut run = RunReport::default();
+    let mut failed: Vec<(PathBuf, usize)> = (plan.error_files.iter().cloned())
+        .zip(plan.errors.iter().map(|(line, _)| *line))
+        .collect();
+    for (line, op) in &plan.operations {
+        report::at_line(op.file(&cfg), *line);
+        let outcome = match session.execute(op, &cfg, &mut run.operations) {
+            Ok(true) => Outcome::Changed,
+            Ok(false) => Outcome::Unchanged,
+            Err(err) => {
+                failed.push((op.file(&cfg).to_path_buf(), *line));
+                Outcome::Failed(err.to_string())
+            }
+        };
+        let stop = cfg.atomic && matches!(outcome, Outcome::Failed(_));
+        run.results.push(OperationResult {
+            file: op.file(&cfg).to_path_buf(),
+            line: *line,
+            src: op.src.clone(),
+            dest: op.dest.clone(),
+            outcome,
+        });
+        if stop {
+            run.rolled_back = true;
+            break;
+        }
+    }
+
+    let failed = failed_entries(failed)?;
+    if run.rolled_back {
+        session.roll_back(&cfg, &failed, &mut run.operations)?;
+    } else {
+        session.finish(&cfg, &failed)?;
+    }
+    Ok(run)
+}
+
+// Failed lines as `retry` expects them, read back from their files
+fn failed_entries(mut lines: Vec<(PathBuf, usize)>) -> io::Result<Vec<state::FailedEntry>> {
+    lines.sort_unstable();
+    lines.dedup();
+    let mut entries = Vec::new();
+    for file_lines in lines.chunk_by(|a, b| a.0 == b.0) {
+        let file = &file_lines[0].0;
+        let content = age::read(file)?;
+        let path = fs::canonicalize(file)?;
+        let all: Vec<&[u8]> = bytes::lines(&content).collect();
+        entries.extend(file_lines.iter().filter_map(|(_, linenum)| {
+            Some(state::FailedEntry {
+                file: path.clone(),
+                linenum: *linenum,
+                line: all.get(linenum - 1)?.to_vec(),
+            })
+        }));
+    }
+    Ok(entries)
+}
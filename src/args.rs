@@ -0,0 +1,130 @@
+// Splits the command line into options and words. Short flags combine
+// (`-Vd`), long options take their value after `=` or as the next argument
+// (`--file=PATH`, `--file PATH`), and `--` makes everything after it a word.
+
+use std::collections::VecDeque;
+
+// Short options whose value follows them, so `-fPATH` and `-Vf PATH` work
+const SHORT_WITH_VALUE: &[char] = &['f', 'r', 't'];
+
+pub enum Arg {
+    // An option, as -x or --name
+    Option(String),
+    // A command, package or operand
+    Word(String),
+}
+
+pub struct Args {
+    rest: VecDeque<String>,
+    // A value given with the last option, as in --file=PATH or -fPATH
+    attached: Option<String>,
+    last: String,
+    words_only: bool,
+}
+
+impl Args {
+    pub fn new(args: impl Iterator<Item = String>) -> Self {
+        Args {
+            rest: args.collect(),
+            attached: None,
+            last: String::new(),
+            words_only: false,
+        }
+    }
+
+    pub fn next(&mut self) -> Result<Option<Arg>, String> {
+        if let Some(value) = self.attached.take() {
+            return Err(format!("{} doesn't take a value, got {value:?}", self.last));
+        }
+        let Some(arg) = self.rest.pop_front() else {
+            return Ok(None);
+        };
+        if self.words_only || arg == "-" || !arg.starts_with('-') {
+            return Ok(Some(Arg::Word(arg)));
+        }
+        if arg == "--" {
+            self.words_only = true;
+            return self.next();
+        }
+
+        let option = if let Some(long) = arg.strip_prefix("--") {
+            match long.split_once('=') {
+                Some((name, value)) => {
+                    self.attached = Some(value.to_string());
+                    format!("--{name}")
+                }
+                None => arg,
+            }
+        } else {
+            // -Vd is -V -d, and -fPATH is -f PATH
+            let mut chars = arg[1..].chars();
+            let first = chars.next().unwrap_or_default();
+            let tail = chars.as_str();
+            if !tail.is_empty() {
+                if SHORT_WITH_VALUE.contains(&first) {
+                    self.attached = Some(tail.to_string());
+                } else {
+                    self.rest.push_front(format!("-{tail}"));
+                }
+            }
+            format!("-{first}")
+        };
+        self.last = option.clone();
+        Ok(Some(Arg::Option(option)))
+    }
+
+    // Puts words back in front of the arguments still to come, as an alias
+    // expands to
+    pub fn prepend(&mut self, words: Vec<String>) {
+        for word in words.into_iter().rev() {
+            self.rest.push_front(word);
+        }
+    }
+
+    // The value of the last option, attached or as the next argument
+    pub fn value(&mut self) -> Result<String, String> {
+        self.attached
+            .take()
+            .or_else(|| self.rest.pop_front())
+            .ok_or_else(|| format!("{} requires a value", self.last))
+    }
+
+    // The value of the last option only if attached, for optional values
+    // like --backup[=DIR]
+    pub fn attached(&mut self) -> Option<String> {
+        self.attached.take()
+    }
+}
+
+// Edits between a and b, counting a swap of neighbouring characters as one
+fn distance(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+// The known name closest to a mistyped one, if it is close enough to be
+// what was meant
+pub fn suggest<'a>(typed: &str, known: &[&'a str]) -> Option<&'a str> {
+    known
+        .iter()
+        .map(|name| (distance(typed, name), *name))
+        .filter(|(d, name)| *d <= 2.max(name.len() / 4) && *d < name.len())
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, name)| name)
+}
@@ -0,0 +1,216 @@
+// Attributes in brackets at the end of an entry set what provisioning
+// secrets needs beyond the content:
+//   secrets/ssh_config -> ~/.ssh/config [mode=600, dir-mode=700]
+//   secrets/gnupg/ = ~/ [mode=600, dir-mode=700, owner=me:staff]
+// mode gives copies their permission bits, every file of a copied
+// directory included, and dir-mode the directories neostow creates on the
+// way and inside copies. Links have no permissions of their own, so with
+// --mode symlink only dir-mode applies. owner hands all of them to a user
+// and optionally a group with chown(1), and only when running as root.
+// They are the modifiers !mode:600, !dir-mode:700 and !owner:me written
+// another way.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use crate::{Config, LinkMode, LogLevel};
+
+#[derive(Clone, Default)]
+pub struct Attributes {
+    mode: Option<u32>,
+    dir_mode: Option<u32>,
+    owner: Option<String>,
+}
+
+impl Attributes {
+    // Takes in one modifier, returning whether it was one of these
+    pub(crate) fn set(&mut self, name: &str, value: Option<&[u8]>) -> io::Result<bool> {
+        let Some(value) = value else {
+            return Ok(false);
+        };
+        let text = String::from_utf8_lossy(value);
+        match name {
+            "mode" => self.mode = Some(parse_mode(&text)?),
+            "dir-mode" => self.dir_mode = Some(parse_mode(&text)?),
+            "owner" if !text.is_empty() && !text.contains(char::is_whitespace) => {
+                self.owner = Some(text.into_owned());
+            }
+            "owner" => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "expected owner=USER or owner=USER:GROUP",
+                ));
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+fn parse_mode(text: &str) -> io::Result<u32> {
+    u32::from_str_radix(text, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{text} is not an octal mode such as 600 or 0755"),
+            )
+        })
+}
+
+#[cfg(unix)]
+fn chmod(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+}
+
+// Windows has no permission bits to speak of
+#[cfg(not(unix))]
+fn chmod(_: &Path, _: u32) -> io::Result<()> {
+    Ok(())
+}
+
+pub(crate) fn is_root() -> bool {
+    static ROOT: OnceLock<bool> = OnceLock::new();
+    *ROOT.get_or_init(|| {
+        cfg!(unix)
+            && Command::new("id")
+                .arg("-u")
+                .output()
+                .is_ok_and(|out| out.stdout.trim_ascii() == b"0")
+    })
+}
+
+#[cfg(unix)]
+pub(crate) fn owner(meta: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::MetadataExt;
+    meta.uid()
+}
+
+#[cfg(not(unix))]
+pub(crate) fn owner(_: &fs::Metadata) -> u32 {
+    0
+}
+
+fn chown(path: &Path, owner: &str, cfg: &Config) -> io::Result<()> {
+    if !is_root() {
+        if cfg.verbose {
+            printfc!(
+                LogLevel::Warn,
+                "Not running as root, leaving the owner of {} as it is",
+                path.display()
+            );
+        }
+        return Ok(());
+    }
+    let status = Command::new("chown")
+        .args(["-h", "-R", owner])
+        .arg(path)
+        .status()?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "chown {owner} {} exited with {status}",
+        path.display()
+    )))
+}
+
+#[cfg(unix)]
+fn mode_of(meta: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(meta.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn mode_of(_: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+// The user id of name, as id(1) has it
+fn uid_of(name: &str) -> io::Result<u32> {
+    let out = Command::new("id").args(["-u", name]).output()?;
+    String::from_utf8_lossy(&out.stdout)
+        .trim()
+        .parse()
+        .map_err(|_| io::Error::new(io::ErrorKind::NotFound, format!("no user {name}")))
+}
+
+// How what is at path differs from attrs, for !assert: the mode of a file,
+// or dir-mode of a directory (mode when there is none), and the user of
+// owner. The group of owner isn't checked.
+pub(crate) fn mismatch(attrs: &Attributes, path: &Path) -> io::Result<Option<String>> {
+    let meta = path.metadata()?;
+    let wanted = if meta.is_dir() {
+        attrs.dir_mode.or(attrs.mode)
+    } else {
+        attrs.mode
+    };
+    if let (Some(wanted), Some(mode)) = (wanted, mode_of(&meta))
+        && wanted != mode
+    {
+        return Ok(Some(format!("mode {mode:o}, not {wanted:o}")));
+    }
+    if cfg!(unix)
+        && let Some(owner) = &attrs.owner
+    {
+        let user = owner.split(':').next().unwrap_or(owner);
+        if uid_of(user)? != self::owner(&meta) {
+            return Ok(Some(format!("not owned by {user}")));
+        }
+    }
+    Ok(None)
+}
+
+// Modes for what is at path, a directory's children first
+fn chmod_tree(path: &Path, attrs: &Attributes) -> io::Result<()> {
+    let meta = path.symlink_metadata()?;
+    if meta.is_dir() {
+        for entry in fs::read_dir(path)? {
+            chmod_tree(&entry?.path(), attrs)?;
+        }
+        if let Some(mode) = attrs.dir_mode {
+            chmod(path, mode)?;
+        }
+    } else if meta.is_file()
+        && let Some(mode) = attrs.mode
+    {
+        chmod(path, mode)?;
+    }
+    Ok(())
+}
+
+/// Sets the attributes on what was just made at dest.
+pub(crate) fn apply(attrs: &Attributes, dest: &Path, cfg: &Config) -> io::Result<()> {
+    if cfg.dry {
+        return Ok(());
+    }
+    if cfg.link_mode == LinkMode::Copy {
+        chmod_tree(dest, attrs)?;
+    }
+    if let Some(owner) = &attrs.owner {
+        chown(dest, owner, cfg)?;
+    }
+    Ok(())
+}
+
+/// Sets the attributes on the directories created to hold a destination.
+pub(crate) fn apply_dirs(
+    attrs: &Attributes,
+    dirs: &[impl AsRef<Path>],
+    cfg: &Config,
+) -> io::Result<()> {
+    for dir in dirs {
+        if let Some(mode) = attrs.dir_mode {
+            chmod(dir.as_ref(), mode)?;
+        }
+        if let Some(owner) = &attrs.owner {
+            chown(dir.as_ref(), owner, cfg)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,327 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::expand::normalize;
+use crate::vfs::{Fs, Node};
+use crate::{Config, LogLevel, Operation, fsops, journal, state, status};
+
+const ORIGINAL_SUFFIX: &str = ".neostow-orig";
+
+#[derive(Clone)]
+pub enum Backup {
+    // Next to the destination, as DEST.bak
+    Suffix,
+    // Under a directory named after the run's time, mirroring the destination
+    Dir(PathBuf),
+}
+
+// The (year, month, day) of a count of days since the epoch (Howard
+// Hinnant's algorithm)
+pub(crate) fn civil(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// UTC time as YYYYMMDD-HHMMSS
+pub fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil(days as i64);
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+impl Backup {
+    pub fn parse(arg: &str) -> Option<Backup> {
+        match arg.strip_prefix("--backup") {
+            Some("") => Some(Backup::Suffix),
+            Some(dir) => Some(Backup::Dir(
+                PathBuf::from(dir.strip_prefix('=')?).join(timestamp()),
+            )),
+            None => None,
+        }
+    }
+
+    fn path_for(&self, dest: &Path, fs: &dyn Fs) -> PathBuf {
+        match self {
+            Backup::Suffix => {
+                let mut path = dest.as_os_str().to_owned();
+                path.push(".bak");
+                // Earlier backups are never clobbered
+                let mut candidate = PathBuf::from(&path);
+                let mut n = 1;
+                while fs.metadata(&candidate).is_ok() {
+                    let mut numbered = path.clone();
+                    numbered.push(format!(".{n}"));
+                    candidate = PathBuf::from(numbered);
+                    n += 1;
+                }
+                candidate
+            }
+            Backup::Dir(dir) => {
+                let absolute = std::path::absolute(dest).unwrap_or_else(|_| dest.to_path_buf());
+                dir.join(
+                    absolute
+                        .components()
+                        .filter(|c| matches!(c, Component::Normal(_)))
+                        .collect::<PathBuf>(),
+                )
+            }
+        }
+    }
+}
+
+// Where !keep-original keeps what was at dest: beside it, or in the
+// directory given to --backup, outside the folders of single runs
+fn original_path(dest: &Path, backup: Option<&Backup>) -> PathBuf {
+    let path = match backup {
+        Some(Backup::Dir(run_dir)) => {
+            let dir = run_dir.parent().unwrap_or(run_dir);
+            let absolute = std::path::absolute(dest).unwrap_or_else(|_| dest.to_path_buf());
+            dir.join(
+                absolute
+                    .components()
+                    .filter(|c| matches!(c, Component::Normal(_)))
+                    .collect::<PathBuf>(),
+            )
+        }
+        _ => dest.to_path_buf(),
+    };
+    let mut path = path.into_os_string();
+    path.push(ORIGINAL_SUFFIX);
+    PathBuf::from(path)
+}
+
+// Copies the real file at dest before its first overwrite. The copy is never
+// replaced after that, so it stays the file the system came with.
+pub(crate) fn keep_original(dest: &Path, cfg: &Config) -> io::Result<()> {
+    let path = original_path(dest, cfg.backup.as_ref());
+    if cfg.fs.metadata(&path).is_ok() {
+        return Ok(());
+    }
+    if cfg.dry {
+        printfc!(
+            LogLevel::Info,
+            "Would keep the original {} as {}",
+            dest.display(),
+            path.display()
+        );
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        cfg.fs.create_dir_all(parent)?;
+    }
+    cfg.fs.copy(dest, &path, false)?;
+    if cfg.verbose {
+        printfc!(
+            LogLevel::Info,
+            "Kept the original {} as {}",
+            dest.display(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+// What dest replaced and can go back to: the original kept by
+// !keep-original, or else its latest backup
+pub(crate) fn kept(dest: &Path, cfg: &Config) -> io::Result<Option<PathBuf>> {
+    let original = original_path(dest, cfg.backup.as_ref());
+    if cfg.fs.metadata(&original).is_ok() {
+        return Ok(Some(original));
+    }
+    let dest = std::path::absolute(dest)?;
+    Ok(state::load_backups()?
+        .into_iter()
+        .rev()
+        .find(|(d, _)| *d == dest)
+        .map(|(_, backup)| backup)
+        .filter(|backup| cfg.fs.metadata(backup).is_ok()))
+}
+
+// Moves what kept returned back to dest, which must be free, so that undo
+// can move it back again
+pub(crate) fn reinstate(dest: &Path, kept: &Path, cfg: &Config) -> io::Result<()> {
+    fsops::move_path(&*cfg.fs, kept, dest)?;
+    journal::record(journal::Change::Reinstated(
+        dest.to_path_buf(),
+        kept.to_path_buf(),
+    ));
+    let mut backups = state::load_backups()?;
+    let before = backups.len();
+    backups.retain(|(_, backup)| backup != kept);
+    if backups.len() != before {
+        state::save_backups(&backups)?;
+    }
+    Ok(())
+}
+
+// Puts the kept original of op's destination back in place of what neostow
+// made there
+fn restore_original(op: &Operation, cfg: &Config) -> io::Result<()> {
+    let path = original_path(&op.dest, cfg.backup.as_ref());
+    if cfg.fs.metadata(&path).is_err() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no original of {} was kept", op.dest.display()),
+        ));
+    }
+    if !cfg.force && !status::op_owned(op, cfg)? {
+        return Err(io::Error::other(format!(
+            "{} was not made by neostow, use --force to replace it anyway",
+            op.dest.display()
+        )));
+    }
+    if cfg.dry {
+        printfc!(
+            LogLevel::Info,
+            "Would restore the original {} from {}",
+            op.dest.display(),
+            path.display()
+        );
+        return Ok(());
+    }
+    if cfg.fs.metadata(&op.dest).is_ok() {
+        cfg.fs.remove(&op.dest)?;
+    }
+    fsops::move_path(&*cfg.fs, &path, &op.dest)?;
+    printfc!(
+        LogLevel::Info,
+        "Restored the original {} from {}",
+        op.dest.display(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Hands destinations back to the files they replaced, as kept by
+/// `!keep-original`: the one at dest, or with None every entry that keeps
+/// its original.
+pub fn restore_originals(
+    ops: &[(usize, Operation)],
+    cfg: &Config,
+    dest: Option<&Path>,
+) -> io::Result<()> {
+    let Some(dest) = dest else {
+        let mut failed = 0;
+        let mut restored = 0;
+        for (_, op) in ops.iter().filter(|(_, op)| op.keep_original) {
+            if cfg
+                .fs
+                .metadata(&original_path(&op.dest, cfg.backup.as_ref()))
+                .is_err()
+            {
+                continue;
+            }
+            match restore_original(op, cfg) {
+                Ok(()) => restored += 1,
+                Err(err) => {
+                    printfc!(LogLevel::Error, "{err}");
+                    failed += 1;
+                }
+            }
+        }
+        if failed > 0 {
+            return Err(io::Error::other(format!(
+                "{failed} originals could not be restored"
+            )));
+        }
+        if restored == 0 {
+            printfc!(LogLevel::Info, "No kept originals to restore");
+        }
+        return Ok(());
+    };
+
+    let dest = normalize(&std::path::absolute(dest)?);
+    let op = ops
+        .iter()
+        .map(|(_, op)| op)
+        .find(|op| std::path::absolute(&op.dest).is_ok_and(|d| normalize(&d) == dest))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "{} is not managed by {}",
+                    dest.display(),
+                    cfg.file.display()
+                ),
+            )
+        })?;
+    restore_original(op, cfg)
+}
+
+// Moves dest out of the way and records where it went, for restore
+pub fn save(dest: &Path, backup: &Backup, cfg: &Config) -> io::Result<()> {
+    let path = backup.path_for(dest, &*cfg.fs);
+    fsops::move_path(&*cfg.fs, dest, &path)?;
+    journal::record(journal::Change::Stashed(dest.to_path_buf(), path.clone()));
+    let mut backups = state::load_backups()?;
+    backups.push((dest.to_path_buf(), path.clone()));
+    state::save_backups(&backups)?;
+    if cfg.verbose {
+        printfc!(
+            LogLevel::Info,
+            "Backed up {} to {}",
+            dest.display(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+// Puts the latest backup of dest back, replacing the link that took its place
+pub fn restore(dest: &Path, cfg: &Config) -> io::Result<()> {
+    let dest = std::path::absolute(dest)?;
+    let mut backups = state::load_backups()?;
+    let Some(pos) = backups.iter().rposition(|(d, _)| *d == dest) else {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no backup of {}", dest.display()),
+        ));
+    };
+    let backup = backups[pos].1.clone();
+
+    if matches!(cfg.fs.metadata(&dest), Ok(Node::File | Node::Dir)) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} exists and is not a link", dest.display()),
+        ));
+    }
+    if cfg.dry {
+        printfc!(
+            LogLevel::Info,
+            "Would restore {} from {}",
+            dest.display(),
+            backup.display()
+        );
+        return Ok(());
+    }
+    if matches!(cfg.fs.metadata(&dest), Ok(Node::Link(_))) {
+        cfg.fs.remove(&dest)?;
+    }
+    fsops::move_path(&*cfg.fs, &backup, &dest)?;
+    backups.remove(pos);
+    state::save_backups(&backups)?;
+    printfc!(
+        LogLevel::Info,
+        "Restored {} from {}",
+        dest.display(),
+        backup.display()
+    );
+    Ok(())
+}
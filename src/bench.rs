@@ -0,0 +1,83 @@
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::time::{Duration, Instant};
+
+use crate::{Config, Parser, execute_op, plan};
+
+pub const DEFAULT_ENTRIES: usize = 10_000;
+
+// Keeps directories small enough that readdir cost stays out of the numbers
+const FILES_PER_DIR: usize = 1_000;
+
+fn generate(root: &Path, entries: usize) -> io::Result<String> {
+    let mut config = String::new();
+    for i in 0..entries {
+        let dir = format!("d{}", i / FILES_PER_DIR);
+        let name = format!("f{i}");
+        if i % FILES_PER_DIR == 0 {
+            fs::create_dir_all(root.join("pkg").join(&dir))?;
+        }
+        fs::write(root.join("pkg").join(&dir).join(&name), "")?;
+        writeln!(
+            config,
+            "{dir}/{name}={}",
+            root.join("target").join(&dir).display()
+        )
+        .unwrap();
+    }
+    Ok(config)
+}
+
+fn report(phase: &str, elapsed: Duration, entries: usize) {
+    let per_entry = elapsed.as_secs_f64() * 1e6 / entries.max(1) as f64;
+    println!("{phase:<8} {:>10.2?} {per_entry:>10.2} µs/entry", elapsed);
+}
+
+fn measure(root: &Path, entries: usize) -> io::Result<()> {
+    let cfg = Config {
+        force: true,
+        // The tree is under the temporary directory, not the home
+        allow_outside_home: true,
+        ..Config::new(root.join("pkg/.neostow"))
+    };
+
+    let config = generate(root, entries)?;
+    println!("Benchmarking {entries} entries in {}", root.display());
+
+    let start = Instant::now();
+    let mut parser = Parser::new(&cfg.packages);
+    let parsed: Vec<_> = config
+        .lines()
+        .filter_map(|line| parser.parse(line.as_bytes()))
+        .collect();
+    report("parse", start.elapsed(), entries);
+
+    let start = Instant::now();
+    let mut planned = Vec::with_capacity(parsed.len());
+    for entry in &parsed {
+        planned.extend(plan(entry, &cfg)?);
+    }
+    report("plan", start.elapsed(), entries);
+
+    let start = Instant::now();
+    let mut operations = 0;
+    for op in &planned {
+        execute_op(op, &cfg, &mut operations)?;
+    }
+    report("execute", start.elapsed(), entries);
+
+    Ok(())
+}
+
+pub fn bench(entries: usize) -> io::Result<()> {
+    let root = env::temp_dir().join(format!("neostow-bench-{}", process::id()));
+    let result = measure(&root, entries);
+    if root.exists() {
+        fs::remove_dir_all(&root)?;
+    }
+    result
+}
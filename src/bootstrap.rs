@@ -0,0 +1,197 @@
+// `bootstrap URL [PACKAGE]...` clones a dotfiles repository into a directory
+// of the current one named after it, as git clone would, then applies it.
+// Given packages, the clone is partial and sparse: blobs are only
+// downloaded for what is checked out, and only the files at the top of the
+// repository are, then the sources of the entries of those packages and the
+// files they include, as this machine's conditions select them. A machine
+// that only needs [server] is spared the themes and fonts of the others;
+// `git sparse-checkout disable` brings in the rest later.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::expand::normalize;
+use crate::{LogLevel, Parser, age, bytes, fetch, glob, include, raw};
+
+// Included files can name more of them, each found once the last is checked
+// out
+const MAX_ROUNDS: usize = 8;
+
+// What git clone would name the directory of url
+fn name(url: &str) -> &str {
+    let last = url
+        .trim_end_matches('/')
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or_default();
+    last.strip_suffix(".git").unwrap_or(last)
+}
+
+fn git(dir: &Path, args: &[&str]) -> io::Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(dir).args(args);
+    fetch::run(&mut cmd, &format!("git {} in {}", args[0], dir.display()))
+}
+
+// A path of the repository as a sparse-checkout pattern matching only it
+fn literal(path: &[u8]) -> String {
+    let mut out = vec![b'/'];
+    for &c in path {
+        if matches!(c, b'*' | b'?' | b'[' | b'\\' | b'!' | b'#') {
+            out.push(b'\\');
+        }
+        out.push(c);
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// The pattern for a source of an entry in the file at rel, the directory of
+// that file in the repository. A pattern of sources is one already, and one
+// that is only known once variables or placeholders are filled in stands
+// for all of the directory it is in.
+fn pattern(rel: &[u8], src: &[u8]) -> String {
+    if let Some(src) = raw(src) {
+        return literal(&[rel, src].concat());
+    }
+    let src = src.strip_prefix(b"./").unwrap_or(src);
+    let path = [rel, src].concat();
+    match path.iter().position(|c| matches!(c, b'{' | b'$' | b'~')) {
+        Some(at) => {
+            let dir = path[..at]
+                .iter()
+                .rposition(|&c| c == b'/')
+                .map_or(0, |k| k + 1);
+            literal(&path[..dir]) + "*"
+        }
+        None if glob::is_pattern(&path) => format!("/{}", String::from_utf8_lossy(&path)),
+        None => literal(&path),
+    }
+}
+
+// Adds the patterns of the entries file holds for packages, and of the
+// files it includes, to out. A file not checked out yet is left for the
+// next round.
+fn patterns(
+    root: &Path,
+    file: &Path,
+    section: Option<Vec<u8>>,
+    packages: &[String],
+    out: &mut BTreeSet<String>,
+) -> io::Result<()> {
+    if !file.exists() {
+        return Ok(());
+    }
+    let dir = file.parent().unwrap_or(root);
+    let mut rel =
+        bytes::from_os(dir.strip_prefix(root).unwrap_or(Path::new("")).as_os_str()).into_owned();
+    if !rel.is_empty() {
+        rel.push(b'/');
+    }
+    let content = age::read(file)?;
+    let mut parser = Parser::within(packages, section);
+    for line in bytes::lines(&content) {
+        let entry = parser.parse(line);
+        if parser.included()
+            && let Some(rest) = include::parse(line)
+            && let Some(path) = include::words(rest)?.into_iter().next()
+        {
+            // Files outside the repository are there already, or not at all
+            if path.starts_with(b"~")
+                || path.starts_with(b"$")
+                || Path::new(&*bytes::to_os(&path)).is_absolute()
+            {
+                continue;
+            }
+            let child = normalize(&dir.join(bytes::to_os(&path)));
+            if let Ok(inside) = child.strip_prefix(root) {
+                out.insert(literal(&bytes::from_os(inside.as_os_str())));
+            }
+            let section = parser.section().map(<[u8]>::to_vec);
+            patterns(root, &child, section, packages, out)?;
+            continue;
+        }
+        let Some(entry) = entry else {
+            continue;
+        };
+        if entry
+            .modifiers
+            .iter()
+            .any(|modifier| modifier.name == "fetch")
+        {
+            continue;
+        }
+        out.insert(pattern(&rel, entry.src));
+    }
+    Ok(())
+}
+
+/// Clones the repository at url for packages, all of it when there are none,
+/// and returns its neostow file.
+pub fn bootstrap(url: &str, packages: &[String]) -> io::Result<PathBuf> {
+    let root = env::current_dir()?.join(name(url));
+    if root
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists and is not empty", root.display()),
+        ));
+    }
+    let mut clone = Command::new("git");
+    clone.args(["clone", "--quiet"]);
+    if !packages.is_empty() {
+        clone.args(["--filter=blob:none", "--sparse"]);
+    }
+    fetch::run(clone.arg(url).arg(&root), &format!("git clone {url}"))?;
+
+    let file = [
+        ".neostow",
+        ".neostow.toml",
+        ".neostow.yaml",
+        ".neostow.yml",
+        ".neostow.age",
+        ".neostow.toml.age",
+        ".neostow.yaml.age",
+        ".neostow.yml.age",
+    ]
+    .iter()
+    .map(|name| root.join(name))
+    .find(|file| file.exists())
+    .ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{url} has no .neostow at its top"),
+        )
+    })?;
+    if packages.is_empty() {
+        return Ok(file);
+    }
+
+    // Only the files at the top, until the entries bring in more
+    let mut checked_out = BTreeSet::new();
+    for _ in 0..MAX_ROUNDS {
+        let mut wanted = BTreeSet::new();
+        patterns(&root, &file, None, packages, &mut wanted)?;
+        if wanted == checked_out {
+            break;
+        }
+        let mut args = vec!["sparse-checkout", "set", "--no-cone", "/*", "!/*/"];
+        args.extend(wanted.iter().map(String::as_str));
+        git(&root, &args)?;
+        checked_out = wanted;
+    }
+    printfc!(
+        LogLevel::Info,
+        "Checked out the top of {} and, for {}:",
+        root.display(),
+        packages.join(", ")
+    );
+    for pattern in &checked_out {
+        printfc!(LogLevel::Info, "  {pattern}");
+    }
+    Ok(file)
+}
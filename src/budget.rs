@@ -0,0 +1,109 @@
+// Copies and templates write the bytes of their sources, where links write
+// nothing, so a `--mode copy` run over a fonts directory can fill a small
+// root partition. Before a run writes any, the bytes it would copy are added
+// up, counting only destinations not already in place; a dry run reports
+// them in its summary, and a run over the budget, 100M unless copy_budget in
+// config.toml or --copy-budget says otherwise, asks before going on.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{
+    Config, LinkMode, LogLevel, Mode, Operation, age, plan_lines, prompt_user, report, status,
+};
+
+pub(crate) const DEFAULT: u64 = 100 << 20;
+
+const UNITS: [(&str, u64); 4] = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10), ("", 1)];
+
+/// Reads a size like 500M, 2G or 4096, in bytes, with 0 or none for no
+/// limit.
+pub fn parse(size: &str) -> Option<Option<u64>> {
+    let size = size.trim();
+    if size == "none" {
+        return Some(None);
+    }
+    let upper = size.to_ascii_uppercase();
+    let number = upper.strip_suffix('B').unwrap_or(&upper);
+    let (number, unit) = UNITS
+        .iter()
+        .find_map(|&(suffix, unit)| Some((number.strip_suffix(suffix)?, unit)))?;
+    let bytes = number.trim().parse::<u64>().ok()?.checked_mul(unit)?;
+    Some(Some(bytes).filter(|&bytes| bytes > 0))
+}
+
+// bytes as the largest unit it makes at least one of, as in 1.5G
+pub(crate) fn human(bytes: u64) -> String {
+    match UNITS.iter().find(|&&(_, unit)| bytes >= unit && unit > 1) {
+        Some(&(suffix, unit)) => format!("{:.1}{suffix}", bytes as f64 / unit as f64),
+        None => format!("{bytes} bytes"),
+    }
+}
+
+// The bytes of the files under path, links counting as what they are
+fn size(path: &Path) -> u64 {
+    let Ok(meta) = path.symlink_metadata() else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| size(&entry.path()))
+        .sum()
+}
+
+fn copies(op: &Operation, cfg: &Config) -> bool {
+    !op.assert && (cfg.link_mode == LinkMode::Copy || op.template.is_some())
+}
+
+// The bytes the run of cfg would copy, with the operations that copy them,
+// largest first
+fn planned(cfg: &Config) -> io::Result<(u64, Vec<(u64, Operation)>)> {
+    // Entries that can't be planned are reported by the run itself
+    let ops = plan_lines(cfg, &age::read(&cfg.file)?, |_, _, _| {})?;
+    let mut sized = Vec::new();
+    for (_, op) in ops {
+        let in_place = op.dest.symlink_metadata().is_ok() && status::op_in_place(&op, cfg)?;
+        if copies(&op, cfg) && !in_place {
+            sized.push((size(&op.src), op));
+        }
+    }
+    sized.sort_by_key(|&(bytes, _)| std::cmp::Reverse(bytes));
+    Ok((sized.iter().map(|(bytes, _)| bytes).sum(), sized))
+}
+
+// Adds up what the run would copy for its summary, and unless it fits the
+// budget, or --force is given, asks whether to go on
+pub(crate) fn check(cfg: &Config) -> io::Result<()> {
+    if matches!(cfg.mode, Mode::Delete) {
+        return Ok(());
+    }
+    let (total, sized) = planned(cfg)?;
+    report::copying(total);
+    let Some(budget) = cfg.copy_budget.filter(|&budget| total > budget) else {
+        return Ok(());
+    };
+    printfc!(
+        LogLevel::Warn,
+        "This run would copy {}, over the budget of {}. The largest:",
+        human(total),
+        human(budget)
+    );
+    for (bytes, op) in sized.iter().take(3) {
+        printfc!(LogLevel::Warn, "  {} {}", human(*bytes), op.src.display());
+    }
+    if cfg.dry || cfg.force {
+        return Ok(());
+    }
+    if !prompt_user("Copy them anyway?")? {
+        return Err(io::Error::other(
+            "Stopped before copying over the budget, raise it with --copy-budget or copy_budget in config.toml",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,58 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+
+#[cfg(unix)]
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+
+// Filenames on Unix are arbitrary bytes, so the neostow file is parsed as
+// bytes and only converted to OS strings at the edges. Windows paths are
+// UTF-16, so invalid UTF-8 there can only be replaced.
+
+#[cfg(unix)]
+pub fn to_os(bytes: &[u8]) -> Cow<'_, OsStr> {
+    Cow::Borrowed(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn to_os(bytes: &[u8]) -> Cow<'_, OsStr> {
+    Cow::Owned(OsString::from(String::from_utf8_lossy(bytes).into_owned()))
+}
+
+#[cfg(unix)]
+pub fn to_os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+pub fn to_os_string(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[cfg(unix)]
+pub fn from_os(s: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Borrowed(s.as_bytes())
+}
+
+#[cfg(not(unix))]
+pub fn from_os(s: &OsStr) -> Cow<'_, [u8]> {
+    Cow::Owned(s.to_string_lossy().into_owned().into_bytes())
+}
+
+pub fn find(haystack: &[u8], byte: u8) -> Option<usize> {
+    haystack.iter().position(|&b| b == byte)
+}
+
+pub fn split_once(s: &[u8], byte: u8) -> Option<(&[u8], &[u8])> {
+    let pos = find(s, byte)?;
+    Some((&s[..pos], &s[pos + 1..]))
+}
+
+// Splits on \n, dropping a trailing \r like BufRead::lines does
+pub fn lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    content
+        .strip_suffix(b"\n")
+        .unwrap_or(content)
+        .split(|&b| b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(move |_| !content.is_empty())
+}
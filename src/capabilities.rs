@@ -0,0 +1,132 @@
+// `neostow capabilities` says what this build can do on this machine, so a
+// wrapper script or a front end can leave out what would only fail when run:
+// whether symlinks can be made without privileges, hard links and reflinks,
+// extended attributes, how watch notices changes, the optional features
+// built in, and the programs some entries need. Links are tried for real in
+// a scratch directory of the state directory, which is on the filesystem of
+// the home in most setups, and removed again. With --output json, or --json,
+// it is one JSON object.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{attrs, fsops, journal, report, state, xattr};
+
+// What each optional feature of the build is called in Cargo.toml
+const FEATURES: [(&str, bool); 1] = [("ffi", cfg!(feature = "ffi"))];
+
+// The programs entries and commands run: git for !fetch, bootstrap and
+// plan-diff, jj and hg for plan-diff, age for .age files, curl for archives
+const PROGRAMS: [&str; 5] = ["git", "jj", "hg", "age", "curl"];
+
+/// What the build and the platform support.
+pub struct Capabilities {
+    /// Whether symlinks can be made, which Windows allows only in developer
+    /// mode or as an administrator
+    pub symlinks: bool,
+    pub hard_links: bool,
+    /// Whether copies can share the blocks of their sources
+    pub reflinks: bool,
+    pub xattrs: bool,
+    /// Whether neostow runs as root, which [owner=...] needs
+    pub root: bool,
+    /// The optional features built in
+    pub features: Vec<&'static str>,
+    /// Each program entries may run, with whether it is on PATH
+    pub programs: Vec<(&'static str, bool)>,
+}
+
+fn on_path(program: &str) -> bool {
+    let exe = format!("{program}{}", env::consts::EXE_SUFFIX);
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(&exe).is_file()))
+}
+
+// Tries each kind of link in dir, with a file to link to
+fn probe(dir: &Path) -> io::Result<(bool, bool, bool, bool)> {
+    let file = dir.join("file");
+    fs::write(&file, b"neostow")?;
+    let symlinks = journal::make_link(Path::new("file"), &dir.join("symlink"), false).is_ok();
+    let hard_links = fs::hard_link(&file, dir.join("hard-link")).is_ok();
+    let reflinks = fsops::reflink(&file, &dir.join("reflink")).is_ok();
+    let xattrs = xattr::list(&file).is_ok();
+    Ok((symlinks, hard_links, reflinks, xattrs))
+}
+
+/// Finds out what this machine supports.
+pub fn detect() -> io::Result<Capabilities> {
+    let dir = state::state_dir().join(format!("capabilities-{}", std::process::id()));
+    fs::create_dir_all(&dir)?;
+    let probed = probe(&dir);
+    fs::remove_dir_all(&dir)?;
+    let (symlinks, hard_links, reflinks, xattrs) = probed?;
+    Ok(Capabilities {
+        symlinks,
+        hard_links,
+        reflinks,
+        xattrs,
+        root: attrs::is_root(),
+        features: FEATURES
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|&(name, _)| name)
+            .collect(),
+        programs: PROGRAMS.iter().map(|&name| (name, on_path(name))).collect(),
+    })
+}
+
+/// Prints what this machine supports, as text or JSON.
+pub fn print(caps: &Capabilities) {
+    let supports = [
+        ("symlinks", caps.symlinks),
+        ("hard_links", caps.hard_links),
+        ("reflinks", caps.reflinks),
+        ("xattrs", caps.xattrs),
+        ("running_as_root", caps.root),
+    ];
+    if report::json() {
+        let mut fields: Vec<String> = vec![
+            format!("\"os\":{}", report::string(env::consts::OS)),
+            format!("\"arch\":{}", report::string(env::consts::ARCH)),
+        ];
+        fields.extend(
+            supports
+                .iter()
+                .map(|(name, yes)| format!("\"{name}\":{yes}")),
+        );
+        fields.push("\"watch\":\"poll\"".to_string());
+        let features: Vec<String> = caps
+            .features
+            .iter()
+            .map(|name| report::string(name))
+            .collect();
+        fields.push(format!("\"features\":[{}]", features.join(",")));
+        let programs: Vec<String> = caps
+            .programs
+            .iter()
+            .map(|(name, found)| format!("\"{name}\":{found}"))
+            .collect();
+        fields.push(format!("\"programs\":{{{}}}", programs.join(",")));
+        println!("{{{}}}", fields.join(","));
+        return;
+    }
+    let yes = |found: bool| if found { "yes" } else { "no" };
+    println!("platform: {} {}", env::consts::OS, env::consts::ARCH);
+    for (name, found) in supports {
+        println!("{}: {}", name.replace('_', " "), yes(found));
+    }
+    println!("watch: poll");
+    println!(
+        "features: {}",
+        if caps.features.is_empty() {
+            "none".to_string()
+        } else {
+            caps.features.join(", ")
+        }
+    );
+    for (name, found) in &caps.programs {
+        println!("{name}: {}", if *found { "found" } else { "not found" });
+    }
+}
@@ -0,0 +1,304 @@
+// `neostow check` and --strict look for lines the parser would otherwise
+// take in its stride: an entry without its `=`, an empty source, a directive
+// or modifier nobody knows. Each problem is reported with where it is, the
+// line itself and what was probably meant:
+//   .neostow:4:6: unknown modifier !manul
+//     nvim=~/.config !manul
+//                     ^ did you mean !manual?
+
+use std::io;
+use std::path::PathBuf;
+
+use crate::{
+    Config, LogLevel, aliases, bytes, cond, defaults, glob, hooks, ignore, include, parse_line,
+    plan, raw, registry, section_header, suggest, template,
+};
+
+const DIRECTIVES: [&str; 10] = [
+    "host", "os", "env", "end", "include", "ignore", "pre", "post", "pre-run", "post-run",
+];
+const MODIFIERS: [&str; 22] = [
+    "managed-dir",
+    "manual",
+    "keep-original",
+    "require-target",
+    "system",
+    "template",
+    "dedup",
+    "assert",
+    "fetch",
+    "tag",
+    "desc",
+    "mode",
+    "dir-mode",
+    "owner",
+    "inherit",
+    "preserve",
+    "prefix",
+    "suffix",
+    "validate",
+    "refresh",
+    "only",
+    "if-exists",
+];
+
+pub struct Problem {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+    pub text: String,
+    pub message: String,
+    pub suggestion: Option<String>,
+}
+
+impl Problem {
+    pub fn print(&self) {
+        printfc!(
+            LogLevel::Error,
+            "{}:{}:{}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.message
+        );
+        println!("  {}", self.text);
+        if let Some(suggestion) = &self.suggestion {
+            println!("  {}^ {suggestion}", " ".repeat(self.column - 1));
+        }
+    }
+}
+
+// The 1-based column where part, a slice of line, starts
+fn column(line: &[u8], part: &[u8]) -> usize {
+    part.as_ptr().addr() - line.as_ptr().addr() + 1
+}
+
+fn closest<'a>(typed: &str, known: &[&'a str]) -> Option<&'a str> {
+    let typed: Vec<char> = typed.chars().collect();
+    known
+        .iter()
+        .map(|name| {
+            (
+                suggest::distance(&typed, &name.chars().collect::<Vec<_>>()),
+                *name,
+            )
+        })
+        .filter(|(d, name)| *d <= 2.max(name.len() / 4) && *d < name.len())
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, name)| name)
+}
+
+// The problems of one line, given with their column, message and suggestion
+fn check_line(
+    line: &[u8],
+    section: Option<&[u8]>,
+    cfg: &Config,
+) -> Vec<(usize, String, Option<String>)> {
+    let text = line.trim_ascii();
+    let start = column(line, text);
+    if text.is_empty() || text.starts_with(b"#") {
+        return Vec::new();
+    }
+
+    if text.starts_with(b"[") {
+        return match section_header(text) {
+            Some(name) if name.trim_ascii().is_empty() => vec![(
+                start,
+                "empty section name".to_string(),
+                Some("name the package, as in [nvim]".to_string()),
+            )],
+            Some(_) => Vec::new(),
+            None => vec![(
+                start,
+                "unclosed section header".to_string(),
+                Some("end it with ]".to_string()),
+            )],
+        };
+    }
+
+    if let Some(rest) = text.strip_prefix(b"@") {
+        if hooks::is_hook(text)
+            || include::parse(text).is_some()
+            || ignore::parse_line(text).is_some()
+        {
+            return Vec::new();
+        }
+        let end = rest
+            .iter()
+            .position(|&b| !(b.is_ascii_alphanumeric() || b == b'-'))
+            .unwrap_or(rest.len());
+        let name = String::from_utf8_lossy(&rest[..end]);
+        if !DIRECTIVES.contains(&&*name) {
+            let suggestion = match closest(&name, &DIRECTIVES) {
+                Some(known) => format!("did you mean @{known}?"),
+                None => "expected one of @host, @os, @env, @end, @include, @ignore, @pre, \
+                         @post, @pre-run or @post-run"
+                    .to_string(),
+            };
+            return vec![(
+                start,
+                format!("unknown directive @{name}"),
+                Some(suggestion),
+            )];
+        }
+        return match cond::evaluate(text) {
+            Ok(_) => Vec::new(),
+            Err(err) => vec![(start, err.to_string(), None)],
+        };
+    }
+
+    match section {
+        Some(b"reload" | b"transform") => return Vec::new(),
+        Some(b"registry") => {
+            return match registry::check_line(text) {
+                Ok(()) => Vec::new(),
+                Err(err) => vec![(start, err.to_string(), None)],
+            };
+        }
+        Some(b"vars") => {
+            return match template::check_line(text) {
+                Ok(()) => Vec::new(),
+                Err(err) => vec![(start, err.to_string(), None)],
+            };
+        }
+        Some(b"aliases") => {
+            return match aliases::parse_alias(text) {
+                Ok(_) => Vec::new(),
+                Err(err) => vec![(
+                    start,
+                    err.to_string(),
+                    Some("quote the arguments, as in ls = \"list --tree\"".to_string()),
+                )],
+            };
+        }
+        _ => {}
+    }
+
+    if let Some(raw) = defaults::parse_line(text) {
+        return match defaults::check_line(raw) {
+            Ok(()) => Vec::new(),
+            Err(err) => vec![(start, err.to_string(), None)],
+        };
+    }
+    let Some(entry) = parse_line(line) else {
+        return Vec::new();
+    };
+    let mut problems = Vec::new();
+    if entry.src.is_empty() {
+        problems.push((
+            start,
+            "empty source".to_string(),
+            Some(format!(
+                "name a file or directory of {} first",
+                cfg.basedir.display()
+            )),
+        ));
+    }
+    for dest in entry.destinations().into_iter().flatten() {
+        if dest.is_empty() {
+            let at = column(line, dest);
+            problems.push((
+                at,
+                "empty destination".to_string(),
+                Some(
+                    "name where it goes, or leave out the = to link it next to the directory"
+                        .into(),
+                ),
+            ));
+        }
+    }
+    // A source with a space in it is more likely a mapping missing its =,
+    // unless there is a file by that name
+    if entry.dest.is_none()
+        && let Some(gap) = entry.src.iter().position(u8::is_ascii_whitespace)
+        && raw(entry.src).is_none()
+        && !glob::is_pattern(entry.src)
+        && !cfg.basedir.join(bytes::to_os(entry.src)).exists()
+    {
+        let (src, dest) = (&entry.src[..gap], entry.src[gap..].trim_ascii());
+        problems.push((
+            column(line, &entry.src[gap..]),
+            "missing =".to_string(),
+            Some(format!(
+                "did you mean {}={}?",
+                String::from_utf8_lossy(src),
+                String::from_utf8_lossy(dest)
+            )),
+        ));
+    }
+    for modifier in &entry.modifiers {
+        if MODIFIERS.contains(&modifier.name) {
+            continue;
+        }
+        // The name is a slice of the line, right after its !
+        let at = column(line, modifier.name.as_bytes()) - 1;
+        let suggestion = match closest(modifier.name, &MODIFIERS) {
+            Some(known) => format!("did you mean !{known}?"),
+            None => format!("expected one of !{}", MODIFIERS.join(", !")),
+        };
+        problems.push((
+            at,
+            format!("unknown modifier !{}", modifier.name),
+            Some(suggestion),
+        ));
+    }
+
+    // What is left to go wrong shows up when the entry is planned
+    if problems.is_empty()
+        && let Err(err) = plan(&entry, cfg)
+    {
+        problems.push((start, err.to_string(), None));
+    }
+    problems
+}
+
+fn check_file(cfg: &Config, problems: &mut Vec<Problem>) -> io::Result<()> {
+    let content = include::read(cfg)?;
+    let mut section: Option<Vec<u8>> = None;
+    for (i, line) in bytes::lines(&content).enumerate() {
+        if let Some(name) = section_header(line) {
+            section = Some(name.trim_ascii().to_vec());
+        }
+        for (column, message, suggestion) in check_line(line, section.as_deref(), cfg) {
+            problems.push(Problem {
+                file: cfg.file.clone(),
+                line: i + 1,
+                column,
+                text: String::from_utf8_lossy(line).into_owned(),
+                message,
+                suggestion,
+            });
+        }
+        if let Some(child) = include::child(cfg, i + 1) {
+            check_file(child, problems)?;
+        }
+    }
+    Ok(())
+}
+
+/// Every problem in the loaded neostow file of cfg and the files it
+/// includes, in the order they appear.
+pub fn check(cfg: &Config) -> io::Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    check_file(cfg, &mut problems)?;
+    Ok(problems)
+}
+
+/// Prints the problems of cfg's file, failing when there are any.
+pub fn report(cfg: &Config) -> io::Result<()> {
+    let problems = check(cfg)?;
+    for problem in &problems {
+        problem.print();
+    }
+    match problems.len() {
+        0 => Ok(()),
+        n => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{n} problem{} in {}",
+                if n == 1 { "" } else { "s" },
+                cfg.file.display()
+            ),
+        )),
+    }
+}
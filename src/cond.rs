@@ -0,0 +1,157 @@
+// `@host(name)`, `@os(name)` and `@env(VAR)` lines gate the entries after
+// them, up to the next condition line, `@end` or section header. Several
+// conditions on one line must all hold; a comma separates alternatives and
+// a leading `!` negates: `@os(linux,freebsd) @env(!SSH_CONNECTION)`.
+//
+// A single entry can be guarded the same way with `[only=linux|macos]` and
+// `[if-exists=~/.tmux]` after it, or `!only:` and `!if-exists:`. Every guard
+// must hold for the entry to be planned.
+
+use std::env;
+use std::io;
+
+use crate::expand::{expand_path, normalize};
+use crate::{Config, Modifier, bytes, hooks, ignore, include, platform};
+
+// What `[only=...]` may name: env::consts::OS of the platforms neostow runs
+// on, and the families
+const PLATFORMS: [&str; 12] = [
+    "linux",
+    "macos",
+    "windows",
+    "freebsd",
+    "openbsd",
+    "netbsd",
+    "dragonfly",
+    "android",
+    "ios",
+    "illumos",
+    "solaris",
+    "unix",
+];
+
+// Hook lines (`@post nvim = ...`) and includes share the `@` but aren't
+// conditions
+pub fn is_directive(line: &[u8]) -> bool {
+    line.trim_ascii().starts_with(b"@")
+        && !hooks::is_hook(line)
+        && include::parse(line).is_none()
+        && ignore::parse_line(line).is_none()
+}
+
+fn holds(kind: &str, value: &str) -> io::Result<bool> {
+    Ok(match kind {
+        "host" => platform::hostname().eq_ignore_ascii_case(value),
+        "os" => value == env::consts::OS || value == env::consts::FAMILY,
+        "env" => match value.split_once('=') {
+            Some((name, expected)) => env::var_os(name).is_some_and(|v| v == expected),
+            None => env::var_os(value).is_some_and(|v| !v.is_empty()),
+        },
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown condition @{kind}, expected @host, @os or @env"),
+            ));
+        }
+    })
+}
+
+// Returns why the entries after a directive line are skipped, or None when
+// every condition on it holds (or it is `@end`)
+pub fn evaluate(line: &[u8]) -> io::Result<Option<String>> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid condition {:?}", bytes::to_os(line.trim_ascii())),
+        )
+    };
+    let line = std::str::from_utf8(line).map_err(|_| invalid())?.trim();
+    if line == "@end" {
+        return Ok(None);
+    }
+
+    let mut rest = line;
+    while let Some(directive) = rest.trim_start().strip_prefix('@') {
+        let (kind, args) = directive.split_once('(').ok_or_else(invalid)?;
+        let (args, tail) = args.split_once(')').ok_or_else(invalid)?;
+        let condition = &directive[..directive.len() - tail.len()];
+        rest = tail;
+
+        let kind = kind.trim();
+        let (negated, args) = match args.trim().strip_prefix('!') {
+            Some(args) => (true, args),
+            None => (false, args),
+        };
+        let mut matched = false;
+        for value in args.split(',').map(str::trim) {
+            matched |= holds(kind, value)?;
+        }
+        if matched == negated {
+            let reason = match kind {
+                "host" => format!(
+                    "@{condition} does not hold, host is {}",
+                    platform::hostname()
+                ),
+                "os" => format!("@{condition} does not hold, os is {}", env::consts::OS),
+                _ => format!("@{condition} does not hold"),
+            };
+            return Ok(Some(reason));
+        }
+    }
+    if !rest.trim().is_empty() && !rest.trim_start().starts_with('#') {
+        return Err(invalid());
+    }
+    Ok(None)
+}
+
+// Returns why an entry with these modifiers is left out by one of its
+// guards, or None when all of them hold
+pub(crate) fn guard(modifiers: &[Modifier], cfg: &Config) -> io::Result<Option<String>> {
+    for modifier in modifiers {
+        let value = match (modifier.name, modifier.value) {
+            ("only" | "if-exists", Some(value)) => value,
+            ("only" | "if-exists", None) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} needs a value, as in [{}=...]",
+                        modifier.name, modifier.name
+                    ),
+                ));
+            }
+            _ => continue,
+        };
+        let text = String::from_utf8_lossy(value);
+        let guard = format!("[{}={text}]", modifier.name);
+        if modifier.name == "only" {
+            let mut matched = false;
+            for platform in text.split('|').map(str::trim) {
+                if !PLATFORMS.contains(&platform) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "unknown platform {platform:?} in {guard}, expected one of {}",
+                            PLATFORMS.join(", ")
+                        ),
+                    ));
+                }
+                matched |= holds("os", platform)?;
+            }
+            if !matched {
+                return Ok(Some(format!(
+                    "{guard} does not hold, os is {}",
+                    env::consts::OS
+                )));
+            }
+        } else {
+            let path = normalize(&cfg.basedir.join(expand_path(value, &cfg.vars)?));
+            if !cfg.fs.exists(&path) {
+                return Ok(Some(format!(
+                    "{guard} does not hold, {} doesn't exist",
+                    path.display()
+                )));
+            }
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,96 @@
+use std::collections::BTreeMap;
+use std::collections::hash_map::{Entry, HashMap};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Config, LogLevel, Mode, Operation, report};
+
+// Destinations planned so far in a run, with the file, line, source and
+// package that claimed them
+pub type Claims = HashMap<PathBuf, (PathBuf, usize, PathBuf, Option<String>)>;
+
+// Fails an operation whose destination an earlier one already claimed, so
+// the mistake surfaces before linking rather than as a "File exists" halfway
+// through, or as a link silently replaced with --overwrite. An !assert entry
+// makes nothing, and claims nothing.
+pub fn claim(claims: &mut Claims, op: &Operation, linenum: usize, cfg: &Config) -> io::Result<()> {
+    if matches!(cfg.mode, Mode::Delete) || op.assert {
+        return Ok(());
+    }
+    match claims.entry(op.dest.clone()) {
+        Entry::Vacant(entry) => {
+            entry.insert((
+                op.file(cfg).to_path_buf(),
+                linenum,
+                op.src.clone(),
+                op.package.clone(),
+            ));
+            Ok(())
+        }
+        Entry::Occupied(entry) => {
+            report::saw(report::Condition::Conflict);
+            let (file, first, src, package) = entry.get();
+            Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!(
+                    "{} is already claimed by {}:{first} ({}){}, see neostow conflicts",
+                    op.dest.display(),
+                    file.display(),
+                    src.strip_prefix(file.parent().unwrap_or(&cfg.basedir))
+                        .unwrap_or(src)
+                        .display(),
+                    of_package(package.as_deref())
+                ),
+            ))
+        }
+    }
+}
+
+// How a claimant's package is named after it, nothing outside any section
+fn of_package(package: Option<&str>) -> String {
+    package.map_or_else(String::new, |name| format!(" of package {name}"))
+}
+
+// Reports destinations claimed by more than one entry, with the package of
+// each, and returns how many there are. The first entry is applied and later
+// ones fail.
+pub fn report(ops: &[(usize, Operation)], cfg: &Config) -> usize {
+    let mut claims: BTreeMap<&Path, Vec<(usize, &Operation)>> = BTreeMap::new();
+    for (linenum, op) in ops.iter().filter(|(_, op)| !op.assert) {
+        claims.entry(&op.dest).or_default().push((*linenum, op));
+    }
+
+    let mut conflicts = 0;
+    for (dest, entries) in claims.iter().filter(|(_, e)| e.len() > 1) {
+        conflicts += 1;
+        let winner = match &entries[0].1.package {
+            Some(name) => format!(", package {name} wins"),
+            None => String::new(),
+        };
+        printfc!(
+            LogLevel::Warn,
+            "{} is claimed by {} entries{winner}",
+            dest.display(),
+            entries.len()
+        );
+        for (i, (linenum, op)) in entries.iter().enumerate() {
+            let file = op.file(cfg);
+            let basedir = file.parent().unwrap_or(&cfg.basedir);
+            let src = op.src.strip_prefix(basedir).unwrap_or(&op.src);
+            let package = of_package(op.package.as_deref());
+            let mark = if i == 0 { " (wins)" } else { "" };
+            println!(
+                "  {}:{}: {}{package}{mark}",
+                file.display(),
+                linenum,
+                src.display()
+            );
+        }
+    }
+
+    if conflicts == 0 {
+        printfc!(LogLevel::Info, "No conflicting destinations");
+    }
+
+    conflicts
+}
@@ -0,0 +1,196 @@
+// `watch` puts back a destination that something else changed, but one that
+// keeps changing is most likely rewritten by another program, and fighting
+// it forever only churns. Each time a destination is put back within
+// FLAP_WINDOW of the last, watch waits twice as long before the next, from
+// BASE_DELAY, and after FLAP_LIMIT of them it leaves the destination alone
+// as flapping. Flapping destinations are recorded in the state directory, so
+// `status` lists them and a restarted watch still leaves them alone, until
+// one is found in place again.
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{LogLevel, bytes, state};
+
+const FLAPPING_FILE: &str = "flapping";
+const BASE_DELAY: Duration = Duration::from_secs(2);
+const FLAP_WINDOW: Duration = Duration::from_secs(10 * 60);
+const FLAP_LIMIT: u32 = 5;
+
+struct Fixes {
+    count: u32,
+    last: Instant,
+    // When the destination may be put back again
+    until: Instant,
+}
+
+// A destination watch gave up on, as recorded in the state directory
+pub(crate) struct Flapping {
+    pub(crate) dest: PathBuf,
+    // How many times it was put back before
+    pub(crate) fixes: u32,
+    // When it was given up on, in seconds since the epoch
+    since: u64,
+}
+
+pub(crate) enum Verdict {
+    // Put it back now
+    Fix,
+    // Put it back once this has passed
+    Hold(Duration),
+    // Leave it alone, logging why if it has just started to flap
+    Flapping(bool),
+}
+
+static FIXES: Mutex<BTreeMap<PathBuf, Fixes>> = Mutex::new(BTreeMap::new());
+static FLAPPING: Mutex<Vec<Flapping>> = Mutex::new(Vec::new());
+
+// The destinations recorded as flapping
+pub(crate) fn load() -> io::Result<Vec<Flapping>> {
+    let Some(content) = state::read_file(&state::host_dir().join(FLAPPING_FILE))? else {
+        return Ok(Vec::new());
+    };
+    let mut found = Vec::new();
+    for line in bytes::lines(&content) {
+        let mut fields = line.splitn(3, |&b| b == b'\t');
+        let (Some(fixes), Some(since), Some(dest)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let number = |field: &[u8]| String::from_utf8_lossy(field).parse::<u64>().ok();
+        let (Some(fixes), Some(since)) = (number(fixes), number(since)) else {
+            continue;
+        };
+        found.push(Flapping {
+            dest: PathBuf::from(bytes::to_os(dest).into_owned()),
+            fixes: u32::try_from(fixes).unwrap_or(u32::MAX),
+            since,
+        });
+    }
+    Ok(found)
+}
+
+fn save(flapping: &[Flapping]) {
+    let path = state::host_dir().join(FLAPPING_FILE);
+    let result = if flapping.is_empty() {
+        state::remove_file(&path)
+    } else {
+        let mut out = Vec::new();
+        for entry in flapping {
+            let _ = write!(out, "{}\t{}\t", entry.fixes, entry.since);
+            out.extend_from_slice(&bytes::from_os(entry.dest.as_os_str()));
+            out.push(b'\n');
+        }
+        state::write_file(&path, &out)
+    };
+    if let Err(err) = result {
+        printfc!(
+            LogLevel::Warn,
+            "Could not record flapping destinations: {err}"
+        );
+    }
+}
+
+// Takes in what an earlier watch gave up on, warning about each
+pub(crate) fn start() {
+    let flapping = load().unwrap_or_else(|err| {
+        printfc!(
+            LogLevel::Warn,
+            "Could not read flapping destinations: {err}"
+        );
+        Vec::new()
+    });
+    for entry in &flapping {
+        printfc!(
+            LogLevel::Warn,
+            "{} is still left alone, it kept changing after being put back {} times",
+            entry.dest.display(),
+            entry.fixes
+        );
+    }
+    *FLAPPING.lock().unwrap_or_else(|e| e.into_inner()) = flapping;
+}
+
+// Decides what to do about dest, found changed and out of place, and counts
+// the fix when it is to be put back now
+pub(crate) fn drifted(dest: &Path) -> Verdict {
+    let mut flapping = FLAPPING.lock().unwrap_or_else(|e| e.into_inner());
+    if flapping.iter().any(|entry| entry.dest == dest) {
+        return Verdict::Flapping(false);
+    }
+    let now = Instant::now();
+    let mut all = FIXES.lock().unwrap_or_else(|e| e.into_inner());
+    let fixes = all.entry(dest.to_path_buf()).or_insert(Fixes {
+        count: 0,
+        last: now,
+        until: now,
+    });
+    if fixes.count > 0 && now.duration_since(fixes.last) > FLAP_WINDOW {
+        fixes.count = 0;
+    }
+    if fixes.count >= FLAP_LIMIT {
+        let count = fixes.count;
+        all.remove(dest);
+        let since = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs());
+        flapping.push(Flapping {
+            dest: dest.to_path_buf(),
+            fixes: count,
+            since,
+        });
+        save(&flapping);
+        return Verdict::Flapping(true);
+    }
+    if now < fixes.until {
+        return Verdict::Hold(fixes.until - now);
+    }
+    fixes.count += 1;
+    fixes.last = now;
+    fixes.until = now + BASE_DELAY * 2u32.pow(fixes.count - 1);
+    Verdict::Fix
+}
+
+// Forgets dest, found in place again, whether it was cooling down or flapping
+pub(crate) fn settled(dest: &Path) {
+    FIXES.lock().unwrap_or_else(|e| e.into_inner()).remove(dest);
+    let mut flapping = FLAPPING.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(k) = flapping.iter().position(|entry| entry.dest == dest) {
+        flapping.remove(k);
+        printfc!(
+            LogLevel::Info,
+            "{} is in place again, no longer left alone",
+            dest.display()
+        );
+        save(&flapping);
+    }
+}
+
+// The destinations a sync is to leave alone for now: those in pending,
+// which drifted held back, and the flapping ones
+pub(crate) fn held(pending: &[PathBuf]) -> Vec<PathBuf> {
+    let mut held = pending.to_vec();
+    held.extend(
+        FLAPPING
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|entry| entry.dest.clone()),
+    );
+    held
+}
+
+// Takes out of pending, the destinations drifted held back, those whose
+// wait has run out
+pub(crate) fn expired(pending: &mut Vec<PathBuf>) -> Vec<PathBuf> {
+    let now = Instant::now();
+    let fixes = FIXES.lock().unwrap_or_else(|e| e.into_inner());
+    let (expired, waiting) = std::mem::take(pending)
+        .into_iter()
+        .partition(|dest| fixes.get(dest).is_none_or(|fixes| now >= fixes.until));
+    *pending = waiting;
+    expired
+}
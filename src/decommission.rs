@@ -0,0 +1,118 @@
+// `neostow decommission` hands a machine back: every link recorded as made
+// here is removed and the file it replaced put back, the directories neostow
+// made are removed once empty, and the records of the links are cleared.
+// Everything goes through the journal, so `neostow undo` reverses it.
+
+use std::fs;
+use std::io;
+
+use crate::{Config, LogLevel, backup, journal, state, status};
+
+/// Unstows everything recorded on this machine, whichever neostow file made
+/// it, and prints what was done.
+pub fn decommission(cfg: &Config, operations: &mut i32) -> io::Result<()> {
+    let mut links: Vec<_> = state::load_links()?.into_iter().collect();
+    // Deepest first, so links inside linked directories go before them
+    links.sort_by(|(a, _), (b, _)| b.cmp(a));
+
+    let (mut removed, mut reinstated, mut left) = (0, 0, Vec::new());
+    for (dest, (_, src)) in &links {
+        if dest.symlink_metadata().is_err() {
+            continue;
+        }
+        if !cfg.force && !status::owned(src, dest, cfg)? {
+            printfc!(
+                LogLevel::Warn,
+                "{} changed since neostow made it, leaving it alone (--force removes it)",
+                dest.display()
+            );
+            left.push(dest.clone());
+            continue;
+        }
+        let kept = backup::kept(dest, cfg)?;
+        if cfg.dry {
+            printfc!(LogLevel::Info, "Would remove {}", dest.display());
+            if let Some(kept) = &kept {
+                printfc!(LogLevel::Info, "Would put back {}", kept.display());
+            }
+            continue;
+        }
+        let result = journal::remove(&*cfg.fs, dest).and_then(|()| match &kept {
+            Some(kept) => backup::reinstate(dest, kept, cfg).map(|()| true),
+            None => Ok(false),
+        });
+        match result {
+            Ok(put_back) => {
+                removed += 1;
+                reinstated += usize::from(put_back);
+                *operations += 1;
+                if cfg.verbose {
+                    printfc!(LogLevel::Info, "Removed {}", dest.display());
+                }
+            }
+            Err(err) => {
+                printfc!(LogLevel::Error, "{}: {err}", dest.display());
+                left.push(dest.clone());
+            }
+        }
+    }
+
+    // Deepest first, and only those nothing else has moved into
+    let mut dirs = state::load_dirs()?;
+    dirs.reverse();
+    let mut emptied = 0;
+    let mut kept_dirs = Vec::new();
+    for dir in dirs {
+        let empty = fs::read_dir(&dir).is_ok_and(|mut entries| entries.next().is_none());
+        if !empty {
+            if dir.exists() {
+                kept_dirs.push(dir);
+            }
+            continue;
+        }
+        if cfg.dry {
+            printfc!(LogLevel::Info, "Would remove {}", dir.display());
+            continue;
+        }
+        match fs::remove_dir(&dir) {
+            Ok(()) => {
+                journal::record(journal::Change::RemovedDir(dir));
+                emptied += 1;
+            }
+            Err(err) => {
+                printfc!(LogLevel::Error, "{}: {err}", dir.display());
+                kept_dirs.push(dir);
+            }
+        }
+    }
+
+    if cfg.dry {
+        return Ok(());
+    }
+    // What is left stays on record for another try
+    let links = links
+        .into_iter()
+        .filter(|(dest, _)| left.contains(dest))
+        .collect();
+    state::save_links(&links)?;
+    state::save_applied(&state::Applied::new())?;
+    state::save_failed(&[])?;
+    kept_dirs.sort();
+    state::save_dirs(&kept_dirs)?;
+    journal::save(&journal::take())?;
+
+    println!(
+        "Removed {removed} links and {emptied} directories, and put back {reinstated} \
+         originals and backups."
+    );
+    if !left.is_empty() {
+        println!("{} destinations were left in place:", left.len());
+        for dest in &left {
+            println!("  {}", dest.display());
+        }
+    }
+    if removed + emptied > 0 {
+        println!("`neostow undo` reverses this.");
+    }
+    Ok(())
+}
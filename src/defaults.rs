@@ -0,0 +1,251 @@
+// !defaults lines set macOS preferences alongside the files, so the whole
+// setup of a machine lives in the neostow file:
+//   !defaults:com.apple.dock autohide -bool true
+//   !defaults:NSGlobalDomain AppleShowAllExtensions -bool true
+//   !defaults:com.apple.screencapture location -string "~/Pictures"
+// A line names a domain, a key and a typed value, -bool, -int, -float or
+// -string, as defaults(1) writes them. Like entries, they belong to the
+// package of their section and follow @host/@os/@env conditions. On macOS,
+// runs write the values that differ and status reports them; elsewhere
+// the lines are read but not applied.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::reload::field;
+use crate::{Config, LogLevel, Mode, bytes, cond, report, section_header, selects};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Bool,
+    Int,
+    Float,
+    Str,
+}
+
+#[derive(Clone)]
+pub struct Preference {
+    section: Option<Vec<u8>>,
+    domain: String,
+    key: String,
+    kind: Kind,
+    value: String,
+}
+
+impl Preference {
+    fn path(&self) -> String {
+        format!("{} {}", self.domain, self.key)
+    }
+
+    fn flag(&self) -> &'static str {
+        match self.kind {
+            Kind::Bool => "-bool",
+            Kind::Int => "-int",
+            Kind::Float => "-float",
+            Kind::Str => "-string",
+        }
+    }
+
+    // defaults read prints booleans as 1 or 0 and numbers in its own way
+    fn matches(&self, read: &str) -> bool {
+        match self.kind {
+            Kind::Bool => boolean(read) == boolean(&self.value),
+            Kind::Int => read.parse::<i64>().ok() == self.value.parse().ok(),
+            Kind::Float => read.parse::<f64>().ok() == self.value.parse().ok(),
+            Kind::Str => read == self.value,
+        }
+    }
+}
+
+fn boolean(s: &str) -> Option<bool> {
+    match s.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "1" => Some(true),
+        "false" | "no" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+// What follows !defaults: on a line
+pub fn parse_line(line: &[u8]) -> Option<&[u8]> {
+    line.trim_ascii().strip_prefix(b"!defaults:")
+}
+
+fn parse_pref(raw: &[u8], section: Option<&[u8]>) -> io::Result<Preference> {
+    let usage = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected !defaults:DOMAIN KEY -bool|-int|-float|-string VALUE",
+        )
+    };
+    let mut fields = Vec::new();
+    let mut rest = raw;
+    while !rest.trim_ascii().is_empty() && !rest.trim_ascii_start().starts_with(b"#") {
+        let (field, next) = field(rest, b' ').ok_or_else(usage)?;
+        fields.push(String::from_utf8_lossy(field).into_owned());
+        rest = next;
+    }
+    let [domain, key, flag, value] = <[String; 4]>::try_from(fields).map_err(|_| usage())?;
+    let kind = match flag.as_str() {
+        "-bool" | "-boolean" => Kind::Bool,
+        "-int" | "-integer" => Kind::Int,
+        "-float" => Kind::Float,
+        "-string" => Kind::Str,
+        _ => return Err(usage()),
+    };
+    let valid = match kind {
+        Kind::Bool => boolean(&value).is_some(),
+        Kind::Int => value.parse::<i64>().is_ok(),
+        Kind::Float => value.parse::<f64>().is_ok(),
+        Kind::Str => true,
+    };
+    if domain.is_empty() || key.is_empty() || !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{value} is not a value for {flag}"),
+        ));
+    }
+    Ok(Preference {
+        section: section.map(<[u8]>::to_vec),
+        domain,
+        key,
+        kind,
+        value,
+    })
+}
+
+// Whether a !defaults line reads, for check
+pub(crate) fn check_line(raw: &[u8]) -> io::Result<()> {
+    parse_pref(raw, None).map(|_| ())
+}
+
+pub fn parse(content: &[u8], path: &Path) -> io::Result<Vec<Preference>> {
+    let mut prefs = Vec::new();
+    let mut section: Option<&[u8]> = None;
+    let mut unmet = false;
+    for (i, line) in bytes::lines(content).enumerate() {
+        let result = if let Some(name) = section_header(line) {
+            section = Some(name.trim_ascii());
+            unmet = false;
+            Ok(())
+        } else if let Some(raw) = parse_line(line) {
+            if unmet {
+                Ok(())
+            } else {
+                parse_pref(raw, section).map(|pref| prefs.push(pref))
+            }
+        } else if cond::is_directive(line) {
+            cond::evaluate(line).map(|reason| unmet = reason.is_some())
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{}:{}: {err}", path.display(), i + 1),
+            ));
+        }
+    }
+    Ok(prefs)
+}
+
+// The packages given select the lines in their sections, as with entries
+fn selected(cfg: &Config) -> impl Iterator<Item = &Preference> {
+    cfg.defaults
+        .iter()
+        .filter(|_| cfg!(target_os = "macos"))
+        .filter(|pref| match &pref.section {
+            _ if cfg.packages.is_empty() => !pref
+                .section
+                .as_deref()
+                .is_some_and(|name| selects(&cfg.disabled, name)),
+            None => false,
+            Some(name) => selects(&cfg.packages, name),
+        })
+}
+
+fn read(pref: &Preference) -> io::Result<Option<String>> {
+    let output = Command::new("defaults")
+        .args(["read", &pref.domain, &pref.key])
+        .output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    Ok(Some(stdout.trim_end_matches('\n').to_string()))
+}
+
+fn write(pref: &Preference) -> io::Result<()> {
+    let output = Command::new("defaults")
+        .args(["write", &pref.domain, &pref.key, pref.flag(), &pref.value])
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(
+        String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    ))
+}
+
+/// Writes the !defaults values of the selected packages that differ,
+/// returning how many could not be.
+pub fn apply(cfg: &Config, operations: &mut i32) -> usize {
+    // Deleting leaves them, as there is no telling what they were before
+    if matches!(cfg.mode, Mode::Delete) {
+        return 0;
+    }
+    let mut failed = 0;
+    for pref in selected(cfg) {
+        let path = pref.path();
+        let result = read(pref).and_then(|read| match read {
+            Some(read) if pref.matches(&read) => {
+                if cfg.dry {
+                    report::pending(report::Pending::UpToDate);
+                }
+                Ok(())
+            }
+            read if cfg.dry => {
+                printfc!(LogLevel::Info, "Would set {path} to {}", pref.value);
+                report::pending(if read.is_some() {
+                    report::Pending::Overwrite
+                } else {
+                    report::Pending::Create
+                });
+                Ok(())
+            }
+            _ => write(pref).map(|()| {
+                *operations += 1;
+                if cfg.verbose {
+                    println!("Set {path} to {}", pref.value);
+                }
+            }),
+        });
+        if let Err(err) = result {
+            printfc!(LogLevel::Error, "{path}: {err}");
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// Prints whether each selected !defaults value is set, returning how many
+/// are not.
+pub fn status(cfg: &Config) -> usize {
+    let mut out_of_sync = 0;
+    for pref in selected(cfg) {
+        let path = pref.path();
+        match read(pref) {
+            Ok(Some(read)) if pref.matches(&read) => {
+                println!("set       {path}");
+                continue;
+            }
+            Ok(Some(read)) => println!("differs   {path} (is {read})"),
+            Ok(None) => println!("missing   {path}"),
+            Err(err) => {
+                printfc!(LogLevel::Error, "{path}: {err}");
+            }
+        }
+        out_of_sync += 1;
+    }
+    out_of_sync
+}
@@ -0,0 +1,269 @@
+// Unified diffs of files and directory trees, so conflicts can be shown
+// without GNU diffutils. Lines are compared with Myers' algorithm.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+const CONTEXT: usize = 3;
+// Like diff, a NUL byte near the start marks a file as binary
+const BINARY_PROBE: usize = 8000;
+// Bounds the frontiers kept for backtracking. Past it, the differing middle
+// is shown as one replaced block rather than searched further.
+const SEARCH_BUDGET: usize = 1 << 24;
+
+enum Edit {
+    Keep(usize, usize),
+    Remove(usize),
+    Insert(usize),
+}
+
+// Splits content into lines, each keeping its terminator
+fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|&b| b == b'\n').collect()
+}
+
+fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_PROBE)].contains(&0)
+}
+
+// The shortest edit script turning a into b
+fn edits(a: &[&[u8]], b: &[&[u8]]) -> Vec<Edit> {
+    // A common prefix and suffix never take part in the search
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (inner_a, inner_b) = (&a[prefix..a.len() - suffix], &b[prefix..b.len() - suffix]);
+
+    let mut out: Vec<Edit> = (0..prefix).map(|i| Edit::Keep(i, i)).collect();
+    let middle = myers(inner_a, inner_b).unwrap_or_else(|| {
+        let removed = (0..inner_a.len()).map(Edit::Remove);
+        removed
+            .chain((0..inner_b.len()).map(Edit::Insert))
+            .collect()
+    });
+    for edit in middle {
+        out.push(match edit {
+            Edit::Keep(i, j) => Edit::Keep(prefix + i, prefix + j),
+            Edit::Remove(i) => Edit::Remove(prefix + i),
+            Edit::Insert(j) => Edit::Insert(prefix + j),
+        });
+    }
+    let (tail_a, tail_b) = (a.len() - suffix, b.len() - suffix);
+    out.extend((0..suffix).map(|k| Edit::Keep(tail_a + k, tail_b + k)));
+    out
+}
+
+fn myers(a: &[&[u8]], b: &[&[u8]]) -> Option<Vec<Edit>> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = (n + m) as usize;
+    let offset = max as isize + 1;
+    let mut v = vec![0isize; 2 * max + 3];
+    let mut trace = Vec::new();
+
+    'search: for d in 0..=max as isize {
+        if trace.len() * v.len() > SEARCH_BUDGET {
+            return None;
+        }
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk the saved frontiers back from the end to recover the path
+    let mut out = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let idx = (k + offset) as usize;
+        let prev_k = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            x -= 1;
+            y -= 1;
+            out.push(Edit::Keep(x as usize, y as usize));
+        }
+        if d == 0 {
+            break;
+        }
+        if x == prev_x {
+            out.push(Edit::Insert(prev_y as usize));
+        } else {
+            out.push(Edit::Remove(prev_x as usize));
+        }
+        (x, y) = (prev_x, prev_y);
+    }
+    out.reverse();
+    Some(out)
+}
+
+// A hunk range as diff -u prints it: an empty range names the line before it
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => format!("{start},0"),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{len}", start + 1),
+    }
+}
+
+fn write_line(out: &mut dyn Write, sign: u8, line: &[u8]) -> io::Result<()> {
+    out.write_all(&[sign])?;
+    out.write_all(line)?;
+    if !line.ends_with(b"\n") {
+        out.write_all(b"\n\\ No newline at end of file\n")?;
+    }
+    Ok(())
+}
+
+fn write_hunks(out: &mut dyn Write, a: &[&[u8]], b: &[&[u8]], edits: &[Edit]) -> io::Result<()> {
+    let changed: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| !matches!(e, Edit::Keep(..)))
+        .map(|(i, _)| i)
+        .collect();
+
+    let mut i = 0;
+    while i < changed.len() {
+        // Changes closer than twice the context share one hunk
+        let mut j = i;
+        while j + 1 < changed.len() && changed[j + 1] - changed[j] <= 2 * CONTEXT + 1 {
+            j += 1;
+        }
+        let start = changed[i].saturating_sub(CONTEXT);
+        let end = (changed[j] + CONTEXT + 1).min(edits.len());
+        let hunk = &edits[start..end];
+
+        // Where the hunk starts in each file, even if it has no lines there
+        let (mut a_start, mut b_start) = (0, 0);
+        for edit in &edits[..start] {
+            match edit {
+                Edit::Keep(..) => {
+                    a_start += 1;
+                    b_start += 1;
+                }
+                Edit::Remove(_) => a_start += 1,
+                Edit::Insert(_) => b_start += 1,
+            }
+        }
+        let a_len = hunk
+            .iter()
+            .filter(|e| !matches!(e, Edit::Insert(_)))
+            .count();
+        let b_len = hunk
+            .iter()
+            .filter(|e| !matches!(e, Edit::Remove(_)))
+            .count();
+        writeln!(
+            out,
+            "@@ -{} +{} @@",
+            range(a_start, a_len),
+            range(b_start, b_len)
+        )?;
+        for edit in hunk {
+            match *edit {
+                Edit::Keep(x, _) => write_line(out, b' ', a[x])?,
+                Edit::Remove(x) => write_line(out, b'-', a[x])?,
+                Edit::Insert(y) => write_line(out, b'+', b[y])?,
+            }
+        }
+        i = j + 1;
+    }
+    Ok(())
+}
+
+fn files(a: &Path, b: &Path, out: &mut dyn Write) -> io::Result<bool> {
+    let (old, new) = (fs::read(a)?, fs::read(b)?);
+    if old == new {
+        return Ok(false);
+    }
+    if is_binary(&old) || is_binary(&new) {
+        writeln!(
+            out,
+            "Binary files {} and {} differ",
+            a.display(),
+            b.display()
+        )?;
+        return Ok(true);
+    }
+    let (old, new) = (split_lines(&old), split_lines(&new));
+    writeln!(out, "--- {}", a.display())?;
+    writeln!(out, "+++ {}", b.display())?;
+    write_hunks(out, &old, &new, &edits(&old, &new))?;
+    Ok(true)
+}
+
+fn sorted_names(dir: &Path) -> io::Result<Vec<std::ffi::OsString>> {
+    let mut names = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+fn trees(a: &Path, b: &Path, out: &mut dyn Write) -> io::Result<bool> {
+    let (names_a, names_b) = (sorted_names(a)?, sorted_names(b)?);
+    let mut differ = false;
+    for name in &names_a {
+        if !names_b.contains(name) {
+            writeln!(out, "Only in {}: {}", a.display(), name.display())?;
+            differ = true;
+        }
+    }
+    for name in &names_b {
+        if !names_a.contains(name) {
+            writeln!(out, "Only in {}: {}", b.display(), name.display())?;
+            differ = true;
+        }
+    }
+    for name in names_a.iter().filter(|name| names_b.contains(name)) {
+        differ |= paths(&a.join(name), &b.join(name), out)?;
+    }
+    Ok(differ)
+}
+
+// Writes the differences between a and b, following links like diff -r
+// does, and returns whether there were any
+pub fn paths(a: &Path, b: &Path, out: &mut dyn Write) -> io::Result<bool> {
+    match (a.is_dir(), b.is_dir()) {
+        (true, true) => trees(a, b, out),
+        (false, false) => files(a, b, out),
+        (a_dir, _) => {
+            let kind = |dir| if dir { "a directory" } else { "a regular file" };
+            writeln!(
+                out,
+                "File {} is {} while file {} is {}",
+                a.display(),
+                kind(a_dir),
+                b.display(),
+                kind(!a_dir)
+            )?;
+            Ok(true)
+        }
+    }
+}
@@ -0,0 +1,180 @@
+// `neostow doctor` looks at the destination side, where status looks at the
+// entries: under a directory, it finds links whose target is gone, links
+// into the dotfiles directory that no entry plans, and places neostow can't
+// read. With fix, broken links neostow made are removed.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{self, Path, PathBuf};
+
+use crate::expand::normalize;
+use crate::{Config, LogLevel, Operation, journal, platform, state};
+
+// How deep to look under the directory
+const MAX_DEPTH: usize = 6;
+
+#[derive(Default)]
+struct Findings {
+    // Broken links, with whether neostow made them
+    broken: Vec<(PathBuf, bool)>,
+    foreign: Vec<(PathBuf, PathBuf)>,
+    denied: Vec<PathBuf>,
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    normalize(&path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+// Whether the file or directory at path can be read
+fn readable(path: &Path) -> bool {
+    let denied = |result: io::Result<()>| {
+        result.is_err_and(|err| err.kind() == io::ErrorKind::PermissionDenied)
+    };
+    if path.is_dir() {
+        !denied(fs::read_dir(path).map(drop))
+    } else {
+        !denied(fs::File::open(path).map(drop))
+    }
+}
+
+struct Scan<'a> {
+    basedir: PathBuf,
+    planned: HashSet<PathBuf>,
+    recorded: HashSet<PathBuf>,
+    findings: &'a mut Findings,
+}
+
+impl Scan<'_> {
+    fn dir(&mut self, dir: &Path, depth: usize) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                if err.kind() == io::ErrorKind::PermissionDenied {
+                    self.findings.denied.push(dir.to_path_buf());
+                }
+                return;
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(kind) = entry.file_type() else {
+                continue;
+            };
+            if kind.is_symlink() {
+                self.link(&path);
+            } else if kind.is_dir() && depth < MAX_DEPTH && path != self.basedir {
+                self.dir(&path, depth + 1);
+            }
+        }
+    }
+
+    fn link(&mut self, link: &Path) {
+        let Ok(target) = fs::read_link(link) else {
+            return;
+        };
+        let target = normalize(&link.parent().unwrap_or(Path::new("")).join(target));
+        let into_basedir = target.starts_with(&self.basedir);
+        let ours = into_basedir || self.recorded.contains(link);
+        if target.symlink_metadata().is_err() {
+            self.findings.broken.push((link.to_path_buf(), ours));
+        } else if into_basedir && !self.planned.contains(link) {
+            self.findings.foreign.push((link.to_path_buf(), target));
+        } else if into_basedir && !readable(&target) {
+            self.findings.denied.push(target);
+        }
+    }
+}
+
+/// Checks the links under dir, by default the target or home directory,
+/// against the plan of the whole file, printing each problem, and returns
+/// how many are left. With fix, broken links into
+/// the dotfiles directory, or recorded as made by neostow, are removed, and
+/// with force any broken link.
+pub fn doctor(
+    ops: &[(usize, Operation)],
+    cfg: &Config,
+    dir: Option<&Path>,
+    fix: bool,
+    operations: &mut i32,
+) -> io::Result<usize> {
+    let dir = match dir {
+        Some(dir) => absolute(dir),
+        None => absolute(cfg.target.as_deref().unwrap_or(&platform::home())),
+    };
+    if !dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("{} is not a directory", dir.display()),
+        ));
+    }
+    let mut findings = Findings::default();
+    Scan {
+        basedir: absolute(&fs::canonicalize(&cfg.basedir)?),
+        planned: ops.iter().map(|(_, op)| absolute(&op.dest)).collect(),
+        recorded: state::load_links()?.into_keys().collect(),
+        findings: &mut findings,
+    }
+    .dir(&dir, 0);
+    // Sources of entries are read wherever they are linked
+    for (_, op) in ops {
+        if op.src.exists() && !readable(&op.src) && !findings.denied.contains(&op.src) {
+            findings.denied.push(op.src.clone());
+        }
+    }
+
+    let mut left = 0;
+    for (link, ours) in &findings.broken {
+        if !fix || !(*ours || cfg.force) {
+            printfc!(LogLevel::Warn, "Broken link {}", link.display());
+            left += 1;
+        } else if cfg.dry {
+            printfc!(
+                LogLevel::Info,
+                "Would remove broken link {}",
+                link.display()
+            );
+        } else {
+            match journal::remove(&*cfg.fs, link) {
+                Ok(()) => {
+                    *operations += 1;
+                    printfc!(LogLevel::Info, "Removed broken link {}", link.display());
+                }
+                Err(err) => {
+                    printfc!(LogLevel::Error, "{}: {err}", link.display());
+                    left += 1;
+                }
+            }
+        }
+    }
+    for (link, target) in &findings.foreign {
+        printfc!(
+            LogLevel::Warn,
+            "{} links to {}, but no entry of {} makes it",
+            link.display(),
+            target.display(),
+            cfg.file.display()
+        );
+    }
+    for path in &findings.denied {
+        printfc!(
+            LogLevel::Warn,
+            "Permission denied reading {}",
+            path.display()
+        );
+    }
+    left += findings.foreign.len() + findings.denied.len();
+
+    if fix && !cfg.dry {
+        journal::save(&journal::take())?;
+    }
+    if left == 0 {
+        printfc!(LogLevel::Info, "No problems under {}", dir.display());
+    } else if !fix && findings.broken.iter().any(|(_, ours)| *ours) {
+        printfc!(
+            LogLevel::Info,
+            "Run with --fix to remove the broken links neostow made"
+        );
+    }
+    Ok(left)
+}
@@ -0,0 +1,163 @@
+// Files are edited with the editor setting of config.toml, or else $VISUAL,
+// then $EDITOR. Each may carry arguments of its own, quoted like a shell
+// would, and a {file} argument places the file among them, as in
+// "code --goto {file}:1"; without one the file goes last.
+//
+// GUI editors like code and subl hand the file to a window and exit at once,
+// so the checks after an edit would run before it is made. With --wait, the
+// flag that makes a known one wait for its window to close is added. An
+// editor that still returns within DETACHED, leaving the file as it was, is
+// taken to have detached: edit warns and waits for Enter before going on.
+// --no-wait opens the file and returns, leaving the checks out.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::{LogLevel, bytes, include, report};
+
+const DETACHED: Duration = Duration::from_secs(1);
+
+// Editors that return before their window closes, and what makes them wait
+const WAIT_FLAGS: [(&str, &str); 14] = [
+    ("code", "--wait"),
+    ("code-insiders", "--wait"),
+    ("codium", "--wait"),
+    ("cursor", "--wait"),
+    ("subl", "--wait"),
+    ("zed", "--wait"),
+    ("atom", "--wait"),
+    ("bbedit", "--wait"),
+    ("gedit", "--wait"),
+    ("mate", "-w"),
+    ("kate", "--block"),
+    ("gvim", "-f"),
+    ("mvim", "-f"),
+    ("open", "-W"),
+];
+
+static TEMPLATE: Mutex<Option<String>> = Mutex::new(None);
+// 0 to wait as the editor does, 1 for --wait, 2 for --no-wait
+static WAIT: AtomicU8 = AtomicU8::new(0);
+
+/// Uses template, from the editor setting, instead of $VISUAL and $EDITOR.
+pub fn set_template(template: String) {
+    *TEMPLATE.lock().unwrap_or_else(|e| e.into_inner()) = Some(template);
+}
+
+/// Makes edits wait for the editor's window to close, or with false return
+/// as soon as the editor does, without the checks after an edit.
+pub fn set_wait(wait: bool) {
+    WAIT.store(if wait { 1 } else { 2 }, Ordering::Relaxed);
+}
+
+/// Whether an edit returns only once it is made, so it can be checked.
+pub fn waits() -> bool {
+    WAIT.load(Ordering::Relaxed) != 2
+}
+
+// arg with each {file} in it replaced by file, if it has one
+fn fill(arg: &[u8], file: &[u8]) -> Option<Vec<u8>> {
+    const FILE: &[u8] = b"{file}";
+    let mut out = Vec::new();
+    let mut rest = arg;
+    while let Some(at) = rest.windows(FILE.len()).position(|w| w == FILE) {
+        out.extend_from_slice(&rest[..at]);
+        out.extend_from_slice(file);
+        rest = &rest[at + FILE.len()..];
+    }
+    if rest.len() == arg.len() {
+        return None;
+    }
+    out.extend_from_slice(rest);
+    Some(out)
+}
+
+fn command(path: &Path) -> io::Result<Command> {
+    let default = if cfg!(windows) { "notepad" } else { "vim" };
+    let template = TEMPLATE.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let editor = template
+        .map(Into::into)
+        .or_else(|| {
+            ["VISUAL", "EDITOR"]
+                .iter()
+                .filter_map(env::var_os)
+                .find(|editor| !editor.is_empty())
+        })
+        .unwrap_or_else(|| default.into());
+    let words = include::words(&bytes::from_os(&editor))?;
+    let Some((program, args)) = words.split_first() else {
+        return Err(io::Error::other("The editor command is empty"));
+    };
+    let program = bytes::to_os(program);
+    let mut command = Command::new(&program);
+
+    // code.cmd on Windows is code too
+    let name = Path::new(&program).file_stem().unwrap_or_default();
+    let flag = WAIT_FLAGS
+        .iter()
+        .find(|(editor, _)| name == *editor)
+        .map(|(_, flag)| flag.as_bytes());
+    if WAIT.load(Ordering::Relaxed) == 1
+        && let Some(flag) = flag
+        && !args.iter().any(|arg| arg == flag)
+    {
+        command.arg(bytes::to_os(flag));
+    }
+
+    let file = bytes::from_os(path.as_os_str());
+    let mut placed = false;
+    for arg in args {
+        match fill(arg, &file) {
+            Some(filled) => {
+                placed = true;
+                command.arg(bytes::to_os(&filled));
+            }
+            None => {
+                command.arg(bytes::to_os(arg));
+            }
+        }
+    }
+    if !placed {
+        command.arg(path);
+    }
+    Ok(command)
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+/// Opens path in the editor and returns once the edit is done, as far as
+/// can be told.
+pub fn open(path: &Path) -> io::Result<()> {
+    let before = modified(path);
+    let started = Instant::now();
+    let status = command(path)?.status()?;
+    if !status.success() {
+        return Err(io::Error::other("Editor failed"));
+    }
+    if !waits() || started.elapsed() >= DETACHED || modified(path) != before {
+        return Ok(());
+    }
+    let hint = if WAIT.load(Ordering::Relaxed) == 1 {
+        "set editor in config.toml to a command that waits"
+    } else {
+        "pass --wait, or set editor in config.toml to a command that waits"
+    };
+    printfc!(
+        LogLevel::Warn,
+        "The editor returned at once, leaving {} as it was. It may have opened it in a window of its own; {hint}",
+        path.display()
+    );
+    // Unless nobody is there to press it
+    if report::assumed().is_none() {
+        report::ask("Press Enter once done editing", "\n", &[])?;
+    }
+    Ok(())
+}
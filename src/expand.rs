@@ -0,0 +1,200 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::bytes;
+use crate::platform;
+use crate::vars::Vars;
+
+fn lookup(name: &str, vars: &Vars) -> Option<OsString> {
+    vars.get(name)
+        .cloned()
+        .filter(|val| !val.is_empty())
+        .or_else(|| platform::var(name))
+}
+
+// An empty expansion silently collapses the path (`/$EMPTY/bin` → `//bin`),
+// so it is an error unless the `${VAR:-default}` form opts out
+fn require(name: &str, vars: &Vars) -> io::Result<OsString> {
+    lookup(name, vars).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("${name} is unset or empty (use ${{{name}:-}} to allow it)"),
+        )
+    })
+}
+
+// $HOME stands for %USERPROFILE% too, where Windows leaves HOME unset
+pub(crate) fn home(vars: &Vars) -> io::Result<OsString> {
+    require("HOME", vars)
+}
+
+// Windows paths use \ as well as /
+fn is_separator(c: u8) -> bool {
+    c == b'/' || (cfg!(windows) && c == b'\\')
+}
+
+// \$ is a literal $, except on Windows where \ separates path components
+const ESCAPES: bool = !cfg!(windows);
+
+// Windows-style %NAME% expands like $NAME there, and %% is a literal %
+const PERCENT_VARS: bool = cfg!(windows);
+
+// Expands the %NAME% that rest, past its opening %, starts with. A % that
+// doesn't open a name is kept as is.
+fn percent<'a>(rest: &'a [u8], vars: &Vars, out: &mut Vec<u8>) -> io::Result<&'a [u8]> {
+    // Names like ProgramFiles(x86) have parentheses
+    let Some(end) = bytes::find(rest, b'%').filter(|&end| {
+        rest[..end]
+            .iter()
+            .all(|&c| c == b'_' || c == b'(' || c == b')' || c.is_ascii_alphanumeric())
+    }) else {
+        out.push(b'%');
+        return Ok(rest);
+    };
+    if end == 0 {
+        out.push(b'%');
+        return Ok(&rest[1..]);
+    }
+    let name = ident(&rest[..end]);
+    let val = lookup(name, vars).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("%{name}% is unset or empty"),
+        )
+    })?;
+    out.extend_from_slice(&bytes::from_os(&val));
+    Ok(&rest[end + 1..])
+}
+
+fn ident_len(s: &[u8]) -> usize {
+    s.iter()
+        .enumerate()
+        .find(|&(i, &c)| !(c == b'_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit())))
+        .map_or(s.len(), |(i, _)| i)
+}
+
+// Identifiers are ASCII, so slicing them out of the line is always valid UTF-8
+fn ident(s: &[u8]) -> &str {
+    std::str::from_utf8(s).unwrap_or_default()
+}
+
+// Sandboxed apps don't read ~/.config, so `{flatpak:ID}` and `{snap:NAME}`
+// stand for the per-app home they are given instead
+fn sandbox_home<'a>(raw: &'a [u8], vars: &Vars) -> io::Result<Option<(Vec<u8>, &'a [u8])>> {
+    let Some(inner) = raw.strip_prefix(b"{") else {
+        return Ok(None);
+    };
+    let (subdir, app, suffix): (&[u8], _, &[u8]) =
+        if let Some(app) = inner.strip_prefix(b"flatpak:") {
+            (b"/.var/app/", app, b"")
+        } else if let Some(app) = inner.strip_prefix(b"snap:") {
+            (b"/snap/", app, b"/current")
+        } else {
+            return Ok(None);
+        };
+    let end = bytes::find(app, b'}')
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "unterminated { in path"))?;
+    let (app, rest) = (&app[..end], &app[end + 1..]);
+    if app.is_empty() || app.iter().any(|&c| is_separator(c)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid app id {:?}", bytes::to_os(app)),
+        ));
+    }
+
+    let mut out = bytes::from_os(&home(vars)?).into_owned();
+    out.extend_from_slice(subdir);
+    out.extend_from_slice(app);
+    out.extend_from_slice(suffix);
+    Ok(Some((out, rest)))
+}
+
+fn expand(raw: &[u8], vars: &Vars) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(raw.len());
+    let mut rest = raw;
+
+    if let Some(after) = raw.strip_prefix(b"~")
+        && after.first().is_none_or(|&c| is_separator(c))
+    {
+        out.extend_from_slice(&bytes::from_os(&home(vars)?));
+        rest = after;
+    } else if let Some((home, after)) = sandbox_home(raw, vars)? {
+        out = home;
+        rest = after;
+    }
+
+    while let Some(pos) = rest
+        .iter()
+        .position(|&c| c == b'$' || (PERCENT_VARS && c == b'%'))
+    {
+        let sigil = rest[pos];
+        if ESCAPES && sigil == b'$' && pos > 0 && rest[pos - 1] == b'\\' {
+            out.extend_from_slice(&rest[..pos - 1]);
+            out.push(b'$');
+            rest = &rest[pos + 1..];
+            continue;
+        }
+        out.extend_from_slice(&rest[..pos]);
+        rest = &rest[pos + 1..];
+        if sigil == b'%' {
+            rest = percent(rest, vars, &mut out)?;
+            continue;
+        }
+
+        if let Some(inner) = rest.strip_prefix(b"{") {
+            let end = bytes::find(inner, b'}').ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "unterminated ${ in path")
+            })?;
+            let body = &inner[..end];
+            rest = &inner[end + 1..];
+            let (name, default) = match body.windows(2).position(|w| w == b":-") {
+                Some(sep) => (&body[..sep], Some(&body[sep + 2..])),
+                None => (body, None),
+            };
+            match (lookup(ident(name), vars), default) {
+                (Some(val), _) => out.extend_from_slice(&bytes::from_os(&val)),
+                (None, Some(default)) => out.extend(expand(default, vars)?),
+                (None, None) => {
+                    out.extend_from_slice(&bytes::from_os(&require(ident(name), vars)?))
+                }
+            }
+            continue;
+        }
+
+        let len = ident_len(rest);
+        if len == 0 {
+            out.push(b'$');
+            continue;
+        }
+        out.extend_from_slice(&bytes::from_os(&require(ident(&rest[..len]), vars)?));
+        rest = &rest[len..];
+    }
+    out.extend_from_slice(rest);
+
+    Ok(out)
+}
+
+pub fn expand_path(raw: &[u8], vars: &Vars) -> io::Result<PathBuf> {
+    expand(raw, vars).map(|out| PathBuf::from(bytes::to_os_string(out)))
+}
+
+// Resolves `.` and `..` lexically, without touching the filesystem, so the
+// result describes where the link would be placed even if it doesn't exist
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match out.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    out.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => out.push(".."),
+            },
+            other => out.push(other),
+        }
+    }
+    out
+}
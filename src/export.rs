@@ -0,0 +1,54 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::{Config, Operation};
+
+fn quote(path: &Path) -> String {
+    let raw = path.display().to_string();
+    format!("\"{}\"", raw.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+// Destinations claimed by more than one source are highlighted, since only
+// one of them can win. !assert entries link nothing, and are left out.
+pub fn dot(ops: &[(usize, Operation)], cfg: &Config, out: &mut impl Write) -> io::Result<()> {
+    let ops: Vec<&Operation> = ops
+        .iter()
+        .map(|(_, op)| op)
+        .filter(|op| !op.assert)
+        .collect();
+    let mut claims: BTreeMap<&Path, usize> = BTreeMap::new();
+    for op in &ops {
+        *claims.entry(op.dest.as_path()).or_default() += 1;
+    }
+
+    writeln!(out, "digraph neostow {{")?;
+    writeln!(out, "  rankdir=LR;")?;
+    writeln!(out, "  node [shape=box];")?;
+
+    writeln!(out, "  subgraph cluster_sources {{")?;
+    writeln!(out, "    label={};", quote(&cfg.basedir))?;
+    for op in &ops {
+        let label = op.src.strip_prefix(&cfg.basedir).unwrap_or(&op.src);
+        let shape = if op.is_dir { "folder" } else { "note" };
+        writeln!(
+            out,
+            "    {} [label={}, shape={shape}];",
+            quote(&op.src),
+            quote(label)
+        )?;
+    }
+    writeln!(out, "  }}")?;
+
+    for (dest, count) in &claims {
+        let color = if *count > 1 { "red" } else { "black" };
+        writeln!(out, "  {} [color={color}];", quote(dest))?;
+    }
+
+    for op in &ops {
+        writeln!(out, "  {} -> {};", quote(&op.src), quote(&op.dest))?;
+    }
+    writeln!(out, "}}")?;
+
+    Ok(())
+}
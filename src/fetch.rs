@@ -0,0 +1,212 @@
+// An entry marked !fetch has a git URL or an archive for its source:
+//   https://github.com/user/nvim-config.git -> ~/.config/nvim !fetch
+// It is cloned into .neostow-cache under the dotfiles directory the first
+// time it is linked and pulled on each later run, or for an archive,
+// downloaded and unpacked once, so the link points into a copy neostow
+// keeps. !fetch:REF clones that branch or tag.
+
+use std::collections::HashSet;
+use std::fs;
+use std::hash::{DefaultHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use crate::{Config, LogLevel, Mode, Operation};
+
+const CACHE_DIR: &str = ".neostow-cache";
+
+const ARCHIVES: [&str; 6] = [".tar.gz", ".tgz", ".tar.xz", ".tar.bz2", ".tar.zst", ".tar"];
+
+// Where a source comes from, and the branch or tag to clone
+pub(crate) struct Remote {
+    url: String,
+    rev: Option<String>,
+}
+
+// The caches fetched so far in this run
+static FETCHED: Mutex<Option<HashSet<PathBuf>>> = Mutex::new(None);
+
+fn is_archive(url: &str) -> bool {
+    ARCHIVES.iter().any(|ext| url.ends_with(ext))
+}
+
+impl Remote {
+    pub(crate) fn parse(src: &[u8], rev: Option<&[u8]>) -> io::Result<Self> {
+        let url = String::from_utf8_lossy(src).into_owned();
+        if !(url.contains("://") || url.starts_with("git@")) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("!fetch needs a URL for its source, found {url}"),
+            ));
+        }
+        let rev = rev.map(|rev| String::from_utf8_lossy(rev).into_owned());
+        if rev.is_some() && is_archive(&url) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "an archive has no branch or tag to fetch",
+            ));
+        }
+        Ok(Remote { url, rev })
+    }
+
+    // The cache, relative to the dotfiles directory. It is named after the
+    // repository or archive, so links made into its parent directory are
+    // too, under a directory telling apart URLs with the same name.
+    pub(crate) fn cache(&self) -> PathBuf {
+        let last = self
+            .url
+            .trim_end_matches('/')
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or_default();
+        let name = ARCHIVES
+            .iter()
+            .chain(&[".git"])
+            .find_map(|ext| last.strip_suffix(ext))
+            .unwrap_or(last);
+        let mut hasher = DefaultHasher::new();
+        hasher.write(self.url.as_bytes());
+        hasher.write(self.rev.as_deref().unwrap_or_default().as_bytes());
+        Path::new(CACHE_DIR)
+            .join(format!("{:08x}", hasher.finish() as u32))
+            .join(name)
+    }
+}
+
+pub(crate) fn run(cmd: &mut Command, what: &str) -> io::Result<()> {
+    let output = cmd.stdin(Stdio::null()).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "{what}: {}",
+        String::from_utf8_lossy(&output.stderr).trim_end()
+    )))
+}
+
+fn clone(remote: &Remote, dir: &Path) -> io::Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.args(["clone", "--quiet", "--filter=blob:none"]);
+    if let Some(rev) = &remote.rev {
+        cmd.args(["--branch", rev]);
+    }
+    run(
+        cmd.arg(&remote.url).arg(dir),
+        &format!("git clone {}", remote.url),
+    )
+}
+
+// Fast-forwards only, so what was edited through the links is never lost
+fn pull(remote: &Remote, dir: &Path) -> io::Result<()> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C")
+        .arg(dir)
+        .args(["pull", "--quiet", "--ff-only", "origin"]);
+    cmd.args(&remote.rev);
+    run(&mut cmd, &format!("git pull in {}", dir.display()))
+}
+
+// Unpacked beside dir first, so a failed download leaves nothing behind. An
+// archive holding one directory, as those of releases do, is that directory.
+fn unpack(remote: &Remote, dir: &Path) -> io::Result<()> {
+    let parent = dir.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(parent)?;
+    let tmp = parent.join(".unpacking");
+    let _ = fs::remove_dir_all(&tmp);
+    fs::create_dir(&tmp)?;
+    let archive = tmp.join("archive");
+    let result = run(
+        Command::new("curl")
+            .args(["-fsSL", "-o"])
+            .arg(&archive)
+            .arg(&remote.url),
+        &format!("curl {}", remote.url),
+    )
+    .and_then(|()| {
+        let out = tmp.join("out");
+        fs::create_dir(&out)?;
+        run(
+            Command::new("tar")
+                .arg("-xf")
+                .arg(&archive)
+                .arg("-C")
+                .arg(&out),
+            &format!("tar -xf {}", remote.url),
+        )?;
+        let items: Vec<_> = fs::read_dir(&out)?.collect::<io::Result<_>>()?;
+        let root = match items.as_slice() {
+            [only] if only.file_type()?.is_dir() => only.path(),
+            _ => out,
+        };
+        fs::rename(root, dir)
+    });
+    let _ = fs::remove_dir_all(&tmp);
+    result
+}
+
+// Updates the cache op links into from its remote, once per run. Dry runs
+// only say what would be fetched, and deleting fetches nothing.
+pub(crate) fn update(op: &Operation, cfg: &Config) -> io::Result<()> {
+    let Some(remote) = &op.fetch else {
+        return Ok(());
+    };
+    if matches!(cfg.mode, Mode::Delete) {
+        return Ok(());
+    }
+    let dir = cfg.basedir.join(remote.cache());
+    let mut fetched = FETCHED.lock().unwrap_or_else(|e| e.into_inner());
+    if !fetched.get_or_insert_default().insert(dir.clone()) {
+        return Ok(());
+    }
+    let exists = dir.symlink_metadata().is_ok();
+    // A sandbox leaves the repository as it is, and links to what was
+    // fetched before
+    if cfg.sandboxed {
+        if !exists {
+            printfc!(
+                LogLevel::Warn,
+                "Not fetching {} in a sandbox, {} stays missing",
+                remote.url,
+                dir.display()
+            );
+        }
+        return Ok(());
+    }
+    if cfg.dry {
+        let verb = if !exists {
+            "fetch"
+        } else if is_archive(&remote.url) {
+            return Ok(());
+        } else {
+            "update"
+        };
+        printfc!(
+            LogLevel::Info,
+            "Would {verb} {} in {}",
+            remote.url,
+            dir.display()
+        );
+        return Ok(());
+    }
+    if !exists {
+        if cfg.verbose {
+            printfc!(LogLevel::Info, "Fetching {}", remote.url);
+        }
+        fs::create_dir_all(dir.parent().unwrap_or(&cfg.basedir))?;
+        return if is_archive(&remote.url) {
+            unpack(remote, &dir)
+        } else {
+            clone(remote, &dir)
+        };
+    }
+    if is_archive(&remote.url) {
+        return Ok(());
+    }
+    // Offline, what was fetched before is still good to link
+    if let Err(err) = pull(remote, &dir) {
+        printfc!(LogLevel::Warn, "{err}, linking what was fetched before");
+    }
+    Ok(())
+}
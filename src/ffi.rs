@@ -0,0 +1,169 @@
+// A small C ABI over the library API, built with the `ffi` feature into
+// target/release/libneostow.so (neostow.dll, libneostow.dylib):
+//
+//     cargo build --release --features ffi
+//
+// Plans are opaque handles. Results cross the boundary as JSON strings that
+// the caller releases with neostow_string_free, and a null return means the
+// call failed, with the reason in neostow_last_error. include/neostow.h
+// declares everything here.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::io;
+use std::path::Path;
+use std::ptr;
+
+use crate::{ExecuteOptions, Mode, Plan, PlanOptions, execute, plan_from_config, report, status};
+
+pub const NEOSTOW_DRY: c_int = 1;
+pub const NEOSTOW_FORCE: c_int = 2;
+pub const NEOSTOW_ATOMIC: c_int = 4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn fail<T>(err: io::Error) -> *mut T {
+    let message = CString::new(err.to_string().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    ptr::null_mut()
+}
+
+fn to_c(s: String) -> *mut c_char {
+    match CString::new(s) {
+        Ok(s) => s.into_raw(),
+        Err(err) => fail(io::Error::new(io::ErrorKind::InvalidData, err)),
+    }
+}
+
+/// The reason the last call on this thread returned null, or null if none
+/// failed. Valid until the next failing call.
+#[unsafe(no_mangle)]
+pub extern "C" fn neostow_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Loads and plans the config file at path. mode is 0 to create links,
+/// 1 to overwrite destinations and 2 to delete links.
+///
+/// # Safety
+///
+/// path must be a valid NUL-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn neostow_plan(path: *const c_char, mode: c_int) -> *mut Plan {
+    if path.is_null() {
+        return fail(io::Error::new(io::ErrorKind::InvalidInput, "path is null"));
+    }
+    // SAFETY: the caller passes a valid C string
+    let path = unsafe { CStr::from_ptr(path) };
+    let Ok(path) = path.to_str() else {
+        return fail(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path is not UTF-8",
+        ));
+    };
+    let mode = match mode {
+        0 => Mode::Create,
+        1 => Mode::Overwrite,
+        2 => Mode::Delete,
+        _ => return fail(io::Error::new(io::ErrorKind::InvalidInput, "unknown mode")),
+    };
+    let options = PlanOptions {
+        mode,
+        ..Default::default()
+    };
+    match plan_from_config(Path::new(path), &options) {
+        Ok(plan) => Box::into_raw(Box::new(plan)),
+        Err(err) => fail(err),
+    }
+}
+
+/// The plan as JSON: its operations and the entries that couldn't be planned.
+///
+/// # Safety
+///
+/// plan must come from neostow_plan and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn neostow_plan_json(plan: *const Plan) -> *mut c_char {
+    // SAFETY: the caller passes a live plan
+    match unsafe { plan.as_ref() } {
+        Some(plan) => to_c(plan.to_json()),
+        None => fail(io::Error::new(io::ErrorKind::InvalidInput, "plan is null")),
+    }
+}
+
+/// Executes the plan with NEOSTOW_* flags and returns the run report as JSON.
+///
+/// # Safety
+///
+/// plan must come from neostow_plan and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn neostow_execute(plan: *const Plan, flags: c_int) -> *mut c_char {
+    // SAFETY: the caller passes a live plan
+    let Some(plan) = (unsafe { plan.as_ref() }) else {
+        return fail(io::Error::new(io::ErrorKind::InvalidInput, "plan is null"));
+    };
+    let options = ExecuteOptions {
+        dry: flags & NEOSTOW_DRY != 0,
+        force: flags & NEOSTOW_FORCE != 0,
+        atomic: flags & NEOSTOW_ATOMIC != 0,
+        ..Default::default()
+    };
+    match execute(plan, &options) {
+        Ok(run) => to_c(run.to_json()),
+        Err(err) => fail(err),
+    }
+}
+
+/// The state of every planned destination as JSON, as `neostow status`
+/// reports it: linked, elsewhere, shadow, conflict or missing.
+///
+/// # Safety
+///
+/// plan must come from neostow_plan and not have been freed.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn neostow_status(plan: *const Plan) -> *mut c_char {
+    // SAFETY: the caller passes a live plan
+    let Some(plan) = (unsafe { plan.as_ref() }) else {
+        return fail(io::Error::new(io::ErrorKind::InvalidInput, "plan is null"));
+    };
+    let states: Vec<String> = plan
+        .operations
+        .iter()
+        .map(|(line, op)| {
+            let (state, error) = match status::classify(op, plan.config()) {
+                Ok(state) => (report::string(state.name()), "null".to_string()),
+                Err(err) => ("null".to_string(), report::string(&err.to_string())),
+            };
+            format!(
+                "{{\"line\":{line},\"source\":{},\"dest\":{},\"state\":{state},\"error\":{error}}}",
+                report::path(Some(&op.src)),
+                report::path(Some(&op.dest)),
+            )
+        })
+        .collect();
+    to_c(format!("[{}]", states.join(",")))
+}
+
+/// # Safety
+///
+/// plan must come from neostow_plan, or be null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn neostow_plan_free(plan: *mut Plan) {
+    if !plan.is_null() {
+        // SAFETY: the plan was boxed by neostow_plan and is freed once
+        drop(unsafe { Box::from_raw(plan) });
+    }
+}
+
+/// # Safety
+///
+/// s must be a string returned by this library, or null.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn neostow_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: the string was created by CString::into_raw
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
@@ -0,0 +1,894 @@
+// A neostow file is written in the line format, or in TOML when it is named
+// *.toml (as .neostow.toml) or --format toml is given:
+//   [[entry]]
+//   src = "nvim/"
+//   dest = ["~/.config", "~/.var/app/io.neovim.nvim/config"]
+//   package = "nvim"
+//   when = "@os(linux)"
+//   desc = "Neovim configuration"
+// or in YAML when it is named *.yaml or *.yml, or --format yaml is given:
+//   entries:
+//     - src: nvim/
+//       dest: [~/.config, ~/.var/app/io.neovim.nvim/config]
+//       package: nvim
+//       when: "@os(linux)"
+// Both are turned into the line format, each line kept where the TOML or
+// YAML it comes from is, so loading, planning, checking and errors naming a
+// line are the same for all three. [vars], [transform], [reload], [aliases]
+// and [registry] tables, or vars: | and the like in YAML, hold the lines of
+// the sections they are named after. Only the YAML these take is read: block
+// mappings and sequences, flow sequences on one line, and quoted or plain
+// scalars.
+
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::{cond, transform};
+
+/// A syntax neostow files can be written in.
+pub trait Format {
+    /// The content of the file at path, in the line format.
+    fn lines(&self, content: Vec<u8>, path: &Path) -> io::Result<Vec<u8>>;
+}
+
+/// The line format, read as it is.
+pub struct Plain;
+
+/// Entries as [[entry]] tables of TOML.
+pub struct Toml;
+
+/// Entries as a sequence of YAML mappings.
+pub struct Yaml;
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Kind {
+    Plain = 1,
+    Toml,
+    Yaml,
+}
+
+impl Kind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "plain" => Some(Kind::Plain),
+            "toml" => Some(Kind::Toml),
+            "yaml" | "yml" => Some(Kind::Yaml),
+            _ => None,
+        }
+    }
+}
+
+// What --format gave, 0 when the name of each file decides
+static FORCED: AtomicU8 = AtomicU8::new(0);
+
+/// Reads files whose name doesn't say otherwise as kind.
+pub fn set_format(kind: Kind) {
+    FORCED.store(kind as u8, Ordering::Relaxed);
+}
+
+/// The format the file at path is written in, by its name or else --format.
+pub(crate) fn kind(path: &Path) -> Kind {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let name = name.strip_suffix(".age").unwrap_or(&name);
+    if name.ends_with(".toml") {
+        return Kind::Toml;
+    }
+    if name.ends_with(".yaml") || name.ends_with(".yml") {
+        return Kind::Yaml;
+    }
+    match FORCED.load(Ordering::Relaxed) {
+        2 => Kind::Toml,
+        3 => Kind::Yaml,
+        _ => Kind::Plain,
+    }
+}
+
+pub(crate) fn is_plain(path: &Path) -> bool {
+    kind(path) == Kind::Plain
+}
+
+/// The format the file at path is read in.
+pub fn of(path: &Path) -> &'static dyn Format {
+    match kind(path) {
+        Kind::Plain => &Plain,
+        Kind::Toml => &Toml,
+        Kind::Yaml => &Yaml,
+    }
+}
+
+impl Format for Plain {
+    fn lines(&self, content: Vec<u8>, _: &Path) -> io::Result<Vec<u8>> {
+        Ok(content)
+    }
+}
+
+enum Value {
+    Str(String),
+    // A literal string, in single quotes
+    Lit(String),
+    // Kept as written, as modes like 600 are
+    Num(String),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+// A basic or literal string, number, boolean or one-line array at the start
+// of raw, and what follows it
+fn parse_value(raw: &str) -> Option<(Value, &str)> {
+    if let Some(rest) = raw.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.char_indices();
+        loop {
+            match chars.next()? {
+                (i, '"') => return Some((Value::Str(out), &rest[i + 1..])),
+                (_, '\\') => out.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    c @ ('"' | '\\') => c,
+                    _ => return None,
+                }),
+                (_, c) => out.push(c),
+            }
+        }
+    }
+    if let Some(rest) = raw.strip_prefix('\'') {
+        let (value, rest) = rest.split_once('\'')?;
+        return Some((Value::Lit(value.to_string()), rest));
+    }
+    if let Some(mut rest) = raw.strip_prefix('[') {
+        let mut items = Vec::new();
+        loop {
+            rest = rest.trim_start();
+            if let Some(after) = rest.strip_prefix(']') {
+                return Some((Value::Array(items), after));
+            }
+            let (item, after) = parse_value(rest)?;
+            items.push(item);
+            rest = after.trim_start();
+            if let Some(after) = rest.strip_prefix(',') {
+                rest = after;
+            } else if !rest.starts_with(']') {
+                return None;
+            }
+        }
+    }
+    let end = raw
+        .find(|c: char| c.is_whitespace() || matches!(c, ',' | ']' | '#'))
+        .unwrap_or(raw.len());
+    let value = match &raw[..end] {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        num if !num.is_empty()
+            && num
+                .trim_start_matches(['+', '-'])
+                .chars()
+                .all(|c| c.is_ascii_digit() || c == '_') =>
+        {
+            Value::Num(num.replace('_', ""))
+        }
+        _ => return None,
+    };
+    Some((value, &raw[end..]))
+}
+
+// key = value, with a bare or quoted key and an optional comment after
+fn parse_pair(line: &str) -> Result<(String, Value), String> {
+    let (key, raw) = match line.strip_prefix('"') {
+        Some(rest) => {
+            let (key, rest) = rest.split_once('"').ok_or("unterminated key")?;
+            let raw = rest
+                .trim_start()
+                .strip_prefix('=')
+                .ok_or("expected key = value")?;
+            (key, raw)
+        }
+        None => {
+            let (key, raw) = line
+                .split_once('=')
+                .ok_or_else(|| format!("expected key = value, found {line}"))?;
+            (key.trim(), raw)
+        }
+    };
+    if key.is_empty() {
+        return Err("expected key = value".to_string());
+    }
+    let invalid =
+        || format!("{key} needs a quoted string, a number, true, false or an array on one line");
+    let (value, rest) = parse_value(raw.trim_start()).ok_or_else(invalid)?;
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(invalid());
+    }
+    Ok((key.to_string(), value))
+}
+
+fn string(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::Str(value) | Value::Lit(value) if value.contains(['#', '\n']) => {
+            Err(format!("{key} can't hold # or a line break"))
+        }
+        Value::Str(value) | Value::Lit(value) => Ok(value),
+        Value::Num(value) => Ok(value),
+        _ => Err(format!("{key} takes a string")),
+    }
+}
+
+// A string, or an array of them
+fn strings(key: &str, value: Value) -> Result<Vec<String>, String> {
+    match value {
+        Value::Array(items) if items.is_empty() => Err(format!("{key} is empty")),
+        Value::Array(items) => items.into_iter().map(|item| string(key, item)).collect(),
+        value => Ok(vec![string(key, value)?]),
+    }
+}
+
+// A source or destination, where a literal string stays one: it is written
+// in single quotes, which the line format takes as it is
+fn path(key: &str, value: Value) -> Result<String, String> {
+    match value {
+        Value::Lit(value) => Ok(format!("'{value}'")),
+        Value::Array(items) if items.is_empty() => Err(format!("{key} is empty")),
+        Value::Array(items) => Ok(items
+            .into_iter()
+            .map(|item| path(key, item))
+            .collect::<Result<Vec<_>, _>>()?
+            .join(", ")),
+        value => string(key, value),
+    }
+}
+
+// The modifier an entry key stands for: !key for true, nothing for false,
+// and !key:VALUE otherwise, with the items of an array joined by commas
+fn modifier(key: &str, value: Value) -> Result<Option<String>, String> {
+    let value = match value {
+        Value::Bool(true) => return Ok(Some(format!("!{key}"))),
+        Value::Bool(false) => return Ok(None),
+        value => strings(key, value)?.join(","),
+    };
+    let quoted = if !value.is_empty()
+        && !value.contains(|c: char| c.is_whitespace() || c == '"' || c == '\'')
+    {
+        value
+    } else if !value.contains('"') {
+        format!("\"{value}\"")
+    } else if !value.contains('\'') {
+        format!("'{value}'")
+    } else {
+        return Err(format!("{key} can't hold both kinds of quotes"));
+    };
+    Ok(Some(format!("!{key}:{quoted}")))
+}
+
+// The lines an [[entry]] table makes, with the package the entry is in
+fn entry(keys: Vec<(String, Value)>) -> Result<(Vec<String>, Option<String>), String> {
+    let (mut src, mut dest, mut link, mut package, mut when) = (None, None, None, None, None);
+    let mut modifiers = Vec::new();
+    for (key, value) in keys {
+        match key.as_str() {
+            "src" => src = Some(path(&key, value)?),
+            "dest" => dest = Some(path(&key, value)?),
+            "link" => link = Some(path(&key, value)?),
+            "package" => package = Some(string(&key, value)?),
+            "when" => when = Some(strings(&key, value)?.join(" ")),
+            _ => modifiers.extend(modifier(&key, value)?),
+        }
+    }
+    let src = src.ok_or("an entry needs a src")?;
+    if !src.starts_with('\'') && (src.contains('=') || src.contains("->")) {
+        return Err(format!("src {src} can't hold = or ->"));
+    }
+    let mut line = match (dest, link) {
+        (Some(dest), None) => format!("{src} = {dest}"),
+        (None, Some(link)) => format!("{src} -> {link}"),
+        (None, None) => return Err("an entry needs a dest or a link".to_string()),
+        (Some(_), Some(_)) => return Err("an entry has a dest or a link, not both".to_string()),
+    };
+    for modifier in modifiers {
+        line.push(' ');
+        line.push_str(&modifier);
+    }
+    let lines = match when {
+        Some(when) if !cond::is_directive(when.as_bytes()) => {
+            return Err(format!(
+                "when takes conditions like @host(NAME), found {when}"
+            ));
+        }
+        Some(when) => vec![when, line, "@end".to_string()],
+        None => vec![line],
+    };
+    Ok((lines, package))
+}
+
+// Writes the lines of the entry starting at start into out, after the header
+// of its package when the lines before leave another section. The header and
+// each key of the table take a line, always enough for those of the entry.
+fn place(
+    start: usize,
+    keys: Vec<(String, Value)>,
+    section: &mut Option<String>,
+    out: &mut [String],
+) -> Result<(), String> {
+    let (lines, package) = entry(keys)?;
+    let mut i = start;
+    if package != *section {
+        let Some(package) = package else {
+            return Err(
+                "an entry without a package must come before every table and packaged entry"
+                    .to_string(),
+            );
+        };
+        out[i] = format!("[{package}]");
+        i += 1;
+        *section = Some(package);
+    }
+    for line in lines {
+        out[i] = line;
+        i += 1;
+    }
+    Ok(())
+}
+
+enum Table {
+    Top,
+    // A section whose lines are kept as they are
+    Section,
+    Let,
+    // Where the entry starts and the keys it has so far
+    Entry(usize, Vec<(String, Value)>),
+}
+
+impl Format for Toml {
+    fn lines(&self, content: Vec<u8>, path: &Path) -> io::Result<Vec<u8>> {
+        let at = |i: usize, msg: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {msg}", path.display(), i + 1),
+            )
+        };
+        let text =
+            String::from_utf8(content).map_err(|_| at(0, "TOML must be UTF-8".to_string()))?;
+        let lines: Vec<&str> = text.split('\n').collect();
+        let mut out = vec![String::new(); lines.len()];
+        // The section the lines written so far leave the line format in
+        let mut section: Option<String> = None;
+
+        let mut table = Table::Top;
+        for (i, raw) in lines.iter().enumerate() {
+            let line = raw.trim();
+            if let Table::Section = table
+                && !line.starts_with('[')
+            {
+                out[i] = raw.to_string();
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(rest) = line.strip_prefix('[') {
+                if let Table::Entry(start, keys) = std::mem::replace(&mut table, Table::Top) {
+                    place(start, keys, &mut section, &mut out).map_err(|msg| at(start, msg))?;
+                }
+                let (array, rest) = match rest.strip_prefix('[') {
+                    Some(rest) => (true, rest),
+                    None => (false, rest),
+                };
+                let close = if array { "]]" } else { "]" };
+                let (name, rest) = rest
+                    .split_once(close)
+                    .ok_or_else(|| at(i, format!("unterminated table header {line}")))?;
+                let rest = rest.trim_start();
+                if !rest.is_empty() && !rest.starts_with('#') {
+                    return Err(at(i, format!("unexpected {rest} after {name}")));
+                }
+                table = match (array, name.trim()) {
+                    (true, "entry") => Table::Entry(i, Vec::new()),
+                    (false, "let") => Table::Let,
+                    (false, name @ ("vars" | "transform" | "reload" | "aliases" | "registry")) => {
+                        out[i] = format!("[{name}]");
+                        section = Some(name.to_string());
+                        Table::Section
+                    }
+                    (_, name) => return Err(at(i, format!("unknown table {name}"))),
+                };
+                continue;
+            }
+            // Rules listed before any table are read as they are
+            if let Table::Top = table
+                && transform::list(line.as_bytes()).is_some()
+            {
+                out[i] = line.to_string();
+                continue;
+            }
+            let (key, value) = parse_pair(line).map_err(|msg| at(i, msg))?;
+            match &mut table {
+                Table::Entry(_, keys) => {
+                    if keys.iter().any(|(seen, _)| *seen == key) {
+                        return Err(at(i, format!("{key} is given twice")));
+                    }
+                    keys.push((key, value));
+                }
+                Table::Let => {
+                    let value = string(&key, value).map_err(|msg| at(i, msg))?;
+                    out[i] = format!("let {key} = {value}");
+                }
+                _ => {
+                    return Err(at(i, format!("{key} is outside of any table")));
+                }
+            }
+        }
+        if let Table::Entry(start, keys) = table {
+            place(start, keys, &mut section, &mut out).map_err(|msg| at(start, msg))?;
+        }
+        Ok(out.join("\n").into_bytes())
+    }
+}
+
+// A YAML scalar or flow sequence, with any comment after it taken off; None
+// for nothing at all, as a key whose block sequence follows has
+fn yaml_value(raw: &str) -> Result<Option<Value>, String> {
+    let raw = raw.trim();
+    if raw.is_empty() || raw.starts_with('#') {
+        return Ok(None);
+    }
+    let (value, rest) = match raw.as_bytes()[0] {
+        b'"' => parse_value(raw).ok_or("unterminated or invalid double-quoted string")?,
+        b'\'' => single_quoted(raw)?,
+        b'[' => {
+            let mut items = Vec::new();
+            let mut rest = &raw[1..];
+            loop {
+                rest = rest.trim_start();
+                if let Some(after) = rest.strip_prefix(']') {
+                    break (Value::Array(items), after);
+                }
+                let (item, after) = match rest.as_bytes().first() {
+                    Some(b'"') => parse_value(rest).ok_or("invalid string in a sequence")?,
+                    Some(b'\'') => single_quoted(rest)?,
+                    Some(_) => {
+                        let end = rest.find([',', ']']).ok_or("unterminated sequence")?;
+                        (plain(rest[..end].trim()), &rest[end..])
+                    }
+                    None => return Err("unterminated sequence".to_string()),
+                };
+                items.push(item);
+                rest = after.trim_start();
+                if let Some(after) = rest.strip_prefix(',') {
+                    rest = after;
+                } else if !rest.starts_with(']') {
+                    return Err("expected , or ] in a sequence".to_string());
+                }
+            }
+        }
+        _ => {
+            // A comment starts at a # after a space
+            let end = raw.find(" #").unwrap_or(raw.len());
+            (plain(raw[..end].trim_end()), "")
+        }
+    };
+    let rest = rest.trim_start();
+    if !rest.is_empty() && !rest.starts_with('#') {
+        return Err(format!("unexpected {rest} after a value"));
+    }
+    Ok(Some(value))
+}
+
+// 'text', where '' stands for a quote, kept as a literal string
+fn single_quoted(raw: &str) -> Result<(Value, &str), String> {
+    let mut out = String::new();
+    let mut rest = &raw[1..];
+    loop {
+        let (part, after) = rest
+            .split_once('\'')
+            .ok_or("unterminated single-quoted string")?;
+        out.push_str(part);
+        match after.strip_prefix('\'') {
+            Some(after) => {
+                out.push('\'');
+                rest = after;
+            }
+            None => return Ok((Value::Lit(out), after)),
+        }
+    }
+}
+
+fn plain(text: &str) -> Value {
+    match text {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        num if num
+            .trim_start_matches(['+', '-'])
+            .chars()
+            .all(|c| c.is_ascii_digit())
+            && num.chars().any(|c| c.is_ascii_digit()) =>
+        {
+            Value::Num(num.to_string())
+        }
+        _ => Value::Str(text.to_string()),
+    }
+}
+
+// key: value, or key: alone
+fn yaml_pair(line: &str) -> Result<(String, Option<Value>), String> {
+    let (key, raw) = match line.split_once(": ") {
+        Some((key, raw)) => (key, raw),
+        None => (
+            line.strip_suffix(':')
+                .ok_or_else(|| format!("expected key: value, found {line}"))?,
+            "",
+        ),
+    };
+    let key = key.trim().trim_matches(['"', '\'']);
+    if key.is_empty() {
+        return Err("expected key: value".to_string());
+    }
+    Ok((key.to_string(), yaml_value(raw)?))
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+enum Block {
+    Top,
+    // A section whose lines are kept as they are, less their indentation
+    // once the first line sets it
+    Section(Option<usize>),
+    Let,
+    Entries,
+}
+
+// An entry of the sequence under entries: where it starts, how far its keys
+// are indented, its keys so far and the key whose block sequence is open
+struct Item {
+    start: usize,
+    indent: usize,
+    keys: Vec<(String, Value)>,
+    open: Option<(String, Vec<Value>)>,
+}
+
+impl Item {
+    fn close(&mut self) {
+        if let Some((key, items)) = self.open.take() {
+            self.keys.push((key, Value::Array(items)));
+        }
+    }
+
+    fn add(&mut self, key: String, value: Option<Value>) -> Result<(), String> {
+        self.close();
+        if self.keys.iter().any(|(seen, _)| *seen == key) {
+            return Err(format!("{key} is given twice"));
+        }
+        match value {
+            Some(value) => self.keys.push((key, value)),
+            None => self.open = Some((key, Vec::new())),
+        }
+        Ok(())
+    }
+}
+
+impl Format for Yaml {
+    fn lines(&self, content: Vec<u8>, path: &Path) -> io::Result<Vec<u8>> {
+        let at = |i: usize, msg: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {msg}", path.display(), i + 1),
+            )
+        };
+        let text =
+            String::from_utf8(content).map_err(|_| at(0, "YAML must be UTF-8".to_string()))?;
+        let lines: Vec<&str> = text.split('\n').collect();
+        let mut out = vec![String::new(); lines.len()];
+        let mut section: Option<String> = None;
+
+        let mut block = Block::Top;
+        let mut item: Option<Item> = None;
+        let finish = |item: Option<Item>, section: &mut Option<String>, out: &mut [String]| {
+            let Some(mut item) = item else {
+                return Ok(());
+            };
+            item.close();
+            let start = item.start;
+            place(start, item.keys, section, out).map_err(|msg| at(start, msg))
+        };
+        for (i, raw) in lines.iter().enumerate() {
+            let raw = raw.trim_end_matches('\r');
+            let line = raw.trim();
+            let depth = indent(raw);
+            if let Block::Section(kept) = &mut block
+                && (depth > 0 || line.is_empty())
+            {
+                if !line.is_empty() {
+                    let depth = *kept.get_or_insert(depth);
+                    out[i] = raw.get(depth.min(indent(raw))..).unwrap_or("").to_string();
+                }
+                continue;
+            }
+            if line.is_empty() || line.starts_with('#') || line == "---" {
+                continue;
+            }
+            if raw.starts_with('\t') {
+                return Err(at(i, "YAML is indented with spaces, not tabs".to_string()));
+            }
+            // A key at the top ends the block before it
+            if depth == 0 && !line.starts_with('-') {
+                finish(item.take(), &mut section, &mut out)?;
+                let (key, value) = yaml_pair(line).map_err(|msg| at(i, msg))?;
+                let literal = matches!(&value, Some(Value::Str(s)) if matches!(s.as_str(), "|" | "|-" | "|+"));
+                block = match (key.as_str(), value) {
+                    ("entries", None) => Block::Entries,
+                    ("let", None) => Block::Let,
+                    (name @ ("vars" | "transform" | "reload" | "aliases" | "registry"), _)
+                        if literal =>
+                    {
+                        out[i] = format!("[{name}]");
+                        section = Some(name.to_string());
+                        Block::Section(None)
+                    }
+                    (name @ ("vars" | "transform" | "reload" | "aliases" | "registry"), _) => {
+                        return Err(at(
+                            i,
+                            format!("{name} takes its lines in a block, as {name}: |"),
+                        ));
+                    }
+                    (name, _) => return Err(at(i, format!("unknown key {name}"))),
+                };
+                continue;
+            }
+            match block {
+                Block::Let => {
+                    let (key, value) = yaml_pair(line).map_err(|msg| at(i, msg))?;
+                    let value = value.ok_or_else(|| at(i, format!("{key} has no value")))?;
+                    let value = string(&key, value).map_err(|msg| at(i, msg))?;
+                    out[i] = format!("let {key} = {value}");
+                }
+                Block::Entries => {
+                    if let Some(rest) = line
+                        .strip_prefix('-')
+                        .filter(|_| item.as_ref().is_none_or(|item| depth < item.indent))
+                    {
+                        finish(item.take(), &mut section, &mut out)?;
+                        let rest = rest.trim_start();
+                        if rest.is_empty() {
+                            return Err(at(
+                                i,
+                                "an entry starts with - and its first key on one line".to_string(),
+                            ));
+                        }
+                        let mut new = Item {
+                            start: i,
+                            indent: raw.len() - rest.len(),
+                            keys: Vec::new(),
+                            open: None,
+                        };
+                        let (key, value) = yaml_pair(rest).map_err(|msg| at(i, msg))?;
+                        new.add(key, value).map_err(|msg| at(i, msg))?;
+                        item = Some(new);
+                        continue;
+                    }
+                    let Some(current) = &mut item else {
+                        return Err(at(
+                            i,
+                            "entries holds a sequence of entries, each starting with -".to_string(),
+                        ));
+                    };
+                    if let Some(rest) = line.strip_prefix("- ")
+                        && let Some((_, items)) = &mut current.open
+                    {
+                        let value = yaml_value(rest)
+                            .map_err(|msg| at(i, msg))?
+                            .ok_or_else(|| at(i, "an empty item".to_string()))?;
+                        items.push(value);
+                        continue;
+                    }
+                    if depth != current.indent {
+                        return Err(at(
+                            i,
+                            format!("{line} is not lined up with the keys of its entry"),
+                        ));
+                    }
+                    let (key, value) = yaml_pair(line).map_err(|msg| at(i, msg))?;
+                    current.add(key, value).map_err(|msg| at(i, msg))?;
+                }
+                Block::Top | Block::Section(_) => {
+                    return Err(at(i, format!("{line} is outside of any key")));
+                }
+            }
+        }
+        finish(item.take(), &mut section, &mut out)?;
+        Ok(out.join("\n").into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toml(text: &str) -> io::Result<String> {
+        let lines = Toml.lines(text.as_bytes().to_vec(), Path::new("neostow.toml"))?;
+        Ok(String::from_utf8(lines).unwrap())
+    }
+
+    fn yaml(text: &str) -> io::Result<String> {
+        let lines = Yaml.lines(text.as_bytes().to_vec(), Path::new("neostow.yaml"))?;
+        Ok(String::from_utf8(lines).unwrap())
+    }
+
+    fn error(result: io::Result<String>) -> String {
+        match result {
+            Ok(lines) => panic!("read as {lines:?}"),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    #[test]
+    fn toml_entries_keep_their_lines() {
+        let text = r#"# dotfiles
+[let]
+editor = "nvim"
+
+[[entry]]
+src = "zshrc"
+dest = "~"
+
+[[entry]]
+src = "nvim/"
+dest = ["~/.config", "~/.var/app/io.neovim.nvim/config"]
+package = "nvim"
+when = "@os(linux)"
+desc = "Neovim configuration"
+
+[transform]
+"^_" = "."
+"#;
+        let expected = [
+            "",
+            "",
+            "let editor = nvim",
+            "",
+            "zshrc = ~",
+            "",
+            "",
+            "",
+            "[nvim]",
+            "@os(linux)",
+            "nvim/ = ~/.config, ~/.var/app/io.neovim.nvim/config !desc:\"Neovim configuration\"",
+            "@end",
+            "",
+            "",
+            "",
+            "[transform]",
+            "\"^_\" = \".\"",
+            "",
+        ];
+        assert_eq!(toml(text).unwrap(), expected.join("\n"));
+    }
+
+    #[test]
+    fn toml_literal_strings_stay_literal() {
+        let text = "[[entry]]\nsrc = 'my=file'\ndest = '~/a b'\ntag = \"it's\"\n";
+        assert_eq!(
+            toml(text).unwrap(),
+            "'my=file' = '~/a b' !tag:\"it's\"\n\n\n\n"
+        );
+    }
+
+    #[test]
+    fn toml_transform_lists_are_kept() {
+        let text = "transform = [{ match = \"^_\", replace = \".\" }]\n[[entry]]\nsrc = \"_zshrc\"\ndest = \"~\"\n";
+        assert_eq!(
+            toml(text).unwrap(),
+            "transform = [{ match = \"^_\", replace = \".\" }]\n_zshrc = ~\n\n\n"
+        );
+    }
+
+    #[test]
+    fn toml_errors_name_their_line() {
+        let cases = [
+            (
+                "[[entry]]\nsrc = \"a\"\n",
+                "neostow.toml:1: an entry needs a dest or a link",
+            ),
+            (
+                "[[entry]]\nsrc = \"a\"\nsrc = \"b\"\n",
+                "neostow.toml:3: src is given twice",
+            ),
+            ("\n[foo]\n", "neostow.toml:2: unknown table foo"),
+            (
+                "src = \"a\"\n",
+                "neostow.toml:1: src is outside of any table",
+            ),
+            (
+                "[[entry]]\nsrc = \"a\"\ndest = \"~\"\nwhen = \"linux\"\n",
+                "neostow.toml:1: when takes conditions like @host(NAME), found linux",
+            ),
+            (
+                "[[entry]]\nsrc = \"a\"\ndest = \"~\"\npackage = \"p\"\n[[entry]]\nsrc = \"b\"\ndest = \"~\"\n",
+                "neostow.toml:5: an entry without a package must come before every table and packaged entry",
+            ),
+            (
+                "[[entry]]\nsrc = \"a=b\"\ndest = \"~\"\n",
+                "neostow.toml:1: src a=b can't hold = or ->",
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(error(toml(text)), expected);
+        }
+    }
+
+    #[test]
+    fn yaml_entries_keep_their_lines() {
+        let text = r#"let:
+  editor: nvim
+entries:
+  - src: zshrc
+    dest: ~
+  - src: nvim/
+    dest: [~/.config, '~/.var/app/io.neovim.nvim/config']
+    package: nvim
+    when: "@os(linux)"
+  - src: bin/
+    dest:
+      - ~/bin
+      - ~/.local/bin
+    package: nvim
+transform: |
+  "^_" = "."
+"#;
+        let expected = [
+            "",
+            "let editor = nvim",
+            "",
+            "zshrc = ~",
+            "",
+            "[nvim]",
+            "@os(linux)",
+            "nvim/ = ~/.config, '~/.var/app/io.neovim.nvim/config'",
+            "@end",
+            "bin/ = ~/bin, ~/.local/bin",
+            "",
+            "",
+            "",
+            "",
+            "[transform]",
+            "\"^_\" = \".\"",
+            "",
+        ];
+        assert_eq!(yaml(text).unwrap(), expected.join("\n"));
+    }
+
+    #[test]
+    fn yaml_errors_name_their_line() {
+        let cases = [
+            (
+                "entries:\n\t- src: a\n",
+                "neostow.yaml:2: YAML is indented with spaces, not tabs",
+            ),
+            (
+                "vars: x\n",
+                "neostow.yaml:1: vars takes its lines in a block, as vars: |",
+            ),
+            ("\nfoo: 1\n", "neostow.yaml:2: unknown key foo"),
+            (
+                "entries:\n  - src: a\n      dest: b\n",
+                "neostow.yaml:3: dest: b is not lined up with the keys of its entry",
+            ),
+            (
+                "entries:\n  - src: 'a\n",
+                "neostow.yaml:2: unterminated single-quoted string",
+            ),
+            (
+                "entries:\n  - dest: b\n",
+                "neostow.yaml:2: an entry needs a src",
+            ),
+            (
+                "entries:\n  - src: a\n    dest: b\n    dest: c\n",
+                "neostow.yaml:4: dest is given twice",
+            ),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(error(yaml(text)), expected);
+        }
+    }
+}
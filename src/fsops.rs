@@ -0,0 +1,75 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::vfs::Fs;
+
+// Shares the blocks of from with a new file at to (btrfs, XFS, bcachefs),
+// so copies of large assets cost no space until either side changes
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+pub(crate) fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+    use std::ffi::{c_int, c_ulong};
+    use std::os::fd::AsRawFd;
+
+    unsafe extern "C" {
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    }
+    const FICLONE: c_ulong = 0x4004_9409;
+
+    let src = fs::File::open(from)?;
+    let dest = fs::File::create_new(to)?;
+    if unsafe { ioctl(dest.as_raw_fd(), FICLONE, src.as_raw_fd()) } < 0 {
+        let err = io::Error::last_os_error();
+        drop(dest);
+        fs::remove_file(to)?;
+        return Err(err);
+    }
+    fs::set_permissions(to, src.metadata()?.permissions())
+}
+
+// fs::copy already clones where it can on macOS (APFS), and copy_file_range
+// may elsewhere
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+pub(crate) fn reflink(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+pub(crate) fn copy_file(from: &Path, to: &Path) -> io::Result<()> {
+    if reflink(from, to).is_err() {
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+// Copies from to to, or with hardlink links every file of the tree instead
+pub fn copy_tree(from: &Path, to: &Path, hardlink: bool) -> io::Result<()> {
+    if !from.is_dir() {
+        if hardlink {
+            fs::hard_link(from, to)?;
+        } else {
+            copy_file(from, to)?;
+        }
+        return Ok(());
+    }
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_tree(&entry.path(), &to.join(entry.file_name()), hardlink)?;
+    }
+    Ok(())
+}
+
+// Whether err is Windows refusing to touch a file another program has open,
+// as an editor or a running shell does with its config: a sharing or lock
+// violation, os error 32 or 33. Elsewhere open files can be replaced.
+pub(crate) fn is_busy(err: &io::Error) -> bool {
+    cfg!(windows) && matches!(err.raw_os_error(), Some(32 | 33))
+}
+
+// Moves from to to, making the directory it goes in first
+pub fn move_path(fs: &dyn Fs, from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs.create_dir_all(parent)?;
+    }
+    fs.rename(from, to)
+}
@@ -0,0 +1,114 @@
+// Shell-style wildcards over path bytes: `*` and `?` stay within one path
+// component, `**` spans any number of them, `[a-z]`/`[!a-z]` match classes
+// and `\` escapes the next byte.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::bytes;
+use crate::vfs::Fs;
+
+pub fn is_pattern(s: &[u8]) -> bool {
+    s.iter().any(|b| matches!(b, b'*' | b'?' | b'['))
+}
+
+pub(crate) fn match_class(class: &[u8], c: u8) -> bool {
+    let (negate, mut class) = match class.split_first() {
+        Some((b'!' | b'^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut found = false;
+    while let Some((&first, rest)) = class.split_first() {
+        if let [b'-', last, tail @ ..] = rest {
+            found |= (first..=*last).contains(&c);
+            class = tail;
+        } else {
+            found |= first == c;
+            class = rest;
+        }
+    }
+    found != negate
+}
+
+pub fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            matches(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(i, &b)| b == b'/' && matches(rest, &text[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+        [b'*', rest @ ..] => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != b'/')
+            .any(|i| matches(rest, &text[i..])),
+        [b'?', rest @ ..] => match text.split_first() {
+            Some((&c, tail)) => c != b'/' && matches(rest, tail),
+            None => false,
+        },
+        [b'[', rest @ ..] if rest.iter().skip(1).any(|&b| b == b']') => {
+            // A `]` right after the opening bracket is part of the class
+            let end = 1 + rest[1..].iter().position(|&b| b == b']').unwrap();
+            match text.split_first() {
+                Some((&c, tail)) => {
+                    c != b'/' && match_class(&rest[..end], c) && matches(&rest[end + 1..], tail)
+                }
+                None => false,
+            }
+        }
+        [b'\\', c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+        [c, rest @ ..] => text.first() == Some(c) && matches(rest, &text[1..]),
+    }
+}
+
+fn walk(
+    fs: &dyn Fs,
+    base: &Path,
+    rel: &Path,
+    depth: usize,
+    pattern: &[u8],
+    dirs_only: bool,
+    out: &mut Vec<PathBuf>,
+) -> io::Result<()> {
+    let entries = match fs.read_dir(&base.join(rel)) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+    for (name, real_dir) in entries {
+        let child = rel.join(name);
+        let is_dir = fs.is_dir(&base.join(&child));
+        if (is_dir || !dirs_only) && matches(pattern, &bytes::from_os(child.as_os_str())) {
+            out.push(child.clone());
+        }
+        // Only real directories are entered, so link cycles can't trap the walk
+        if depth > 1 && real_dir {
+            walk(fs, base, &child, depth - 1, pattern, dirs_only, out)?;
+        }
+    }
+    Ok(())
+}
+
+// Returns the paths under base matching pattern, relative to base and sorted.
+// A trailing `/` only matches directories.
+pub fn expand(fs: &dyn Fs, base: &Path, pattern: &[u8]) -> io::Result<Vec<PathBuf>> {
+    let dirs_only = pattern.ends_with(b"/");
+    let pattern = pattern.strip_suffix(b"/").unwrap_or(pattern);
+
+    // Leading literal components only narrow where the walk starts
+    let components: Vec<&[u8]> = pattern.split(|&b| b == b'/').collect();
+    let literal = components.iter().take_while(|c| !is_pattern(c)).count();
+    let start = PathBuf::from(bytes::to_os_string(components[..literal].join(&b'/')));
+    let depth = if components[literal..].contains(&&b"**"[..]) {
+        usize::MAX
+    } else {
+        components.len() - literal
+    };
+
+    let mut out = Vec::new();
+    walk(fs, base, &start, depth, pattern, dirs_only, &mut out)?;
+    out.sort();
+    Ok(out)
+}
@@ -0,0 +1,100 @@
+// The symbols output is drawn with: the arrows between sources and
+// destinations, the branches of list --tree, the dash before a !desc and the
+// marks status puts before each state. Terminals and fonts that render them
+// poorly get ASCII instead with ascii_only = true in config.toml, which a
+// C or POSIX locale implies, and glyphs = "arrow=>>, ok=*" in config.toml
+// sets any of them to something else.
+
+use std::env;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// A symbol of the output.
+#[derive(Clone, Copy)]
+pub enum Glyph {
+    Arrow,
+    Back,
+    Dash,
+    Branch,
+    Last,
+    Ok,
+    Bad,
+    Warn,
+}
+
+const COUNT: usize = 8;
+
+// By Glyph: its name in glyphs, then how it is drawn in Unicode and ASCII
+const TABLE: [(&str, &str, &str); COUNT] = [
+    ("arrow", "→", "->"),
+    ("back", "←", "<-"),
+    ("dash", "—", "-"),
+    ("branch", "├──", "|--"),
+    ("last", "└──", "`--"),
+    ("ok", "✓", "+"),
+    ("bad", "✗", "x"),
+    ("warn", "!", "!"),
+];
+
+// 0 until decided, 1 for Unicode and 2 for ASCII
+static ASCII: AtomicU8 = AtomicU8::new(0);
+static CUSTOM: RwLock<[Option<String>; COUNT]> = RwLock::new([const { None }; COUNT]);
+
+/// Draws every glyph not set otherwise in ASCII, or in Unicode.
+pub fn set_ascii_only(ascii: bool) {
+    ASCII.store(if ascii { 2 } else { 1 }, Ordering::Relaxed);
+}
+
+/// Reads a list such as `arrow=>>, ok=*`, or None when it names a glyph
+/// there isn't.
+pub fn parse(list: &str) -> Option<Vec<(Glyph, String)>> {
+    let glyphs = [
+        Glyph::Arrow,
+        Glyph::Back,
+        Glyph::Dash,
+        Glyph::Branch,
+        Glyph::Last,
+        Glyph::Ok,
+        Glyph::Bad,
+        Glyph::Warn,
+    ];
+    list.split(',')
+        .filter(|item| !item.trim().is_empty())
+        .map(|item| {
+            let (name, drawn) = item.split_once('=')?;
+            let at = TABLE
+                .iter()
+                .position(|(known, _, _)| *known == name.trim())?;
+            Some((glyphs[at], drawn.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Draws each glyph of glyphs as given.
+pub fn set(glyphs: Vec<(Glyph, String)>) {
+    let mut custom = CUSTOM.write().unwrap_or_else(|e| e.into_inner());
+    for (glyph, drawn) in glyphs {
+        custom[glyph as usize] = Some(drawn);
+    }
+}
+
+// Whether the locale says the terminal only takes ASCII
+fn ascii_locale() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+        .is_some_and(|locale| locale == "C" || locale == "POSIX")
+}
+
+/// How glyph is drawn.
+pub fn get(glyph: Glyph) -> String {
+    if let Some(drawn) = &CUSTOM.read().unwrap_or_else(|e| e.into_inner())[glyph as usize] {
+        return drawn.clone();
+    }
+    let ascii = match ASCII.load(Ordering::Relaxed) {
+        0 => ascii_locale(),
+        chosen => chosen == 2,
+    };
+    let (_, unicode, plain) = TABLE[glyph as usize];
+    (if ascii { plain } else { unicode }).to_string()
+}
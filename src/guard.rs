@@ -0,0 +1,109 @@
+// `neostow guard install` writes a shell script that wraps the usual editors
+// to warn before a managed copy is edited in place, where the next run would
+// overwrite the changes:
+//   neostow: ~/.config/app.toml is a copy made by neostow, edits to it will be overwritten
+//            edit its source with: neostow -f ~/dots/.neostow edit-file ~/.config/app.toml
+// The editor still opens. Links need no guard, as editing them edits the
+// source, so only destinations that are real files or directories are
+// listed, as of the time of install; running it again after an apply
+// refreshes the list.
+
+use std::fs;
+use std::io;
+use std::path::{self, Path, PathBuf};
+
+use crate::expand::normalize;
+use crate::{Config, LinkMode, LogLevel, Operation, state};
+
+const GUARD_FILE: &str = "guard.sh";
+const EDITORS: [&str; 9] = [
+    "vi", "vim", "nvim", "nano", "emacs", "micro", "hx", "kak", "code",
+];
+
+fn quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+fn script(dests: &[PathBuf], file: &Path) -> String {
+    let patterns: Vec<String> = dests
+        .iter()
+        .map(|dest| format!("{0}|{0}/*", quote(dest)))
+        .collect();
+    let mut out = format!(
+        "# Written by neostow guard install: warns before a copy neostow manages is\n\
+         # edited in place. Source it from your shell's startup file.\n\
+         __neostow_guard() {{\n\
+         \x20 for __neostow_arg in \"$@\"; do\n\
+         \x20   case $__neostow_arg in\n\
+         \x20     -*) continue ;;\n\
+         \x20     /*) __neostow_path=$__neostow_arg ;;\n\
+         \x20     *) __neostow_path=$PWD/$__neostow_arg ;;\n\
+         \x20   esac\n\
+         \x20   case $__neostow_path in\n\
+         \x20     {})\n\
+         \x20       printf 'neostow: %s is a copy made by neostow, edits to it will be overwritten\\n' \"$__neostow_arg\" >&2\n\
+         \x20       printf '         edit its source with: neostow -f %s edit-file %s\\n' {} \"$__neostow_path\" >&2 ;;\n\
+         \x20   esac\n\
+         \x20 done\n\
+         }}\n",
+        patterns.join("|"),
+        quote(file)
+    );
+    for editor in EDITORS {
+        out.push_str(&format!(
+            "{editor}() {{ __neostow_guard \"$@\"; command {editor} \"$@\"; }}\n"
+        ));
+    }
+    out
+}
+
+/// Writes the guard script for the copies among the destinations of ops
+/// and returns where it is.
+pub fn install(ops: &[(usize, Operation)], cfg: &Config) -> io::Result<PathBuf> {
+    if cfg!(windows) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "guard install writes a POSIX shell script, which Windows shells can't source",
+        ));
+    }
+    let file = fs::canonicalize(&cfg.file)?;
+    // A hard link is the source itself, so editing it edits the source
+    let mut dests: Vec<PathBuf> = if cfg.link_mode == LinkMode::Hardlink {
+        Vec::new()
+    } else {
+        ops.iter()
+            .filter(|(_, op)| {
+                op.dest
+                    .symlink_metadata()
+                    .is_ok_and(|meta| !meta.file_type().is_symlink())
+            })
+            .filter_map(|(_, op)| path::absolute(&op.dest).ok())
+            .map(|dest| normalize(&dest))
+            .collect()
+    };
+    dests.sort();
+    dests.dedup();
+
+    let path = state::host_dir().join(GUARD_FILE);
+    if dests.is_empty() {
+        printfc!(
+            LogLevel::Warn,
+            "No destination of {} is a copy, so there is nothing to guard",
+            cfg.file.display()
+        );
+        let _ = fs::remove_file(&path);
+        return Ok(path);
+    }
+    fs::create_dir_all(state::host_dir())?;
+    fs::write(&path, script(&dests, &file))?;
+    printfc!(
+        LogLevel::Info,
+        "Guarding {} cop{} in {}",
+        dests.len(),
+        if dests.len() == 1 { "y" } else { "ies" },
+        path.display()
+    );
+    println!("Add this line to your shell's startup file to turn it on:");
+    println!("  [ -f {0} ] && . {0}", quote(&path));
+    Ok(path)
+}
@@ -0,0 +1,211 @@
+// `neostow log` lists the runs on this machine one by one, from the history
+// `neostow stats` sums up, each with what it drifted, found in the way and
+// failed on. --since and --until narrow it to a window, written as journalctl
+// takes them: a date (2026-10-14), a date and time (2026-10-14 18:30), a time
+// today (18:30), today, yesterday, now, or a span ago (2 hours ago, 30min
+// ago), in UTC like the other times neostow keeps. --failed keeps the runs
+// where an entry failed.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{LogLevel, backup, bytes, state, stats};
+
+const DAY: u64 = 86400;
+
+// The seconds of each unit a span can be given in
+const UNITS: [(&[&str], u64); 5] = [
+    (&["s", "sec", "secs", "second", "seconds"], 1),
+    (&["m", "min", "mins", "minute", "minutes"], 60),
+    (&["h", "hr", "hrs", "hour", "hours"], 3600),
+    (&["d", "day", "days"], DAY),
+    (&["w", "week", "weeks"], 7 * DAY),
+];
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// The days since the epoch of a date, when there is one
+fn days(year: i64, month: i64, day: i64) -> Option<i64> {
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let days = era * 146_097 + yoe * 365 + yoe / 4 - yoe / 100 + doy - 719_468;
+    // February 30th and the like
+    (backup::civil(days) == (year, month, day)).then_some(days)
+}
+
+// HH:MM or HH:MM:SS, in seconds
+fn clock(text: &str) -> Option<u64> {
+    let mut fields = text.split(':').map(|field| field.parse::<u64>().ok());
+    let (Some(Some(hours)), Some(Some(minutes))) = (fields.next(), fields.next()) else {
+        return None;
+    };
+    let seconds = match fields.next() {
+        Some(seconds) => seconds?,
+        None => 0,
+    };
+    if fields.next().is_some() || hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    Some(hours * 3600 + minutes * 60 + seconds)
+}
+
+// YYYY-MM-DD, in seconds
+fn date(text: &str) -> Option<u64> {
+    let mut fields = text.splitn(3, '-').map(|field| field.parse::<i64>().ok());
+    let (Some(Some(year)), Some(Some(month)), Some(Some(day))) =
+        (fields.next(), fields.next(), fields.next())
+    else {
+        return None;
+    };
+    u64::try_from(days(year, month, day)?).ok().map(|d| d * DAY)
+}
+
+// 2 hours ago, 2h ago or -2h, in seconds
+fn span(text: &str) -> Option<u64> {
+    let text = text
+        .strip_suffix(" ago")
+        .or_else(|| text.strip_prefix('-'))?
+        .trim();
+    let digits = text.find(|c: char| !c.is_ascii_digit())?;
+    let count: u64 = text[..digits].parse().ok()?;
+    let unit = text[digits..].trim();
+    let (_, secs) = UNITS.iter().find(|(names, _)| names.contains(&unit))?;
+    count.checked_mul(*secs)
+}
+
+/// The time text stands for, in seconds since the epoch, or None when it
+/// isn't one --since and --until take.
+pub fn time(text: &str) -> Option<u64> {
+    let text = text.trim().to_ascii_lowercase();
+    let now = now();
+    let today = now - now % DAY;
+    match text.as_str() {
+        "now" => return Some(now),
+        "today" => return Some(today),
+        "yesterday" => return Some(today.saturating_sub(DAY)),
+        "tomorrow" => return Some(today + DAY),
+        _ => {}
+    }
+    if let Some(secs) = text.strip_prefix('@') {
+        return secs.parse().ok();
+    }
+    if let Some(ago) = span(&text) {
+        return Some(now.saturating_sub(ago));
+    }
+    if let Some(secs) = clock(&text) {
+        return Some(today + secs);
+    }
+    match text.split_once([' ', 't']) {
+        Some((day, time)) => Some(date(day)? + clock(time.trim())?),
+        None => date(&text),
+    }
+}
+
+fn stamp(secs: u64) -> String {
+    let (year, month, day) = backup::civil((secs / DAY) as i64);
+    let rem = secs % DAY;
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+struct Run<'a> {
+    secs: u64,
+    mode: &'a str,
+    entries: &'a str,
+    // None for runs recorded before failures were
+    failed: Option<usize>,
+    // What drifted, was in the way or failed
+    details: Vec<(&'a str, &'a [u8])>,
+}
+
+/// Lists the runs between since and until, only those where an entry failed
+/// with failed.
+pub fn log(since: Option<u64>, until: Option<u64>, failed: bool) -> io::Result<()> {
+    let path = state::host_dir().join(stats::HISTORY_FILE);
+    if !path.exists() {
+        printfc!(LogLevel::Info, "No runs recorded yet");
+        return Ok(());
+    }
+    let content = fs::read(path)?;
+
+    let mut runs: Vec<Run> = Vec::new();
+    for line in bytes::lines(&content) {
+        let mut fields = line.splitn(2, |&b| b == b'\t');
+        let (Some(kind), Some(rest)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        match kind {
+            b"run" => {
+                let Ok(rest) = std::str::from_utf8(rest) else {
+                    continue;
+                };
+                let mut fields = rest.split('\t');
+                let (Some(Ok(secs)), Some(mode), Some(entries)) = (
+                    fields.next().map(str::parse::<u64>),
+                    fields.next(),
+                    fields.next(),
+                ) else {
+                    continue;
+                };
+                runs.push(Run {
+                    secs,
+                    mode,
+                    entries,
+                    failed: fields.next().and_then(|n| n.parse().ok()),
+                    details: Vec::new(),
+                });
+            }
+            b"drift" | b"conflict" | b"failed" => {
+                if let Some(run) = runs.last_mut() {
+                    let kind = std::str::from_utf8(kind).expect("matched above");
+                    run.details.push((kind, rest));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    runs.retain(|run| {
+        since.is_none_or(|since| run.secs >= since)
+            && until.is_none_or(|until| run.secs <= until)
+            && (!failed || run.failed.is_some_and(|n| n > 0))
+    });
+    if runs.is_empty() {
+        printfc!(LogLevel::Info, "No runs match");
+        return Ok(());
+    }
+
+    println!(
+        "{:<19}  {:<9} {:>7} {:>6}",
+        "Time (UTC)", "Mode", "Entries", "Failed"
+    );
+    for run in runs {
+        let failed = run.failed.map_or("-".to_string(), |n| n.to_string());
+        println!(
+            "{:<19}  {:<9} {:>7} {failed:>6}",
+            stamp(run.secs),
+            run.mode,
+            run.entries
+        );
+        for (kind, detail) in run.details {
+            let detail = bytes::to_os(detail);
+            println!("    {kind:<9} {}", Path::new(&*detail).display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,160 @@
+// Commands run around a run or an entry, declared anywhere in the file:
+//   @pre-run = git pull --ff-only
+//   @post-run = notify-send neostow done
+//   @pre nvim = mkdir -p ~/.local/state/nvim
+//   @post nvim = nvim --headless +PlugInstall +qa
+// Entry hooks run once per run at most, the post hook only when the entry
+// changed something. Like entries, hooks follow @host/@os/@env conditions.
+
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+
+use crate::reload::field;
+use crate::{Config, Operation, bytes, cond, lookup, section_header, shell};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum When {
+    Pre,
+    Post,
+}
+
+#[derive(Clone)]
+struct EntryHook {
+    entry: String,
+    when: When,
+    command: OsString,
+}
+
+#[derive(Clone, Default)]
+pub struct Hooks {
+    pub pre_run: Vec<OsString>,
+    pub post_run: Vec<OsString>,
+    entries: Vec<EntryHook>,
+}
+
+const KEYWORDS: [&[u8]; 4] = [b"pre-run", b"post-run", b"pre", b"post"];
+
+// Splits `@keyword rest` off a hook line
+fn keyword(line: &[u8]) -> Option<(&[u8], &[u8])> {
+    let rest = line.trim_ascii().strip_prefix(b"@")?;
+    let end = rest
+        .iter()
+        .position(|&b| b.is_ascii_whitespace() || b == b'=')
+        .unwrap_or(rest.len());
+    let (name, rest) = rest.split_at(end);
+    KEYWORDS.contains(&name).then_some((name, rest))
+}
+
+pub fn is_hook(line: &[u8]) -> bool {
+    keyword(line).is_some()
+}
+
+fn parse_line(line: &[u8], hooks: &mut Hooks) -> io::Result<()> {
+    let Some((name, rest)) = keyword(line) else {
+        return Ok(());
+    };
+    let invalid = |expected: &str| {
+        io::Error::new(io::ErrorKind::InvalidInput, format!("expected {expected}"))
+    };
+    let (entry, rest) = match name {
+        b"pre" | b"post" => {
+            let (entry, rest) =
+                field(rest, b'=').ok_or_else(|| invalid("@post ENTRY = COMMAND"))?;
+            if entry.is_empty() {
+                return Err(invalid("@post ENTRY = COMMAND"));
+            }
+            (Some(String::from_utf8_lossy(entry).into_owned()), rest)
+        }
+        _ => (None, rest),
+    };
+    let command = rest
+        .trim_ascii_start()
+        .strip_prefix(b"=")
+        .and_then(|rest| field(rest, b'#'))
+        .map(|(command, _)| command)
+        .filter(|command| !command.is_empty())
+        .ok_or_else(|| invalid("= COMMAND"))?;
+    let command = bytes::to_os(command).into_owned();
+
+    match (name, entry) {
+        (b"pre-run", _) => hooks.pre_run.push(command),
+        (b"post-run", _) => hooks.post_run.push(command),
+        (name, Some(entry)) => hooks.entries.push(EntryHook {
+            entry,
+            when: if name == b"pre" {
+                When::Pre
+            } else {
+                When::Post
+            },
+            command,
+        }),
+        _ => unreachable!(),
+    }
+    Ok(())
+}
+
+// Reads every hook line of content, the text of the file at path
+pub fn parse(content: &[u8], path: &Path) -> io::Result<Hooks> {
+    let mut hooks = Hooks::default();
+    let mut unmet = false;
+    for (i, line) in bytes::lines(content).enumerate() {
+        let result = if section_header(line).is_some() {
+            unmet = false;
+            Ok(())
+        } else if is_hook(line) {
+            if unmet {
+                Ok(())
+            } else {
+                parse_line(line, &mut hooks)
+            }
+        } else if cond::is_directive(line) {
+            cond::evaluate(line).map(|reason| unmet = reason.is_some())
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{}:{}: {err}", path.display(), i + 1),
+            ));
+        }
+    }
+    Ok(hooks)
+}
+
+impl Hooks {
+    pub(crate) fn extend(&mut self, other: &Hooks) {
+        self.pre_run.extend(other.pre_run.iter().cloned());
+        self.post_run.extend(other.post_run.iter().cloned());
+        self.entries.extend(other.entries.iter().cloned());
+    }
+
+    // The entry hooks for op, by their index so a run can tell which ran
+    pub fn for_op<'a>(
+        &'a self,
+        op: &'a Operation,
+        cfg: &'a Config,
+        when: When,
+    ) -> impl Iterator<Item = (usize, &'a OsString)> {
+        self.entries
+            .iter()
+            .enumerate()
+            .filter(move |(_, hook)| hook.when == when && lookup::matches(op, cfg, &hook.entry))
+            .map(|(i, hook)| (i, &hook.command))
+    }
+}
+
+pub fn run(command: &OsString, what: &str, cfg: &Config) -> io::Result<()> {
+    let hook = format!("{what} hook `{}`", command.display());
+    let Some(status) = shell::status(&mut shell::command(command), &hook, cfg)? else {
+        return Ok(());
+    };
+    if !status.success() {
+        return Err(io::Error::other(format!(
+            "{what} hook `{}` exited with {status}",
+            command.display()
+        )));
+    }
+    Ok(())
+}
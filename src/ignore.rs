@@ -0,0 +1,145 @@
+// Files in the dotfiles that are never linked where neostow walks a
+// directory: the children of managed and unfolded directories, and the
+// matches of a source pattern. Patterns come from @ignore lines,
+//   @ignore README.md *.swp .git/
+// and from .neostowignore files, which take gitignore syntax: a pattern
+// with no / in it matches a name at any depth, one with a / matches the
+// path from the directory of the file, a trailing / only matches
+// directories and a leading ! takes a path back in. A .neostowignore
+// applies to its own directory and everything below it, and @ignore lines
+// to the whole dotfiles directory. Like entries, @ignore lines follow
+// @host/@os/@env conditions. A source named outright is always linked.
+
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use crate::expand::normalize;
+use crate::reload::field;
+use crate::{Config, bytes, cond, glob, section_header};
+
+pub(crate) const IGNORE_FILE: &str = ".neostowignore";
+
+// The patterns of an @ignore line
+pub fn parse_line(line: &[u8]) -> Option<&[u8]> {
+    let rest = line.trim_ascii().strip_prefix(b"@ignore")?;
+    if !rest.first().is_some_and(u8::is_ascii_whitespace) {
+        return None;
+    }
+    field(rest, b'#').map(|(patterns, _)| patterns)
+}
+
+pub fn parse(content: &[u8], path: &Path) -> io::Result<Vec<Vec<u8>>> {
+    let mut patterns = Vec::new();
+    let mut unmet = false;
+    for (i, line) in bytes::lines(content).enumerate() {
+        let result = if section_header(line).is_some() {
+            unmet = false;
+            Ok(())
+        } else if let Some(raw) = parse_line(line) {
+            if !unmet {
+                patterns.extend(
+                    raw.split(u8::is_ascii_whitespace)
+                        .filter(|p| !p.is_empty())
+                        .map(<[u8]>::to_vec),
+                );
+            }
+            Ok(())
+        } else if cond::is_directive(line) {
+            cond::evaluate(line).map(|reason| unmet = reason.is_some())
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{}:{}: {err}", path.display(), i + 1),
+            ));
+        }
+    }
+    Ok(patterns)
+}
+
+fn read_ignore_file(dir: &Path) -> Vec<Vec<u8>> {
+    let Ok(content) = fs::read(dir.join(IGNORE_FILE)) else {
+        return Vec::new();
+    };
+    bytes::lines(&content)
+        .map(<[u8]>::trim_ascii)
+        .filter(|line| !line.is_empty() && !line.starts_with(b"#"))
+        .map(<[u8]>::to_vec)
+        .collect()
+}
+
+// Whether the last of patterns to match path, relative to their directory,
+// ignores it
+fn decide(patterns: &[Vec<u8>], path: &[u8], is_dir: bool, ignored: &mut bool) {
+    let name = path.rsplit(|&b| b == b'/').next().unwrap_or(path);
+    for pattern in patterns {
+        let (negated, pattern) = match pattern.strip_prefix(b"!") {
+            Some(rest) => (true, rest),
+            None => (false, &pattern[..]),
+        };
+        let dir_only = pattern.ends_with(b"/");
+        let pattern = pattern.strip_suffix(b"/").unwrap_or(pattern);
+        if dir_only && !is_dir {
+            continue;
+        }
+        let hit = if bytes::find(pattern, b'/').is_some() {
+            glob::matches(pattern.strip_prefix(b"/").unwrap_or(pattern), path)
+        } else {
+            glob::matches(pattern, name)
+        };
+        if hit {
+            *ignored = !negated;
+        }
+    }
+}
+
+fn relative(cfg: &Config, src: &Path) -> Option<PathBuf> {
+    if let Ok(rel) = src.strip_prefix(&cfg.basedir) {
+        return Some(rel.to_path_buf());
+    }
+    let absolute = |path: &Path| std::path::absolute(path).map(|path| normalize(&path));
+    let (src, base) = (absolute(src).ok()?, absolute(&cfg.basedir).ok()?);
+    src.strip_prefix(base).ok().map(Path::to_path_buf)
+}
+
+/// Whether src, a path in the dotfiles directory, is left out by the
+/// @ignore patterns or a .neostowignore above it. Inside an ignored
+/// directory everything is, as with git.
+pub fn ignored(cfg: &Config, src: &Path, is_dir: bool) -> bool {
+    if src.file_name().is_some_and(|name| name == IGNORE_FILE) {
+        return true;
+    }
+    let Some(rel) = relative(cfg, src) else {
+        return false;
+    };
+    let names: Vec<Vec<u8>> = rel
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => Some(bytes::from_os(name).into_owned()),
+            _ => None,
+        })
+        .collect();
+
+    // The patterns of each directory on the way down, from the top
+    let mut levels = vec![[cfg.ignores.clone(), read_ignore_file(&cfg.basedir)].concat()];
+    let mut dir = cfg.basedir.clone();
+    for (depth, name) in names.iter().enumerate() {
+        let last = depth + 1 == names.len();
+        let mut ignored = false;
+        for (level, patterns) in levels.iter().enumerate() {
+            let path = names[level..=depth].join(&b'/');
+            decide(patterns, &path, is_dir || !last, &mut ignored);
+        }
+        if ignored {
+            return true;
+        }
+        if !last {
+            dir.push(bytes::to_os(name));
+            levels.push(read_ignore_file(&dir));
+        }
+    }
+    false
+}
@@ -0,0 +1,176 @@
+// `neostow import-stow` turns a GNU Stow directory into a .neostow. Each
+// package, a directory mirroring the target tree, becomes a [package]
+// section. Stow folds a directory into one link until another package needs
+// it too, so the entries follow what is in the target: a real directory there
+// is descended into, anything else is linked whole, as Stow would. Names
+// written dot-NAME for `stow --dotfiles` get a [transform] rule.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::expand::normalize;
+use crate::init::portable;
+use crate::{Config, LogLevel, journal, platform, prompt_user};
+
+// What Stow never links, besides README, LICENSE and COPYING at the top of
+// a package
+fn ignored(name: &str, top: bool) -> bool {
+    matches!(
+        name,
+        ".git" | ".gitignore" | ".gitmodules" | ".hg" | ".svn" | "CVS" | ".stow-local-ignore"
+    ) || name.ends_with('~')
+        || (top
+            && ["README", "LICENSE", "COPYING"]
+                .iter()
+                .any(|p| name.starts_with(p)))
+}
+
+// The name a package item gets in the target
+fn target_name(name: &str) -> String {
+    match name.strip_prefix("dot-") {
+        Some(rest) => format!(".{rest}"),
+        None => name.to_string(),
+    }
+}
+
+fn sorted(dir: &Path) -> io::Result<Vec<(String, bool)>> {
+    let mut items = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        items.push((name, entry.file_type()?.is_dir()));
+    }
+    items.sort();
+    Ok(items)
+}
+
+// The (source, target directory, destination) of each item under src, a
+// directory of the package mirroring dest
+fn entries(
+    src: &Path,
+    rel: &Path,
+    dest: &Path,
+    out: &mut Vec<(PathBuf, PathBuf, PathBuf)>,
+) -> io::Result<()> {
+    for (name, is_dir) in sorted(&src.join(rel))? {
+        if ignored(&name, rel.as_os_str().is_empty()) {
+            continue;
+        }
+        let item = dest.join(target_name(&name));
+        let real_dir = item
+            .symlink_metadata()
+            .is_ok_and(|meta| meta.is_dir() && !meta.file_type().is_symlink());
+        if is_dir && real_dir {
+            entries(src, &rel.join(&name), &item, out)?;
+        } else {
+            out.push((rel.join(&name), dest.to_path_buf(), item));
+        }
+    }
+    Ok(())
+}
+
+/// Writes a .neostow into stow_dir with the entries of its packages linked
+/// into target, and with remove_links, removes the links Stow made there so
+/// neostow can make its own.
+pub fn stow(stow_dir: &Path, target: &Path, remove_links: bool, cfg: &Config) -> io::Result<()> {
+    let stow_dir = fs::canonicalize(stow_dir)?;
+    let target = fs::canonicalize(target)?;
+    let home = platform::home();
+
+    let mut out = Vec::new();
+    writeln!(
+        out,
+        "# Imported by neostow import-stow from the packages of {}",
+        stow_dir.display()
+    )?;
+    let mut links = Vec::new();
+    let mut dotfiles = false;
+    let mut count = 0;
+    for (package, is_dir) in sorted(&stow_dir)? {
+        if !is_dir || package.starts_with('.') {
+            continue;
+        }
+        let mut found = Vec::new();
+        entries(&stow_dir.join(&package), Path::new(""), &target, &mut found)?;
+        if found.is_empty() {
+            continue;
+        }
+        writeln!(out, "\n[{package}]")?;
+        for (rel, dir, dest) in found {
+            dotfiles |= rel
+                .iter()
+                .any(|name| name.to_string_lossy().starts_with("dot-"));
+            let src = Path::new(&package).join(&rel);
+            let slash = if stow_dir.join(&src).is_dir() {
+                "/"
+            } else {
+                ""
+            };
+            writeln!(out, "{}{slash}={}/", src.display(), portable(&dir, &home))?;
+            count += 1;
+            // Only links into the stow directory are Stow's to take away
+            if let Ok(link) = fs::read_link(&dest)
+                && normalize(&dest.parent().unwrap_or(&target).join(link)).starts_with(&stow_dir)
+            {
+                links.push(dest);
+            }
+        }
+    }
+    if dotfiles {
+        writeln!(out, "\n[transform]\n\"^dot-\" = \".\"")?;
+    }
+
+    let file = stow_dir.join(".neostow");
+    if cfg.dry {
+        io::stdout().write_all(&out)?;
+    } else {
+        if file.exists()
+            && !cfg.force
+            && !prompt_user(&format!("{} already exists. Replace it?", file.display()))?
+        {
+            return Ok(());
+        }
+        fs::write(&file, out)?;
+        printfc!(
+            LogLevel::Info,
+            "Wrote {} with {count} entries",
+            file.display()
+        );
+    }
+
+    if !remove_links {
+        if !links.is_empty() {
+            printfc!(
+                LogLevel::Info,
+                "{} links Stow made are still in place, remove them with --remove-links or stow -D",
+                links.len()
+            );
+        }
+        return Ok(());
+    }
+    let mut removed = 0;
+    for link in &links {
+        if cfg.dry {
+            printfc!(LogLevel::Info, "Would remove {}", link.display());
+            continue;
+        }
+        match journal::remove(&*cfg.fs, link) {
+            Ok(()) => removed += 1,
+            Err(err) => {
+                printfc!(LogLevel::Error, "{}: {err}", link.display());
+            }
+        }
+    }
+    if !cfg.dry {
+        journal::save(&journal::take())?;
+        printfc!(
+            LogLevel::Info,
+            "Removed {removed} links Stow made, run neostow -f {} to link them again",
+            file.display()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,306 @@
+// `@include PATH` pulls the entries of another neostow file in at that line,
+// so a top-level file can compose one file per application or package:
+//   @include nvim/.neostow
+//   [work]
+//   @include ~/work-dots/.neostow
+// A relative PATH is resolved against the directory of the including file,
+// and the included file's sources against its own directory. An include
+// inside a package section is only followed when that package is selected,
+// and entries outside any section of the included file then belong to it.
+// An include can also fill in `{NAME}` placeholders, so one file serves as a
+// template for several applications:
+//   @include lib/xdg-app.neostow with name=nvim
+//   @include lib/xdg-app.neostow with name=helix dir="Helix Editor"
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::expand::{expand_path, normalize};
+use crate::hooks::Hooks;
+use crate::{Config, LogLevel, Operation, Parser, age, bytes, reload, section_header, transform};
+
+pub type Params = Vec<(Vec<u8>, Vec<u8>)>;
+
+// What follows @include on an include line
+pub fn parse(line: &[u8]) -> Option<&[u8]> {
+    let rest = line.trim_ascii().strip_prefix(b"@include")?;
+    if !rest.first().is_some_and(u8::is_ascii_whitespace) {
+        return None;
+    }
+    Some(rest.trim_ascii())
+}
+
+// The words of s up to a comment, split at whitespace outside quotes, with
+// the quotes removed
+pub(crate) fn words(s: &[u8]) -> io::Result<Vec<Vec<u8>>> {
+    let mut words = Vec::new();
+    let mut word: Option<Vec<u8>> = None;
+    let mut quote = None;
+    for &b in s {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => word.get_or_insert_default().push(b),
+            None if b == b'"' || b == b'\'' => {
+                quote = Some(b);
+                word.get_or_insert_default();
+            }
+            None if b.is_ascii_whitespace() => words.extend(word.take()),
+            None if b == b'#' => break,
+            None => word.get_or_insert_default().push(b),
+        }
+    }
+    if quote.is_some() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unterminated quote",
+        ));
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+// The path of an include and the placeholders it fills
+fn spec(raw: &[u8]) -> io::Result<(Vec<u8>, Params)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected @include PATH [with NAME=VALUE...]",
+        )
+    };
+    let mut words = words(raw)?.into_iter();
+    let path = words
+        .next()
+        .filter(|path| !path.is_empty())
+        .ok_or_else(invalid)?;
+    let mut params = Params::new();
+    match words.next() {
+        None => {}
+        Some(with) if with == b"with" => {
+            for word in words {
+                let (name, value) = bytes::split_once(&word, b'=')
+                    .filter(|(name, _)| !name.is_empty())
+                    .ok_or_else(invalid)?;
+                params.push((name.to_vec(), value.to_vec()));
+            }
+            if params.is_empty() {
+                return Err(invalid());
+            }
+        }
+        Some(_) => return Err(invalid()),
+    }
+    Ok((path, params))
+}
+
+// Replaces each `{NAME}` given in params. Other braces, as in globs, and
+// `${VAR}` are left alone.
+pub fn fill(params: &Params, content: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(open) = bytes::find(rest, b'{') {
+        out.extend_from_slice(&rest[..open]);
+        let after = &rest[open + 1..];
+        let value = bytes::find(after, b'}')
+            .filter(|_| open == 0 || rest[open - 1] != b'$')
+            .and_then(|close| {
+                let (_, value) = params.iter().find(|(name, _)| **name == after[..close])?;
+                Some((value, close))
+            });
+        match value {
+            Some((value, close)) => {
+                out.extend_from_slice(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                out.push(b'{');
+                rest = after;
+            }
+        }
+    }
+    out.extend_from_slice(rest);
+    out
+}
+
+// The content of a loaded file, with its placeholders filled
+pub fn read(cfg: &Config) -> io::Result<Vec<u8>> {
+    let content = age::read(&cfg.file)?;
+    if cfg.params.is_empty() {
+        return Ok(content);
+    }
+    Ok(fill(&cfg.params, &content))
+}
+
+// An included file is named by where it was included from, so errors point
+// at the line to fix
+fn at(cfg: &Config, linenum: usize, err: io::Error) -> io::Error {
+    io::Error::new(
+        err.kind(),
+        format!("{}:{linenum}: {err}", cfg.file.display()),
+    )
+}
+
+fn resolve(cfg: &Config, raw: &[u8]) -> io::Result<PathBuf> {
+    let dir = cfg.file.parent().unwrap_or(Path::new("."));
+    Ok(normalize(&dir.join(expand_path(raw, &cfg.vars)?)))
+}
+
+// The path of each @include in content, the text of cfg's file, as written
+// and as it resolves, whether it is followed or not
+pub(crate) fn paths(cfg: &Config, content: &[u8]) -> io::Result<Vec<(Vec<u8>, PathBuf)>> {
+    let mut paths = Vec::new();
+    for (i, line) in bytes::lines(content).enumerate() {
+        let Some(raw) = parse(line) else {
+            continue;
+        };
+        let (written, _) = spec(raw).map_err(|err| at(cfg, i + 1, err))?;
+        let path = resolve(cfg, &written).map_err(|err| at(cfg, i + 1, err))?;
+        paths.push((written, path));
+    }
+    Ok(paths)
+}
+
+// Loads the files content includes, the text of cfg's file, each with its own
+// variables and rules on top of its includer's. section is the package the
+// content starts in, stack the files being included, outermost first, and
+// sections collects every package section met on the way.
+pub fn load(
+    cfg: &Config,
+    content: &[u8],
+    section: Option<Vec<u8>>,
+    stack: &mut Vec<PathBuf>,
+    sections: &mut Vec<Vec<u8>>,
+) -> io::Result<Vec<(usize, Config)>> {
+    let mut parser = Parser::within(&cfg.packages, section).without(&cfg.disabled);
+    let mut includes = Vec::new();
+    for (i, line) in bytes::lines(content).enumerate() {
+        if let Some(name) = section_header(line) {
+            sections.push(name.trim_ascii().to_vec());
+        }
+        parser.parse(line);
+        let Some(raw) = parse(line).filter(|_| parser.included()) else {
+            continue;
+        };
+        let (path, params) = spec(raw)
+            .and_then(|(path, params)| Ok((resolve(cfg, &path)?, params)))
+            .map_err(|err| at(cfg, i + 1, err))?;
+        let canonical = fs::canonicalize(&path).map_err(|err| {
+            at(
+                cfg,
+                i + 1,
+                io::Error::new(
+                    err.kind(),
+                    format!("cannot include {}: {err}", path.display()),
+                ),
+            )
+        })?;
+        if stack.contains(&canonical) {
+            let chain: Vec<String> = stack
+                .iter()
+                .chain([&canonical])
+                .map(|p| p.display().to_string())
+                .collect();
+            return Err(at(
+                cfg,
+                i + 1,
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("include cycle: {}", chain.join(" -> ")),
+                ),
+            ));
+        }
+
+        let mut child = Config {
+            basedir: path.parent().map(PathBuf::from).unwrap_or_default(),
+            file: path,
+            includes: Vec::new(),
+            params,
+            ..cfg.clone()
+        };
+        let raw_content = age::read(&child.file).map_err(|err| at(cfg, i + 1, err))?;
+        // A misspelt name would otherwise leave the placeholder in silently
+        for (name, _) in &child.params {
+            let placeholder = [b"{", name.as_slice(), b"}"].concat();
+            if !raw_content
+                .windows(placeholder.len())
+                .any(|w| w == placeholder)
+            {
+                printfc!(
+                    LogLevel::Warn,
+                    "{}:{}: {} has no {} placeholder",
+                    cfg.file.display(),
+                    i + 1,
+                    child.file.display(),
+                    String::from_utf8_lossy(&placeholder)
+                );
+            }
+        }
+        let child_content = fill(&child.params, &raw_content);
+        child.load_rules(&child_content)?;
+        stack.push(canonical);
+        child.includes = load(
+            &child,
+            &child_content,
+            parser.section().map(<[u8]>::to_vec),
+            stack,
+            sections,
+        )?;
+        stack.pop();
+        includes.push((i + 1, child));
+    }
+    Ok(includes)
+}
+
+// Every file's rules and unmanaged paths apply to the whole run, so those of
+// all included files are gathered and handed to each of them, the top-level
+// file's first
+pub fn merge(cfg: &mut Config) {
+    type Rules = (Vec<reload::Rule>, Vec<transform::Rule>, Hooks, Vec<PathBuf>);
+    fn gather(cfg: &Config, all: &mut Rules) {
+        for (_, child) in &cfg.includes {
+            all.0.extend(child.reloads.iter().cloned());
+            all.1.extend(child.transforms.iter().cloned());
+            all.2.extend(&child.hooks);
+            all.3.extend(child.unmanaged.iter().cloned());
+            gather(child, all);
+        }
+    }
+    fn hand_down(cfg: &mut Config, all: &Rules) {
+        cfg.reloads = all.0.clone();
+        cfg.transforms = all.1.clone();
+        cfg.hooks = all.2.clone();
+        cfg.unmanaged = all.3.clone();
+        for (_, child) in &mut cfg.includes {
+            hand_down(child, all);
+        }
+    }
+
+    if cfg.includes.is_empty() {
+        return;
+    }
+    let mut all = (
+        cfg.reloads.clone(),
+        cfg.transforms.clone(),
+        cfg.hooks.clone(),
+        cfg.unmanaged.clone(),
+    );
+    gather(cfg, &mut all);
+    hand_down(cfg, &all);
+}
+
+// The loaded file included at linenum
+pub fn child(cfg: &Config, linenum: usize) -> Option<&Config> {
+    cfg.includes
+        .iter()
+        .find(|(line, _)| *line == linenum)
+        .map(|(_, child)| child)
+}
+
+// Records that ops were planned from the file child, unless they come from
+// a file it includes in turn
+pub fn mark(ops: &mut [(usize, Operation)], child: &Config) {
+    let origin: Arc<Path> = Arc::from(child.file.as_path());
+    for (_, op) in ops {
+        op.origin.get_or_insert_with(|| origin.clone());
+    }
+}
@@ -0,0 +1,176 @@
+// Writes a starter .neostow for a directory of dotfiles. Every top-level item
+// gets a commented-out mapping to review, and with from_links, links already
+// pointing into the directory are turned back into the entries behind them.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::expand::normalize;
+use crate::{LogLevel, format, platform, prompt_user, suggest};
+
+// How deep to look for links under the from_links directory
+const MAX_DEPTH: usize = 4;
+
+// Links under dir that point into basedir, as (link, target) pairs
+fn links_into(dir: &Path, basedir: &Path, depth: usize, found: &mut Vec<(PathBuf, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(kind) = entry.file_type() else {
+            continue;
+        };
+        if kind.is_symlink() {
+            let Ok(target) = fs::read_link(&path) else {
+                continue;
+            };
+            let target = normalize(&dir.join(target));
+            if target.starts_with(basedir) {
+                found.push((path, target));
+            }
+        } else if kind.is_dir() && depth < MAX_DEPTH && path != basedir {
+            links_into(&path, basedir, depth + 1, found);
+        }
+    }
+}
+
+// $HOME/... for paths under the home directory, so the file stays portable
+pub(crate) fn portable(path: &Path, home: &Path) -> String {
+    match path.strip_prefix(home) {
+        Ok(rest) if rest.as_os_str().is_empty() => "$HOME".to_string(),
+        Ok(rest) => format!("$HOME/{}", rest.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+// The entries of the existing links, naming the link itself where it is
+// named differently from its source
+fn from_links(dir: &Path, basedir: &Path, home: &Path) -> (Vec<String>, Vec<PathBuf>) {
+    let mut links = Vec::new();
+    links_into(dir, basedir, 0, &mut links);
+    links.sort();
+
+    let mut lines = Vec::new();
+    let mut covered = Vec::new();
+    for (link, target) in links {
+        let Ok(src) = target.strip_prefix(basedir) else {
+            continue;
+        };
+        let parent = link.parent().unwrap_or(Path::new("/"));
+        if link.file_name() == target.file_name() {
+            lines.push(format!("{}={}/", src.display(), portable(parent, home)));
+        } else {
+            lines.push(format!("{} -> {}", src.display(), portable(&link, home)));
+        }
+        covered.push(src.to_path_buf());
+    }
+    (lines, covered)
+}
+
+pub fn init(dir: &Path, links_dir: Option<&Path>) -> io::Result<()> {
+    let basedir = fs::canonicalize(dir)?;
+    let file = basedir.join(".neostow");
+    if file.exists() && !prompt_user(&format!("{} already exists. Replace it?", file.display()))? {
+        return Ok(());
+    }
+
+    let mut out = Vec::new();
+    writeln!(
+        out,
+        "# Generated by neostow init. Commented mappings are proposals:"
+    )?;
+    writeln!(
+        out,
+        "# check their destinations, then uncomment the ones to keep."
+    )?;
+    let mut covered = Vec::new();
+    let mut linked_entries = 0;
+    if let Some(links_dir) = links_dir {
+        let links_dir = fs::canonicalize(links_dir)?;
+        let (lines, linked) = from_links(&links_dir, &basedir, &platform::home());
+        linked_entries = lines.len();
+        if !lines.is_empty() {
+            writeln!(out, "\n# Already linked under {}", links_dir.display())?;
+            for line in &lines {
+                writeln!(out, "{line}")?;
+            }
+        }
+        covered = linked;
+    }
+
+    let mut proposed = 0;
+    for (name, is_dir) in suggest::candidates(&basedir)? {
+        // Items with a link already have their entry
+        if covered.iter().any(|src| src.starts_with(&name)) {
+            continue;
+        }
+        if proposed == 0 {
+            writeln!(out)?;
+        }
+        let src = if is_dir {
+            format!("{name}/")
+        } else {
+            name.clone()
+        };
+        writeln!(out, "# {src}={}", suggest::propose(&name))?;
+        proposed += 1;
+    }
+
+    fs::write(&file, out)?;
+    printfc!(
+        LogLevel::Info,
+        "Wrote {} with {} entries from links and {proposed} to review",
+        file.display(),
+        linked_entries
+    );
+    Ok(())
+}
+
+const TEMPLATE: &str = "\
+# Each line links a source, relative to this directory, into a directory:
+#   zshrc=$HOME/
+# With -> instead of =, the right side is the link itself:
+#   bashrc -> $HOME/.bashrc
+# Entries under a [package] header apply with `neostow PACKAGE`:
+#   [nvim]
+#   nvim/ -> $HOME/.config/nvim
+";
+
+const TOML_TEMPLATE: &str = "\
+# Each [[entry]] links a source, relative to this directory, into a
+# directory, or with link, to that path:
+#   [[entry]]
+#   src = \"zshrc\"
+#   dest = \"$HOME/\"
+#
+#   [[entry]]
+#   src = \"nvim/\"
+#   link = \"$HOME/.config/nvim\"
+#   package = \"nvim\"
+";
+
+const YAML_TEMPLATE: &str = "\
+# Each item of entries links a source, relative to this directory, into a
+# directory, or with link, to that path:
+#   entries:
+#     - src: zshrc
+#       dest: $HOME/
+#     - src: nvim/
+#       link: $HOME/.config/nvim
+#       package: nvim
+";
+
+/// Writes a neostow file of comments showing how entries are written, in
+/// the format of its name, for `edit` to start from.
+pub fn template(file: &Path) -> io::Result<()> {
+    let template = match format::kind(file) {
+        format::Kind::Plain => TEMPLATE,
+        format::Kind::Toml => TOML_TEMPLATE,
+        format::Kind::Yaml => YAML_TEMPLATE,
+    };
+    fs::write(file, template)?;
+    printfc!(LogLevel::Info, "Created {}", file.display());
+    Ok(())
+}
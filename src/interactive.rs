@@ -0,0 +1,77 @@
+use std::io;
+
+use crate::{Config, LogLevel, Mode, Operation, report, run_diff, status};
+
+// What to do with a destination that is in the way. Answering in capitals
+// applies the same choice to every later conflict of the run.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Choice {
+    Overwrite,
+    Skip,
+    Adopt,
+    Abort,
+}
+
+// A destination is in the way when something other than the entry's own
+// link is there. Managed and unfolded directories are real by design.
+pub fn in_the_way(op: &Operation, cfg: &Config) -> bool {
+    if matches!(cfg.mode, Mode::Delete) || op.managed || (op.is_dir && cfg.no_folding) {
+        return false;
+    }
+    op.dest.symlink_metadata().is_ok() && !status::op_in_place(op, cfg).unwrap_or(false)
+}
+
+const CHOICES: [&str; 8] = ["o", "s", "a", "d", "q", "O", "S", "A"];
+
+pub fn ask(op: &Operation, remembered: &mut Option<Choice>) -> io::Result<Choice> {
+    let is_link = op.dest.is_symlink();
+    match *remembered {
+        // Links found after "adopt all" still need an answer of their own
+        Some(Choice::Adopt) if is_link => {}
+        Some(choice) => return Ok(choice),
+        None => {}
+    }
+    // Nobody to ask overwrites with --assume-yes and skips otherwise
+    if let Some(yes) = report::assumed() {
+        return Ok(if yes { Choice::Overwrite } else { Choice::Skip });
+    }
+    loop {
+        let question = match &op.desc {
+            Some(desc) => format!(
+                "{} is in the way of {desc} ({})",
+                op.dest.display(),
+                op.src.display()
+            ),
+            None => format!(
+                "{} is in the way of {}",
+                op.dest.display(),
+                op.src.display()
+            ),
+        };
+        let hint = "\n[o]verwrite, [s]kip, [a]dopt, view [d]iff, [q]uit (O/S/A for all): ";
+        // The end of input leaves nobody to answer
+        let Some(input) = report::ask(&question, hint, &CHOICES)? else {
+            return Ok(Choice::Abort);
+        };
+        let input = input.as_str();
+        let choice = match input.to_lowercase().as_str() {
+            "o" | "overwrite" => Choice::Overwrite,
+            "s" | "skip" => Choice::Skip,
+            "a" | "adopt" if is_link => {
+                printfc!(LogLevel::Warn, "Only real files can be adopted, not links");
+                continue;
+            }
+            "a" | "adopt" => Choice::Adopt,
+            "q" | "quit" => Choice::Abort,
+            "d" | "diff" => {
+                run_diff(&op.src, &op.dest)?;
+                continue;
+            }
+            _ => continue,
+        };
+        if input.len() == 1 && input.chars().all(|c| c.is_ascii_uppercase()) {
+            *remembered = Some(choice);
+        }
+        return Ok(choice);
+    }
+}
@@ -0,0 +1,349 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+#[cfg(windows)]
+use std::os::windows::fs::{symlink_dir, symlink_file};
+
+use crate::vfs::{Fs, Node, RealFs};
+use crate::{LinkMode, backup, bytes, fsops, state, store};
+
+const JOURNAL_FILE: &str = "journal";
+const STASH_DIR: &str = "stash";
+
+// Every change a run makes to the filesystem, in order, so the run can be
+// rolled back when it fails midway or undone afterwards
+pub enum Change {
+    Created(PathBuf),
+    // A copy or hard link tree made in place of a link
+    Copied(PathBuf),
+    // A link that was removed, with the target it pointed to
+    Unlinked(PathBuf, PathBuf),
+    // A real file or directory moved aside, with where it went
+    Stashed(PathBuf, PathBuf),
+    // A kept original or backup moved back to its destination, with where
+    // it was kept
+    Reinstated(PathBuf, PathBuf),
+    // An empty directory neostow had made, and removed again
+    RemovedDir(PathBuf),
+}
+
+fn changes() -> &'static Mutex<Vec<Change>> {
+    static CHANGES: OnceLock<Mutex<Vec<Change>>> = OnceLock::new();
+    CHANGES.get_or_init(Default::default)
+}
+
+pub fn record(change: Change) {
+    changes()
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(change);
+}
+
+pub fn take() -> Vec<Change> {
+    std::mem::take(&mut *changes().lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+// Removed files are kept per run, so only the latest run can be undone
+fn run_stash() -> &'static PathBuf {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        state::host_dir().join(STASH_DIR).join(format!(
+            "{}-{}",
+            backup::timestamp(),
+            std::process::id()
+        ))
+    })
+}
+
+#[cfg(unix)]
+pub(crate) fn make_link(src: &Path, dest: &Path, _is_dir: bool) -> io::Result<()> {
+    symlink(src, dest)
+}
+
+// ERROR_PRIVILEGE_NOT_HELD, from symlinks without developer mode or admin
+const PRIVILEGE_NOT_HELD: i32 = 1314;
+
+fn needs_privilege(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(PRIVILEGE_NOT_HELD) || err.kind() == io::ErrorKind::PermissionDenied
+}
+
+// Directories fall back to a junction, which needs no privilege. Junctions
+// take an absolute target, while the link's is relative to its directory.
+#[cfg(windows)]
+pub(crate) fn make_link(src: &Path, dest: &Path, is_dir: bool) -> io::Result<()> {
+    if !is_dir {
+        return symlink_file(src, dest);
+    }
+    match symlink_dir(src, dest) {
+        Err(err) if needs_privilege(&err) => {
+            let target = std::path::absolute(dest.parent().unwrap_or(Path::new("")).join(src))?;
+            let status = std::process::Command::new("cmd")
+                .arg("/C")
+                .arg("mklink")
+                .arg("/J")
+                .arg(dest)
+                .arg(target)
+                .stdout(std::process::Stdio::null())
+                .status()?;
+            if status.success() { Ok(()) } else { Err(err) }
+        }
+        result => result,
+    }
+}
+
+// Planning also runs where there are no symlinks (wasm32), for previews
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn make_link(src: &Path, dest: &Path, _is_dir: bool) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "cannot link {} to {}: no symlinks on this platform",
+            src.display(),
+            dest.display()
+        ),
+    ))
+}
+
+pub fn link(fs: &dyn Fs, src: &Path, dest: &Path, is_dir: bool, mode: LinkMode) -> io::Result<()> {
+    if mode == LinkMode::Symlink {
+        match fs.symlink(src, dest, is_dir) {
+            Ok(()) => record(Change::Created(dest.to_path_buf())),
+            // Files fall back to a hard link where symlinks need privileges,
+            // which is undone like a copy
+            Err(err) if cfg!(windows) && !is_dir && needs_privilege(&err) => {
+                std::fs::hard_link(src, dest).map_err(|_| err)?;
+                record(Change::Copied(dest.to_path_buf()));
+            }
+            Err(err) => return Err(err),
+        }
+        return Ok(());
+    }
+    vacant(fs, dest)?;
+    if let Err(err) = fs.copy(src, dest, mode == LinkMode::Hardlink) {
+        // Nothing half copied is left behind
+        let _ = fs.remove(dest);
+        return Err(err);
+    }
+    record(Change::Copied(dest.to_path_buf()));
+    Ok(())
+}
+
+// Unlike making a link, copying would write over what is already there
+fn vacant(fs: &dyn Fs, dest: &Path) -> io::Result<()> {
+    if fs.metadata(dest).is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dest.display()),
+        ));
+    }
+    Ok(())
+}
+
+// Copies src to dest through the store, for !dedup, undone like a copy. The
+// store is always on the real filesystem.
+pub fn share(src: &Path, dest: &Path) -> io::Result<()> {
+    let fs = &RealFs;
+    vacant(fs, dest)?;
+    if let Err(err) = store::link_tree(src, dest) {
+        let _ = fs.remove(dest);
+        return Err(err);
+    }
+    record(Change::Copied(dest.to_path_buf()));
+    Ok(())
+}
+
+// Writes content to a new file at dest, undone like a copy
+pub fn write(fs: &dyn Fs, dest: &Path, content: &[u8]) -> io::Result<()> {
+    fs.create(dest, content).map_err(|err| match err.kind() {
+        io::ErrorKind::AlreadyExists => io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} already exists", dest.display()),
+        ),
+        _ => err,
+    })?;
+    record(Change::Copied(dest.to_path_buf()));
+    Ok(())
+}
+
+// Clears dest for a new link. Links are simply removed, while real files and
+// directories are stashed rather than deleted, so they can still be restored.
+pub fn remove(fs: &dyn Fs, dest: &Path) -> io::Result<()> {
+    let Ok(node) = fs.metadata(dest) else {
+        return Ok(());
+    };
+    if let Node::Link(target) = node {
+        fs.remove(dest)?;
+        record(Change::Unlinked(dest.to_path_buf(), target));
+        return Ok(());
+    }
+    let absolute = std::path::absolute(dest)?;
+    let mut stash = run_stash().join(
+        absolute
+            .components()
+            .filter(|c| matches!(c, std::path::Component::Normal(_)))
+            .collect::<PathBuf>(),
+    );
+    // One process can stash a destination twice, as selftest's runs do
+    let base = stash.clone().into_os_string();
+    let mut n = 1;
+    while fs.metadata(&stash).is_ok() {
+        let mut numbered = base.clone();
+        numbered.push(format!(".{n}"));
+        stash = PathBuf::from(numbered);
+        n += 1;
+    }
+    fsops::move_path(fs, dest, &stash)?;
+    record(Change::Stashed(dest.to_path_buf(), stash));
+    Ok(())
+}
+
+// Reverts changes newest first, carrying on past errors so that as much as
+// possible is put back; returns how many were reverted
+pub fn revert(fs: &dyn Fs, changes: &[Change]) -> (usize, Vec<io::Error>) {
+    let mut reverted = 0;
+    let mut errors = Vec::new();
+    for change in changes.iter().rev() {
+        let is_link = |dest| matches!(fs.metadata(dest), Ok(Node::Link(_)));
+        let result = match change {
+            Change::Created(dest) => {
+                if is_link(dest) {
+                    fs.remove(dest)
+                } else {
+                    Ok(())
+                }
+            }
+            Change::Copied(dest) => {
+                if fs.metadata(dest).is_ok() {
+                    fs.remove(dest)
+                } else {
+                    Ok(())
+                }
+            }
+            Change::Unlinked(dest, target) => {
+                if fs.metadata(dest).is_ok() {
+                    fs.remove(dest).and_then(|()| link_back(fs, target, dest))
+                } else {
+                    link_back(fs, target, dest)
+                }
+            }
+            Change::Stashed(dest, stash) => {
+                let cleared = if is_link(dest) {
+                    fs.remove(dest)
+                } else {
+                    Ok(())
+                };
+                cleared.and_then(|()| fsops::move_path(fs, stash, dest))
+            }
+            Change::Reinstated(dest, kept) => fsops::move_path(fs, dest, kept),
+            Change::RemovedDir(dir) => fs.create_dir_all(dir),
+        };
+        match result {
+            Ok(()) => reverted += 1,
+            Err(err) => errors.push(err),
+        }
+    }
+    (reverted, errors)
+}
+
+fn link_back(fs: &dyn Fs, target: &Path, dest: &Path) -> io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs.create_dir_all(parent)?;
+    }
+    // Only Windows needs to know, and the target is relative to the link
+    let is_dir = cfg!(windows) && fs.is_dir(&dest.parent().unwrap_or(Path::new("")).join(target));
+    fs.symlink(target, dest, is_dir)
+}
+
+fn encode(change: &Change) -> Vec<u8> {
+    let (kind, dest, other) = match change {
+        Change::Created(dest) => ("created", dest, None),
+        Change::Copied(dest) => ("copied", dest, None),
+        Change::Unlinked(dest, target) => ("unlinked", dest, Some(target)),
+        Change::Stashed(dest, stash) => ("stashed", dest, Some(stash)),
+        Change::Reinstated(dest, kept) => ("reinstated", dest, Some(kept)),
+        Change::RemovedDir(dir) => ("rmdir", dir, None),
+    };
+    let mut line = kind.as_bytes().to_vec();
+    line.push(b'\t');
+    line.extend_from_slice(&bytes::from_os(dest.as_os_str()));
+    if let Some(other) = other {
+        line.push(b'\t');
+        line.extend_from_slice(&bytes::from_os(other.as_os_str()));
+    }
+    line
+}
+
+fn decode(line: &[u8]) -> Option<Change> {
+    let (kind, rest) = bytes::split_once(line, b'\t')?;
+    let path = |b: &[u8]| PathBuf::from(bytes::to_os(b).into_owned());
+    match kind {
+        b"created" => Some(Change::Created(path(rest))),
+        b"copied" => Some(Change::Copied(path(rest))),
+        b"unlinked" => {
+            let (dest, target) = bytes::split_once(rest, b'\t')?;
+            Some(Change::Unlinked(path(dest), path(target)))
+        }
+        b"stashed" => {
+            let (dest, stash) = bytes::split_once(rest, b'\t')?;
+            Some(Change::Stashed(path(dest), path(stash)))
+        }
+        b"reinstated" => {
+            let (dest, kept) = bytes::split_once(rest, b'\t')?;
+            Some(Change::Reinstated(path(dest), path(kept)))
+        }
+        b"rmdir" => Some(Change::RemovedDir(path(rest))),
+        _ => None,
+    }
+}
+
+// The id of the run that wrote the journal, if it recorded one, and its
+// changes
+pub fn load() -> io::Result<(Option<String>, Vec<Change>)> {
+    let Some(content) = state::read_file(&state::host_dir().join(JOURNAL_FILE))? else {
+        return Ok((None, Vec::new()));
+    };
+    let run = bytes::lines(&content)
+        .find_map(|line| line.strip_prefix(b"run\t"))
+        .map(|id| String::from_utf8_lossy(id).into_owned());
+    Ok((run, bytes::lines(&content).filter_map(decode).collect()))
+}
+
+// Replaces the previous run's journal, and the files it stashed, with this
+// run's. Runs that changed nothing leave the previous one in place.
+pub fn save(changes: &[Change]) -> io::Result<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let dir = state::host_dir();
+    let stashes = dir.join(STASH_DIR);
+    if let Ok(entries) = fs::read_dir(&stashes) {
+        for entry in entries {
+            let entry = entry?.path();
+            if entry != *run_stash() {
+                fs::remove_dir_all(entry)?;
+            }
+        }
+    }
+
+    let mut out = format!("run\t{}\n", state::run_id()).into_bytes();
+    for change in changes {
+        out.extend(encode(change));
+        out.push(b'\n');
+    }
+    state::write_file(&dir.join(JOURNAL_FILE), &out)
+}
+
+// Forgets the journal once it has been undone, along with its stash
+pub fn clear() -> io::Result<()> {
+    let dir = state::host_dir();
+    state::remove_file(&dir.join(JOURNAL_FILE))?;
+    let stashes = dir.join(STASH_DIR);
+    if stashes.exists() {
+        fs::remove_dir_all(stashes)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,2496 @@
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{self, Path, PathBuf};
+use std::sync::Arc;
+
+pub use api::{
+    ExecuteOptions, OperationResult, Outcome, Plan, PlanOptions, RunReport, execute,
+    plan_from_config, plan_from_source,
+};
+use expand::{expand_path, normalize};
+use glyphs::Glyph;
+pub use log::{Color, LogLevel, printfc_func, set_color};
+use trace::Phase;
+
+#[derive(Clone, Default)]
+pub enum Mode {
+    #[default]
+    Create,
+    Overwrite,
+    Delete,
+}
+
+impl Mode {
+    fn name(&self) -> &'static str {
+        match self {
+            Mode::Create => "create",
+            Mode::Overwrite => "overwrite",
+            Mode::Delete => "delete",
+        }
+    }
+}
+
+/// How a destination is made from its source.
+#[derive(Clone, Copy, Default, PartialEq)]
+pub enum LinkMode {
+    #[default]
+    Symlink,
+    Copy,
+    Hardlink,
+}
+
+impl LinkMode {
+    fn name(&self) -> &'static str {
+        match self {
+            LinkMode::Symlink => "symlink",
+            LinkMode::Copy => "copy",
+            LinkMode::Hardlink => "hard link",
+        }
+    }
+}
+
+/// What `--group-by` gathers entries by before applying them.
+#[derive(Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    /// The [section] an entry is in
+    Package,
+    /// The top of the destination's path, with home as one root
+    Root,
+    /// The entry's `!tag`
+    Tag,
+}
+
+/// Everything a run depends on: the config file, what to do with it, and
+/// the variables and reload rules loaded from it.
+#[derive(Clone)]
+pub struct Config {
+    pub file: PathBuf,
+    pub basedir: PathBuf,
+    pub mode: Mode,
+    pub link_mode: LinkMode,
+    /// What copies take on from their source besides the content
+    pub preserve: meta::Preserve,
+    pub verbose: bool,
+    pub force: bool,
+    pub dry: bool,
+    /// Describe in dry runs the commands a real run would start
+    pub dry_run_hooks: bool,
+    /// Ask before overwriting a file without showing how it differs
+    pub no_diff: bool,
+    /// Set by `sandbox::enter`: commands are described rather than run, and
+    /// registry values and macOS defaults are left alone
+    pub sandboxed: bool,
+    pub debug: bool,
+    pub skip_unavailable: bool,
+    pub gc_broken: bool,
+    pub no_folding: bool,
+    pub adopt: bool,
+    /// Ask what to do with each destination that is in the way
+    pub interactive: bool,
+    pub backup: Option<backup::Backup>,
+    pub atomic: bool,
+    /// Apply `!manual` entries too, which are otherwise only planned
+    pub include_manual: bool,
+    /// Apply entries one target root at a time, home first, asking before
+    /// moving on to the next
+    pub pause_between_roots: bool,
+    /// Apply entries one group at a time, saying which group each is in
+    pub group_by: Option<GroupBy>,
+    /// Pass over entries already in place instead of finding them in the
+    /// way, as re-applying after a change does
+    pub skip_in_place: bool,
+    /// Let entries reach outside $HOME, or the target, without `!system`
+    pub allow_outside_home: bool,
+    /// Stop at the first entry that fails, leaving the rest unapplied
+    pub fail_fast: bool,
+    /// How many entries are applied at once
+    pub jobs: usize,
+    /// At most how many of them under each of these directories, for mounts
+    /// that more at once only slows down
+    pub mount_jobs: Vec<(PathBuf, usize)>,
+    /// Refuse to apply a file with any problem `check` would report
+    pub strict: bool,
+    /// Say where copies come from in a comment at their top
+    pub provenance: bool,
+    /// The bytes copies and templates may write in a run before it asks,
+    /// or None for no limit
+    pub copy_budget: Option<u64>,
+    /// Destinations given with --set for this run only, by the source of
+    /// the entry they replace the destinations of
+    pub overrides: Vec<(Vec<u8>, Vec<u8>)>,
+    pub vars: vars::Vars,
+    pub root: Option<PathBuf>,
+    /// Where destinations go instead of `/`, with `~` and `$HOME` standing
+    /// for this directory too
+    pub target: Option<PathBuf>,
+    pub reloads: Vec<reload::Rule>,
+    pub registry: Vec<registry::Value>,
+    pub defaults: Vec<defaults::Preference>,
+    pub transforms: Vec<transform::Rule>,
+    pub hooks: hooks::Hooks,
+    pub packages: Vec<String>,
+    /// Packages `neostow disable` keeps out of runs that name none
+    pub disabled: Vec<String>,
+    /// Destinations declared with `unmanage`, which nothing plans into
+    pub unmanaged: Vec<PathBuf>,
+    /// Patterns of @ignore lines, for what is never linked from a directory
+    pub ignores: Vec<Vec<u8>>,
+    /// The files pulled in with `@include`, loaded and ready to plan, by the
+    /// line that includes them
+    pub includes: Vec<(usize, Config)>,
+    /// The `{NAME}` placeholders filled throughout this file, when it was
+    /// included `with NAME=VALUE`
+    pub params: Vec<(Vec<u8>, Vec<u8>)>,
+    /// What planning sees of the filesystem
+    pub fs: Arc<dyn vfs::Fs>,
+}
+
+impl Config {
+    /// Defaults for the given config file, with sources relative to its
+    /// directory. Call `load` before planning.
+    pub fn new(file: PathBuf) -> Self {
+        let basedir = file
+            .parent()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("."));
+        Config {
+            file,
+            basedir,
+            mode: Mode::Create,
+            link_mode: LinkMode::Symlink,
+            preserve: meta::Preserve::default(),
+            verbose: false,
+            force: false,
+            dry: false,
+            debug: false,
+            skip_unavailable: false,
+            gc_broken: false,
+            no_folding: false,
+            adopt: false,
+            interactive: false,
+            backup: None,
+            atomic: false,
+            include_manual: false,
+            pause_between_roots: false,
+            group_by: None,
+            skip_in_place: false,
+            allow_outside_home: false,
+            fail_fast: false,
+            dry_run_hooks: false,
+            no_diff: false,
+            sandboxed: false,
+            jobs: 1,
+            mount_jobs: Vec::new(),
+            strict: false,
+            provenance: false,
+            copy_budget: Some(budget::DEFAULT),
+            overrides: Vec::new(),
+            vars: vars::Vars::new(),
+            root: None,
+            target: None,
+            reloads: Vec::new(),
+            registry: Vec::new(),
+            defaults: Vec::new(),
+            transforms: Vec::new(),
+            hooks: hooks::Hooks::default(),
+            packages: Vec::new(),
+            disabled: Vec::new(),
+            unmanaged: Vec::new(),
+            ignores: Vec::new(),
+            includes: Vec::new(),
+            params: Vec::new(),
+            fs: Arc::new(vfs::RealFs),
+        }
+    }
+
+    /// Evaluates the file's variables and reload rules, loads the files it
+    /// includes, and checks that every selected package has a section.
+    pub fn load(&mut self) -> io::Result<()> {
+        self.load_from(&age::read(&self.file)?)
+    }
+
+    /// Like `load`, with content standing in for the file. Variables set
+    /// beforehand act like environment variables.
+    pub fn load_from(&mut self, content: &[u8]) -> io::Result<()> {
+        if let Some(target) = &self.target {
+            self.vars
+                .entry("HOME".to_string())
+                .or_insert_with(|| target.clone().into_os_string());
+        }
+        self.load_rules(content)?;
+        let mut stack = vec![fs::canonicalize(&self.file).unwrap_or_else(|_| self.file.clone())];
+        let mut sections = Vec::new();
+        self.includes = include::load(self, content, None, &mut stack, &mut sections)?;
+        include::merge(self);
+        check_packages(self, &sections)?;
+        Ok(())
+    }
+
+    // What a file declares besides entries, without the files it includes
+    fn load_rules(&mut self, content: &[u8]) -> io::Result<()> {
+        self.vars = vars::parse(
+            content,
+            std::mem::take(&mut self.vars),
+            &self.packages,
+            &self.disabled,
+        )?;
+        template::parse(content, &self.file, &mut self.vars)?;
+        self.reloads = reload::parse(content, &self.file, &self.vars)?;
+        self.registry = registry::parse(content, &self.file)?;
+        self.defaults = defaults::parse(content, &self.file)?;
+        self.transforms = transform::parse(content, &self.file)?;
+        self.hooks = hooks::parse(content, &self.file)?;
+        self.unmanaged = unmanage::parse(content, &self.file, &self.vars)?;
+        self.ignores = ignore::parse(content, &self.file)?;
+        Ok(())
+    }
+}
+
+#[macro_export]
+macro_rules! printfc {
+    ($level:expr, $($arg:tt)*) => {
+        $crate::printfc_func($level, format_args!($($arg)*)).unwrap();
+    };
+}
+
+pub mod activation;
+pub mod adopt;
+mod age;
+pub mod aliases;
+mod api;
+mod attrs;
+pub mod backup;
+pub mod bench;
+pub mod bootstrap;
+pub mod budget;
+mod bytes;
+pub mod capabilities;
+pub mod check;
+mod cond;
+pub mod conflicts;
+mod cooldown;
+pub mod decommission;
+pub mod defaults;
+mod diff;
+pub mod doctor;
+pub mod editor;
+pub mod expand;
+pub mod export;
+mod fetch;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod format;
+mod fsops;
+mod glob;
+pub mod glyphs;
+pub mod guard;
+pub mod history;
+pub mod hooks;
+mod ignore;
+pub mod import;
+mod include;
+pub mod init;
+mod interactive;
+mod journal;
+pub mod list;
+pub mod log;
+pub mod lookup;
+mod managed;
+pub mod meta;
+mod netfs;
+pub mod overlay;
+pub mod overrides;
+pub mod ownership;
+mod parallel;
+pub mod plan_diff;
+pub mod platform;
+mod provenance;
+pub mod prune;
+mod refresh;
+mod regex;
+pub mod registry;
+pub mod reload;
+pub mod report;
+pub mod resolve;
+pub mod sandbox;
+pub mod selftest;
+pub mod service;
+pub mod settings;
+pub mod setup;
+mod shell;
+pub mod shellenv;
+pub mod snapshot;
+mod state;
+pub mod stats;
+pub mod status;
+mod store;
+pub mod suggest;
+mod template;
+pub mod trace;
+pub mod transform;
+mod twoway;
+mod unmanage;
+pub mod vars;
+pub mod vcs;
+pub mod vfs;
+mod waiting;
+pub mod watch;
+mod xattr;
+
+fn report_op(src: &Path, dest: &Path, cfg: &Config, result: &str) {
+    report::record(report::Result {
+        source: Some(src),
+        dest: Some(dest),
+        action: cfg.mode.name(),
+        result,
+        error: None,
+    });
+}
+
+fn create_symlink(op: &Operation, cfg: &Config) -> io::Result<bool> {
+    let (src, dest) = (op.src.as_path(), op.dest.as_path());
+    if let Mode::Overwrite = cfg.mode
+        && cfg.fs.exists(dest)
+        && !matches!(cfg.fs.metadata(dest)?, vfs::Node::Link(_))
+    {
+        // With --jobs, a diff and its prompt still come out together
+        let _terminal = report::terminal();
+        let differ = if cfg.no_diff {
+            diff::paths(src, dest, &mut io::sink())?
+        } else {
+            run_diff(src, dest)?
+        };
+
+        // A dry run shows the diff of what it would replace, and asks nothing
+        if differ
+            && !cfg.force
+            && !cfg.dry
+            && !prompt_user(&format!(
+                "{} exists and is not a symlink. Overwrite?",
+                op.subject()
+            ))?
+        {
+            return Ok(false);
+        }
+    }
+    // Someone else's link is only replaced once confirmed, like a real file
+    if let Mode::Overwrite = cfg.mode
+        && !cfg.force
+        && !cfg.dry
+        && matches!(cfg.fs.metadata(dest), Ok(vfs::Node::Link(_)))
+        && !status::op_owned(op, cfg)?
+    {
+        let _terminal = report::terminal();
+        if !prompt_user(&format!(
+            "{} links outside {}. Replace it?",
+            op.subject(),
+            cfg.basedir.display()
+        ))? {
+            return Ok(false);
+        }
+    }
+
+    // Rendered before anything is removed, so a template that fails leaves
+    // what is there alone
+    let rendered = match &op.template {
+        Some(vars) if !cfg.dry && !matches!(cfg.mode, Mode::Delete) => {
+            Some(template::render(src, vars)?)
+        }
+        _ => None,
+    };
+    match cfg.mode {
+        Mode::Delete => {
+            // A stale line must not take a real file or someone else's link
+            // with it
+            if !cfg.force && !status::op_owned(op, cfg)? {
+                return Err(io::Error::other(format!(
+                    "{} was not made by neostow from {}, use --force to delete it anyway",
+                    dest.display(),
+                    cfg.basedir.display()
+                )));
+            }
+            if cfg.dry {
+                return describe(op, cfg);
+            }
+            journal::remove(&*cfg.fs, dest)?;
+        }
+        Mode::Overwrite => {
+            let is_real = matches!(cfg.fs.metadata(dest), Ok(vfs::Node::File | vfs::Node::Dir));
+            if op.keep_original && is_real && !status::op_in_place(op, cfg)? {
+                backup::keep_original(dest, cfg)?;
+            }
+            if cfg.dry {
+                return describe(op, cfg);
+            }
+            if let Some(backup) = &cfg.backup
+                && is_real
+            {
+                backup::save(dest, backup, cfg)?;
+            } else {
+                journal::remove(&*cfg.fs, dest)?;
+            }
+            place(op, rendered, cfg)?;
+        }
+        Mode::Create => {
+            if cfg.dry {
+                return describe(op, cfg);
+            }
+            // Run again, an entry already in place has nothing left to make
+            if status::op_in_place(op, cfg)? {
+                report_op(src, dest, cfg, "unchanged");
+                return Ok(false);
+            }
+            place(op, rendered, cfg)?;
+        }
+    }
+
+    Ok(true)
+}
+
+// Makes op's destination: its source rendered, for a template, or a link or
+// copy of it
+fn place(op: &Operation, rendered: Option<Vec<u8>>, cfg: &Config) -> io::Result<()> {
+    match rendered {
+        Some(content) => journal::write(&*cfg.fs, &op.dest, &content),
+        None if op.dedup && cfg.link_mode == LinkMode::Copy => journal::share(&op.src, &op.dest),
+        None => journal::link(&*cfg.fs, &op.src, &op.dest, op.is_dir, cfg.link_mode),
+    }
+}
+
+// What a dry run prints and counts instead of changing dest
+fn describe(op: &Operation, cfg: &Config) -> io::Result<bool> {
+    let (src, dest) = (op.src.as_path(), op.dest.as_path());
+    let node = cfg.fs.metadata(dest).ok();
+    let exists = node.is_some();
+    let kind = match cfg.mode {
+        Mode::Delete if exists => report::Pending::Remove,
+        Mode::Delete => report::Pending::UpToDate,
+        _ if exists && status::op_in_place(op, cfg)? => report::Pending::UpToDate,
+        Mode::Overwrite if exists => report::Pending::Overwrite,
+        Mode::Create if exists => report::Pending::Conflict,
+        _ => report::Pending::Create,
+    };
+    report::pending(kind);
+    let arrow = || {
+        if !report::json() {
+            report::out(&format!(
+                "{} {} {}",
+                src.display(),
+                glyphs::get(Glyph::Arrow),
+                dest.display()
+            ));
+        }
+    };
+    let result = match kind {
+        report::Pending::UpToDate => {
+            if cfg.verbose {
+                printfc!(LogLevel::Info, "Up to date: {}", dest.display());
+            }
+            "unchanged"
+        }
+        report::Pending::Conflict => {
+            printfc!(
+                LogLevel::Warn,
+                "{} is in the way (use -o to replace it)",
+                dest.display()
+            );
+            "conflict"
+        }
+        report::Pending::Remove => {
+            printfc!(LogLevel::Info, "Would remove {}", dest.display());
+            "planned"
+        }
+        report::Pending::Overwrite => {
+            let verb = match cfg.backup {
+                Some(_) if !matches!(node, Some(vfs::Node::Link(_))) => "back up",
+                _ => "remove",
+            };
+            printfc!(LogLevel::Info, "Would {verb} {}", dest.display());
+            arrow();
+            "planned"
+        }
+        report::Pending::Create => {
+            arrow();
+            "planned"
+        }
+    };
+    report_op(src, dest, cfg, result);
+    Ok(false)
+}
+
+/// A `!name` or `!name:value` modifier following a mapping.
+pub struct Modifier<'a> {
+    pub name: &'a str,
+    pub value: Option<&'a [u8]>,
+}
+
+/// One mapping line of a `.neostow` file, borrowing from the line.
+pub struct Entry<'a> {
+    pub src: &'a [u8],
+    pub dest: Option<&'a [u8]>,
+    /// Whether dest is the destination itself, as in `bashrc -> ~/.bashrc`,
+    /// rather than the directory the source goes into
+    pub exact: bool,
+    pub modifiers: Vec<Modifier<'a>>,
+}
+
+impl<'a> Entry<'a> {
+    /// Each destination of the entry, as in `alacritty = ~/.config,
+    /// ~/.var/app/org.alacritty/.config`, or just None to link the source
+    /// next to the directory. Commas inside `${...}` or quotes don't separate.
+    pub fn destinations(&self) -> Vec<Option<&'a [u8]>> {
+        let Some(dest) = self.dest else {
+            return vec![None];
+        };
+        let mut dests = Vec::new();
+        let (mut depth, mut start) = (0usize, 0);
+        let mask = quoted(dest);
+        for (i, &c) in dest.iter().enumerate() {
+            match c {
+                _ if mask[i] => {}
+                b'{' => depth += 1,
+                b'}' => depth = depth.saturating_sub(1),
+                b',' if depth == 0 => {
+                    dests.push(Some(dest[start..i].trim_ascii()));
+                    start = i + 1;
+                }
+                _ => {}
+            }
+        }
+        dests.push(Some(dest[start..].trim_ascii()));
+        dests
+    }
+}
+
+/// A single link to create, replace or remove.
+pub struct Operation {
+    pub src: PathBuf,
+    pub dest: PathBuf,
+    pub is_dir: bool,
+    validators: Vec<OsString>,
+    refreshes: Vec<refresh::Kind>,
+    managed: bool,
+    preserve: meta::Preserve,
+    // Whether the first real file replaced is kept as DEST.neostow-orig
+    keep_original: bool,
+    // Whether the directory the destination goes in must exist already, from
+    // !require-target
+    require_target: bool,
+    // Modes and owner from [mode=600] and the like
+    attrs: attrs::Attributes,
+    // Where a !fetch source is cloned or downloaded from
+    fetch: Option<Arc<fetch::Remote>>,
+    // The variables a [template] entry renders its source with
+    template: Option<Arc<vars::Vars>>,
+    // Whether its copies are linked from the store, from !dedup
+    dedup: bool,
+    // Whether the destination is only checked, never made, from !assert
+    assert: bool,
+    // What --group-by tag puts the entry under
+    tag: Option<String>,
+    /// What the entry is, from `!desc`, for listings and prompts
+    pub desc: Option<String>,
+    /// The [section] the entry is in, once planned from a file
+    pub package: Option<String>,
+    /// Whether the entry is marked `!manual`, and only applied with
+    /// `include_manual`
+    pub manual: bool,
+    // The included file the entry is in, None for the top-level file
+    origin: Option<Arc<Path>>,
+}
+
+impl Operation {
+    /// The neostow file holding the entry the operation was planned from,
+    /// which is cfg's own file unless the entry is in an included one.
+    pub fn file<'a>(&'a self, cfg: &'a Config) -> &'a Path {
+        self.origin.as_deref().unwrap_or(&cfg.file)
+    }
+
+    // How prompts name the destination, by what it is when there is a !desc
+    fn subject(&self) -> String {
+        match &self.desc {
+            Some(desc) => format!("{desc} at '{}'", self.dest.display()),
+            None => format!("Destination '{}'", self.dest.display()),
+        }
+    }
+}
+
+// Modifiers follow the mapping as `!name` or `!name:value`, where the value
+// may be quoted to contain spaces: `nvim=~/.config !validate:"nvim --headless"`
+fn parse_modifiers(mut s: &[u8]) -> Vec<Modifier<'_>> {
+    let mut modifiers = Vec::new();
+    while let Some(rest) = s.trim_ascii_start().strip_prefix(b"!") {
+        let name_end = rest
+            .iter()
+            .position(|&b| b.is_ascii_whitespace() || b == b':')
+            .unwrap_or(rest.len());
+        let name = std::str::from_utf8(&rest[..name_end]).unwrap_or_default();
+        s = &rest[name_end..];
+
+        let value = match s.strip_prefix(b":") {
+            Some(value) => match value.first() {
+                Some(&quote @ (b'"' | b'\'')) => {
+                    let body = &value[1..];
+                    let end = bytes::find(body, quote).unwrap_or(body.len());
+                    s = body.get(end + 1..).unwrap_or_default();
+                    Some(&body[..end])
+                }
+                _ => {
+                    let end = value
+                        .iter()
+                        .position(u8::is_ascii_whitespace)
+                        .unwrap_or(value.len());
+                    s = &value[end..];
+                    Some(&value[..end])
+                }
+            },
+            None => None,
+        };
+        modifiers.push(Modifier { name, value });
+    }
+    modifiers
+}
+
+// Takes [key=value, ...] off the end of line, as the modifiers they stand for
+fn split_attributes(line: &[u8]) -> (&[u8], Vec<Modifier<'_>>) {
+    let Some(open) = line
+        .strip_suffix(b"]")
+        .and_then(|rest| rest.iter().rposition(|&b| b == b'['))
+        .filter(|&open| open > 0 && line[open - 1].is_ascii_whitespace())
+    else {
+        return (line, Vec::new());
+    };
+    let modifiers = line[open + 1..line.len() - 1]
+        .split(|&b| b == b',' || b.is_ascii_whitespace())
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let (name, value) = match bytes::split_once(item, b'=') {
+                Some((name, value)) => (name, Some(value)),
+                None => (item, None),
+            };
+            Modifier {
+                name: std::str::from_utf8(name).unwrap_or_default(),
+                value,
+            }
+        })
+        .collect();
+    (line[..open].trim_ascii(), modifiers)
+}
+
+fn section_header(line: &[u8]) -> Option<&[u8]> {
+    line.trim_ascii().strip_prefix(b"[")?.strip_suffix(b"]")
+}
+
+// Calls each with the lines of every [name] section in content, the text of
+// the file at path, leaving out those under an unmet condition. Errors are
+// prefixed with the line they came from.
+fn section_lines(
+    content: &[u8],
+    path: &Path,
+    name: &[u8],
+    mut each: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut inside = false;
+    let mut unmet = false;
+    for (i, line) in bytes::lines(content).enumerate() {
+        if let Some(header) = section_header(line) {
+            inside = header.trim_ascii() == name;
+            unmet = false;
+            continue;
+        }
+        if !inside {
+            continue;
+        }
+        let result = if cond::is_directive(line) {
+            cond::evaluate(line).map(|reason| unmet = reason.is_some())
+        } else if unmet || hooks::is_hook(line) {
+            Ok(())
+        } else {
+            each(line)
+        };
+        if let Err(err) = result {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{}:{}: {err}", path.display(), i + 1),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parses a `.neostow` file line by line, tracking which section each line
+/// belongs to. [reload] and [transform] hold rules, [aliases] command lines
+/// and [vars] template values rather than entries, and any other [name] groups entries into a
+/// package that can be selected.
+/// Lines after an unmet `@host`/`@os`/`@env` condition are skipped too.
+pub struct Parser<'c> {
+    packages: &'c [String],
+    disabled: &'c [String],
+    section: Option<Vec<u8>>,
+    unmet: Option<String>,
+    // The condition line behind unmet
+    gate: Vec<u8>,
+    skipped: bool,
+    included: bool,
+}
+
+impl<'c> Parser<'c> {
+    pub fn new(packages: &'c [String]) -> Self {
+        Parser::within(packages, None)
+    }
+
+    // A parser for an included file, starting in the includer's section
+    pub(crate) fn within(packages: &'c [String], section: Option<Vec<u8>>) -> Self {
+        Parser {
+            packages,
+            disabled: &[],
+            section,
+            unmet: None,
+            gate: Vec::new(),
+            skipped: false,
+            included: false,
+        }
+    }
+
+    // Leaves out the sections of disabled when no packages are given
+    pub(crate) fn without(self, disabled: &'c [String]) -> Self {
+        Parser { disabled, ..self }
+    }
+
+    /// Whether the last parsed line was an `@include` to follow.
+    pub fn included(&self) -> bool {
+        self.included
+    }
+
+    pub(crate) fn section(&self) -> Option<&[u8]> {
+        self.section.as_deref()
+    }
+
+    /// Why the entry on the last parsed line was skipped by a condition.
+    pub fn skipped(&self) -> Option<&str> {
+        self.unmet.as_deref().filter(|_| self.skipped)
+    }
+
+    // The condition line that skipped it
+    pub(crate) fn gate(&self) -> &[u8] {
+        &self.gate
+    }
+
+    pub fn parse<'a>(&mut self, line: &'a [u8]) -> Option<Entry<'a>> {
+        self.skipped = false;
+        self.included = false;
+        if let Some(name) = section_header(line) {
+            self.section = Some(name.trim_ascii().to_vec());
+            self.unmet = None;
+            return None;
+        }
+        if cond::is_directive(line) {
+            // A condition that can't be read excludes its entries rather
+            // than applying them everywhere
+            self.unmet = cond::evaluate(line).unwrap_or_else(|err| {
+                printfc!(LogLevel::Error, "{err}");
+                Some(err.to_string())
+            });
+            self.gate = line.trim_ascii().to_vec();
+            return None;
+        }
+        let selected = match self.section.as_deref() {
+            Some(b"reload" | b"transform" | b"aliases" | b"registry" | b"vars") => false,
+            _ if self.packages.is_empty() => !self
+                .section
+                .as_deref()
+                .is_some_and(|name| selects(self.disabled, name)),
+            // Entries outside any section belong to no package
+            None => false,
+            Some(name) => selects(self.packages, name),
+        };
+        if include::parse(line).is_some() {
+            // A top-level include is always followed, leaving the choice to
+            // the sections of the included file
+            self.included = self.unmet.is_none() && (selected || self.section.is_none());
+            return None;
+        }
+        if !selected {
+            return None;
+        }
+        let entry = parse_line(line)?;
+        if self.unmet.is_some() {
+            self.skipped = true;
+            return None;
+        }
+        Some(entry)
+    }
+}
+
+// Whether the packages given select the section name, by name or by a glob
+// like zsh*
+pub(crate) fn selects(packages: &[String], name: &[u8]) -> bool {
+    packages
+        .iter()
+        .any(|package| glob::matches(package.as_bytes(), name))
+}
+
+// Every package named on the command line must have a section in the file
+// or in a file it includes
+fn check_packages(cfg: &Config, sections: &[Vec<u8>]) -> io::Result<()> {
+    for package in &cfg.packages {
+        if sections
+            .iter()
+            .any(|name| glob::matches(package.as_bytes(), name))
+        {
+            continue;
+        }
+        let msg = if glob::is_pattern(package.as_bytes()) {
+            format!("no section of {} matches {package}", cfg.file.display())
+        } else {
+            format!("no [{package}] section in {}", cfg.file.display())
+        };
+        return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+    }
+    Ok(())
+}
+
+pub fn parse_line(line: &[u8]) -> Option<Entry<'_>> {
+    let mut line = line.trim_ascii();
+    if line.is_empty()
+        || line.starts_with(b"#")
+        || cond::is_directive(line)
+        || hooks::is_hook(line)
+        || include::parse(line).is_some()
+        || unmanage::parse_line(line).is_some()
+        || defaults::parse_line(line).is_some()
+        || vars::parse_let(line).is_some()
+        || transform::list(line).is_some()
+    {
+        return None;
+    }
+
+    if let Some(comment_start) = unquoted(line, 1, |w| w[0] == b'#')
+        && comment_start > 0
+    {
+        line = line[..comment_start].trim_ascii();
+    }
+
+    // [attributes] may come before or after the modifiers
+    let (rest, mut modifiers) = split_attributes(line);
+    line = rest;
+    if let Some(pos) = unquoted(line, 2, |w| w[0].is_ascii_whitespace() && w[1] == b'!') {
+        modifiers.extend(parse_modifiers(&line[pos + 1..]));
+        line = line[..pos].trim_ascii();
+    }
+    let (rest, attributes) = split_attributes(line);
+    line = rest;
+    modifiers.extend(attributes);
+
+    if let Some(pos) = unquoted(line, 2, |w| w == b"->") {
+        return Some(Entry {
+            src: line[..pos].trim_ascii(),
+            dest: Some(line[pos + 2..].trim_ascii()),
+            exact: true,
+            modifiers,
+        });
+    }
+    match unquoted(line, 1, |w| w[0] == b'=') {
+        Some(pos) => Some(Entry {
+            src: line[..pos].trim_ascii(),
+            dest: Some(line[pos + 1..].trim_ascii()),
+            exact: false,
+            modifiers,
+        }),
+        None => Some(Entry {
+            src: line,
+            dest: None,
+            exact: false,
+            modifiers,
+        }),
+    }
+}
+
+/// The text of a source or destination written in single quotes, as in
+/// `'C:\Users\me\AppData'`: a raw string, like a TOML literal string, taken
+/// as it is without expanding variables or ~ and with every \ literal.
+pub fn raw(field: &[u8]) -> Option<&[u8]> {
+    field.strip_prefix(b"'")?.strip_suffix(b"'")
+}
+
+// Which bytes of line are in a single-quoted field, quotes included. A quote
+// only opens one where a source or destination starts, so an apostrophe
+// within a name is just an apostrophe.
+fn quoted(line: &[u8]) -> Vec<bool> {
+    let mut mask = vec![false; line.len()];
+    let mut i = 0;
+    while i < line.len() {
+        let starts = line[..i]
+            .iter()
+            .rfind(|c| !c.is_ascii_whitespace())
+            .is_none_or(|c| matches!(c, b'=' | b'>' | b','));
+        if line[i] == b'\''
+            && starts
+            && let Some(len) = bytes::find(&line[i + 1..], b'\'')
+        {
+            mask[i..i + len + 2].fill(true);
+            i += len + 2;
+        } else {
+            i += 1;
+        }
+    }
+    mask
+}
+
+// Where the first window of len bytes matching is outside quotes
+fn unquoted(line: &[u8], len: usize, matches: impl Fn(&[u8]) -> bool) -> Option<usize> {
+    let mask = quoted(line);
+    line.windows(len)
+        .enumerate()
+        .find(|&(i, w)| !mask[i] && matches(w))
+        .map(|(i, _)| i)
+}
+
+// Resolves one source of an entry to its source and destination paths, or
+// None when the source doesn't exist. affix is the !prefix and !suffix of the
+// entry, put around the destination name after the [transform] rules. An
+// exact destination is used as is.
+fn locate(
+    entry_src: &OsStr,
+    dest: Option<&[u8]>,
+    exact: bool,
+    affix: (&[u8], &[u8]),
+    fetched: bool,
+    cfg: &Config,
+) -> io::Result<Option<(PathBuf, PathBuf)>> {
+    let (src, dest_base) = match dest {
+        Some(dest) => {
+            let dest_base = match raw(dest) {
+                Some(dest) => PathBuf::from(bytes::to_os(dest).into_owned()),
+                None => expand_path(dest, &cfg.vars)?,
+            };
+            (cfg.basedir.join(entry_src), dest_base)
+        }
+        None => {
+            let src_path = cfg.basedir.join(entry_src);
+            let src_dir = Path::new(entry_src)
+                .parent()
+                .unwrap_or_else(|| Path::new(""));
+            let parent_dir = match &cfg.target {
+                Some(target) => target,
+                None => cfg.basedir.parent().unwrap_or(&cfg.basedir),
+            };
+            let dest_base = parent_dir.join(src_dir); // join parent's dir with src dir
+            (src_path, dest_base)
+        }
+    };
+
+    // Adopting fills in missing sources from their destinations, and a
+    // source to fetch is only there once it is linked
+    if !cfg.fs.exists(&src) && !cfg.adopt && !fetched {
+        report::saw(report::Condition::MissingSource);
+        if cfg.verbose {
+            printfc!(LogLevel::Error, "Source {:?} not found", src);
+        }
+        return Ok(None);
+    }
+
+    if cfg.debug {
+        printfc!(LogLevel::Debug, "Source file: {}", src.display());
+        printfc!(LogLevel::Debug, "Destination: {}", dest_base.display());
+    }
+
+    let mut dest = if exact {
+        normalize(&dest_base)
+    } else {
+        let name = transform::apply(&cfg.transforms, src.file_name().unwrap())?;
+        normalize(&dest_base.join(transform::affix(&name, affix.0, affix.1)))
+    };
+
+    // Like DESTDIR, the target stands in for / too: /etc/hosts → TARGET/etc/hosts
+    if let Some(target) = &cfg.target
+        && !dest.starts_with(target)
+    {
+        dest = target.join(
+            normalize(&path::absolute(&dest)?)
+                .components()
+                .filter(|c| matches!(c, path::Component::Normal(_)))
+                .collect::<PathBuf>(),
+        );
+    }
+
+    if let Some(root) = &cfg.root
+        && !normalize(&path::absolute(&dest)?).starts_with(root)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is outside of root {}", dest.display(), root.display()),
+        ));
+    }
+
+    // Before anything stats the destination, which would hang there
+    if cfg.skip_unavailable && netfs::is_unavailable(&dest) {
+        return Err(netfs::unavailable_error(&dest));
+    }
+
+    Ok(Some((src, dest)))
+}
+
+// Unless allowed, destinations stay strictly inside $HOME, or the target, so
+// a mistyped variable can't point a run at /usr. Nor may a link on the way
+// to one lead out of it.
+fn confine(dest: &Path, cfg: &Config) -> io::Result<()> {
+    let home = match &cfg.target {
+        Some(target) => target.clone(),
+        None => match expand::home(&cfg.vars) {
+            Ok(home) => normalize(Path::new(&home)),
+            Err(_) => return Ok(()),
+        },
+    };
+    let dest = normalize(&path::absolute(dest)?);
+    if dest == home || !dest.starts_with(&home) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not inside {}, mark the entry !system or pass --allow-outside-home",
+                dest.display(),
+                home.display()
+            ),
+        ));
+    }
+    if let Some(dir) = dest.ancestors().skip(1).find(|dir| cfg.fs.exists(dir))
+        && let (Ok(real), Ok(real_home)) = (fs::canonicalize(dir), fs::canonicalize(&home))
+        && !real.starts_with(&real_home)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} leads out of {} through a link to {}",
+                dir.display(),
+                home.display(),
+                real.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// A source containing wildcards expands into one operation per match
+pub fn plan(entry: &Entry, cfg: &Config) -> io::Result<Vec<Operation>> {
+    let mut validators = Vec::new();
+    let mut refreshes = Vec::new();
+    let mut managed = false;
+    let mut manual = false;
+    let mut keep_original = false;
+    let mut require_target = false;
+    let mut system = false;
+    let mut template = false;
+    let mut dedup = false;
+    let mut assert = false;
+    let mut fetch = None;
+    let mut tag = None;
+    let mut desc = None;
+    let mut attrs = attrs::Attributes::default();
+    let mut preserve = cfg.preserve;
+    let (mut prefix, mut suffix): (&[u8], &[u8]) = (b"", b"");
+    for modifier in &entry.modifiers {
+        match (modifier.name, modifier.value) {
+            ("managed-dir", None) => managed = true,
+            ("manual", None) => manual = true,
+            ("keep-original", None) => keep_original = true,
+            ("system", None) => system = true,
+            ("require-target", None) => require_target = true,
+            ("template", None) => template = true,
+            ("dedup", None) => dedup = true,
+            ("assert", None) => assert = true,
+            ("fetch", rev) => {
+                let url = raw(entry.src).unwrap_or(entry.src);
+                fetch = Some(Arc::new(fetch::Remote::parse(url, rev)?));
+            }
+            ("tag", Some(_)) if tag.is_some() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "an entry has one !tag",
+                ));
+            }
+            ("tag", Some(name)) => tag = Some(String::from_utf8_lossy(name).into_owned()),
+            ("desc", Some(text)) => desc = Some(String::from_utf8_lossy(text).into_owned()),
+            ("mode" | "dir-mode" | "owner", value) => {
+                if !attrs.set(modifier.name, value)? {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "{} needs a value, as in [{}=...]",
+                            modifier.name, modifier.name
+                        ),
+                    ));
+                }
+            }
+            ("inherit", None) => preserve.mode = true,
+            ("inherit", Some(b"times")) => {
+                preserve.mode = true;
+                preserve.times = true;
+            }
+            ("preserve", Some(list)) => preserve = preserve.union(meta::Preserve::parse(list)?),
+            ("inherit", Some(value)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "unknown !inherit:{}, expected times",
+                        bytes::to_os(value).display()
+                    ),
+                ));
+            }
+            ("prefix" | "suffix", Some(value)) if value.contains(&b'/') => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("!{} can't contain /", modifier.name),
+                ));
+            }
+            ("prefix", Some(value)) => prefix = value,
+            ("suffix", Some(value)) => suffix = value,
+            ("validate", Some(cmd)) => validators.push(bytes::to_os(cmd).into_owned()),
+            // Checked by cond::guard once the rest are known to be valid
+            ("only" | "if-exists", _) => {}
+            ("refresh", Some(kind)) if let Some(kind) = refresh::Kind::parse(kind) => {
+                refreshes.push(kind);
+            }
+            ("refresh", Some(kind)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "unknown refresh {:?}, expected fonts, desktop or mime",
+                        bytes::to_os(kind)
+                    ),
+                ));
+            }
+            (name, _) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid modifier !{name}"),
+                ));
+            }
+        }
+    }
+
+    if cond::guard(&entry.modifiers, cfg)?.is_some() {
+        return Ok(Vec::new());
+    }
+    // A quoted source names one file, whatever is in it
+    let src = raw(entry.src).unwrap_or(entry.src);
+    let pattern = raw(entry.src).is_none() && glob::is_pattern(src);
+    if entry.exact && pattern {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "-> names one destination, so its source can't be a pattern",
+        ));
+    }
+    if entry.exact && !(prefix.is_empty() && suffix.is_empty()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "!prefix and !suffix don't apply to -> entries, name the destination instead",
+        ));
+    }
+
+    let sources = if let Some(remote) = &fetch {
+        vec![remote.cache().into_os_string()]
+    } else if pattern {
+        let matches = glob::expand(cfg.fs.as_ref(), &cfg.basedir, src)?;
+        if matches.is_empty() {
+            report::saw(report::Condition::MissingSource);
+        }
+        if matches.is_empty() && cfg.verbose {
+            printfc!(
+                LogLevel::Error,
+                "No sources match {:?}",
+                bytes::to_os(entry.src)
+            );
+        }
+        matches
+            .into_iter()
+            .filter(|rel| {
+                let src = cfg.basedir.join(rel);
+                !ignore::ignored(cfg, &src, cfg.fs.is_dir(&src))
+            })
+            .map(PathBuf::into_os_string)
+            .collect()
+    } else {
+        vec![bytes::to_os(src).into_owned()]
+    };
+
+    let dests = match overrides::dest(cfg, entry.src) {
+        Some(dest) => vec![Some(dest)],
+        None => entry.destinations(),
+    };
+    if dests.len() > 1 && dests.contains(&Some(b"")) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "empty destination in a list of destinations",
+        ));
+    }
+    let mut ops = Vec::new();
+    for (entry_src, dest) in sources
+        .iter()
+        .flat_map(|src| dests.iter().map(move |dest| (src, *dest)))
+    {
+        let Some((src, dest)) = locate(
+            entry_src,
+            dest,
+            entry.exact,
+            (prefix, suffix),
+            fetch.is_some() || assert,
+            cfg,
+        )?
+        else {
+            continue;
+        };
+        if !(system || cfg.allow_outside_home) {
+            confine(&dest, cfg)?;
+        }
+        if unmanage::covers(&cfg.unmanaged, &dest) {
+            if cfg.verbose {
+                printfc!(LogLevel::Info, "Leaving unmanaged {} alone", dest.display());
+            }
+            continue;
+        }
+        // What is fetched is a directory, even before it is
+        let is_dir = fetch.is_some()
+            || cfg.fs.is_dir(&src)
+            || (!cfg.fs.exists(&src) && cfg.fs.is_dir(&dest));
+        if managed && !is_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "!managed-dir requires a directory, {} is not",
+                    src.display()
+                ),
+            ));
+        }
+        if template && is_dir {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("template requires a file, {} is a directory", src.display()),
+            ));
+        }
+        ops.push(Operation {
+            src,
+            dest,
+            is_dir,
+            validators: validators.clone(),
+            refreshes: refreshes.clone(),
+            managed,
+            preserve,
+            keep_original,
+            require_target,
+            attrs: attrs.clone(),
+            fetch: fetch.clone(),
+            template: template.then(|| Arc::new(cfg.vars.clone())),
+            dedup,
+            assert,
+            tag: tag.clone(),
+            desc: desc.clone(),
+            package: None,
+            manual,
+            origin: None,
+        });
+    }
+    Ok(ops)
+}
+
+// Returns whether the destination was actually changed
+pub fn execute_op(op: &Operation, cfg: &Config, operations: &mut i32) -> io::Result<bool> {
+    // What an !assert entry describes is only checked, by status
+    if op.assert {
+        return Ok(false);
+    }
+    if !matches!(cfg.mode, Mode::Delete) {
+        for validator in &op.validators {
+            if !shell::run_with_path(validator, &op.src, cfg)? {
+                return Err(io::Error::other(format!(
+                    "validator `{}` rejected {}",
+                    validator.display(),
+                    op.src.display()
+                )));
+            }
+        }
+    }
+
+    if op.managed {
+        return managed::sync(op, cfg, operations);
+    }
+    if op.is_dir && cfg.no_folding {
+        return managed::unfold(op, cfg, operations);
+    }
+
+    if let Some(parent) = op.dest.parent()
+        && !cfg.dry
+    {
+        let created = state::make_dirs(&*cfg.fs, parent)?;
+        attrs::apply_dirs(&op.attrs, &created, cfg)?;
+    }
+
+    let success = create_symlink(op, cfg)?;
+    if success
+        && (cfg.link_mode == LinkMode::Copy || op.template.is_some())
+        && !matches!(cfg.mode, Mode::Delete)
+    {
+        // A header would keep a rendered template from ever matching again,
+        // and would be written into every destination sharing a blob
+        if cfg.provenance && op.template.is_none() && !op.dedup {
+            provenance::stamp(&op.src, &op.dest)?;
+        }
+        meta::apply(&op.src, &op.dest, op.preserve)?;
+    }
+    if success && !matches!(cfg.mode, Mode::Delete) {
+        attrs::apply(&op.attrs, &op.dest, cfg)?;
+    }
+
+    if success {
+        *operations += 1;
+        report_op(&op.src, &op.dest, cfg, "done");
+        if cfg.verbose && !report::json() {
+            let mode_str = match cfg.mode {
+                Mode::Create => "Created",
+                Mode::Overwrite => "Overwritten",
+                Mode::Delete => "Deleted",
+            };
+            report::out(&format!(
+                "{mode_str} {}: {} => {}",
+                cfg.link_mode.name(),
+                op.src.display(),
+                op.dest.display()
+            ));
+        }
+    }
+
+    Ok(success)
+}
+
+// Plans every selected line, handing entries that can't be planned to
+// on_error along with the config of the file they are in
+fn plan_lines(
+    cfg: &Config,
+    content: &[u8],
+    mut on_error: impl FnMut(&Config, usize, io::Error),
+) -> io::Result<Vec<(usize, Operation)>> {
+    let mut ops = Vec::new();
+    plan_file(
+        cfg,
+        content,
+        None,
+        &mut on_error,
+        &mut |_, _, _| {},
+        &mut ops,
+    )?;
+    Ok(ops)
+}
+
+// The file, line and reason of every selected entry left out by a guard
+fn guarded_lines(cfg: &Config) -> io::Result<Vec<(PathBuf, usize, String)>> {
+    let mut guarded = Vec::new();
+    let mut on_guard = |file_cfg: &Config, linenum, reason| {
+        guarded.push((file_cfg.file.clone(), linenum, reason));
+    };
+    plan_file(
+        cfg,
+        &age::read(&cfg.file)?,
+        None,
+        &mut |_, _, _| {},
+        &mut on_guard,
+        &mut Vec::new(),
+    )?;
+    Ok(guarded)
+}
+
+// Tells the operations of an entry which section the parser found it in
+fn in_package(mut ops: Vec<Operation>, parser: &Parser) -> Vec<Operation> {
+    if let Some(section) = parser.section() {
+        let package = String::from_utf8_lossy(section).into_owned();
+        for op in &mut ops {
+            op.package = Some(package.clone());
+        }
+    }
+    ops
+}
+
+fn plan_file(
+    cfg: &Config,
+    content: &[u8],
+    section: Option<Vec<u8>>,
+    on_error: &mut dyn FnMut(&Config, usize, io::Error),
+    on_guard: &mut dyn FnMut(&Config, usize, String),
+    ops: &mut Vec<(usize, Operation)>,
+) -> io::Result<()> {
+    let mut parser = Parser::within(&cfg.packages, section).without(&cfg.disabled);
+    for (i, line) in bytes::lines(content).enumerate() {
+        let Some(entry) = parser.parse(line) else {
+            if parser.included()
+                && let Some(child) = include::child(cfg, i + 1)
+            {
+                let start = ops.len();
+                let section = parser.section().map(<[u8]>::to_vec);
+                plan_file(
+                    child,
+                    &include::read(child)?,
+                    section,
+                    on_error,
+                    on_guard,
+                    ops,
+                )?;
+                include::mark(&mut ops[start..], child);
+            }
+            continue;
+        };
+        if let Ok(Some(reason)) = cond::guard(&entry.modifiers, cfg) {
+            on_guard(cfg, i + 1, reason);
+            continue;
+        }
+        match plan(&entry, cfg) {
+            Ok(planned) => ops.extend(
+                in_package(planned, &parser)
+                    .into_iter()
+                    .map(|op| (i + 1, op)),
+            ),
+            Err(err) => on_error(cfg, i + 1, err),
+        }
+    }
+    Ok(())
+}
+
+/// Plans the whole file at once, pairing each operation with its line number
+/// in the file it comes from.
+pub fn load_plan(cfg: &Config) -> io::Result<Vec<(usize, Operation)>> {
+    plan_lines(cfg, &age::read(&cfg.file)?, |file_cfg, linenum, err| {
+        report_error(cfg, &file_cfg.file, linenum, None, &err)
+    })
+}
+
+/// Refuses to go on as root when an entry would leave root-owned links or
+/// directories among files of another user, as `sudo neostow` does in a home
+/// directory.
+pub fn refuse_root(cfg: &Config) -> io::Result<()> {
+    if !attrs::is_root() {
+        return Ok(());
+    }
+    // Entries that can't be planned are reported by the run itself
+    let ops = plan_lines(cfg, &age::read(&cfg.file)?, |_, _, _| {})?;
+    for (_, op) in ops {
+        let Some((dir, meta)) = op
+            .dest
+            .ancestors()
+            .find_map(|dir| Some((dir, dir.symlink_metadata().ok()?)))
+        else {
+            continue;
+        };
+        let uid = attrs::owner(&meta);
+        if uid != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "running as root, {} would be owned by root in {}, which belongs to uid {uid}. Run neostow as that user, or pass --allow-root",
+                    op.dest.display(),
+                    dir.display()
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn report_error(
+    cfg: &Config,
+    file: &Path,
+    linenum: usize,
+    op: Option<&Operation>,
+    err: &io::Error,
+) {
+    let skipped = cfg.skip_unavailable && netfs::is_unavailable_error(err);
+    report::at_line(file, linenum);
+    report::record(report::Result {
+        source: op.map(|op| op.src.as_path()),
+        dest: op.map(|op| op.dest.as_path()),
+        action: cfg.mode.name(),
+        result: if skipped { "skipped" } else { "failed" },
+        error: Some(err.to_string()),
+    });
+    if skipped {
+        printfc!(
+            LogLevel::Warn,
+            "{}:{}: Skipped: {err}",
+            file.display(),
+            linenum
+        );
+    } else {
+        printfc!(LogLevel::Error, "{}:{}: {err}", file.display(), linenum);
+    }
+}
+
+// An entry left out by its own guard, told apart from one under a condition
+fn report_guarded(cfg: &Config, linenum: usize, reason: &str) {
+    report::at_line(&cfg.file, linenum);
+    report::record(report::Result {
+        source: None,
+        dest: None,
+        action: cfg.mode.name(),
+        result: "guarded",
+        error: Some(reason.to_string()),
+    });
+    if cfg.dry || cfg.verbose {
+        printfc!(
+            LogLevel::Info,
+            "{}:{}: Guarded, {reason}",
+            cfg.file.display(),
+            linenum
+        );
+    }
+}
+
+fn report_skip(cfg: &Config, linenum: usize, reason: &str) {
+    report::at_line(&cfg.file, linenum);
+    report::record(report::Result {
+        source: None,
+        dest: None,
+        action: cfg.mode.name(),
+        result: "skipped",
+        error: Some(reason.to_string()),
+    });
+    if cfg.dry || cfg.verbose {
+        printfc!(
+            LogLevel::Info,
+            "{}:{}: Skipped, {reason}",
+            cfg.file.display(),
+            linenum
+        );
+    }
+}
+
+// An entry whose program isn't installed is passed over rather than given
+// the directory it would make
+fn skip_without_target(op: &Operation, cfg: &Config) -> bool {
+    let Some(dir) = op.dest.parent().filter(|_| op.require_target) else {
+        return false;
+    };
+    if cfg.fs.is_dir(dir) {
+        waiting::resume(cfg, &op.dest.display().to_string());
+        return false;
+    }
+    waiting::wait_for_dir(cfg, &op.dest, dir);
+    let reason = format!("{} doesn't exist", dir.display());
+    report::record(report::Result {
+        source: Some(&op.src),
+        dest: Some(&op.dest),
+        action: cfg.mode.name(),
+        result: "no-target",
+        error: Some(reason.clone()),
+    });
+    if !report::json() {
+        if cfg.dry {
+            report::out(&format!(
+                "{} {} {} ({reason})",
+                op.src.display(),
+                glyphs::get(Glyph::Arrow),
+                op.dest.display()
+            ));
+        } else if cfg.verbose {
+            printfc!(LogLevel::Info, "Skipped {}, {reason}", op.dest.display());
+        }
+    }
+    true
+}
+
+const MANUAL: &str = "requires --include-manual";
+
+// Deleting a manual entry is as deliberate as applying it
+fn skip_manual(op: &Operation, cfg: &Config) -> bool {
+    if !op.manual || cfg.include_manual {
+        return false;
+    }
+    report::record(report::Result {
+        source: Some(&op.src),
+        dest: Some(&op.dest),
+        action: cfg.mode.name(),
+        result: "skipped",
+        error: Some(MANUAL.to_string()),
+    });
+    if !report::json() {
+        if cfg.dry {
+            report::out(&format!(
+                "{} {} {} ({MANUAL})",
+                op.src.display(),
+                glyphs::get(Glyph::Arrow),
+                op.dest.display()
+            ));
+        } else {
+            printfc!(LogLevel::Info, "Skipped {}, {MANUAL}", op.dest.display());
+        }
+    }
+    true
+}
+
+// What is checked before op touches its destination, given the content
+// recorded for it: two-way conflicts, then whether something else is there,
+// or a link made before has gone missing
+fn check_dest(op: &Operation, cfg: &Config, recorded: Option<u64>) -> io::Result<bool> {
+    twoway::check(op, cfg, recorded)?;
+    let drifted = !op.managed
+        && !matches!(cfg.mode, Mode::Delete)
+        && match cfg.fs.metadata(&op.dest) {
+            Ok(_) => !status::op_in_place(op, cfg).unwrap_or(true),
+            Err(_) => recorded.is_some(),
+        };
+    // A dry run changes nothing, though --fail-on drift still hears of it
+    if drifted && recorded.is_some() {
+        report::saw(report::Condition::Drift);
+    }
+    Ok(!cfg.dry && drifted)
+}
+
+// Upper bound on lines held in memory at once, so generated configs with
+// hundreds of thousands of entries keep a flat footprint
+const BATCH_SIZE: usize = 4096;
+
+// Bookkeeping shared by every way of executing a plan: the content recorded
+// for two-way checks, and the reloads and refreshes the changes call for
+struct Session {
+    applied: state::Applied,
+    // What this run's file made, by destination, and the file itself
+    links: state::Links,
+    file: PathBuf,
+    triggered: Vec<bool>,
+    refreshes: Vec<refresh::Kind>,
+    // Entry hooks already run, by index
+    hooks_run: Vec<usize>,
+    // An --interactive answer given for all conflicts
+    answer: Option<interactive::Choice>,
+    // For stats: the entries executed, the destinations found drifted and
+    // put right, and those left alone because something was in the way
+    entries: usize,
+    drifted: Vec<PathBuf>,
+    conflicted: Vec<PathBuf>,
+    // Destinations put in place, which leave the queue of conflicts
+    placed: Vec<PathBuf>,
+}
+
+impl Session {
+    // A failing pre-run hook stops the run before anything changes
+    fn new(cfg: &Config) -> io::Result<Self> {
+        for command in &cfg.hooks.pre_run {
+            hooks::run(command, "pre-run", cfg)?;
+        }
+        Ok(Session {
+            applied: state::load_applied()?,
+            links: state::load_links()?,
+            file: fs::canonicalize(&cfg.file).unwrap_or_else(|_| cfg.file.clone()),
+            triggered: vec![false; cfg.reloads.len()],
+            refreshes: Vec::new(),
+            hooks_run: Vec::new(),
+            answer: None,
+            entries: 0,
+            drifted: Vec::new(),
+            conflicted: Vec::new(),
+            placed: Vec::new(),
+        })
+    }
+
+    // Runs the entry hooks of op that haven't run yet in this session
+    fn run_hooks(&mut self, op: &Operation, cfg: &Config, when: hooks::When) -> io::Result<()> {
+        let what = match when {
+            hooks::When::Pre => "pre",
+            hooks::When::Post => "post",
+        };
+        for (i, command) in cfg.hooks.for_op(op, cfg, when) {
+            if !self.hooks_run.contains(&i) {
+                self.hooks_run.push(i);
+                trace::time(op, Phase::Hooks, || hooks::run(command, what, cfg))?;
+            }
+        }
+        Ok(())
+    }
+
+    // With --interactive, a destination in the way is overwritten, skipped
+    // or adopted as answered; quitting fails with Interrupted
+    fn execute(&mut self, op: &Operation, cfg: &Config, operations: &mut i32) -> io::Result<bool> {
+        self.entries += 1;
+        if op.assert || skip_manual(op, cfg) || skip_without_target(op, cfg) {
+            return Ok(false);
+        }
+        if !cfg.interactive || cfg.dry || !interactive::in_the_way(op, cfg) {
+            return self.apply(op, cfg, operations);
+        }
+        match interactive::ask(op, &mut self.answer)? {
+            interactive::Choice::Overwrite => {
+                let cfg = Config {
+                    mode: Mode::Overwrite,
+                    force: true,
+                    ..cfg.clone()
+                };
+                self.apply(op, &cfg, operations)
+            }
+            interactive::Choice::Adopt => {
+                adopt::take_over(op, cfg)?;
+                self.apply(op, cfg, operations)
+            }
+            interactive::Choice::Skip => {
+                report_op(&op.src, &op.dest, cfg, "skipped");
+                Ok(false)
+            }
+            interactive::Choice::Abort => Err(io::Error::new(
+                io::ErrorKind::Interrupted,
+                "aborted, later entries were not applied",
+            )),
+        }
+    }
+
+    fn apply(&mut self, op: &Operation, cfg: &Config, operations: &mut i32) -> io::Result<bool> {
+        if cfg.skip_in_place
+            && !(op.managed || op.is_dir && cfg.no_folding)
+            && !matches!(cfg.mode, Mode::Delete)
+            && status::op_in_place(op, cfg).unwrap_or(false)
+        {
+            report_op(&op.src, &op.dest, cfg, "unchanged");
+            return Ok(false);
+        }
+        let recorded = self.applied.get(&op.dest).copied();
+        let result = trace::time(op, Phase::Change, || fetch::update(op, cfg))
+            .and_then(|()| trace::time(op, Phase::Stat, || check_dest(op, cfg, recorded)))
+            .and_then(|drifted| {
+                self.run_hooks(op, cfg, hooks::When::Pre)?;
+                let changed = trace::time(op, Phase::Change, || execute_op(op, cfg, operations))?;
+                Ok((drifted, changed))
+            });
+        self.conclude(op, cfg, result)
+    }
+
+    // The bookkeeping after op was executed, given whether its destination
+    // had drifted and whether it changed
+    fn conclude(
+        &mut self,
+        op: &Operation,
+        cfg: &Config,
+        result: io::Result<(bool, bool)>,
+    ) -> io::Result<bool> {
+        let (drifted, changed) = result.inspect_err(|err| {
+            // Not when what is there is this entry's own link
+            if err.kind() == io::ErrorKind::AlreadyExists
+                && !status::op_in_place(op, cfg).unwrap_or(false)
+            {
+                report::saw(report::Condition::Conflict);
+                self.conflicted.push(op.dest.clone());
+                if cfg.dry {
+                    report::pending(report::Pending::Conflict);
+                }
+            }
+        })?;
+        // The entry is in place by now, so a failing hook is only reported.
+        // Dry runs change nothing, but still list what would run.
+        if (changed || cfg.dry)
+            && let Err(err) = self.run_hooks(op, cfg, hooks::When::Post)
+        {
+            printfc!(LogLevel::Error, "{err}");
+        }
+        if !changed {
+            // So --dry-run-hooks describes the reloads a run would start
+            if cfg.dry && !status::op_in_place(op, cfg).unwrap_or(true) {
+                self.trigger(op, cfg);
+            }
+            return Ok(false);
+        }
+        if drifted {
+            self.drifted.push(op.dest.clone());
+        }
+        self.placed.push(op.dest.clone());
+        match cfg.mode {
+            Mode::Delete => {
+                self.applied.remove(&op.dest);
+                self.links
+                    .remove(&path::absolute(&op.dest).unwrap_or_else(|_| op.dest.clone()));
+            }
+            _ if !op.is_dir && !op.managed => {
+                if let Ok(hash) = twoway::content_hash(&op.src) {
+                    self.applied.insert(op.dest.clone(), hash);
+                }
+                self.record_link(op);
+            }
+            // Directories that aren't one link are never pruned whole
+            _ if !op.managed && !cfg.no_folding => self.record_link(op),
+            _ => {}
+        }
+        self.trigger(op, cfg);
+        Ok(true)
+    }
+
+    // The reload rules and caches a change to op's destination calls for
+    fn trigger(&mut self, op: &Operation, cfg: &Config) {
+        for (rule, hit) in cfg.reloads.iter().zip(self.triggered.iter_mut()) {
+            *hit = *hit || rule.matches(&op.dest);
+        }
+        for kind in &op.refreshes {
+            if !self.refreshes.contains(kind) {
+                self.refreshes.push(*kind);
+            }
+        }
+    }
+
+    fn record_link(&mut self, op: &Operation) {
+        let dest = path::absolute(&op.dest).unwrap_or_else(|_| op.dest.clone());
+        let src = path::absolute(&op.src).unwrap_or_else(|_| op.src.clone());
+        self.links.insert(dest, (self.file.clone(), src));
+    }
+
+    // Reverts every change made so far; only the failures are kept
+    fn roll_back(
+        self,
+        cfg: &Config,
+        failed: &[state::FailedEntry],
+        operations: &mut i32,
+    ) -> io::Result<()> {
+        let (reverted, errors) = journal::revert(&*cfg.fs, &journal::take());
+        for err in &errors {
+            printfc!(LogLevel::Error, "Rollback: {err}");
+        }
+        printfc!(LogLevel::Warn, "Rolled back {reverted} changes");
+        *operations = 0;
+        if !cfg.dry {
+            state::save_failed(failed)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self, cfg: &Config, failed: &[state::FailedEntry]) -> io::Result<()> {
+        let changes = journal::take();
+        if !cfg.dry {
+            state::save_failed(failed)?;
+            waiting::save(cfg)?;
+            state::save_applied(&self.applied)?;
+            state::save_links(&self.links)?;
+            journal::save(&changes)?;
+            store::collect()?;
+            resolve::update(&self.file, &self.conflicted, &self.placed)?;
+            stats::record(
+                &cfg.mode,
+                self.entries,
+                &self.drifted,
+                &self.conflicted,
+                failed,
+            )?;
+        }
+        refresh::run(&self.refreshes, cfg);
+        reload::run(&cfg.reloads, &self.triggered, cfg);
+        for command in &cfg.hooks.post_run {
+            if let Err(err) = hooks::run(command, "post-run", cfg) {
+                printfc!(LogLevel::Error, "{err}");
+            }
+        }
+        Ok(())
+    }
+}
+
+// What running a file and the files it includes share
+struct Run<'a> {
+    session: Session,
+    claims: conflicts::Claims,
+    failed: Vec<state::FailedEntry>,
+    deferred: Vec<Deferred<'a>>,
+    // Held back as their destination was in use, for retry_busy
+    busy: Vec<Deferred<'a>>,
+    executed: bool,
+    rolled_back: bool,
+    aborted: bool,
+}
+
+// An operation held back by --pause-between-roots or --group-by, with the
+// line it came from
+struct Deferred<'a> {
+    cfg: &'a Config,
+    path: PathBuf,
+    linenum: usize,
+    line: Vec<u8>,
+    op: Operation,
+}
+
+const BUSY_RETRIES: u32 = 3;
+const BUSY_DELAY: std::time::Duration = std::time::Duration::from_secs(2);
+
+// Holds back an operation whose destination another program has open, to
+// be tried again once the rest of the run is done
+fn hold_busy<'a>(busy: &mut Vec<Deferred<'a>>, item: Deferred<'a>) {
+    printfc!(
+        LogLevel::Warn,
+        "{}:{}: {} is in use by another program, trying it again at the end",
+        item.cfg.file.display(),
+        item.linenum,
+        item.op.dest.display()
+    );
+    busy.push(item);
+}
+
+// Tries the operations held back by hold_busy a few times, a little apart.
+// Those still in use are reported as busy rather than failed, and recorded
+// for `neostow retry`, to run once the program holding them is closed.
+fn retry_busy(run: &mut Run, operations: &mut i32) {
+    for attempt in 1..=BUSY_RETRIES {
+        if run.busy.is_empty() {
+            return;
+        }
+        std::thread::sleep(BUSY_DELAY);
+        for item in std::mem::take(&mut run.busy) {
+            report::at_line(&item.cfg.file, item.linenum);
+            let Err(err) = run.session.execute(&item.op, item.cfg, operations) else {
+                continue;
+            };
+            if !fsops::is_busy(&err) {
+                report_error(item.cfg, &item.cfg.file, item.linenum, Some(&item.op), &err);
+                record_failed(&mut run.failed, &item.path, item.linenum, &item.line);
+            } else if attempt < BUSY_RETRIES {
+                run.busy.push(item);
+            } else {
+                report::record(report::Result {
+                    source: Some(&item.op.src),
+                    dest: Some(&item.op.dest),
+                    action: item.cfg.mode.name(),
+                    result: "busy",
+                    error: Some(err.to_string()),
+                });
+                printfc!(
+                    LogLevel::Warn,
+                    "{}:{}: {} is still in use, close the program holding it and run neostow retry",
+                    item.cfg.file.display(),
+                    item.linenum,
+                    item.op.dest.display()
+                );
+                record_failed(&mut run.failed, &item.path, item.linenum, &item.line);
+            }
+        }
+    }
+}
+
+// A glob entry can fail for several matches, but is retried once
+fn record_failed(failed: &mut Vec<state::FailedEntry>, path: &Path, linenum: usize, line: &[u8]) {
+    if failed
+        .last()
+        .is_some_and(|f| f.linenum == linenum && f.file == path)
+    {
+        return;
+    }
+    failed.push(state::FailedEntry {
+        file: path.to_path_buf(),
+        linenum,
+        line: line.to_vec(),
+    });
+}
+
+// Destinations under $HOME share one root, anything else is grouped by its
+// top-level directory, like /etc
+fn target_root(dest: &Path, home: &Path) -> PathBuf {
+    if !home.as_os_str().is_empty() && dest.starts_with(home) {
+        return home.to_path_buf();
+    }
+    dest.components().take(2).collect()
+}
+
+// The group --group-by puts item in, None for entries in no package or
+// without a tag
+fn group_of(item: &Deferred, group_by: GroupBy, home: &Path) -> Option<String> {
+    match group_by {
+        GroupBy::Package => item.op.package.clone(),
+        GroupBy::Root => Some(
+            target_root(&item.op.dest, home)
+                .to_string_lossy()
+                .into_owned(),
+        ),
+        GroupBy::Tag => item.op.tag.clone(),
+    }
+}
+
+// Runs what --pause-between-roots or --group-by held back, one group at a
+// time in the order they first come up, with home first among roots.
+// Declining a root leaves it and the ones after it alone.
+fn run_grouped(cfg: &Config, run: &mut Run, operations: &mut i32) -> io::Result<()> {
+    let group_by = cfg.group_by.unwrap_or(GroupBy::Root);
+    let home = platform::home();
+    let mut groups: Vec<(Option<String>, Vec<Deferred>)> = Vec::new();
+    for item in std::mem::take(&mut run.deferred) {
+        let group = group_of(&item, group_by, &home);
+        match groups.iter_mut().find(|(g, _)| *g == group) {
+            Some((_, items)) => items.push(item),
+            None => groups.push((group, vec![item])),
+        }
+    }
+    if group_by == GroupBy::Root {
+        let home = home.to_string_lossy();
+        groups.sort_by_key(|(root, _)| root.as_deref() != Some(&home));
+    }
+
+    let total = groups.len();
+    for (n, (group, items)) in groups.into_iter().enumerate() {
+        let title = match (group_by, &group) {
+            (GroupBy::Root, Some(root)) => format!("under {root}"),
+            (GroupBy::Package, Some(name)) => format!("of package {name}"),
+            (GroupBy::Package, None) => String::from("in no package"),
+            (GroupBy::Tag, Some(tag)) => format!("tagged {tag}"),
+            (_, None) => String::from("without a tag"),
+        };
+        let question = format!("Continue with {} entries {title}?", items.len());
+        if cfg.pause_between_roots && n > 0 && !cfg.dry && !cfg.force && !prompt_user(&question)? {
+            printfc!(
+                LogLevel::Info,
+                "Stopped before entries {title}, {} of {total} roots were left alone",
+                total - n
+            );
+            return Ok(());
+        }
+        if cfg.verbose || cfg.dry {
+            printfc!(LogLevel::Info, "Entries {title}");
+        }
+        report::in_group(group.as_deref());
+        for item in items {
+            report::at_line(&item.cfg.file, item.linenum);
+            run.executed = true;
+            if let Err(err) = run.session.execute(&item.op, item.cfg, operations) {
+                if fsops::is_busy(&err) && !cfg.atomic {
+                    hold_busy(&mut run.busy, item);
+                    continue;
+                }
+                run.aborted = err.kind() == io::ErrorKind::Interrupted || cfg.fail_fast;
+                report_error(item.cfg, &item.cfg.file, item.linenum, Some(&item.op), &err);
+                record_failed(&mut run.failed, &item.path, item.linenum, &item.line);
+                if cfg.atomic {
+                    run.rolled_back = true;
+                }
+                if run.aborted || run.rolled_back {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// With --atomic, the whole file is planned before anything is executed, and
+// a failure rolls back every change the run made
+pub fn run(cfg: &Config, operations: &mut i32) -> io::Result<()> {
+    if cfg.strict {
+        check::report(cfg)?;
+    }
+    report::plan_start(&cfg.file, cfg.mode.name(), cfg.dry);
+    if cfg.dry {
+        report::summarize_plan();
+    }
+    budget::check(cfg)?;
+    if cfg.debug {
+        printfc!(LogLevel::Debug, "Run {}", state::run_id());
+    }
+    waiting::start(cfg);
+    let mut run = Run {
+        session: Session::new(cfg)?,
+        claims: conflicts::Claims::new(),
+        failed: Vec::new(),
+        deferred: Vec::new(),
+        busy: Vec::new(),
+        executed: false,
+        rolled_back: false,
+        aborted: false,
+    };
+    run_file(cfg, None, &mut run, operations)?;
+    if !run.deferred.is_empty() && !run.aborted && !run.rolled_back {
+        run_grouped(cfg, &mut run, operations)?;
+        report::in_group(None);
+    }
+    if !run.aborted && !run.rolled_back {
+        retry_busy(&mut run, operations);
+    }
+    if run.aborted && cfg.fail_fast && report::failed() > 0 {
+        printfc!(
+            LogLevel::Error,
+            "Stopped at the first failure, later entries were not applied"
+        );
+    }
+    if !run.aborted && !run.rolled_back && !cfg.sandboxed {
+        registry::apply(cfg, operations);
+        defaults::apply(cfg, operations);
+    }
+
+    if run.rolled_back {
+        run.session.roll_back(cfg, &run.failed, operations)
+    } else {
+        run.session.finish(cfg, &run.failed)
+    }
+}
+
+// Runs the lines of one file in batches. An include ends a batch, so the
+// included file runs in its place, between the lines around it.
+fn run_file<'a>(
+    cfg: &'a Config,
+    section: Option<Vec<u8>>,
+    run: &mut Run<'a>,
+    operations: &mut i32,
+) -> io::Result<()> {
+    let batch_size = if cfg.atomic { usize::MAX } else { BATCH_SIZE };
+    let mut lines = age::open(&cfg.file)?.split(b'\n');
+    let path = fs::canonicalize(&cfg.file)?;
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut parser = Parser::within(&cfg.packages, section).without(&cfg.disabled);
+    let mut linenum = 0;
+
+    loop {
+        batch.clear();
+        for line in lines.by_ref() {
+            let mut line = line?;
+            if !cfg.params.is_empty() {
+                line = include::fill(&cfg.params, &line);
+            }
+            let include = include::parse(&line).is_some();
+            batch.push(line);
+            if include || batch.len() == batch_size {
+                break;
+            }
+        }
+        if batch.is_empty() {
+            break;
+        }
+
+        let failed = &mut run.failed;
+        // err is None for the failures --jobs workers reported as they went
+        let mut fail = |i: usize, op: Option<&Operation>, err: Option<io::Error>| {
+            if let Some(err) = err {
+                report_error(cfg, &cfg.file, linenum + i + 1, op, &err);
+            }
+            record_failed(failed, &path, linenum + i + 1, &batch[i]);
+        };
+
+        let mut planned = Vec::new();
+        let mut first_failed = None;
+        for (i, line) in batch.iter().enumerate() {
+            let Some(entry) = parser.parse(line) else {
+                if let Some(reason) = parser.skipped() {
+                    report_skip(cfg, linenum + i + 1, reason);
+                    waiting::wait_for_condition(cfg, line, parser.gate());
+                }
+                continue;
+            };
+            waiting::resume(cfg, &String::from_utf8_lossy(line.trim_ascii()));
+            if let Ok(Some(reason)) = cond::guard(&entry.modifiers, cfg) {
+                report_guarded(cfg, linenum + i + 1, &reason);
+                continue;
+            }
+            let ops = match plan(&entry, cfg) {
+                Ok(ops) => in_package(ops, &parser),
+                Err(err) => {
+                    fail(i, None, Some(err));
+                    first_failed.get_or_insert(i);
+                    continue;
+                }
+            };
+            for op in ops {
+                match conflicts::claim(&mut run.claims, &op, linenum + i + 1, cfg) {
+                    Ok(()) => planned.push((i, op)),
+                    Err(err) => {
+                        fail(i, Some(&op), Some(err));
+                        first_failed.get_or_insert(i);
+                    }
+                }
+            }
+        }
+        if cfg.atomic && first_failed.is_some() {
+            // Files included earlier may have changed things already
+            if run.executed {
+                printfc!(LogLevel::Error, "Planning failed");
+                run.rolled_back = true;
+            } else {
+                printfc!(LogLevel::Error, "Planning failed, nothing was changed");
+                run.aborted = true;
+            }
+            return Ok(());
+        }
+        // Entries before the first that failed still run
+        if cfg.fail_fast
+            && let Some(k) = first_failed
+        {
+            planned.retain(|(i, _)| *i < k);
+            run.aborted = true;
+        }
+
+        let grouped = cfg.pause_between_roots || cfg.group_by.is_some();
+        if cfg.jobs > 1 && !cfg.interactive && !grouped {
+            run.executed |= !planned.is_empty();
+            let failures = parallel::run(&mut run.session, &planned, cfg, linenum + 1, operations);
+            let mut busy = Vec::new();
+            let mut stop = false;
+            for (k, err) in failures {
+                if fsops::is_busy(&err) && !cfg.atomic {
+                    busy.push(k);
+                    continue;
+                }
+                fail(planned[k].0, None, None);
+                stop = cfg.atomic || cfg.fail_fast;
+            }
+            if stop {
+                run.rolled_back = cfg.atomic;
+                run.aborted = cfg.fail_fast;
+                return Ok(());
+            }
+            for (k, (i, op)) in planned.drain(..).enumerate() {
+                if busy.contains(&k) {
+                    let item = Deferred {
+                        cfg,
+                        path: path.clone(),
+                        linenum: linenum + i + 1,
+                        line: batch[i].clone(),
+                        op,
+                    };
+                    hold_busy(&mut run.busy, item);
+                }
+            }
+        }
+        for (i, op) in planned {
+            if grouped {
+                run.deferred.push(Deferred {
+                    cfg,
+                    path: path.clone(),
+                    linenum: linenum + i + 1,
+                    line: batch[i].clone(),
+                    op,
+                });
+                continue;
+            }
+            report::at_line(&cfg.file, linenum + i + 1);
+            run.executed = true;
+            if let Err(err) = run.session.execute(&op, cfg, operations) {
+                if fsops::is_busy(&err) && !cfg.atomic {
+                    let item = Deferred {
+                        cfg,
+                        path: path.clone(),
+                        linenum: linenum + i + 1,
+                        line: batch[i].clone(),
+                        op,
+                    };
+                    hold_busy(&mut run.busy, item);
+                    continue;
+                }
+                run.aborted = err.kind() == io::ErrorKind::Interrupted || cfg.fail_fast;
+                fail(i, Some(&op), Some(err));
+                if cfg.atomic {
+                    run.rolled_back = true;
+                }
+                if run.aborted || run.rolled_back {
+                    return Ok(());
+                }
+            }
+        }
+        linenum += batch.len();
+        if run.aborted {
+            return Ok(());
+        }
+
+        if parser.included()
+            && let Some(child) = include::child(cfg, linenum)
+        {
+            run_file(child, parser.section().map(<[u8]>::to_vec), run, operations)?;
+            if run.aborted || run.rolled_back {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Applies every selected entry of the file and returns how many links
+/// changed. Entries that fail are reported and recorded for `retry`.
+pub fn apply(cfg: &Config) -> io::Result<i32> {
+    let mut operations = 0;
+    run(cfg, &mut operations)?;
+    Ok(operations)
+}
+
+// Reverts the changes recorded by the last run that made any
+pub fn undo(cfg: &Config) -> io::Result<()> {
+    let (run, changes) = journal::load()?;
+    if changes.is_empty() {
+        printfc!(LogLevel::Info, "Nothing to undo");
+        return Ok(());
+    }
+    let run = run.map_or_else(|| String::from("the last run"), |id| format!("run {id}"));
+    if cfg.dry {
+        printfc!(
+            LogLevel::Info,
+            "Would undo {} changes of {run}",
+            changes.len()
+        );
+        return Ok(());
+    }
+    if cfg.verbose {
+        printfc!(LogLevel::Info, "Undoing {run}");
+    }
+
+    let (reverted, errors) = journal::revert(&*cfg.fs, &changes);
+    for err in &errors {
+        printfc!(LogLevel::Error, "{err}");
+    }
+    if errors.is_empty() {
+        journal::clear()?;
+    }
+    println!("{reverted} changes were undone.");
+    Ok(())
+}
+
+// Runs the failed entries again, file by file, each in a session of its own
+// with the file loaded as a run would, so what the run does besides the
+// change itself, like recording links and clearing the queue of conflicts,
+// happens here too
+pub fn retry(cfg: &Config, operations: &mut i32) -> io::Result<()> {
+    report::plan_start(&cfg.file, "retry", cfg.dry);
+    if cfg.dry {
+        report::summarize_plan();
+    }
+    let entries = state::load_failed()?;
+    if entries.is_empty() {
+        printfc!(LogLevel::Info, "No failed entries to retry");
+        return Ok(());
+    }
+
+    let mut files: Vec<(PathBuf, Vec<state::FailedEntry>)> = Vec::new();
+    for entry in entries {
+        match files.iter_mut().find(|(file, _)| *file == entry.file) {
+            Some((_, entries)) => entries.push(entry),
+            None => files.push((entry.file.clone(), vec![entry])),
+        }
+    }
+    let top = fs::canonicalize(&cfg.file).unwrap_or_else(|_| cfg.file.clone());
+    let mut failed = Vec::new();
+    while !files.is_empty() {
+        let (file, mut entries) = files.remove(0);
+        entries.sort_by_key(|entry| entry.linenum);
+        // The entries of the files still to come stay on record meanwhile
+        let pending: Vec<_> = files
+            .iter()
+            .flat_map(|(_, entries)| entries.iter().cloned())
+            .collect();
+        let file_cfg = if file == top {
+            cfg.clone()
+        } else {
+            let fresh = Config::new(file.clone());
+            let mut file_cfg = Config {
+                file: fresh.file,
+                basedir: fresh.basedir,
+                ..cfg.clone()
+            };
+            if let Err(err) = file_cfg.load() {
+                for entry in entries {
+                    report_error(cfg, &file, entry.linenum, None, &err);
+                    failed.push(entry);
+                }
+                continue;
+            }
+            file_cfg
+        };
+        retry_file(&file_cfg, &entries, &pending, &mut failed, operations)?;
+    }
+    Ok(())
+}
+
+// Plans the whole file, so each entry is taken in its section and checked
+// against the destinations claimed before it, and executes the operations
+// of the lines retried. A line that reads differently by now is planned
+// alone, as it was when it failed.
+fn retry_file(
+    cfg: &Config,
+    entries: &[state::FailedEntry],
+    pending: &[state::FailedEntry],
+    failed: &mut Vec<state::FailedEntry>,
+    operations: &mut i32,
+) -> io::Result<()> {
+    let content = age::read(&cfg.file)?;
+    let lines: Vec<&[u8]> = bytes::lines(&content).collect();
+    let unchanged = |entry: &state::FailedEntry| {
+        lines
+            .get(entry.linenum.wrapping_sub(1))
+            .is_some_and(|line| line.trim_ascii() == entry.line.trim_ascii())
+    };
+    let retried = |linenum: usize| {
+        entries
+            .iter()
+            .find(|entry| entry.linenum == linenum && unchanged(entry))
+    };
+
+    let mut planned = Vec::new();
+    let ops = plan_lines(cfg, &content, |file_cfg, linenum, err| {
+        if file_cfg.file == cfg.file
+            && let Some(entry) = retried(linenum)
+        {
+            report_error(cfg, &cfg.file, linenum, None, &err);
+            record_failed(failed, &entry.file, linenum, &entry.line);
+        }
+    })?;
+    let mut claims = conflicts::Claims::new();
+    for (linenum, op) in ops {
+        let claimed = conflicts::claim(&mut claims, &op, linenum, cfg);
+        if op.origin.is_some() {
+            continue;
+        }
+        let Some(entry) = retried(linenum) else {
+            continue;
+        };
+        match claimed {
+            Ok(()) => planned.push((entry, op)),
+            Err(err) => {
+                report_error(cfg, &cfg.file, linenum, Some(&op), &err);
+                record_failed(failed, &entry.file, linenum, &entry.line);
+            }
+        }
+    }
+    for entry in entries.iter().filter(|entry| !unchanged(entry)) {
+        let Some(parsed) = parse_line(&entry.line) else {
+            continue;
+        };
+        match plan(&parsed, cfg) {
+            Ok(ops) => planned.extend(ops.into_iter().map(|op| (entry, op))),
+            Err(err) => {
+                report_error(cfg, &cfg.file, entry.linenum, None, &err);
+                record_failed(failed, &entry.file, entry.linenum, &entry.line);
+            }
+        }
+    }
+
+    let mut session = Session::new(cfg)?;
+    for (entry, op) in planned {
+        report::at_line(&cfg.file, entry.linenum);
+        if let Err(err) = session.execute(&op, cfg, operations) {
+            report_error(cfg, &cfg.file, entry.linenum, Some(&op), &err);
+            record_failed(failed, &entry.file, entry.linenum, &entry.line);
+        }
+    }
+    let kept: Vec<_> = failed.iter().chain(pending).cloned().collect();
+    session.finish(cfg, &kept)
+}
+
+pub fn edit_file(path: &Path) -> io::Result<()> {
+    editor::open(path)
+}
+
+// Scripts, and runs given --assume-yes or --assume-no, get an answer
+// without being asked
+pub fn prompt_user(prompt: &str) -> io::Result<bool> {
+    if let Some(yes) = report::assumed() {
+        let answer = if yes { "yes" } else { "no" };
+        printfc!(LogLevel::Info, "{prompt} Assuming {answer}");
+        return Ok(yes);
+    }
+    let input = report::ask(prompt, " [y/N] \n", &["y", "n"])?.unwrap_or_default();
+    Ok(matches!(input.to_lowercase().as_str(), "y" | "yes"))
+}
+
+// Shows how dest differs from src, paged when it is long, and returns
+// whether it does
+pub(crate) fn run_diff(src: &Path, dest: &Path) -> io::Result<bool> {
+    let mut out = Vec::new();
+    let differ = diff::paths(src, dest, &mut out)?;
+    let verdict = if differ {
+        "Files differ."
+    } else {
+        "Files are identical."
+    };
+    writeln!(out, "{verdict}")?;
+    // Diffs go to stderr when stdout carries JSON
+    if report::json() {
+        io::stderr().write_all(&out)?;
+        return Ok(differ);
+    }
+    report::page(&out)?;
+    Ok(differ)
+}
@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+
+use crate::glyphs::{self, Glyph};
+use crate::log::{COLOR_GREEN, COLOR_RED, COLOR_RESET, COLOR_YELLOW, colored};
+use crate::status::{State, classify};
+use crate::{Config, Operation, glob, platform};
+
+const KEYS: [&str; 7] = ["package", "tag", "desc", "state", "kind", "src", "dest"];
+
+/// A `--where KEY=VALUE` condition on the entries to list. VALUE is a glob,
+/// and an empty one matches entries without a package, tag or description.
+pub struct Filter {
+    key: &'static str,
+    pattern: String,
+}
+
+impl Filter {
+    pub fn parse(arg: &str) -> Result<Self, String> {
+        let Some((key, pattern)) = arg.split_once('=') else {
+            return Err(format!("expected KEY=VALUE, not '{arg}'"));
+        };
+        let Some(&key) = KEYS.iter().find(|&&k| k == key) else {
+            return Err(format!(
+                "unknown key '{key}', expected one of {}",
+                KEYS.join(", ")
+            ));
+        };
+        let pattern = match pattern.strip_prefix("~/") {
+            Some(rest) if key == "dest" => {
+                platform::home().join(rest).to_string_lossy().into_owned()
+            }
+            _ => pattern.to_string(),
+        };
+        Ok(Filter { key, pattern })
+    }
+
+    fn accepts(&self, op: &Operation, state: &str, cfg: &Config) -> bool {
+        let value = match self.key {
+            "package" => op.package.clone().unwrap_or_default(),
+            "tag" => op.tag.clone().unwrap_or_default(),
+            "desc" => op.desc.clone().unwrap_or_default(),
+            "state" => state.to_string(),
+            "kind" => String::from(if op.is_dir { "dir" } else { "file" }),
+            "src" => source(op, cfg).to_string_lossy().into_owned(),
+            _ => op.dest.to_string_lossy().into_owned(),
+        };
+        glob::matches(self.pattern.as_bytes(), value.as_bytes())
+    }
+}
+
+// What listing tells apart: linked, not linked yet, or in the way of
+// linking, either by a file of its own or by another entry claiming the
+// same destination
+fn state(op: &Operation, claimed: usize, cfg: &Config) -> (&'static str, &'static str) {
+    if claimed > 1 {
+        return ("conflicting", COLOR_RED);
+    }
+    match classify(op, cfg) {
+        Ok(State::Linked) => ("linked", COLOR_GREEN),
+        Ok(State::Holds) => ("holds", COLOR_GREEN),
+        Ok(State::Broken(_)) => ("broken", COLOR_RED),
+        Ok(State::Conflict) => ("conflicting", COLOR_RED),
+        _ => ("unlinked", COLOR_YELLOW),
+    }
+}
+
+fn source<'a>(op: &'a Operation, cfg: &Config) -> &'a Path {
+    op.src.strip_prefix(&cfg.basedir).unwrap_or(&op.src)
+}
+
+// The !desc of op, set off from the paths before it
+fn desc(op: &Operation) -> String {
+    op.desc.as_deref().map_or_else(String::new, |desc| {
+        format!(" {} {desc}", glyphs::get(Glyph::Dash))
+    })
+}
+
+// Prints every mapping of the plan that passes filters, colored by state
+// when out is a terminal. As a tree, destinations are grouped under their
+// directory.
+pub fn list(
+    ops: &[(usize, Operation)],
+    cfg: &Config,
+    tree: bool,
+    filters: &[Filter],
+) -> io::Result<()> {
+    let mut claims: BTreeMap<&Path, usize> = BTreeMap::new();
+    for (_, op) in ops.iter().filter(|(_, op)| !op.assert) {
+        *claims.entry(op.dest.as_path()).or_default() += 1;
+    }
+    // Claims are counted over every entry, so a conflict with one filtered
+    // out still shows
+    let ops: Vec<&Operation> = ops
+        .iter()
+        .map(|(_, op)| op)
+        .filter(|op| {
+            filters.is_empty() || {
+                let (state, _) =
+                    state(op, claims.get(op.dest.as_path()).copied().unwrap_or(0), cfg);
+                filters.iter().all(|f| f.accepts(op, state, cfg))
+            }
+        })
+        .collect();
+
+    let mut out = io::stdout().lock();
+    let color = colored(out.is_terminal());
+    let paint = |op: &Operation, text: &str| {
+        let (name, code) = state(op, claims.get(op.dest.as_path()).copied().unwrap_or(0), cfg);
+        if color {
+            format!("{code}{text}{COLOR_RESET}")
+        } else {
+            format!("{text} ({name})")
+        }
+    };
+
+    if !tree {
+        for op in ops {
+            let dest = paint(op, &op.dest.display().to_string());
+            writeln!(
+                out,
+                "{} {} {dest}{}",
+                source(op, cfg).display(),
+                glyphs::get(Glyph::Arrow),
+                desc(op)
+            )?;
+        }
+        return Ok(());
+    }
+
+    let mut dirs: BTreeMap<&Path, Vec<&Operation>> = BTreeMap::new();
+    for op in ops {
+        let dir = op.dest.parent().unwrap_or(Path::new("/"));
+        dirs.entry(dir).or_default().push(op);
+    }
+    for (dir, mut ops) in dirs {
+        ops.sort_by(|a, b| a.dest.cmp(&b.dest));
+        writeln!(out, "{}", dir.display())?;
+        for (i, op) in ops.iter().enumerate() {
+            let branch = if i + 1 == ops.len() {
+                glyphs::get(Glyph::Last)
+            } else {
+                glyphs::get(Glyph::Branch)
+            };
+            let slash = if op.is_dir { "/" } else { "" };
+            let name = op.dest.file_name().unwrap_or_default().display();
+            let name = paint(op, &format!("{name}{slash}"));
+            writeln!(
+                out,
+                "{branch} {name} {} {}{}",
+                glyphs::get(Glyph::Back),
+                source(op, cfg).display(),
+                desc(op)
+            )?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+// Messages go through printfc!, which formats them and leaves the rest to
+// this module: whether a level shows at all (--log-level, --quiet), whether
+// it is colored (--color, --no-color, NO_COLOR and whether the stream is a
+// terminal), and which file it is also appended to (--log-file):
+//   2026-10-15T09:30:12Z ERROR /home/me/dots/.neostow:3: ...
+
+use std::env;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{backup, report};
+
+pub(crate) const COLOR_RED: &str = "\x1b[91m";
+pub(crate) const COLOR_YELLOW: &str = "\x1b[33m";
+pub(crate) const COLOR_GREEN: &str = "\x1b[38;5;47m";
+pub(crate) const COLOR_BLUE: &str = "\x1b[38;5;75m";
+pub(crate) const COLOR_RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Fatal,
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    fn label(self) -> &'static str {
+        match self {
+            LogLevel::Fatal => "FATAL",
+            LogLevel::Error => "ERROR",
+            LogLevel::Warn => "WARNING",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+
+    /// The level --log-level names: error, warn, info or debug.
+    pub fn parse(name: &str) -> Option<LogLevel> {
+        match name {
+            "error" => Some(LogLevel::Error),
+            "warn" | "warning" => Some(LogLevel::Warn),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+}
+
+/// When log lines and listings are colored. Auto, the default, colors them
+/// on a terminal unless NO_COLOR is set.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Color {
+    Always,
+    Never,
+    Auto,
+}
+
+static COLOR: AtomicU8 = AtomicU8::new(Color::Auto as u8);
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Debug as u8);
+static LOG_FILE: Mutex<Option<File>> = Mutex::new(None);
+
+pub fn set_color(color: Color) {
+    COLOR.store(color as u8, Ordering::Relaxed);
+}
+
+/// Hides messages less severe than level. Debug messages only show with
+/// --debug in any case.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Whether messages of level show on the terminal.
+pub fn shows(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+/// Appends every message, whatever the level shown, to the file at path.
+pub fn set_file(path: &Path) -> io::Result<()> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    *LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    Ok(())
+}
+
+// Whether output to a terminal or not is colored
+pub(crate) fn colored(terminal: bool) -> bool {
+    match COLOR.load(Ordering::Relaxed) {
+        c if c == Color::Always as u8 => true,
+        c if c == Color::Never as u8 => false,
+        _ => terminal && env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()),
+    }
+}
+
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, rem) = (secs / 86400, secs % 86400);
+    let (year, month, day) = backup::civil(days as i64);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[doc(hidden)]
+pub fn printfc_func(level: LogLevel, fmt: fmt::Arguments) -> io::Result<()> {
+    if let Some(file) = LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+        writeln!(file, "{} {} {fmt}", timestamp(), level.label())?;
+    }
+    if !shows(level) {
+        return Ok(());
+    }
+
+    let color = match level {
+        LogLevel::Fatal | LogLevel::Error => COLOR_RED,
+        LogLevel::Warn => COLOR_YELLOW,
+        LogLevel::Info => COLOR_GREEN,
+        LogLevel::Debug => COLOR_BLUE,
+    };
+    let to_stderr = report::json() || matches!(level, LogLevel::Fatal | LogLevel::Error);
+    let (mut out, terminal): (Box<dyn Write>, bool) = if to_stderr {
+        (Box::new(io::stderr()), io::stderr().is_terminal())
+    } else {
+        (Box::new(io::stdout()), io::stdout().is_terminal())
+    };
+    let label = level.label();
+    // One write, so lines from --jobs workers never interleave
+    let line = if colored(terminal) {
+        format!("{color}[{label}]:{COLOR_RESET} {fmt}\n")
+    } else {
+        format!("[{label}]: {fmt}\n")
+    };
+    // A --jobs worker's lines wait for the entries before its own
+    if report::keep(to_stderr, &line) {
+        return Ok(());
+    }
+    out.write_all(line.as_bytes())?;
+    out.flush()?;
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::path::{Path, PathBuf};
+
+use crate::expand::normalize;
+use crate::{Config, Operation};
+
+// An entry matches by its source relative to basedir, its file name, or the
+// full destination path, so both `nvim` and `~/.config/nvim` find it
+pub fn matches(op: &Operation, cfg: &Config, query: &str) -> bool {
+    let query = Path::new(query.trim_end_matches('/'));
+    let as_dest = std::path::absolute(query).map(|p| normalize(&p)).ok();
+
+    op.src
+        .strip_prefix(&cfg.basedir)
+        .is_ok_and(|rel| rel == query)
+        || op
+            .src
+            .file_name()
+            .is_some_and(|name| name == query.as_os_str())
+        || as_dest
+            .as_deref()
+            .is_some_and(|dest| std::path::absolute(&op.dest).is_ok_and(|d| d == dest))
+}
+
+pub fn find<'a>(ops: &'a [(usize, Operation)], cfg: &Config, query: &str) -> Option<&'a Operation> {
+    ops.iter()
+        .map(|(_, op)| op)
+        .find(|op| matches(op, cfg, query))
+}
+
+// Directories are returned as is, files as their parent, so the result can
+// always be handed to `cd`
+pub fn dir_of(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        path.to_path_buf()
+    } else {
+        path.parent().map(PathBuf::from).unwrap_or_default()
+    }
+}
+
+// Maps a live path back to the source backing it. Paths below a linked
+// directory resolve into that directory's source, the deepest link winning.
+pub fn source_of(ops: &[(usize, Operation)], path: &Path) -> Option<PathBuf> {
+    let path = normalize(&std::path::absolute(path).ok()?);
+
+    ops.iter()
+        .filter_map(|(_, op)| {
+            let dest = normalize(&std::path::absolute(&op.dest).ok()?);
+            let rest = path.strip_prefix(&dest).ok()?;
+            if rest.as_os_str().is_empty() {
+                Some((dest, op.src.clone()))
+            } else if op.is_dir {
+                Some((dest, op.src.join(rest)))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|(dest, _)| dest.components().count())
+        .map(|(_, src)| src)
+}
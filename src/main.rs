@@ -1,66 +1,89 @@
 use std::env;
-use std::fmt;
 use std::fs;
-use std::io::{self, BufRead, Write};
-use std::path::{Path, PathBuf};
-use std::process::{Command, exit};
+use std::io::{self, IsTerminal};
+use std::path::{self, Path, PathBuf};
+use std::process::exit;
 
-#[cfg(unix)]
-use std::os::unix::fs::symlink;
+use neostow::expand::normalize;
+use neostow::{
+    Color, Config, GroupBy, LinkMode, LogLevel, Mode, activation, adopt, aliases, backup, bench,
+    bootstrap, budget, capabilities, check, conflicts, decommission, doctor, edit_file, editor,
+    export, format, glyphs, guard, history, import, init, list, load_plan, log, lookup, meta,
+    overlay, overrides, ownership, plan_diff, platform, printfc, prompt_user, prune, report,
+    resolve, retry, run, sandbox, selftest, service, set_color, settings, setup, shellenv,
+    snapshot, stats, status, suggest, trace, undo, vars, vcs, watch,
+};
 
-#[cfg(windows)]
-use std::os::windows::fs::{symlink_dir, symlink_file};
+use args::{Arg, Args};
 
-enum Mode {
-    Create,
-    Overwrite,
-    Delete,
-}
-
-struct Config {
-    file: PathBuf,
-    basedir: PathBuf,
-    mode: Mode,
-    verbose: bool,
-    force: bool,
-    dry: bool,
-    debug: bool,
-}
-
-const COLOR_RED: &str = "\x1b[91m";
-// const COLOR_YELLOW: &str = "\x1b[33m";
-const COLOR_GREEN: &str = "\x1b[38;5;47m";
-const COLOR_BLUE: &str = "\x1b[38;5;75m";
-const COLOR_RESET: &str = "\x1b[0m";
+mod args;
 
-#[derive(Debug)]
-enum LogLevel {
-    Fatal,
-    Error,
-    // Warn,
-    Info,
-    Debug,
+enum Action {
+    Apply,
+    Edit,
+    Retry,
+    ExportDot,
+    // Install when true, uninstall when false
+    Service(bool),
+    GuardInstall,
+    Conflicts,
+    List,
+    Path(String),
+    EditFile(PathBuf),
+    Status,
+    Adopt,
+    Restore(PathBuf),
+    RestoreOriginal(Option<PathBuf>),
+    Undo,
+    Setup,
+    Init(Option<PathBuf>),
+    Suggest,
+    Bench,
+    Stats,
+    Log,
+    Env,
+    Check,
+    Prune,
+    Decommission,
+    Doctor(Option<PathBuf>),
+    Watch,
+    // The Stow directory, then the target, which defaults to its parent
+    ImportStow(PathBuf, Option<PathBuf>),
+    PlanDiff(Option<PathBuf>, Option<PathBuf>),
+    ChownFix,
+    Bootstrap(String),
+    Selftest,
+    Capabilities,
+    Resolve,
+    // Enable when true, disable when false
+    Enable(bool),
+    NewHost,
+    // The root, then whether to compare it against its snapshot
+    Snapshot(Option<PathBuf>, bool),
 }
 
-fn printfc_func(level: LogLevel, fmt: fmt::Arguments) -> io::Result<()> {
-    let (color, label, mut out): (&str, &str, Box<dyn Write>) = match level {
-        LogLevel::Fatal => (COLOR_RED, "FATAL", Box::new(io::stderr())),
-        LogLevel::Error => (COLOR_RED, "ERROR", Box::new(io::stderr())),
-        // LogLevel::Warn => (COLOR_YELLOW, "WARNING", Box::new(io::stdout())),
-        LogLevel::Info => (COLOR_GREEN, "INFO", Box::new(io::stdout())),
-        LogLevel::Debug => (COLOR_BLUE, "DEBUG", Box::new(io::stdout())),
-    };
-
-    write!(out, "{}[{}]:{} ", color, label, COLOR_RESET)?;
-    write!(out, "{}\n", fmt)?;
-    out.flush()?;
-    Ok(())
-}
-
-macro_rules! printfc {
-    ($level:expr, $($arg:tt)*) => {
-        printfc_func($level, format_args!($($arg)*)).unwrap();
-    };
+// Says how many entries of each package delete --all would remove, and asks
+fn confirm_delete_all(cfg: &Config) -> io::Result<bool> {
+    let ops = load_plan(cfg)?;
+    let mut packages: Vec<(Option<&str>, usize)> = Vec::new();
+    for (_, op) in &ops {
+        let package = op.package.as_deref();
+        match packages.iter_mut().find(|(name, _)| *name == package) {
+            Some((_, count)) => *count += 1,
+            None => packages.push((package, 1)),
+        }
+    }
+    for (package, count) in &packages {
+        match package {
+            Some(name) => println!("  {count} in [{name}]"),
+            None => println!("  {count} in no package"),
+        }
+    }
+    prompt_user(&format!(
+        "Remove all {} entries of {}?",
+        ops.len(),
+        cfg.file.display()
+    ))
 }
 
 fn help() {
@@ -68,13 +91,83 @@ fn help() {
         "\
 neostow | The Declarative GNU Stow
 
-Usage:  neostow [OPTIONS] <COMMAND>
+Usage:  neostow [OPTIONS] <COMMAND> [PACKAGE]...
 
 Commands:
-  delete
-          Delete symlinks
-  edit
-          Edit the neostow file
+  adopt [--review] [PACKAGE... | PATH...]
+          Move existing destinations, or files no entry has yet, into the repo and link them
+  bootstrap <URL> [PACKAGE]...
+          Clone a dotfiles repository and apply it, only checking out what the packages need
+  capabilities
+          Say what this build and platform support, for scripts with --json
+  check
+          Validate the neostow file and report every problem in it
+  chown-fix
+          Give links and directories left owned by another user back to the owner of $HOME
+  conflicts
+          Report destinations claimed by more than one entry
+  decommission
+          Remove everything neostow made on this machine and put back what it replaced
+  delete [--all] [PACKAGE]...
+          Delete symlinks, of the packages given or with --all of every entry
+  disable <PACKAGE>...
+          Leave packages out of runs that name none, on this machine
+  doctor [--fix] [DIR]
+          Find broken links, stray links into the dotfiles and unreadable paths
+  edit [local | host | INCLUDE]
+          Edit the neostow file, its local or host override file, or a file it includes
+  edit-file <DESTINATION>
+          Edit the source backing a destination
+  enable <PACKAGE>...
+          Let packages disabled before back into runs that name none
+  env
+          Print shell exports for the active config and its bin directories
+  export dot
+          Print a Graphviz graph of the mappings
+  guard install
+          Write a shell script warning before managed copies are edited in place
+  import-stow [--remove-links] <STOW-DIR> [TARGET-DIR]
+          Write a neostow file for the packages of a GNU Stow directory
+  init [--from-links DIR] [DIR]
+          Write a starter neostow file for a directory
+  list [--tree] [--where KEY=VALUE]...
+          Print every mapping, colored by whether it is linked
+  log [--since TIME] [--until TIME] [--failed]
+          List the runs on this machine, with what failed, drifted or was in the way
+  new-host
+          Write an override file for this machine and include it from the neostow file
+  path [--dest] <ENTRY>
+          Print the source directory of an entry
+  plan-diff <OLD> <NEW> | --rev <OLD>[..NEW]
+          Compare the plans of two versions of a neostow file
+  prune
+          Remove links made by earlier runs whose entries are gone from the file
+  resolve
+          Decide about the destinations earlier runs found in the way
+  restore <DESTINATION>
+          Put back the latest backup of a destination
+  restore-original <DESTINATION> | --all
+          Put back the original kept by !keep-original, removing the link
+  retry
+          Re-attempt entries that failed in the last run
+  selftest
+          Try the file out on a throwaway home: create, verify, overwrite, delete
+  service install|uninstall
+          Apply the neostow file on a schedule with systemd, launchd or Task Scheduler
+  setup
+          Create a neostow file with a guided wizard
+  snapshot [diff] [DIR]
+          Record every path under DIR, or compare it against the record
+  stats
+          Show trends of the runs on this machine
+  status
+          Report whether each destination is linked
+  suggest
+          Suggest mappings for well-known or installed programs
+  undo
+          Revert the changes of the last run
+  watch
+          Apply, then apply again whenever the neostow file or a source changes
 
 Options:
   -F, --force
@@ -82,279 +175,1226 @@ Options:
   -V, --verbose
           Enable verbosity
   -d, --dry
-          Describe potential operations
+          Describe potential operations, exiting with 2 if any are pending
   -f, --file <FILE>
           Load an alternative neostow file
   -h, --help
           Displays this message and exits
   -o, --overwrite
           Overwrite existing symlinks
+  -s, --skip-unavailable
+          Skip destinations on unreachable network mounts
+  -r, --root <DIR>
+          Reject destinations outside of DIR
+      --plan-diff
+          With edit, show and apply the resulting plan changes
+      --gc-broken
+          Remove dangling symlinks in managed directories
+      --convert-shadows
+          With status, replace identical copies with links
+      --no-folding
+          Link the files of directory sources instead of the directory
+      --backup[=DIR]
+          With overwrite, back up replaced files instead of deleting them
+      --atomic
+          Roll back the whole run if any entry fails
+      --output <FORMAT>
+          Print results as text (default), json or ndjson
+      --mode <MODE>
+          Make destinations as symlinks (default), copies or hard links
+  -t, --target <DIR>
+          Put destinations under DIR, with ~ and $HOME expanding to it
+      --preserve=LIST
+          With --mode copy, give copies the mode, times or xattr of their source
+  -i, --interactive
+          Ask what to do with each destination in the way
+      --open-report
+          Open an HTML report of the run when it ends
+      --from-links <DIR>
+          With init, turn links under DIR into entries
+      --include-manual
+          Also apply entries marked !manual
+      --pause-between-roots
+          Apply entries one target root at a time, home first
+      --jobs <N>
+          Apply up to N entries at once
+      --strict
+          Refuse to apply a file with any problem check would report
+      --all
+          With restore-original, restore every kept original, with delete, remove every entry
+      --fix
+          With doctor, remove the broken links neostow made
+      --provenance
+          With --mode copy, say in each copy which source it comes from
+      --color <WHEN>
+          Color log lines and listings: always, never or auto
+      --no-color
+          Never color output, as --color never
+  -q, --quiet
+          Show only warnings and errors
+      --log-level <LEVEL>
+          Show messages of LEVEL and above: error, warn, info or debug
+      --log-file <PATH>
+          Also append every message, timestamped, to PATH
+      --interval <MINUTES>
+          With service install, apply every MINUTES minutes
+      --notify[=KIND]
+          When a run of 10 seconds or more ends, notify the terminal with osc9 (default) or osc777
+      --group-by <KEY>
+          Apply and report entries grouped by package, root or tag
+      --remove-links
+          With import-stow, remove the links Stow made once the file is written
+      --where <KEY=VALUE>
+          With list, only print entries where KEY matches the glob VALUE
+      --rev <REVISIONS>
+          With plan-diff, compare the neostow file at two revisions, as OLD..NEW or OLD
+      --allow-outside-home
+          Let entries link outside $HOME without marking them !system
+      --allow-root
+          Run as root even where links would land among files of another user
+      --fail-fast
+          Stop at the first entry that fails
+      --format <FORMAT>
+          Read neostow files not named *.toml or *.yaml as toml, yaml or plain
+      --dry-run-hooks
+          Dry run that lists the hooks, validators and reloads a run would start
+      --sandbox <DIR>
+          Rehearse the run in DIR, on copies of the destinations
+      --trace[=N]
+          List the N slowest entries (default 10) with where their time went
+      --assume-yes
+          Answer yes to every question instead of asking
+      --assume-no
+          Answer no to every question instead of asking
+      --no-diff
+          Ask before overwriting a file without showing the diff
+      --wait
+          With edit and edit-file, make GUI editors wait for their window to close
+      --no-wait
+          With edit and edit-file, return once the editor does, without checking the file
+      --override-home <DIR>
+          Use DIR as the home directory, for ~, $HOME and config.toml
+      --override-hostname <NAME>
+          Use NAME as the host name, for @host() and $HOSTNAME
+      --copy-budget <SIZE>
+          Ask before copies and templates write more than SIZE, 100M unless set, or none
+      --json
+          Print JSON, as --output json
+      --fail-on <LIST>
+          Exit with 1 only on these: failed, drift, missing-source, conflict, out-of-sync
+      --set <SOURCE=DEST>
+          Give the entry with SOURCE the destination DEST for this run only
+      --review
+          With adopt, list the whole batch with diffs and ask once before moving anything
+      --since <TIME>
+          With log, list the runs from TIME on
+      --until <TIME>
+          With log, list the runs up to TIME
+      --failed
+          With log, list only the runs where an entry failed
   -v, --version
           Displays program version"
     );
 }
 
-fn create_symlink(src: &Path, dest: &Path, is_dir: bool, cfg: &Config) -> io::Result<bool> {
-    if dest.exists() && !dest.symlink_metadata()?.file_type().is_symlink() {
-        if let Mode::Overwrite = cfg.mode {
-            let do_prompt = run_diff(src, dest, is_dir)?;
+fn version() {
+    println!("1.0.0");
+}
 
-            if do_prompt && !cfg.force {
-                if !prompt_user(&format!(
-                    "Destination '{}' exists and is not a symlink. Overwrite?",
-                    dest.display()
-                ))? {
-                    return Ok(false);
+const COMMANDS: &[&str] = &[
+    "adopt",
+    "bench",
+    "bootstrap",
+    "capabilities",
+    "check",
+    "chown-fix",
+    "conflicts",
+    "decommission",
+    "delete",
+    "disable",
+    "doctor",
+    "edit",
+    "edit-file",
+    "enable",
+    "env",
+    "export",
+    "guard",
+    "service",
+    "init",
+    "list",
+    "log",
+    "new-host",
+    "path",
+    "plan-diff",
+    "prune",
+    "restore",
+    "restore-original",
+    "resolve",
+    "retry",
+    "selftest",
+    "setup",
+    "snapshot",
+    "stats",
+    "status",
+    "suggest",
+    "undo",
+    "watch",
+    "import-stow",
+];
+
+const OPTIONS: &[&str] = &[
+    "--force",
+    "--verbose",
+    "--debug",
+    "--dry",
+    "--file",
+    "--help",
+    "--overwrite",
+    "--skip-unavailable",
+    "--root",
+    "--target",
+    "--plan-diff",
+    "--gc-broken",
+    "--convert-shadows",
+    "--no-folding",
+    "--backup",
+    "--atomic",
+    "--output",
+    "--json",
+    "--fail-on",
+    "--mode",
+    "--preserve",
+    "--interactive",
+    "--open-report",
+    "--version",
+    "--tree",
+    "--where",
+    "--dest",
+    "--entries",
+    "--from-links",
+    "--include-manual",
+    "--pause-between-roots",
+    "--group-by",
+    "--jobs",
+    "--interval",
+    "--strict",
+    "--allow-outside-home",
+    "--allow-root",
+    "--fail-fast",
+    "--dry-run-hooks",
+    "--provenance",
+    "--copy-budget",
+    "--set",
+    "--color",
+    "--quiet",
+    "--no-color",
+    "--log-level",
+    "--log-file",
+    "--notify",
+    "--trace",
+    "--assume-yes",
+    "--assume-no",
+    "--no-diff",
+    "--wait",
+    "--no-wait",
+    "--all",
+    "--fix",
+    "--remove-links",
+    "--git",
+    "--rev",
+    "--since",
+    "--until",
+    "--failed",
+    "--format",
+    "--sandbox",
+    "--override-home",
+    "--override-hostname",
+];
+
+// .neostow stands for what it is written as too
+fn variant_of(file: &Path) -> Option<PathBuf> {
+    [
+        ".age",
+        ".toml",
+        ".toml.age",
+        ".yaml",
+        ".yaml.age",
+        ".yml",
+        ".yml.age",
+    ]
+    .iter()
+    .find_map(|ext| {
+        let mut other = file.as_os_str().to_owned();
+        other.push(ext);
+        Some(PathBuf::from(other)).filter(|other| other.exists())
+    })
+}
+
+// Opens file, the neostow file or one it includes, in the editor, then
+// checks the neostow file like `check`, and while it has problems offers to
+// open it again, so they are fixed before the next run trips on them
+fn edit_checked(cfg: &Config, file: &Path) -> io::Result<()> {
+    if !editor::waits() {
+        return edit_file(file);
+    }
+    loop {
+        edit_file(file)?;
+        let mut edited = cfg.clone();
+        let loaded = edited.load();
+        let result = check::report(&edited).and(loaded);
+        match result {
+            Ok(()) => return Ok(()),
+            Err(err) => {
+                printfc!(LogLevel::Error, "{err}");
+                if !prompt_user("Edit it again?")? {
+                    exit(1);
                 }
             }
         }
     }
+}
 
-    match cfg.mode {
-        Mode::Delete => {
-            if cfg.dry {
-                printfc!(LogLevel::Info, "Would remove {}", dest.display());
-                return Ok(false);
-            }
-            if dest.exists() {
-                if dest.is_dir() {
-                    fs::remove_dir_all(dest)?;
-                } else {
-                    fs::remove_file(dest)?;
-                }
-            }
+// Exits with 1 when one of conditions came up, for --fail-on
+fn exit_on(conditions: &[report::Condition]) {
+    if conditions.iter().any(|&condition| report::seen(condition)) {
+        exit(1);
+    }
+}
+
+fn fail(msg: impl std::fmt::Display) -> ! {
+    printfc!(LogLevel::Fatal, "{msg}");
+    exit(1);
+}
+
+// --override-home and --override-hostname take effect before anything is
+// looked up, config.toml under home among them
+fn override_platform() {
+    let mut args = env::args().skip(1).take_while(|arg| arg != "--");
+    while let Some(arg) = args.next() {
+        let (name, attached) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg, None),
+        };
+        if !matches!(name.as_str(), "--override-home" | "--override-hostname") {
+            continue;
         }
-        Mode::Overwrite => {
-            if cfg.dry {
-                printfc!(LogLevel::Info, "Would remove {}", dest.display());
-                println!("{} → {}", src.display(), dest.display());
-                return Ok(false);
+        let Some(value) = attached.or_else(|| args.next()) else {
+            continue;
+        };
+        if name == "--override-home" {
+            match path::absolute(&value) {
+                Ok(dir) => platform::set_home(normalize(&dir)),
+                Err(err) => fail(format!("--override-home: {err}")),
             }
-            if dest.exists() {
-                if dest.is_dir() {
-                    fs::remove_dir_all(dest)?;
-                } else {
-                    fs::remove_file(dest)?;
+        } else {
+            platform::set_hostname(value);
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args = Args::new(env::args().skip(1));
+    override_platform();
+    let settings = settings::load().unwrap_or_else(|err| fail(err));
+    // What `neostow env` exported stands in for -f and packages, then the
+    // nearest .neostow from here up, then the one config.toml names
+    let cwd = env::current_dir()?;
+    let file = match env::var_os("NEOSTOW_CONFIG") {
+        Some(file) if !file.is_empty() => PathBuf::from(file),
+        _ => settings::find_file(&cwd)
+            .or(settings.file)
+            .unwrap_or_else(|| cwd.join(".neostow")),
+    };
+    let mut cfg = Config::new(file);
+    cfg.link_mode = settings.mode.unwrap_or_default();
+    cfg.backup = settings.backup;
+    if let Some(color) = settings.color {
+        set_color(color);
+    }
+    if let Some(notify) = settings.notify {
+        report::set_notify(notify);
+    }
+    if let Some(template) = settings.editor {
+        editor::set_template(template);
+    }
+    if let Some(budget) = settings.copy_budget {
+        cfg.copy_budget = budget;
+    }
+    if let Some(limits) = settings.mount_jobs {
+        cfg.mount_jobs = limits;
+    }
+    if let Some(kind) = settings.vcs {
+        vcs::set_vcs(kind);
+    }
+    if let Some(ascii) = settings.ascii_only {
+        glyphs::set_ascii_only(ascii);
+    }
+    if let Some(custom) = settings.glyphs {
+        glyphs::set(custom);
+    }
+    let mut operations: i32 = 0;
+    let mut action = Action::Apply;
+    let mut command = None;
+    // The usage of a command still waiting for its operand
+    let mut awaiting: Option<&str> = None;
+    let mut show_plan_diff = false;
+    let mut wait = None;
+    let mut show_dest = false;
+    let mut convert_shadows = false;
+    let mut tree = false;
+    let mut filters = Vec::new();
+    let mut entries = None;
+    let mut from_links = None;
+    let mut aliased = false;
+    let mut all = false;
+    let mut fix = false;
+    let mut remove_links = false;
+    let mut review = false;
+    let mut interval = None;
+    let mut revisions = None;
+    let mut since = None;
+    let mut until = None;
+    let mut failed_only = false;
+    let mut allow_root = false;
+    let mut assume = None;
+    let mut sandbox: Option<PathBuf> = None;
+    let mut fail_on: Option<Vec<report::Condition>> = None;
+    loop {
+        let arg = match args.next() {
+            Ok(Some(arg)) => arg,
+            Ok(None) => break,
+            Err(err) => fail(err),
+        };
+        let value = |args: &mut Args| args.value().unwrap_or_else(|err| fail(err));
+        let word = match arg {
+            Arg::Option(option) => {
+                match option.as_str() {
+                    "-o" | "--overwrite" => cfg.mode = Mode::Overwrite,
+                    "-V" | "--verbose" => cfg.verbose = true,
+                    "-v" | "--version" => {
+                        version();
+                        return Ok(());
+                    }
+                    "-D" | "--debug" => cfg.debug = true,
+                    "-d" | "--dry" => cfg.dry = true,
+                    "--dry-run-hooks" => {
+                        cfg.dry = true;
+                        cfg.dry_run_hooks = true;
+                    }
+                    "-s" | "--skip-unavailable" => cfg.skip_unavailable = true,
+                    "--gc-broken" => cfg.gc_broken = true,
+                    "--no-folding" => cfg.no_folding = true,
+                    "--atomic" => cfg.atomic = true,
+                    "--include-manual" => cfg.include_manual = true,
+                    "--pause-between-roots" => cfg.pause_between_roots = true,
+                    "--strict" => cfg.strict = true,
+                    "--allow-outside-home" => cfg.allow_outside_home = true,
+                    "--allow-root" => allow_root = true,
+                    "--fail-fast" => cfg.fail_fast = true,
+                    "--provenance" => cfg.provenance = true,
+                    "--copy-budget" => match budget::parse(&value(&mut args)) {
+                        Some(budget) => cfg.copy_budget = budget,
+                        None => fail("--copy-budget takes a size, like 500M or 2G, or none"),
+                    },
+                    "--set" => match overrides::parse(&value(&mut args)) {
+                        Some(set) => cfg.overrides.push(set),
+                        None => fail("--set takes SOURCE=DEST, like 'nvim=~/.config-test'"),
+                    },
+                    "-i" | "--interactive" => cfg.interactive = true,
+                    "--open-report" => report::set_html(),
+                    "--plan-diff" => show_plan_diff = true,
+                    "--tree" => tree = true,
+                    "--where" => match list::Filter::parse(&value(&mut args)) {
+                        Ok(filter) => filters.push(filter),
+                        Err(err) => fail(format!("--where: {err}")),
+                    },
+                    "--convert-shadows" => convert_shadows = true,
+                    "--dest" => show_dest = true,
+                    "--all" => all = true,
+                    "--fix" => fix = true,
+                    "--remove-links" => remove_links = true,
+                    "--review" => review = true,
+                    "--failed" => failed_only = true,
+                    "--since" | "--until" => {
+                        let text = value(&mut args);
+                        let Some(time) = history::time(&text) else {
+                            fail(format!(
+                                "{option} takes a date, a time today, today, yesterday, now or a span ago like \"2 hours ago\", not {text}"
+                            ))
+                        };
+                        if option == "--since" {
+                            since = Some(time);
+                        } else {
+                            until = Some(time);
+                        }
+                    }
+                    "--rev" | "--git" => revisions = Some(value(&mut args)),
+                    "-F" | "--force" => cfg.force = true,
+                    "-h" | "--help" => {
+                        help();
+                        return Ok(());
+                    }
+                    "--mode" => match value(&mut args).as_str() {
+                        "symlink" => cfg.link_mode = LinkMode::Symlink,
+                        "copy" => cfg.link_mode = LinkMode::Copy,
+                        "hardlink" => cfg.link_mode = LinkMode::Hardlink,
+                        _ => fail("Usage: neostow --mode <symlink|copy|hardlink>"),
+                    },
+                    "--group-by" => match value(&mut args).as_str() {
+                        "package" => cfg.group_by = Some(GroupBy::Package),
+                        "root" => cfg.group_by = Some(GroupBy::Root),
+                        "tag" => cfg.group_by = Some(GroupBy::Tag),
+                        _ => fail("Usage: neostow --group-by <package|root|tag>"),
+                    },
+                    "-q" | "--quiet" => log::set_level(LogLevel::Warn),
+                    "--no-color" => set_color(Color::Never),
+                    "--log-level" => match LogLevel::parse(&value(&mut args)) {
+                        Some(level) => {
+                            cfg.debug |= level == LogLevel::Debug;
+                            log::set_level(level);
+                        }
+                        None => fail("Usage: neostow --log-level <error|warn|info|debug>"),
+                    },
+                    "--log-file" => {
+                        if let Err(err) = log::set_file(Path::new(&value(&mut args))) {
+                            fail(err);
+                        }
+                    }
+                    "--color" => match value(&mut args).as_str() {
+                        "always" => set_color(Color::Always),
+                        "never" => set_color(Color::Never),
+                        "auto" => set_color(Color::Auto),
+                        _ => fail("Usage: neostow --color <always|never|auto>"),
+                    },
+                    "--format" => match format::Kind::parse(&value(&mut args)) {
+                        Some(kind) => format::set_format(kind),
+                        None => fail("Usage: neostow --format <toml|yaml|plain>"),
+                    },
+                    "--output" => match value(&mut args).as_str() {
+                        "json" => report::set_json(),
+                        "ndjson" => report::set_ndjson(),
+                        "text" => {}
+                        _ => fail("Usage: neostow --output <text|json|ndjson>"),
+                    },
+                    "--json" => report::set_json(),
+                    "--fail-on" => {
+                        let conditions: Option<Vec<_>> = value(&mut args)
+                            .split(',')
+                            .map(|name| report::Condition::parse(name.trim()))
+                            .collect();
+                        match conditions {
+                            Some(conditions) => fail_on = Some(conditions),
+                            None => fail(
+                                "--fail-on takes a list of failed, drift, missing-source, conflict and out-of-sync",
+                            ),
+                        }
+                    }
+                    "--preserve" => match meta::Preserve::parse(value(&mut args).as_bytes()) {
+                        Ok(preserve) => cfg.preserve = preserve,
+                        Err(err) => fail(err),
+                    },
+                    "--backup" => {
+                        let arg = match args.attached() {
+                            Some(dir) => format!("--backup={dir}"),
+                            None => String::from("--backup"),
+                        };
+                        cfg.backup = backup::Backup::parse(&arg);
+                    }
+                    "--assume-yes" | "--assume-no" => {
+                        let yes = option == "--assume-yes";
+                        if assume.is_some_and(|assumed| assumed != yes) {
+                            fail("--assume-yes and --assume-no can't both be given");
+                        }
+                        assume = Some(yes);
+                        report::set_assume(yes);
+                    }
+                    "--no-diff" => cfg.no_diff = true,
+                    "--wait" => wait = Some(true),
+                    "--no-wait" => wait = Some(false),
+                    "--trace" => match args.attached().map_or(Ok(10), |n| n.parse()) {
+                        Ok(slowest) if slowest > 0 => trace::set_trace(slowest),
+                        _ => fail("Usage: neostow --trace[=N], with N at least 1"),
+                    },
+                    "--notify" => {
+                        let kind = args.attached().unwrap_or_else(|| String::from("osc9"));
+                        match report::Notify::parse(&kind) {
+                            Some(notify) => report::set_notify(notify),
+                            None => fail("Usage: neostow --notify[=osc9|osc777]"),
+                        }
+                    }
+                    "-r" | "--root" => {
+                        cfg.root = Some(normalize(&path::absolute(value(&mut args))?));
+                    }
+                    "-t" | "--target" => {
+                        cfg.target = Some(normalize(&path::absolute(value(&mut args))?));
+                    }
+                    "--sandbox" => sandbox = Some(PathBuf::from(value(&mut args))),
+                    // Taken in by override_platform already
+                    "--override-home" | "--override-hostname" => {
+                        value(&mut args);
+                    }
+                    "-f" | "--file" => {
+                        cfg.file = PathBuf::from(value(&mut args));
+                        cfg.basedir = cfg
+                            .file
+                            .parent()
+                            .map(PathBuf::from)
+                            .unwrap_or_else(|| PathBuf::from("."));
+                    }
+                    "--from-links" => from_links = Some(PathBuf::from(value(&mut args))),
+                    "--jobs" => match value(&mut args).parse() {
+                        Ok(jobs) if jobs > 0 => cfg.jobs = jobs,
+                        _ => fail("Usage: neostow --jobs <N>, with N at least 1"),
+                    },
+                    "--interval" => match value(&mut args).parse() {
+                        Ok(minutes) if minutes > 0 => interval = Some(minutes),
+                        _ => {
+                            fail("Usage: neostow service install --interval <MINUTES>, at least 1")
+                        }
+                    },
+                    "--entries" => match value(&mut args).parse() {
+                        Ok(n) => entries = Some(n),
+                        Err(_) => fail("Usage: neostow bench [--entries N]"),
+                    },
+                    _ => match args::suggest(&option, OPTIONS) {
+                        Some(known) => {
+                            fail(format!("Unknown argument: {option}, did you mean {known}?"))
+                        }
+                        None => fail(format!("Unknown argument: {option}")),
+                    },
                 }
+                continue;
             }
-            #[cfg(unix)]
-            symlink(src, dest)?;
-            #[cfg(windows)]
-            {
-                if is_dir {
-                    symlink_dir(src, dest)?;
-                } else {
-                    symlink_file(src, dest)?;
+            Arg::Word(word) => word,
+        };
+
+        if let Some(usage) = awaiting.take() {
+            match &mut action {
+                Action::Path(query) | Action::Bootstrap(query) => *query = word,
+                Action::Restore(dest) | Action::EditFile(dest) | Action::ImportStow(dest, _) => {
+                    *dest = PathBuf::from(word)
+                }
+                Action::RestoreOriginal(dest) => *dest = Some(PathBuf::from(word)),
+                Action::ExportDot if word == "dot" => {}
+                Action::Service(install) if word == "install" || word == "uninstall" => {
+                    *install = word == "install";
                 }
+                Action::GuardInstall if word == "install" => {}
+                _ => fail(format!("Usage: neostow {usage}")),
             }
+            continue;
+        }
+        // snapshot takes diff, then an optional directory
+        if let Action::Snapshot(dir @ None, diff) = &mut action {
+            if word == "diff" && !*diff {
+                *diff = true;
+            } else {
+                *dir = Some(PathBuf::from(word));
+            }
+            continue;
+        }
+        // init and doctor take an optional directory
+        if let Action::Init(dir @ None)
+        | Action::Doctor(dir @ None)
+        | Action::ImportStow(_, dir @ None)
+        | Action::PlanDiff(dir @ None, _)
+        | Action::PlanDiff(Some(_), dir @ None) = &mut action
+        {
+            *dir = Some(PathBuf::from(word));
+            continue;
         }
-        Mode::Create => {
-            if cfg.dry {
-                println!("{} → {}", src.display(), dest.display());
-                return Ok(false);
+        let next = match word.as_str() {
+            // delete is a mode the other commands can run in
+            "delete" => {
+                cfg.mode = Mode::Delete;
+                continue;
             }
-            #[cfg(unix)]
-            symlink(src, dest)?;
-            #[cfg(windows)]
-            {
-                if is_dir {
-                    symlink_dir(src, dest)?;
-                } else {
-                    symlink_file(src, dest)?;
+            "undo" => Action::Undo,
+            "edit" => Action::Edit,
+            "retry" => Action::Retry,
+            "conflicts" => Action::Conflicts,
+            "resolve" => Action::Resolve,
+            "enable" => Action::Enable(true),
+            "disable" => Action::Enable(false),
+            "new-host" => Action::NewHost,
+            "list" => Action::List,
+            "status" => Action::Status,
+            "setup" => Action::Setup,
+            "init" => Action::Init(None),
+            "suggest" => Action::Suggest,
+            "bench" => Action::Bench,
+            "stats" => Action::Stats,
+            "log" => Action::Log,
+            "env" => Action::Env,
+            "check" => Action::Check,
+            "prune" => Action::Prune,
+            "decommission" => Action::Decommission,
+            "doctor" => Action::Doctor(None),
+            "watch" => Action::Watch,
+            "plan-diff" => Action::PlanDiff(None, None),
+            "chown-fix" => Action::ChownFix,
+            "selftest" => Action::Selftest,
+            "capabilities" => Action::Capabilities,
+            "snapshot" => Action::Snapshot(None, false),
+            "bootstrap" => {
+                awaiting = Some("bootstrap <URL> [PACKAGE]...");
+                Action::Bootstrap(String::new())
+            }
+            "import-stow" => {
+                awaiting = Some("import-stow [--remove-links] <STOW-DIR> [TARGET-DIR]");
+                Action::ImportStow(PathBuf::new(), None)
+            }
+            "adopt" => {
+                cfg.adopt = true;
+                Action::Adopt
+            }
+            "restore-original" => {
+                awaiting = Some("restore-original <DESTINATION> | --all");
+                Action::RestoreOriginal(None)
+            }
+            "restore" => {
+                awaiting = Some("restore <DESTINATION>");
+                Action::Restore(PathBuf::new())
+            }
+            "path" => {
+                awaiting = Some("path [--dest] <ENTRY>");
+                Action::Path(String::new())
+            }
+            "edit-file" => {
+                awaiting = Some("edit-file <DESTINATION>");
+                Action::EditFile(PathBuf::new())
+            }
+            "export" => {
+                awaiting = Some("export dot");
+                Action::ExportDot
+            }
+            "service" => {
+                awaiting = Some("service install [--interval MINUTES] | uninstall");
+                Action::Service(true)
+            }
+            "guard" => {
+                awaiting = Some("guard install");
+                Action::GuardInstall
+            }
+            // An alias of the neostow file stands for its arguments
+            _ if command.is_none() && cfg.packages.is_empty() && !aliased => {
+                match aliases::find(&cfg.file, &word) {
+                    Ok(Some(words)) => {
+                        args.prepend(words);
+                        aliased = true;
+                    }
+                    Ok(None) => cfg.packages.push(word),
+                    Err(err) => fail(err),
                 }
+                continue;
             }
+            _ => {
+                cfg.packages.push(word);
+                continue;
+            }
+        };
+        if let Some(first) = command.replace(word.clone()) {
+            fail(format!("{first} and {word} can't be used together"));
         }
+        action = next;
     }
-
-    Ok(true)
-}
-
-fn expand_path(raw: &str) -> PathBuf {
-    let replaced = std::env::vars().fold(raw.to_string(), |acc, (key, val)| {
-        acc.replace(&format!("${}", key), &val)
-    });
-
-    if replaced.starts_with("~") {
-        if let Some(home) = env::var("HOME").ok() {
-            return PathBuf::from(replaced.replacen("~", &home, 1));
+    // --all stands in for the destination
+    match (&action, all) {
+        (Action::RestoreOriginal(None), true) => awaiting = None,
+        (Action::RestoreOriginal(Some(_)), true) => {
+            fail("Usage: neostow restore-original <DESTINATION> | --all")
         }
+        _ => {}
     }
-
-    PathBuf::from(replaced)
-}
-
-fn process_line(line: &str, cfg: &Config, operations: &mut i32) -> io::Result<()> {
-    let mut line = line.trim();
-    if line.is_empty() || line.starts_with('#') {
-        return Ok(());
+    if let Some(usage) = awaiting {
+        fail(format!("Usage: neostow {usage}"));
     }
-
-    if let Some(comment_start) = line.find('#') {
-        if comment_start > 0 {
-            line = &line[..comment_start].trim();
+    // Links of packages left out would look orphaned
+    let whole_file = match action {
+        Action::Prune => Some("prune"),
+        Action::Doctor(_) => Some("doctor"),
+        _ => None,
+    };
+    if let Some(command) = whole_file
+        && !cfg.packages.is_empty()
+    {
+        fail(format!(
+            "{command} compares against every entry of the file, so it takes no packages"
+        ));
+    }
+    if matches!(action, Action::Snapshot(..)) && !cfg.packages.is_empty() {
+        fail("Usage: neostow snapshot [diff] [DIR]");
+    }
+    if matches!(action, Action::NewHost) && !cfg.packages.is_empty() {
+        fail("new-host asks which packages this machine uses, so it takes none");
+    }
+    if let Action::Enable(enable) = action
+        && cfg.packages.is_empty()
+    {
+        let command = if enable { "enable" } else { "disable" };
+        fail(format!("Usage: neostow {command} <PACKAGE>..."));
+    }
+    // delete --all removes the entries of every package, after asking
+    let delete_all = all && matches!(cfg.mode, Mode::Delete);
+    if delete_all {
+        if !cfg.packages.is_empty() {
+            fail("delete --all removes the entries of every package, so it takes none");
         }
+        cfg.include_manual = true;
     }
-
-    let (src, dest_base) = if let Some(eq_pos) = line.find('=') {
-        let parts: Vec<&str> = line.splitn(2, '=').map(str::trim).collect();
-        (cfg.basedir.join(parts[0]), expand_path(parts[1]))
-    } else {
-        let src_path = cfg.basedir.join(line);
-        let src_dir = Path::new(line).parent().unwrap_or_else(|| Path::new(""));
-        let parent_dir = cfg.basedir.parent().unwrap_or(&cfg.basedir);
-        let dest_base = parent_dir.join(src_dir); // join parent's dir with src dir
-        (src_path, dest_base)
-    };
-
-    if !src.exists() {
-        if cfg.verbose {
-            printfc!(LogLevel::Error, "Source {:?} not found", src);
+    // Words with a slash given to adopt are files to adopt, which need no
+    // entry yet
+    let mut adopt_paths = Vec::new();
+    if let Action::Adopt = action {
+        let (paths, packages): (Vec<String>, _) = std::mem::take(&mut cfg.packages)
+            .into_iter()
+            .partition(|word| word.contains('/') || word.contains(path::MAIN_SEPARATOR));
+        if !paths.is_empty() && !packages.is_empty() {
+            fail("adopt takes packages or paths to files, not both");
         }
-        return Ok(());
+        adopt_paths = paths.into_iter().map(PathBuf::from).collect();
+        cfg.packages = packages;
     }
-
-    if cfg.debug {
-        printfc!(LogLevel::Debug, "Source file: {}", src.display());
-        printfc!(LogLevel::Debug, "Destination: {}", dest_base.display());
+    // What edit opens instead of the neostow file
+    let mut edit_target = None;
+    if let Action::Edit = action {
+        if cfg.packages.len() > 1 {
+            fail("Usage: neostow edit [local | host | INCLUDE]");
+        }
+        edit_target = cfg.packages.pop();
     }
-
-    let is_dir = src.is_dir();
-
-    let dest = dest_base.join(src.file_name().unwrap());
-
-    if let Some(parent) = dest.parent() {
-        if !cfg.dry {
-            fs::create_dir_all(parent)?;
+    if cfg.packages.is_empty()
+        && whole_file.is_none()
+        && !delete_all
+        && let Ok(packages) = env::var("NEOSTOW_PACKAGES")
+    {
+        cfg.packages = packages.split_whitespace().map(String::from).collect();
+    }
+    // Runs that name no packages leave out those disabled here
+    if cfg.packages.is_empty() && whole_file.is_none() && !delete_all {
+        match activation::disabled(&cfg.file) {
+            Ok(disabled) => cfg.disabled = disabled,
+            Err(err) => fail(err),
         }
     }
 
-    let success = create_symlink(&src, &dest, is_dir, &cfg)?;
-
-    if success {
-        *operations += 1;
-        if cfg.verbose {
-            let mode_str = match cfg.mode {
-                Mode::Create => "Created symlink",
-                Mode::Overwrite => "Overwritten symlink",
-                Mode::Delete => "Deleted symlink",
-            };
-            println!(
-                "{}",
-                &format!("{mode_str}: {} => {}", src.display(), dest.display())
+    // Options that only make sense for one command
+    let command = command.as_deref().unwrap_or("apply");
+    for (given, option, owner) in [
+        (tree, "--tree", "list"),
+        (!filters.is_empty(), "--where", "list"),
+        (show_dest, "--dest", "path"),
+        (show_plan_diff, "--plan-diff", "edit"),
+        (convert_shadows, "--convert-shadows", "status"),
+        (entries.is_some(), "--entries", "bench"),
+        (from_links.is_some(), "--from-links", "init"),
+        (remove_links, "--remove-links", "import-stow"),
+        (review, "--review", "adopt"),
+        (revisions.is_some(), "--rev", "plan-diff"),
+        (since.is_some(), "--since", "log"),
+        (until.is_some(), "--until", "log"),
+        (failed_only, "--failed", "log"),
+        (all && !delete_all, "--all", "restore-original"),
+        (fix, "--fix", "doctor"),
+        (interval.is_some(), "--interval", "service"),
+        (sandbox.is_some(), "--sandbox", "apply"),
+    ] {
+        if given && command != owner {
+            fail(format!("{option} only applies to {owner}"));
+        }
+    }
+    if let Some(wait) = wait {
+        if !matches!(command, "edit" | "edit-file") {
+            fail("--wait and --no-wait only apply to edit and edit-file");
+        }
+        if !wait && show_plan_diff {
+            fail(
+                "--plan-diff compares the plan once the edit is done, it can't be combined with --no-wait",
             );
         }
+        editor::set_wait(wait);
+    }
+    if matches!(action, Action::Selftest) && (sandbox.is_some() || cfg.target.is_some()) {
+        fail(
+            "selftest puts destinations under a home of its own, it can't be combined with --target or --sandbox",
+        );
+    }
+    if sandbox.is_some() && cfg.target.is_some() {
+        fail("--sandbox puts destinations under its directory, it can't be combined with --target");
+    }
+    if cfg.provenance && cfg.link_mode != LinkMode::Copy {
+        fail("--provenance only applies to --mode copy");
+    }
+    if cfg.pause_between_roots && cfg.group_by.is_some_and(|by| by != GroupBy::Root) {
+        fail("--pause-between-roots groups by root, it can't be combined with another --group-by");
     }
 
-    Ok(())
-}
+    // bootstrap applies what it clones, like a run from there would
+    if let Action::Bootstrap(url) = &action {
+        match bootstrap::bootstrap(url, &cfg.packages) {
+            Ok(file) => {
+                cfg.basedir = file.parent().map(PathBuf::from).unwrap_or_default();
+                cfg.file = file;
+            }
+            Err(err) => fail(err),
+        }
+        action = Action::Apply;
+    }
 
-fn run(cfg: &Config, operations: &mut i32) -> io::Result<()> {
-    let file = fs::File::open(&cfg.file)?;
-    let reader = io::BufReader::new(file);
-    let mut linenum = 0;
+    match action {
+        Action::Setup => return setup::setup(),
+        Action::Init(dir) => {
+            let dir = dir.unwrap_or_else(|| cfg.basedir.clone());
+            if let Err(err) = init::init(&dir, from_links.as_deref()) {
+                fail(err);
+            }
+            return Ok(());
+        }
+        Action::ImportStow(stow_dir, target) => {
+            let target = target
+                .or_else(|| {
+                    fs::canonicalize(&stow_dir)
+                        .ok()?
+                        .parent()
+                        .map(PathBuf::from)
+                })
+                .unwrap_or_else(|| PathBuf::from(".."));
+            if let Err(err) = import::stow(&stow_dir, &target, remove_links, &cfg) {
+                fail(err);
+            }
+            return Ok(());
+        }
+        Action::PlanDiff(old, new) => {
+            let result = match (old, new, revisions) {
+                (Some(old), Some(new), None) => plan_diff::files(&old, &new, &cfg),
+                (None, None, Some(range)) => plan_diff::revisions(&range, &cfg),
+                _ => fail("Usage: neostow plan-diff <OLD> <NEW> | --rev <OLD>[..NEW]"),
+            };
+            if let Err(err) = result {
+                fail(err);
+            }
+            return Ok(());
+        }
+        Action::Suggest => return suggest::suggest(&cfg.basedir, &cfg.file),
+        Action::Bench => return bench::bench(entries.unwrap_or(bench::DEFAULT_ENTRIES)),
+        Action::Stats => return stats::stats(),
+        Action::Log => return history::log(since, until, failed_only),
+        Action::Capabilities => {
+            match capabilities::detect() {
+                Ok(caps) => capabilities::print(&caps),
+                Err(err) => fail(err),
+            }
+            return Ok(());
+        }
+        Action::Snapshot(root, diff) => {
+            let root = root.or(cfg.target.clone());
+            let result = match (diff, root) {
+                (false, root) => snapshot::take(&root.unwrap_or_else(platform::home)).map(|()| 0),
+                (true, root) => snapshot::diff(root.as_deref()),
+            };
+            match result {
+                Ok(0) => return Ok(()),
+                Ok(_) => exit(1),
+                Err(err) => fail(err),
+            }
+        }
+        // What was made here is on record, no neostow file needed
+        Action::Decommission => {
+            let result = decommission::decommission(&cfg, &mut operations);
+            report::finish(operations);
+            if let Err(err) = result {
+                fail(err);
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
 
-    for line in reader.lines() {
-        linenum += 1;
-        if let Err(err) = process_line(&line?, &cfg, operations) {
-            printfc!(LogLevel::Error, "{}:{}: {err}", cfg.file.display(), linenum);
+    if let Action::Edit = action {
+        if !cfg.file.exists() {
+            match variant_of(&cfg.file) {
+                Some(found) => cfg.file = found,
+                None => init::template(&cfg.file)?,
+            }
+        }
+        let before = if show_plan_diff {
+            plan_diff::current(&cfg)?
+        } else {
+            Vec::new()
+        };
+        let mut edited = cfg.clone();
+        if edit_target.is_some() {
+            cfg.vars = vars::load(&cfg.file)?;
         }
+        let file = match &edit_target {
+            Some(target) => overlay::edit_target(&cfg, target).unwrap_or_else(|err| fail(err)),
+            None => cfg.file.clone(),
+        };
+        edit_checked(&cfg, &file)?;
+        if !show_plan_diff {
+            return Ok(());
+        }
+        let diff = plan_diff::PlanDiff::new(before, plan_diff::current(&edited)?);
+        edited.load()?;
+        let result = plan_diff::confirm_and_apply(&diff, &edited, &mut operations);
+        report::finish(operations);
+        return result;
     }
 
-    Ok(())
-}
+    if let Action::Restore(dest) = &action {
+        if let Err(err) = backup::restore(dest, &cfg) {
+            printfc!(LogLevel::Fatal, "{err}");
+            exit(1);
+        }
+        return Ok(());
+    }
 
-fn edit_file(path: &Path) -> io::Result<()> {
-    let editor = env::var("EDITOR").unwrap_or_else(|_| "vim".into());
-    let status = Command::new(editor).arg(path).status()?;
-    if !status.success() {
-        return Err(io::Error::new(io::ErrorKind::Other, "Editor failed"));
+    if let Action::Undo = action {
+        return undo(&cfg);
     }
-    Ok(())
-}
 
-fn prompt_user(prompt: &str) -> io::Result<bool> {
-    println!("{prompt} [y/N] ");
-    let mut input = String::new();
-    io::stdin().read_line(&mut input)?;
-    Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
-}
+    if !cfg.file.exists()
+        && let Some(found) = variant_of(&cfg.file)
+    {
+        cfg.file = found;
+    }
+    if !cfg.file.exists() && io::stdin().is_terminal() {
+        printfc!(LogLevel::Warn, "{:?} not found", cfg.file);
+        if prompt_user("Create one with the setup wizard?")? {
+            return setup::setup();
+        }
+        exit(1);
+    }
+    if !cfg.file.exists() {
+        printfc!(LogLevel::Fatal, "{:?} not found", cfg.file);
+        exit(1);
+    }
 
-fn run_diff(src: &Path, dest: &Path, is_dir: bool) -> io::Result<bool> {
-    let mut cmd = Command::new("diff");
-    if is_dir {
-        cmd.arg("-r");
-    }
-    let status = cmd.arg("-u").arg(src).arg(dest).status()?;
-    if !status.success() {
-        println!("Files differ.");
-        Ok(true)
-    } else {
-        println!("Files are identical.");
-        Ok(false)
+    if let Action::Selftest = action {
+        match selftest::selftest(&cfg) {
+            Ok(true) => return Ok(()),
+            Ok(false) => exit(1),
+            Err(err) => fail(err),
+        }
     }
-}
 
-fn version() {
-    println!("1.0.0");
-}
+    let loaded = match &sandbox {
+        Some(dir) => sandbox::enter(&mut cfg, dir),
+        None => cfg.load(),
+    };
+    if let Err(err) = loaded {
+        // The line that stopped loading is usually among the problems, with
+        // the others around it
+        if let Action::Check = action
+            && let Err(problems) = check::report(&cfg)
+        {
+            fail(problems);
+        }
+        // A mistyped command is taken for a package without a section
+        let typo = cfg
+            .packages
+            .iter()
+            .filter(|package| err.to_string().contains(&format!("[{package}]")))
+            .find_map(|package| args::suggest(package, COMMANDS));
+        match typo {
+            Some(command) => fail(format!("{err}, did you mean the {command} command?")),
+            None => fail(err),
+        }
+    }
 
-fn main() -> io::Result<()> {
-    let mut args = env::args().skip(1);
-    let mut cfg = Config {
-        file: env::current_dir()?.join(".neostow"),
-        basedir: env::current_dir()?,
-        mode: Mode::Create,
-        verbose: false,
-        force: false,
-        dry: false,
-        debug: false,
+    let cfg = cfg;
+    if let Err(err) = overrides::check(&cfg) {
+        fail(err);
+    }
+    // sudo neostow would leave root-owned links in a user's home. Removing
+    // them leaves nothing behind.
+    let writes = match action {
+        Action::Apply | Action::Watch => !matches!(cfg.mode, Mode::Delete),
+        Action::Adopt | Action::RestoreOriginal(_) => true,
+        Action::Doctor(_) => fix,
+        Action::Status => convert_shadows,
+        Action::Resolve | Action::Retry => true,
+        _ => false,
     };
-    let mut operations: i32 = 0;
-    while let Some(arg) = args.next() {
-        match arg.as_str() {
-            "delete" => cfg.mode = Mode::Delete,
-            "-o" | "--overwrite" => cfg.mode = Mode::Overwrite,
-            "-V" | "--verbose" => cfg.verbose = true,
-            "-v" | "--version" => {
-                version();
-                return Ok(());
-            }
-            "-D" | "--debug" => cfg.debug = true,
-            "-d" | "--dry" => cfg.dry = true,
-            "-F" | "--force" => {
-                cfg.force = true;
-            }
-            "-h" | "--help" => {
-                help();
-                return Ok(());
-            }
-            "-f" | "--file" => {
-                if let Some(path) = args.next() {
-                    cfg.file = PathBuf::from(path);
-                    cfg.basedir = cfg
-                        .file
-                        .parent()
-                        .map(PathBuf::from)
-                        .unwrap_or_else(|| PathBuf::from("."));
-                }
+    if writes
+        && !cfg.dry
+        && !allow_root
+        && let Err(err) = neostow::refuse_root(&cfg)
+    {
+        fail(err);
+    }
+    match action {
+        Action::ExportDot => return export::dot(&load_plan(&cfg)?, &cfg, &mut io::stdout()),
+        Action::Conflicts => {
+            if conflicts::report(&load_plan(&cfg)?, &cfg) > 0 {
+                exit(1);
             }
-            "edit" => {
-                return edit_file(&cfg.file);
+            return Ok(());
+        }
+        Action::NewHost => {
+            if let Err(err) = overlay::new_host(&cfg) {
+                fail(err);
             }
-            _ => {
-                printfc!(LogLevel::Fatal, "Unknown argument: {arg}");
+            return Ok(());
+        }
+        Action::Enable(enable) => {
+            if let Err(err) = activation::set(&cfg, enable) {
+                fail(err);
+            }
+            return Ok(());
+        }
+        Action::Resolve => {
+            let result = resolve::resolve(&cfg, &mut operations);
+            report::finish(operations);
+            return result;
+        }
+        Action::Retry => {
+            let result = retry(&cfg, &mut operations);
+            report::finish(operations);
+            if result.is_ok() && report::failed() > 0 {
+                exit(1);
+            }
+            return result;
+        }
+        Action::List => return list::list(&load_plan(&cfg)?, &cfg, tree, &filters),
+        Action::Watch => return watch::watch(&cfg),
+        Action::Env => return shellenv::print(&load_plan(&cfg)?, &cfg, &mut io::stdout()),
+        Action::Service(install) => {
+            let result = if install {
+                service::install(&cfg, interval.unwrap_or(service::DEFAULT_INTERVAL))
+            } else {
+                service::uninstall(&cfg)
+            };
+            if let Err(err) = result {
+                fail(err);
+            }
+            return Ok(());
+        }
+        Action::GuardInstall => {
+            if let Err(err) = guard::install(&load_plan(&cfg)?, &cfg) {
+                fail(err);
+            }
+            return Ok(());
+        }
+        Action::Check => {
+            if let Err(err) = check::report(&cfg) {
+                fail(err);
+            }
+            printfc!(LogLevel::Info, "No problems in {}", cfg.file.display());
+            return Ok(());
+        }
+        Action::Path(query) => {
+            let ops = load_plan(&cfg)?;
+            let Some(op) = lookup::find(&ops, &cfg, &query) else {
+                printfc!(LogLevel::Fatal, "No entry matches {query}");
+                exit(1);
+            };
+            let target = if show_dest { &op.dest } else { &op.src };
+            println!("{}", lookup::dir_of(target).display());
+            return Ok(());
+        }
+        Action::EditFile(dest) => {
+            let Some(src) = lookup::source_of(&load_plan(&cfg)?, &dest) else {
+                printfc!(
+                    LogLevel::Fatal,
+                    "{} is not managed by {}",
+                    dest.display(),
+                    cfg.file.display()
+                );
                 exit(1);
+            };
+            if cfg.verbose {
+                printfc!(LogLevel::Info, "Editing {}", src.display());
+            }
+            return edit_file(&src);
+        }
+        Action::RestoreOriginal(dest) => {
+            if let Err(err) = backup::restore_originals(&load_plan(&cfg)?, &cfg, dest.as_deref()) {
+                fail(err);
             }
+            return Ok(());
         }
+        Action::Doctor(dir) => {
+            let result = doctor::doctor(
+                &load_plan(&cfg)?,
+                &cfg,
+                dir.as_deref(),
+                fix,
+                &mut operations,
+            );
+            if fix {
+                report::finish(operations);
+            }
+            match result {
+                Ok(0) => return Ok(()),
+                Ok(_) => exit(1),
+                Err(err) => fail(err),
+            }
+        }
+        Action::Prune => {
+            let result = prune::prune(&load_plan(&cfg)?, &cfg, &mut operations);
+            report::finish(operations);
+            if let Err(err) = result {
+                fail(err);
+            }
+            return Ok(());
+        }
+        Action::ChownFix => {
+            let result = ownership::chown_fix(&load_plan(&cfg)?, &cfg, &mut operations);
+            report::finish(operations);
+            match result {
+                Ok(0) => return Ok(()),
+                Ok(_) => exit(1),
+                Err(err) => fail(err),
+            }
+        }
+        Action::Adopt => {
+            let ops = load_plan(&cfg)?;
+            let (ops, new) = if adopt_paths.is_empty() {
+                (ops, Vec::new())
+            } else {
+                adopt::given(&adopt_paths, ops, &cfg).unwrap_or_else(|err| fail(err))
+            };
+            let result = adopt::adopt(&ops, &new, &cfg, review, &mut operations);
+            report::finish(operations);
+            return result;
+        }
+        Action::Status => {
+            let out_of_sync =
+                status::status(&load_plan(&cfg)?, &cfg, convert_shadows, &mut operations);
+            if convert_shadows || report::json() || report::html() {
+                report::finish(operations);
+            }
+            if let Some(conditions) = &fail_on {
+                exit_on(conditions);
+            } else if out_of_sync > 0 {
+                exit(1);
+            }
+            return Ok(());
+        }
+        _ => {}
     }
 
-    if !cfg.file.exists() {
-        printfc!(LogLevel::Fatal, "{:?} not found", cfg.file);
-        exit(1);
+    if delete_all && !cfg.dry && !cfg.force && !confirm_delete_all(&cfg)? {
+        return Ok(());
     }
-
-    let cfg = cfg;
     let result = run(&cfg, &mut operations);
-    println!("{} operations were performed.", operations);
-    result
+    report::finish(operations);
+    if let Err(err) = result {
+        printfc!(LogLevel::Fatal, "{err}");
+        exit(1);
+    }
+    if let Some(conditions) = &fail_on {
+        exit_on(conditions);
+        return Ok(());
+    }
+    // Failed entries were reported as they came, the run still fails
+    if report::failed() > 0 {
+        exit(1);
+    }
+    // Like git diff --exit-code, so scripts can ask whether all is in sync
+    if cfg.dry && report::out_of_sync() {
+        exit(2);
+    }
+    Ok(())
 }
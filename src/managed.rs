@@ -0,0 +1,182 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{
+    Config, LogLevel, Mode, Operation, attrs, execute_op, ignore, journal, report, state, status,
+    transform, unmanage,
+};
+
+// Links pointing into the source directory are the ones neostow placed there;
+// anything else in the directory belongs to someone else and is left alone
+fn is_ours(target: &Path, src: &Path) -> bool {
+    target.starts_with(src)
+}
+
+fn remove_stale(link: &Path, cfg: &Config, operations: &mut i32) -> io::Result<bool> {
+    if cfg.dry {
+        printfc!(LogLevel::Info, "Would remove {}", link.display());
+        report::pending(report::Pending::Remove);
+        return Ok(false);
+    }
+    journal::remove(&*cfg.fs, link)?;
+    *operations += 1;
+    if cfg.verbose {
+        report::out(&format!("Removed stale symlink: {}", link.display()));
+    }
+    Ok(true)
+}
+
+// A directory link left where a real directory should be is only replaced
+// with -o, and removed when deleting
+fn replace_link(dest: &Path, cfg: &Config, what: &str) -> io::Result<bool> {
+    if !dest
+        .symlink_metadata()
+        .is_ok_and(|meta| meta.file_type().is_symlink())
+    {
+        return Ok(false);
+    }
+    if matches!(cfg.mode, Mode::Create) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} is a symlink, not {what} (use -o to replace it)",
+                dest.display()
+            ),
+        ));
+    }
+    if cfg.dry {
+        printfc!(LogLevel::Info, "Would remove {}", dest.display());
+        report::pending(report::Pending::Overwrite);
+        return Ok(false);
+    }
+    journal::remove(&*cfg.fs, dest)?;
+    Ok(true)
+}
+
+fn child_op(op: &Operation, child: &fs::DirEntry, cfg: &Config) -> io::Result<Operation> {
+    Ok(Operation {
+        src: child.path(),
+        dest: op
+            .dest
+            .join(transform::apply(&cfg.transforms, &child.file_name())?),
+        is_dir: child.file_type()?.is_dir(),
+        validators: Vec::new(),
+        refreshes: Vec::new(),
+        managed: false,
+        preserve: op.preserve,
+        keep_original: op.keep_original,
+        require_target: false,
+        fetch: None,
+        attrs: op.attrs.clone(),
+        template: None,
+        dedup: op.dedup,
+        assert: op.assert,
+        tag: op.tag.clone(),
+        desc: op.desc.clone(),
+        package: op.package.clone(),
+        manual: op.manual,
+        origin: op.origin.clone(),
+    })
+}
+
+// A managed directory is a real directory holding one link per child of the
+// source, kept in sync on every run: missing children are linked and links to
+// children that are gone are removed
+pub fn sync(op: &Operation, cfg: &Config, operations: &mut i32) -> io::Result<bool> {
+    replace_link(&op.dest, cfg, "a managed directory")?;
+    let delete = matches!(cfg.mode, Mode::Delete);
+    if !delete && !cfg.dry {
+        let created = state::make_dirs(&*cfg.fs, &op.dest)?;
+        attrs::apply_dirs(&op.attrs, &created, cfg)?;
+    }
+
+    let mut changed = false;
+    for child in fs::read_dir(&op.src)? {
+        let child_op = child_op(op, &child?, cfg)?;
+        if unmanage::covers(&cfg.unmanaged, &child_op.dest)
+            || ignore::ignored(cfg, &child_op.src, child_op.is_dir)
+        {
+            continue;
+        }
+        let linked = status::in_place(&child_op.src, &child_op.dest, cfg)?;
+        // Deleting only ever removes our own links, linking skips them
+        if linked == delete {
+            changed |= execute_op(&child_op, cfg, operations)?;
+        }
+    }
+
+    let Ok(entries) = fs::read_dir(&op.dest) else {
+        return Ok(changed);
+    };
+    for entry in entries {
+        let entry = entry?;
+        let link = entry.path();
+        if unmanage::covers(&cfg.unmanaged, &link) {
+            continue;
+        }
+        let Ok(target) = fs::read_link(&link) else {
+            continue;
+        };
+        // A link is expected where its source's name, transformed, puts it,
+        // unless the source has since been ignored
+        let expected = target.parent() == Some(op.src.as_path())
+            && target.file_name().is_some_and(|name| {
+                transform::apply(&cfg.transforms, name).is_ok_and(|name| name == entry.file_name())
+            })
+            && !ignore::ignored(cfg, &target, target.is_dir());
+        let stale = if is_ours(&target, &op.src) {
+            !expected || !target.exists()
+        } else {
+            // Foreign links are only collected on request, and only once dangling
+            cfg.gc_broken && !delete && !link.exists()
+        };
+        if stale {
+            changed |= remove_stale(&link, cfg, operations)?;
+        }
+    }
+
+    Ok(changed)
+}
+
+// Without folding, a directory source becomes a tree of real directories with
+// links only at its files, like GNU stow's unfolded layout, so other programs
+// can still write files into those directories
+pub fn unfold(op: &Operation, cfg: &Config, operations: &mut i32) -> io::Result<bool> {
+    let delete = matches!(cfg.mode, Mode::Delete);
+    if replace_link(&op.dest, cfg, "an unfolded directory")? && delete {
+        *operations += 1;
+        return Ok(true);
+    }
+    if !delete && !cfg.dry {
+        let created = state::make_dirs(&*cfg.fs, &op.dest)?;
+        attrs::apply_dirs(&op.attrs, &created, cfg)?;
+    }
+
+    let mut changed = false;
+    for child in fs::read_dir(&op.src)? {
+        let child_op = child_op(op, &child?, cfg)?;
+        if unmanage::covers(&cfg.unmanaged, &child_op.dest)
+            || ignore::ignored(cfg, &child_op.src, child_op.is_dir)
+        {
+            continue;
+        }
+        if child_op.is_dir {
+            changed |= unfold(&child_op, cfg, operations)?;
+            continue;
+        }
+        let linked = status::in_place(&child_op.src, &child_op.dest, cfg)?;
+        if linked == delete {
+            changed |= execute_op(&child_op, cfg, operations)?;
+        }
+    }
+
+    // Directories left empty go too; anything still inside isn't ours
+    if delete
+        && !cfg.dry
+        && fs::read_dir(&op.dest).is_ok_and(|mut entries| entries.next().is_none())
+    {
+        fs::remove_dir(&op.dest)?;
+    }
+    Ok(changed)
+}
@@ -0,0 +1,149 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::xattr;
+
+// Which metadata of a source its copies take on, besides the content.
+// Hard links share it by nature and symlinks have none of their own.
+#[derive(Clone, Copy, Default)]
+pub struct Preserve {
+    pub mode: bool,
+    pub times: bool,
+    pub xattr: bool,
+}
+
+impl Preserve {
+    // Reads a comma-separated list like rsync's: mode,times,xattr
+    pub fn parse(list: &[u8]) -> io::Result<Preserve> {
+        let mut preserve = Preserve::default();
+        for item in list.split(|&b| b == b',').map(<[u8]>::trim_ascii) {
+            match item {
+                b"mode" => preserve.mode = true,
+                b"times" => preserve.times = true,
+                b"xattr" => preserve.xattr = true,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "cannot preserve {:?}, expected mode, times or xattr",
+                            String::from_utf8_lossy(item)
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(preserve)
+    }
+
+    pub fn union(self, other: Preserve) -> Preserve {
+        Preserve {
+            mode: self.mode || other.mode,
+            times: self.times || other.times,
+            xattr: self.xattr || other.xattr,
+        }
+    }
+
+    // What a rename keeps, for copies standing in for one
+    pub(crate) fn everything() -> Preserve {
+        Preserve {
+            mode: true,
+            times: true,
+            xattr: cfg!(any(
+                target_os = "linux",
+                target_os = "android",
+                target_os = "macos"
+            )),
+        }
+    }
+
+    fn any(&self) -> bool {
+        self.mode || self.times || self.xattr
+    }
+}
+
+#[cfg(unix)]
+fn describe(perms: &fs::Permissions) -> String {
+    use std::os::unix::fs::PermissionsExt;
+    format!("{:o}", perms.mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn describe(perms: &fs::Permissions) -> String {
+    if perms.readonly() {
+        "read-only"
+    } else {
+        "writable"
+    }
+    .to_string()
+}
+
+// Gives the copy at dest the metadata of src. Directories are done after
+// their children, since filling them in moves their own times.
+pub fn apply(src: &Path, dest: &Path, preserve: Preserve) -> io::Result<()> {
+    if !preserve.any() {
+        return Ok(());
+    }
+    let meta = fs::metadata(src)?;
+    if meta.is_dir() {
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            apply(&entry.path(), &dest.join(entry.file_name()), preserve)?;
+        }
+    }
+    // Setting attributes can take write permission the mode may take away,
+    // and doesn't move the times
+    if preserve.xattr {
+        xattr::copy(src, dest)?;
+    }
+    if preserve.times {
+        let times = fs::FileTimes::new()
+            .set_accessed(meta.accessed()?)
+            .set_modified(meta.modified()?);
+        fs::File::open(dest)?.set_times(times)?;
+    }
+    if preserve.mode {
+        fs::set_permissions(dest, meta.permissions())?;
+    }
+    Ok(())
+}
+
+// Describes the first difference in preserved metadata between src and its
+// copy at dest, if any
+pub fn drift(src: &Path, dest: &Path, preserve: Preserve) -> io::Result<Option<String>> {
+    if !preserve.any() {
+        return Ok(None);
+    }
+    let (a, b) = (fs::metadata(src)?, fs::metadata(dest)?);
+    if a.is_dir() && b.is_dir() {
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            let drift = drift(&entry.path(), &dest.join(entry.file_name()), preserve)?;
+            if drift.is_some() {
+                return Ok(drift);
+            }
+        }
+    }
+    if preserve.xattr && xattr::list(src)? != xattr::list(dest)? {
+        return Ok(Some(format!(
+            "{} has other extended attributes than its source",
+            dest.display()
+        )));
+    }
+    if preserve.mode && a.permissions() != b.permissions() {
+        return Ok(Some(format!(
+            "{} is {}, its source {}",
+            dest.display(),
+            describe(&b.permissions()),
+            describe(&a.permissions())
+        )));
+    }
+    // Directory times move whenever their content does
+    if preserve.times && !a.is_dir() && a.modified()? != b.modified()? {
+        return Ok(Some(format!(
+            "{} was modified at another time than its source",
+            dest.display()
+        )));
+    }
+    Ok(None)
+}
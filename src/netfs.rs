@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, OnceLock, mpsc};
+use std::thread;
+use std::time::Duration;
+
+// A hung NFS/SSHFS mount blocks stat(2) indefinitely, so probes run detached
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+const NETWORK_FS: &[&str] = &[
+    "nfs",
+    "nfs4",
+    "cifs",
+    "smb3",
+    "smbfs",
+    "9p",
+    "afs",
+    "ceph",
+    "glusterfs",
+    "davfs",
+    "fuse.sshfs",
+    "fuse.rclone",
+    "fuse.davfs2",
+];
+
+// Linux errno values not covered by io::ErrorKind
+const EHOSTDOWN: i32 = 112;
+
+struct Mount {
+    point: PathBuf,
+    fstype: String,
+}
+
+static MOUNTS: OnceLock<Vec<Mount>> = OnceLock::new();
+// Whether each network mount answered, probed once per run
+static PROBED: OnceLock<Mutex<HashMap<PathBuf, bool>>> = OnceLock::new();
+
+fn parse(table: &str) -> Vec<Mount> {
+    table
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let _device = fields.next()?;
+            // Spaces in mount points are octal-escaped in the mount table
+            let point = fields.next()?.replace("\\040", " ");
+            let fstype = fields.next()?.to_string();
+            Some(Mount {
+                point: PathBuf::from(point),
+                fstype,
+            })
+        })
+        .collect()
+}
+
+fn mounts() -> &'static [Mount] {
+    MOUNTS.get_or_init(|| {
+        fs::read_to_string("/proc/self/mounts")
+            .map(|table| parse(&table))
+            .unwrap_or_default()
+    })
+}
+
+fn probed() -> MutexGuard<'static, HashMap<PathBuf, bool>> {
+    PROBED.get_or_init(Default::default).lock().unwrap()
+}
+
+// Returns the mount point of the network filesystem holding path, if any
+fn network_mount(path: &Path) -> Option<&'static Path> {
+    let mount = mounts()
+        .iter()
+        .filter(|m| path.starts_with(&m.point))
+        .max_by_key(|m| m.point.as_os_str().len())?;
+    if NETWORK_FS.contains(&mount.fstype.as_str()) {
+        Some(&mount.point)
+    } else {
+        None
+    }
+}
+
+fn probe(point: &Path) -> bool {
+    let (tx, rx) = mpsc::channel();
+    let point = point.to_path_buf();
+    thread::spawn(move || {
+        let _ = tx.send(fs::metadata(point));
+    });
+    match rx.recv_timeout(PROBE_TIMEOUT) {
+        Ok(Ok(_)) => true,
+        Ok(Err(err)) => !is_unavailable_error(&err),
+        Err(_) => false,
+    }
+}
+
+pub fn is_unavailable(path: &Path) -> bool {
+    let Some(point) = network_mount(path) else {
+        return false;
+    };
+    let reachable = *probed()
+        .entry(point.to_path_buf())
+        .or_insert_with(|| probe(point));
+    !reachable
+}
+
+pub fn is_unavailable_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::NetworkDown
+            | io::ErrorKind::NotConnected
+            | io::ErrorKind::TimedOut
+            | io::ErrorKind::StaleNetworkFileHandle
+    ) || err.raw_os_error() == Some(EHOSTDOWN)
+}
+
+pub fn unavailable_error(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::HostUnreachable,
+        format!("{} is on an unavailable network mount", path.display()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vfs::{Fs, MemoryFs, Node};
+    use crate::{Config, parse_line, plan};
+    use std::ffi::OsString;
+    use std::sync::Arc;
+
+    const MOUNT: &str = "/mnt/nfs";
+
+    // Stands for a hung mount: any look under it fails the test, where a
+    // real one would never return
+    struct Hung(MemoryFs);
+
+    impl Hung {
+        fn at(&self, path: &Path) -> &MemoryFs {
+            assert!(!path.starts_with(MOUNT), "{} was looked at", path.display());
+            &self.0
+        }
+    }
+
+    impl Fs for Hung {
+        fn exists(&self, path: &Path) -> bool {
+            self.at(path).exists(path)
+        }
+
+        fn is_dir(&self, path: &Path) -> bool {
+            self.at(path).is_dir(path)
+        }
+
+        fn read_dir(&self, path: &Path) -> io::Result<Vec<(OsString, bool)>> {
+            self.at(path).read_dir(path)
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<Node> {
+            self.at(path).metadata(path)
+        }
+
+        fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+            self.at(path).create_dir_all(path)
+        }
+
+        fn create(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+            self.at(path).create(path, content)
+        }
+
+        fn symlink(&self, target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+            self.at(link).symlink(target, link, is_dir)
+        }
+
+        fn remove(&self, path: &Path) -> io::Result<()> {
+            self.at(path).remove(path)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            self.at(to).rename(from, to)
+        }
+
+        fn copy(&self, from: &Path, to: &Path, hardlink: bool) -> io::Result<()> {
+            self.at(to).copy(from, to, hardlink)
+        }
+    }
+
+    #[test]
+    fn unavailable_mounts_are_never_looked_at() {
+        let table = format!("/dev/sda1 / ext4 rw 0 0\nserver:/export {MOUNT} nfs4 rw 0 0\n");
+        assert!(MOUNTS.set(parse(&table)).is_ok());
+        probed().insert(PathBuf::from(MOUNT), false);
+
+        let mut files = MemoryFs::new();
+        files.add_file("/repo/bashrc");
+        files.add_dir("/home/me");
+        let mut cfg = Config::new(PathBuf::from("/repo/neostow"));
+        cfg.fs = Arc::new(Hung(files));
+        cfg.skip_unavailable = true;
+        cfg.allow_outside_home = true;
+
+        let entry = parse_line(b"bashrc=/mnt/nfs/me").unwrap();
+        let Err(err) = plan(&entry, &cfg) else {
+            panic!("planned a destination on an unavailable mount");
+        };
+        assert!(is_unavailable_error(&err));
+        assert_eq!(
+            err.to_string(),
+            "/mnt/nfs/me/bashrc is on an unavailable network mount"
+        );
+
+        let entry = parse_line(b"bashrc=/home/me").unwrap();
+        let ops = plan(&entry, &cfg).unwrap();
+        assert_eq!(ops[0].dest, Path::new("/home/me/bashrc"));
+    }
+}
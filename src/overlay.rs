@@ -0,0 +1,306 @@
+// Files layered over the main neostow file for one machine. `neostow
+// new-host` writes hosts/NAME.neostow beside it for the machine it runs on,
+// every line under an @host(NAME) condition so its entries apply there and
+// nowhere else, with the OS it finds, and adds an @include of it to the main
+// file, where it is committed with the rest. It asks which packages the
+// machine uses, gives each a section in the file to hold what differs there,
+// and disables the others on this machine as `neostow disable` does.
+//
+// `neostow edit host` opens that file, and `neostow edit local` opens
+// .neostow.local, which is for this machine too but kept out of version
+// control, so it is included under @host(NAME) and other machines pass over
+// it. `neostow edit NAME` opens the file of an @include named NAME. A file
+// that isn't there yet is created from a template, and the host and local
+// files are included from the main file as they are.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{
+    Config, LogLevel, activation, age, bytes, format, include, init, platform, report,
+    section_header,
+};
+
+// Sections that hold something else than the entries of a package
+pub(crate) const RESERVED: [&[u8]; 5] = [b"reload", b"transform", b"aliases", b"registry", b"vars"];
+
+/// The file `neostow edit local` opens, beside the neostow file.
+pub const LOCAL_FILE: &str = ".neostow.local";
+
+/// The host file of this machine, relative to the directory of the neostow
+/// file.
+pub fn host_file() -> PathBuf {
+    Path::new("hosts").join(format!("{}.neostow", platform::hostname()))
+}
+
+// Adds the packages of content and the files cfg includes to found, once each
+fn packages(cfg: &Config, content: &[u8], found: &mut Vec<String>) -> io::Result<()> {
+    for line in bytes::lines(content) {
+        let Some(name) = section_header(line).map(<[u8]>::trim_ascii) else {
+            continue;
+        };
+        let name = String::from_utf8_lossy(name).into_owned();
+        if !RESERVED.contains(&name.as_bytes()) && !found.contains(&name) {
+            found.push(name);
+        }
+    }
+    for (_, child) in &cfg.includes {
+        packages(child, &include::read(child)?, found)?;
+    }
+    Ok(())
+}
+
+// Which of packages this machine uses, all of them unless asked and answered
+fn choose(packages: &[String]) -> io::Result<Vec<String>> {
+    if packages.is_empty() || report::assumed().is_some() {
+        return Ok(packages.to_vec());
+    }
+    printfc!(LogLevel::Info, "Packages: {}", packages.join(", "));
+    loop {
+        let Some(answer) = report::ask("Packages this machine uses", " [all]: ", &[])? else {
+            return Ok(packages.to_vec());
+        };
+        let chosen: Vec<String> = answer
+            .split([',', ' '])
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        if chosen.is_empty() {
+            return Ok(packages.to_vec());
+        }
+        match chosen.iter().find(|name| !packages.contains(name)) {
+            Some(name) => {
+                printfc!(LogLevel::Warn, "There is no package {name}");
+            }
+            None => return Ok(chosen),
+        }
+    }
+}
+
+// The neostow file of cfg must be one lines can be added to
+pub(crate) fn writable(cfg: &Config, command: &str) -> io::Result<()> {
+    if age::is_encrypted(&cfg.file) || !format::is_plain(&cfg.file) {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{command} adds lines to {}, which needs to be in the line format and not encrypted",
+                cfg.file.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+// A host file for the host, with a section for each package
+fn template(host: &str, main: &Path, packages: &[String]) -> String {
+    let os = env::consts::OS;
+    let name = main.file_name().unwrap_or_default().to_string_lossy();
+    let gate = format!("@host({host}) @os({os})\n");
+    let mut out = format!(
+        "# Entries for {host}, a {os} machine, included from {name}. Each\n\
+         # section starts with the condition that keeps them to this machine.\n\
+         {gate}"
+    );
+    for package in packages {
+        out.push_str(&format!("\n[{package}]\n{gate}"));
+    }
+    out
+}
+
+// Adds `@include rel` to the main file, unless it is there already. With
+// host, it is only followed on that host.
+fn register(file: &Path, rel: &Path, host: Option<&str>) -> io::Result<bool> {
+    let line = format!("@include {}", rel.display());
+    if bytes::lines(&fs::read(file)?).any(|existing| existing.trim_ascii() == line.as_bytes()) {
+        return Ok(false);
+    }
+    let lines = match host {
+        Some(host) => format!("@host({host})\n{line}\n@end"),
+        None => line,
+    };
+    add_lines(file, &lines)?;
+    Ok(true)
+}
+
+// Adds lines to the neostow file before its first section, where they
+// belong to no package
+pub(crate) fn add_lines(file: &Path, lines: &str) -> io::Result<()> {
+    let content = fs::read(file)?;
+    let at = bytes::lines(&content)
+        .position(|line| section_header(line).is_some())
+        .map(|i| {
+            content
+                .split_inclusive(|&b| b == b'\n')
+                .take(i)
+                .map(<[u8]>::len)
+                .sum()
+        })
+        .unwrap_or(content.len());
+    let mut out = content[..at].to_vec();
+    if !out.is_empty() && !out.ends_with(b"\n") {
+        out.push(b'\n');
+    }
+    out.extend_from_slice(lines.as_bytes());
+    out.push(b'\n');
+    if at < content.len() {
+        out.push(b'\n');
+    }
+    out.extend_from_slice(&content[at..]);
+    fs::write(file, out)
+}
+
+/// Writes the host file of this machine and includes it from the neostow
+/// file of cfg.
+pub fn new_host(cfg: &Config) -> io::Result<()> {
+    writable(cfg, "new-host")?;
+    let host = platform::hostname();
+    let rel = host_file();
+    let path = cfg.basedir.join(&rel);
+    if path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "{} exists already, open it with neostow edit host",
+                path.display()
+            ),
+        ));
+    }
+
+    let mut found = Vec::new();
+    packages(cfg, &age::read(&cfg.file)?, &mut found)?;
+    let chosen = choose(&found)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, template(&host, &cfg.file, &chosen))?;
+    printfc!(LogLevel::Info, "Created {}", path.display());
+    included(cfg, &rel, None)?;
+
+    let left_out: Vec<String> = found
+        .into_iter()
+        .filter(|package| !chosen.contains(package))
+        .collect();
+    if !left_out.is_empty() {
+        activation::set(
+            &Config {
+                packages: left_out,
+                ..cfg.clone()
+            },
+            false,
+        )?;
+    }
+    Ok(())
+}
+
+fn included(cfg: &Config, rel: &Path, host: Option<&str>) -> io::Result<()> {
+    if register(&cfg.file, rel, host)? {
+        printfc!(
+            LogLevel::Info,
+            "Included {} from {}",
+            rel.display(),
+            cfg.file.display()
+        );
+    }
+    Ok(())
+}
+
+// Whether name stands for the include written so: the path as written, its
+// file name without .neostow, or for a .neostow file, its directory
+fn named(written: &[u8], path: &Path, name: &str) -> bool {
+    if written == name.as_bytes() {
+        return true;
+    }
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = file_name.strip_suffix(".neostow").unwrap_or(&file_name);
+    if stem.is_empty() {
+        return path
+            .parent()
+            .and_then(Path::file_name)
+            .is_some_and(|dir| dir.to_string_lossy() == name);
+    }
+    stem == name
+}
+
+// The file of the first @include named name in cfg's file or the files it
+// includes
+fn find(cfg: &Config, name: &str) -> io::Result<Option<PathBuf>> {
+    let content = age::read(&cfg.file)?;
+    let paths = include::paths(cfg, &content)?;
+    if let Some((_, path)) = paths
+        .iter()
+        .find(|(written, path)| named(written, path, name))
+    {
+        return Ok(Some(path.clone()));
+    }
+    for (_, path) in paths.into_iter().filter(|(_, path)| path.exists()) {
+        let child = Config {
+            basedir: path.parent().map(PathBuf::from).unwrap_or_default(),
+            file: path,
+            ..cfg.clone()
+        };
+        if let Some(found) = find(&child, name)? {
+            return Ok(Some(found));
+        }
+    }
+    Ok(None)
+}
+
+/// The file `neostow edit target` opens: the local file for local, the host
+/// file for host, or else the file of the @include named target. A file not
+/// there yet is created first, and included when it is the local or host one.
+pub fn edit_target(cfg: &Config, target: &str) -> io::Result<PathBuf> {
+    let host = platform::hostname();
+    match target {
+        "local" => {
+            let path = cfg.basedir.join(LOCAL_FILE);
+            if !path.exists() {
+                writable(cfg, "edit local")?;
+                fs::write(&path, local_template(&host))?;
+                printfc!(LogLevel::Info, "Created {}", path.display());
+                included(cfg, Path::new(LOCAL_FILE), Some(&host))?;
+            }
+            Ok(path)
+        }
+        "host" => {
+            let rel = host_file();
+            let path = cfg.basedir.join(&rel);
+            if !path.exists() {
+                writable(cfg, "edit host")?;
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&path, template(&host, &cfg.file, &[]))?;
+                printfc!(LogLevel::Info, "Created {}", path.display());
+                included(cfg, &rel, None)?;
+            }
+            Ok(path)
+        }
+        name => {
+            let Some(path) = find(cfg, name)? else {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!(
+                        "{} includes no file named {name}; edit takes local, host or the name of an included file",
+                        cfg.file.display()
+                    ),
+                ));
+            };
+            if !path.exists() {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir)?;
+                }
+                init::template(&path)?;
+            }
+            Ok(path)
+        }
+    }
+}
+
+fn local_template(host: &str) -> String {
+    format!(
+        "# Entries for this machine only, which stay out of version control: add\n\
+         # {LOCAL_FILE} to .gitignore. The neostow file includes it on {host} alone.\n"
+    )
+}
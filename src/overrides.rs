@@ -0,0 +1,67 @@
+// `--set SOURCE=DEST` gives one entry another destination for a single run,
+// so a new place can be tried before the file is changed: `--set
+// 'nvim=~/.config-test'` plans the entry whose source is nvim as if its line
+// read `nvim = ~/.config-test`, in place of every destination the file gives
+// it. SOURCE is the source as written in the file, pattern or not, and
+// naming one no entry has is an error rather than a run that changes
+// nothing.
+
+use std::io;
+
+use crate::{Config, age, bytes, include, parse_line, raw};
+
+// The source of an entry, without the quotes and the slash it may be
+// written with
+fn name(src: &[u8]) -> &[u8] {
+    let src = raw(src).unwrap_or(src);
+    src.strip_suffix(b"/").unwrap_or(src)
+}
+
+/// Splits the argument of --set into the source and its destination for
+/// this run.
+pub fn parse(arg: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let (src, dest) = arg.split_once('=')?;
+    let (src, dest) = (name(src.trim().as_bytes()), dest.trim().as_bytes());
+    (!src.is_empty() && !dest.is_empty()).then(|| (src.to_vec(), dest.to_vec()))
+}
+
+// The destination --set gives the entry with source src, if any
+pub(crate) fn dest<'a>(cfg: &'a Config, src: &[u8]) -> Option<&'a [u8]> {
+    cfg.overrides
+        .iter()
+        .find(|(name_set, _)| name_set.as_slice() == name(src))
+        .map(|(_, dest)| dest.as_slice())
+}
+
+// Adds the source of every entry of cfg and the files it includes to found
+fn sources(cfg: &Config, content: &[u8], found: &mut Vec<Vec<u8>>) -> io::Result<()> {
+    for line in bytes::lines(content) {
+        if let Some(entry) = parse_line(line) {
+            found.push(name(entry.src).to_vec());
+        }
+    }
+    for (_, child) in &cfg.includes {
+        sources(child, &include::read(child)?, found)?;
+    }
+    Ok(())
+}
+
+/// Fails naming the first source given to --set that no entry has.
+pub fn check(cfg: &Config) -> io::Result<()> {
+    if cfg.overrides.is_empty() {
+        return Ok(());
+    }
+    let mut found = Vec::new();
+    sources(cfg, &age::read(&cfg.file)?, &mut found)?;
+    match cfg.overrides.iter().find(|(src, _)| !found.contains(src)) {
+        Some((src, _)) => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!(
+                "--set {} matches no entry of {}",
+                bytes::to_os(src).display(),
+                cfg.file.display()
+            ),
+        )),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,119 @@
+// `neostow chown-fix` finds destinations under $HOME, the directories made on
+// the way to them and whatever a copy or unfolded directory holds, that don't
+// belong to the owner of $HOME, as a run with sudo leaves them. They are given
+// back where privileges allow, and the rest is reported.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{Config, LogLevel, Operation, expand};
+
+#[cfg(unix)]
+fn owner(path: &Path) -> Option<(u32, u32)> {
+    use std::os::unix::fs::MetadataExt;
+    let meta = path.symlink_metadata().ok()?;
+    Some((meta.uid(), meta.gid()))
+}
+
+// Windows has no owners to set this way
+#[cfg(not(unix))]
+fn owner(_: &Path) -> Option<(u32, u32)> {
+    None
+}
+
+#[cfg(unix)]
+fn give(path: &Path, (uid, gid): (u32, u32)) -> io::Result<()> {
+    std::os::unix::fs::lchown(path, Some(uid), Some(gid))
+}
+
+#[cfg(not(unix))]
+fn give(_: &Path, _: (u32, u32)) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+// Everything under a real directory, which neostow filled
+fn walk(dir: &Path, out: &mut BTreeSet<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            walk(&path, out);
+        }
+        out.insert(path);
+    }
+}
+
+/// Gives the destinations of ops under $HOME, and the directories leading to
+/// them, back to the owner of $HOME. Returns how many are still owned by
+/// someone else.
+pub fn chown_fix(
+    ops: &[(usize, Operation)],
+    cfg: &Config,
+    operations: &mut i32,
+) -> io::Result<usize> {
+    let home = match &cfg.target {
+        Some(target) => target.clone(),
+        None => PathBuf::from(expand::home(&cfg.vars)?),
+    };
+    let Some(expected) = owner(&home) else {
+        return Ok(0);
+    };
+
+    let mut paths = BTreeSet::new();
+    for (_, op) in ops {
+        if !op.dest.starts_with(&home) {
+            continue;
+        }
+        for dir in op.dest.ancestors().take_while(|dir| *dir != home) {
+            if dir.symlink_metadata().is_ok() {
+                paths.insert(dir.to_path_buf());
+            }
+        }
+        if op.dest.symlink_metadata().is_ok_and(|meta| meta.is_dir()) {
+            walk(&op.dest, &mut paths);
+        }
+    }
+
+    let mut left = 0;
+    for path in paths {
+        let Some(found) = owner(&path).filter(|found| *found != expected) else {
+            continue;
+        };
+        if cfg.dry {
+            printfc!(
+                LogLevel::Info,
+                "Would give {} back to uid {}, it belongs to uid {}",
+                path.display(),
+                expected.0,
+                found.0
+            );
+            continue;
+        }
+        match give(&path, expected) {
+            Ok(()) => {
+                *operations += 1;
+                printfc!(
+                    LogLevel::Info,
+                    "Gave {} back to uid {}",
+                    path.display(),
+                    expected.0
+                );
+            }
+            Err(err) => {
+                printfc!(
+                    LogLevel::Warn,
+                    "{} belongs to uid {}, not {}: {err}",
+                    path.display(),
+                    found.0,
+                    expected.0
+                );
+                left += 1;
+            }
+        }
+    }
+    Ok(left)
+}
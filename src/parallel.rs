@@ -0,0 +1,208 @@
+// With --jobs N, the entries of a batch are applied on N threads. Entries
+// whose destinations are the same or nested are chained and run in file
+// order on one thread, so a directory link and the links under it never
+// race. The session is only locked for its bookkeeping and hooks, never
+// around the filesystem work, and a failure under --atomic or --fail-fast
+// keeps workers from starting anything more. What each entry writes, its
+// log lines, its results and its failure, is held until the entries before
+// it are written, so the output reads as it would without --jobs. mount_jobs
+// in config.toml caps how many run at once under a directory, as an SSHFS or
+// NFS mount only gets slower with more calls in flight; a worker whose next
+// entry is under one at its cap waits for a slot there.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, PoisonError};
+use std::thread;
+
+use crate::{
+    Config, Operation, Session, check_dest, execute_op, fetch, fsops, hooks, report, report_error,
+    skip_manual, skip_without_target, trace,
+};
+use trace::Phase;
+
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// Chains are named after their first operation
+fn union(parent: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (find(parent, a), find(parent, b));
+    parent[a.max(b)] = a.min(b);
+}
+
+// The operations as chains of indices, each in file order, ordered by their
+// first operation
+fn chains(ops: &[(usize, Operation)]) -> Vec<Vec<usize>> {
+    let mut parent: Vec<usize> = (0..ops.len()).collect();
+    let mut by_dest: HashMap<&Path, usize> = HashMap::new();
+    for (k, (_, op)) in ops.iter().enumerate() {
+        match by_dest.get(op.dest.as_path()) {
+            Some(&other) => union(&mut parent, k, other),
+            None => {
+                by_dest.insert(&op.dest, k);
+            }
+        }
+    }
+    for (k, (_, op)) in ops.iter().enumerate() {
+        for ancestor in op.dest.ancestors().skip(1) {
+            if let Some(&other) = by_dest.get(ancestor) {
+                union(&mut parent, k, other);
+            }
+        }
+    }
+
+    let mut chains: Vec<Vec<usize>> = Vec::new();
+    let mut chain_of: HashMap<usize, usize> = HashMap::new();
+    for k in 0..ops.len() {
+        let root = find(&mut parent, k);
+        match chain_of.get(&root) {
+            Some(&chain) => chains[chain].push(k),
+            None => {
+                chain_of.insert(root, chains.len());
+                chains.push(vec![k]);
+            }
+        }
+    }
+    chains
+}
+
+// Which of cfg.mount_jobs caps entries to dest, the innermost when they nest
+fn mount(cfg: &Config, dest: &Path) -> Option<usize> {
+    cfg.mount_jobs
+        .iter()
+        .enumerate()
+        .filter(|(_, (dir, _))| dest.starts_with(dir))
+        .max_by_key(|(_, (dir, _))| dir.components().count())
+        .map(|(m, _)| m)
+}
+
+// What Session::execute does, holding the session only between the steps
+// that touch the filesystem. --interactive runs never get here.
+fn execute(
+    session: &Mutex<&mut Session>,
+    op: &Operation,
+    cfg: &Config,
+    operations: &mut i32,
+) -> io::Result<bool> {
+    let lock = || session.lock().unwrap_or_else(PoisonError::into_inner);
+    let recorded = {
+        let mut session = lock();
+        session.entries += 1;
+        session.applied.get(&op.dest).copied()
+    };
+    if op.assert || skip_manual(op, cfg) || skip_without_target(op, cfg) {
+        return Ok(false);
+    }
+    let result = trace::time(op, Phase::Change, || fetch::update(op, cfg))
+        .and_then(|()| trace::time(op, Phase::Stat, || check_dest(op, cfg, recorded)))
+        .and_then(|drifted| {
+            lock().run_hooks(op, cfg, hooks::When::Pre)?;
+            let changed = trace::time(op, Phase::Change, || execute_op(op, cfg, operations))?;
+            Ok((drifted, changed))
+        });
+    lock().conclude(op, cfg, result)
+}
+
+// Runs ops, with first_line the line of the first in the batch, and returns
+// the failures by index into ops, in order
+pub(crate) fn run(
+    session: &mut Session,
+    ops: &[(usize, Operation)],
+    cfg: &Config,
+    first_line: usize,
+    operations: &mut i32,
+) -> Vec<(usize, io::Error)> {
+    let chains = chains(ops);
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let session = Mutex::new(session);
+    let failed = Mutex::new(Vec::new());
+    let mounts: Vec<_> = ops.iter().map(|(_, op)| mount(cfg, &op.dest)).collect();
+    // How many entries are running under each of cfg.mount_jobs
+    let running = Mutex::new(vec![0; cfg.mount_jobs.len()]);
+    let freed = Condvar::new();
+    // The next entry to write out, and those done before their turn
+    let done = Mutex::new((0, BTreeMap::new()));
+    let write = |k: usize, held: report::Held| {
+        let mut done = done.lock().unwrap_or_else(PoisonError::into_inner);
+        let (turn, waiting) = &mut *done;
+        waiting.insert(k, held);
+        while let Some(held) = waiting.remove(turn) {
+            report::release(held);
+            *turn += 1;
+        }
+    };
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..cfg.jobs.min(chains.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut operations = 0;
+                    while !stop.load(Ordering::Relaxed)
+                        && let Some(chain) = chains.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        for &k in chain {
+                            if stop.load(Ordering::Relaxed) {
+                                return operations;
+                            }
+                            let (i, op) = &ops[k];
+                            report::at_line(&cfg.file, first_line + i);
+                            if let Some(m) = mounts[k] {
+                                let lock = running.lock().unwrap_or_else(PoisonError::into_inner);
+                                let mut running = freed
+                                    .wait_while(lock, |running| running[m] >= cfg.mount_jobs[m].1)
+                                    .unwrap_or_else(PoisonError::into_inner);
+                                running[m] += 1;
+                            }
+                            report::hold();
+                            let result = execute(&session, op, cfg, &mut operations);
+                            if let Some(m) = mounts[k] {
+                                running.lock().unwrap_or_else(PoisonError::into_inner)[m] -= 1;
+                                freed.notify_all();
+                            }
+                            if let Err(err) = result {
+                                // Busy destinations are no failure to stop at
+                                // yet, unless everything is to be rolled back
+                                if cfg.atomic || cfg.fail_fast && !fsops::is_busy(&err) {
+                                    stop.store(true, Ordering::Relaxed);
+                                }
+                                // Busy destinations are retried later, not failed yet
+                                if cfg.atomic || !fsops::is_busy(&err) {
+                                    report_error(cfg, &cfg.file, first_line + i, Some(op), &err);
+                                }
+                                failed
+                                    .lock()
+                                    .unwrap_or_else(PoisonError::into_inner)
+                                    .push((k, err));
+                            }
+                            write(k, report::held());
+                        }
+                    }
+                    operations
+                })
+            })
+            .collect();
+        for worker in workers {
+            *operations += worker
+                .join()
+                .unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+        }
+    });
+    // Entries after one --atomic or --fail-fast stopped at never ran, and
+    // leave gaps
+    let (_, rest) = done.into_inner().unwrap_or_else(PoisonError::into_inner);
+    for held in rest.into_values() {
+        report::release(held);
+    }
+
+    let mut failed = failed.into_inner().unwrap_or_else(PoisonError::into_inner);
+    failed.sort_by_key(|(k, _)| *k);
+    failed
+}
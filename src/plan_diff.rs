@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glyphs::{self, Glyph};
+use crate::vcs::{self, Vcs};
+use crate::{
+    Config, LogLevel, Mode, Operation, Session, age, format, plan_lines, prompt_user, state, status,
+};
+
+// Plans are compared by destination, since that is what a link occupies. A
+// source that moved from one destination to another is retargeted rather
+// than removed and added.
+pub struct PlanDiff {
+    pub added: Vec<Operation>,
+    pub removed: Vec<Operation>,
+    pub changed: Vec<(Operation, Operation)>,
+    pub retargeted: Vec<(Operation, Operation)>,
+}
+
+impl PlanDiff {
+    pub fn new(old: Vec<(usize, Operation)>, new: Vec<(usize, Operation)>) -> Self {
+        let mut old: BTreeMap<PathBuf, Operation> = old
+            .into_iter()
+            .map(|(_, op)| (op.dest.clone(), op))
+            .collect();
+        let mut diff = PlanDiff {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+            retargeted: Vec::new(),
+        };
+
+        let mut added = Vec::new();
+        for (_, op) in new {
+            match old.remove(&op.dest) {
+                Some(prev) if prev.src != op.src => diff.changed.push((prev, op)),
+                Some(_) => {}
+                None => added.push(op),
+            }
+        }
+        diff.removed = old.into_values().collect();
+        for op in added {
+            match diff.removed.iter().position(|prev| prev.src == op.src) {
+                Some(i) => diff.retargeted.push((diff.removed.remove(i), op)),
+                None => diff.added.push(op),
+            }
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.changed.is_empty()
+            && self.retargeted.is_empty()
+    }
+
+    pub fn print(&self) {
+        let arrow = glyphs::get(Glyph::Arrow);
+        for op in &self.added {
+            println!("+ {} {arrow} {}", op.src.display(), op.dest.display());
+        }
+        for op in &self.removed {
+            println!("- {} {arrow} {}", op.src.display(), op.dest.display());
+        }
+        for (prev, op) in &self.changed {
+            println!(
+                "~ {}: {} {arrow} {}",
+                op.dest.display(),
+                prev.src.display(),
+                op.src.display()
+            );
+        }
+        for (prev, op) in &self.retargeted {
+            println!(
+                "> {}: {} {arrow} {}",
+                op.src.display(),
+                prev.dest.display(),
+                op.dest.display()
+            );
+        }
+    }
+
+    // Applied in a session like a run, so links, hooks and the queue of
+    // conflicts are kept as they would be. What stops it partway is on
+    // record all the same.
+    pub fn apply(&self, cfg: &Config, operations: &mut i32) -> io::Result<()> {
+        let mut session = Session::new(cfg)?;
+        let result = self.apply_in(&mut session, cfg, operations);
+        session.finish(cfg, &state::load_failed()?)?;
+        result
+    }
+
+    // Removed entries are only unlinked while they still come from their old
+    // source, so a file put in place since the last run is left alone
+    fn apply_in(
+        &self,
+        session: &mut Session,
+        cfg: &Config,
+        operations: &mut i32,
+    ) -> io::Result<()> {
+        let delete = Config {
+            mode: Mode::Delete,
+            ..cfg.clone()
+        };
+        let moved = self.retargeted.iter().map(|(prev, _)| prev);
+        for op in self.removed.iter().chain(moved) {
+            if status::op_in_place(op, cfg)? {
+                session.execute(op, &delete, operations)?;
+            } else {
+                printfc!(
+                    LogLevel::Warn,
+                    "Not removing {}: it no longer comes from {}",
+                    op.dest.display(),
+                    op.src.display()
+                );
+            }
+        }
+
+        let overwrite = Config {
+            mode: Mode::Overwrite,
+            ..cfg.clone()
+        };
+        for (_, op) in &self.changed {
+            session.execute(op, &overwrite, operations)?;
+        }
+
+        let create = Config {
+            mode: Mode::Create,
+            ..cfg.clone()
+        };
+        let moved = self.retargeted.iter().map(|(_, op)| op);
+        for op in self.added.iter().chain(moved) {
+            session.execute(op, &create, operations)?;
+        }
+
+        Ok(())
+    }
+}
+
+pub fn confirm_and_apply(diff: &PlanDiff, cfg: &Config, operations: &mut i32) -> io::Result<()> {
+    if diff.is_empty() {
+        printfc!(LogLevel::Info, "The plan is unchanged");
+        return Ok(());
+    }
+
+    diff.print();
+    if cfg.dry {
+        return Ok(());
+    }
+    if !cfg.force && !prompt_user("Apply these changes?")? {
+        return Ok(());
+    }
+    diff.apply(cfg, operations)
+}
+
+// The plan content would make as the file at path, with the options of base
+fn plan_as(content: &[u8], path: &Path, base: &Config) -> io::Result<Vec<(usize, Operation)>> {
+    let fresh = Config::new(path.to_path_buf());
+    let mut cfg = Config {
+        file: fresh.file,
+        basedir: fresh.basedir,
+        ..base.clone()
+    };
+    cfg.load_from(content)?;
+    plan_lines(&cfg, content, |file_cfg, linenum, err| {
+        printfc!(
+            LogLevel::Warn,
+            "{}:{linenum}: {err}",
+            file_cfg.file.display()
+        );
+    })
+}
+
+/// The plan of the neostow file of cfg as it is now, with the file loaded
+/// afresh, so what it includes, renames and leaves unmanaged counts.
+pub fn current(cfg: &Config) -> io::Result<Vec<(usize, Operation)>> {
+    plan_as(&age::read(&cfg.file)?, &cfg.file, cfg)
+}
+
+// The file at path as of the revision rev of vcs
+fn at_revision(vcs: &dyn Vcs, path: &Path, rev: &str) -> io::Result<Vec<u8>> {
+    format::of(path).lines(vcs.show(path, rev)?, path)
+}
+
+/// Compares the plans of the files old and new, both planned against the
+/// sources on disk, and prints what the change would add, remove, point at
+/// another source or move to another destination. Nothing is applied.
+pub fn files(old: &Path, new: &Path, cfg: &Config) -> io::Result<()> {
+    let before = plan_as(&age::read(old)?, old, cfg)?;
+    let after = plan_as(&age::read(new)?, new, cfg)?;
+    show(&PlanDiff::new(before, after));
+    Ok(())
+}
+
+/// Like `files` for the neostow file of cfg at two revisions of the
+/// repository it is kept in, given as OLD..NEW, or as OLD alone to compare
+/// with the file as it is now. OLD left out is the last commit.
+pub fn revisions(range: &str, cfg: &Config) -> io::Result<()> {
+    let vcs = vcs::of(&cfg.basedir);
+    let (old, new) = match range.split_once("..") {
+        Some((old, new)) => (old, Some(new).filter(|new| !new.is_empty())),
+        None => (range, None),
+    };
+    let old = if old.is_empty() { vcs.base() } else { old };
+    if cfg.debug {
+        printfc!(
+            LogLevel::Debug,
+            "Reading {} from the {} repository",
+            cfg.file.display(),
+            vcs.name()
+        );
+    }
+    let before = plan_as(&at_revision(vcs, &cfg.file, old)?, &cfg.file, cfg)?;
+    let content = match new {
+        Some(new) => at_revision(vcs, &cfg.file, new)?,
+        None => age::read(&cfg.file)?,
+    };
+    let after = plan_as(&content, &cfg.file, cfg)?;
+    show(&PlanDiff::new(before, after));
+    Ok(())
+}
+
+fn show(diff: &PlanDiff) {
+    if diff.is_empty() {
+        printfc!(LogLevel::Info, "The plans are the same");
+    }
+    diff.print();
+}
@@ -0,0 +1,174 @@
+// The home directory, host name, user name and XDG base directories are
+// looked up here, and only here, without running a program. For tests,
+// --override-home and --override-hostname, or NEOSTOW_HOME and
+// NEOSTOW_HOSTNAME, stand in for the real ones everywhere they are used:
+// ~ and $HOME, @host(...) sections, $HOSTNAME, and the config, state and
+// data directories. Under an overridden home the XDG variables are left
+// out, so nothing is read from or written to the real one.
+
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::sync::{OnceLock, RwLock};
+
+static HOME: RwLock<Option<PathBuf>> = RwLock::new(None);
+static HOSTNAME: RwLock<Option<String>> = RwLock::new(None);
+
+/// Uses dir as the home directory, as --override-home does.
+pub fn set_home(dir: PathBuf) {
+    *HOME.write().unwrap_or_else(|e| e.into_inner()) = Some(dir);
+}
+
+/// Uses name as the host name, as --override-hostname does.
+pub fn set_hostname(name: String) {
+    *HOSTNAME.write().unwrap_or_else(|e| e.into_inner()) = Some(name);
+}
+
+fn non_empty(name: &str) -> Option<OsString> {
+    env::var_os(name).filter(|value| !value.is_empty())
+}
+
+fn overridden_home() -> Option<PathBuf> {
+    let home = HOME.read().unwrap_or_else(|e| e.into_inner()).clone();
+    home.or_else(|| non_empty("NEOSTOW_HOME").map(PathBuf::from))
+}
+
+/// The home directory, or an empty path when there is none. Windows sets
+/// USERPROFILE, and HOME only under some shells.
+pub fn home() -> PathBuf {
+    overridden_home()
+        .map(OsString::from)
+        .or_else(|| non_empty("HOME"))
+        .or_else(|| non_empty("USERPROFILE").filter(|_| cfg!(windows)))
+        .map(PathBuf::from)
+        .unwrap_or_default()
+}
+
+#[cfg(unix)]
+fn system_hostname() -> Option<String> {
+    use std::ffi::{CStr, c_char, c_int};
+
+    unsafe extern "C" {
+        fn gethostname(name: *mut c_char, len: usize) -> c_int;
+    }
+    let mut buf = [0 as c_char; 256];
+    // The last byte stays 0, as a name that doesn't fit may be cut short
+    // without one
+    if unsafe { gethostname(buf.as_mut_ptr(), buf.len() - 1) } != 0 {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr(buf.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn system_hostname() -> Option<String> {
+    env::var("COMPUTERNAME").ok()
+}
+
+/// The host name @host(...) matches.
+pub fn hostname() -> String {
+    static SYSTEM: OnceLock<String> = OnceLock::new();
+    if let Some(name) = &*HOSTNAME.read().unwrap_or_else(|e| e.into_inner()) {
+        return name.clone();
+    }
+    if let Some(name) = non_empty("NEOSTOW_HOSTNAME") {
+        return name.to_string_lossy().into_owned();
+    }
+    SYSTEM
+        .get_or_init(|| {
+            system_hostname()
+                .or_else(|| env::var("HOSTNAME").ok())
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .clone()
+}
+
+#[cfg(unix)]
+fn system_username() -> Option<String> {
+    use std::ffi::{CStr, c_char};
+
+    // Every unix starts struct passwd with the name, and only it is read
+    #[repr(C)]
+    struct Passwd {
+        name: *const c_char,
+    }
+    unsafe extern "C" {
+        fn getuid() -> u32;
+        fn getpwuid(uid: u32) -> *const Passwd;
+    }
+    let entry = unsafe { getpwuid(getuid()) };
+    if entry.is_null() || unsafe { (*entry).name }.is_null() {
+        return None;
+    }
+    let name = unsafe { CStr::from_ptr((*entry).name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(unix))]
+fn system_username() -> Option<String> {
+    None
+}
+
+/// The name of the user running neostow, as the environment has it, or
+/// else the user database.
+pub fn username() -> Option<String> {
+    ["USER", "LOGNAME", "USERNAME"]
+        .iter()
+        .find_map(|name| non_empty(name))
+        .map(|name| name.to_string_lossy().into_owned())
+        .or_else(system_username)
+}
+
+// What $NAME stands for in paths when the file doesn't set it: the
+// environment, except for what is looked up here
+pub(crate) fn var(name: &str) -> Option<OsString> {
+    match name {
+        "HOME" => Some(home().into_os_string()).filter(|home| !home.is_empty()),
+        "HOSTNAME" => Some(hostname().into()).filter(|name: &OsString| !name.is_empty()),
+        "USER" | "LOGNAME" | "USERNAME" => non_empty(name).or_else(|| username().map(Into::into)),
+        _ => non_empty(name),
+    }
+}
+
+// An XDG base directory, unless home is overridden, else the Windows
+// folder, else the default under home
+fn base_dir(xdg: &str, windows: &str, default: &str) -> PathBuf {
+    if overridden_home().is_none() {
+        if let Some(dir) = non_empty(xdg) {
+            return PathBuf::from(dir);
+        }
+        if cfg!(windows)
+            && let Some(dir) = non_empty(windows)
+        {
+            return PathBuf::from(dir);
+        }
+    }
+    home().join(default)
+}
+
+// $XDG_CONFIG_HOME, or %APPDATA% on Windows, else ~/.config
+pub(crate) fn config_home() -> PathBuf {
+    base_dir("XDG_CONFIG_HOME", "APPDATA", ".config")
+}
+
+// $XDG_STATE_HOME, or %LOCALAPPDATA% on Windows, else ~/.local/state
+pub(crate) fn state_home() -> PathBuf {
+    base_dir("XDG_STATE_HOME", "LOCALAPPDATA", ".local/state")
+}
+
+// $XDG_DATA_HOME, else ~/.local/share
+#[cfg(all(unix, not(target_os = "macos")))]
+pub(crate) fn data_home() -> PathBuf {
+    base_dir("XDG_DATA_HOME", "", ".local/share")
+}
+
+// $XDG_RUNTIME_DIR, which only the session sets up
+pub(crate) fn runtime_dir() -> Option<PathBuf> {
+    if overridden_home().is_some() {
+        return None;
+    }
+    non_empty("XDG_RUNTIME_DIR").map(PathBuf::from)
+}
@@ -0,0 +1,104 @@
+// With --provenance, copies say where they come from in a comment at the
+// top, written the way their kind of file writes comments:
+//   # managed by neostow from /home/me/dots/nginx/site.conf; local edits will be overwritten
+// so whoever edits the live file knows to edit the source instead. Files
+// without comments, or of a kind not known here, are left as they are.
+// Comparisons with the source leave the line out again.
+
+use std::borrow::Cow;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::bytes;
+
+const MARKER: &[u8] = b"managed by neostow from ";
+
+// The opening and closing of a line comment for a file, by name and then by
+// a #! line
+fn syntax(name: &str, content: &[u8]) -> Option<(&'static str, &'static str)> {
+    let name = name.to_ascii_lowercase();
+    let ext = name.rsplit_once('.').map_or("", |(_, ext)| ext);
+    let hash = ("# ", "");
+    match (name.trim_start_matches('.'), ext) {
+        (
+            "bashrc" | "bash_profile" | "bash_logout" | "profile" | "zshrc" | "zshenv" | "zprofile"
+            | "gitconfig" | "gitignore" | "inputrc" | "makefile" | "config",
+            _,
+        ) => Some(hash),
+        ("vimrc" | "gvimrc" | "exrc", _) => Some(("\" ", "")),
+        ("xresources" | "xdefaults", _) => Some(("! ", "")),
+        (
+            _,
+            "sh" | "bash" | "zsh" | "fish" | "py" | "rb" | "pl" | "conf" | "cfg" | "toml" | "yaml"
+            | "yml" | "desktop" | "service" | "timer" | "socket" | "nix" | "env" | "properties",
+        ) => Some(hash),
+        (
+            _,
+            "js" | "mjs" | "ts" | "c" | "h" | "cc" | "cpp" | "hpp" | "rs" | "go" | "java" | "kt"
+            | "swift" | "jsonc" | "json5" | "kdl" | "scss" | "zig",
+        ) => Some(("// ", "")),
+        (_, "lua" | "sql" | "hs") => Some(("-- ", "")),
+        (_, "ini" | "el" | "lisp" | "scm" | "clj") => Some(("; ", "")),
+        (_, "vim") => Some(("\" ", "")),
+        (_, "tex" | "sty" | "erl") => Some(("% ", "")),
+        (_, "xml" | "html" | "htm" | "svg" | "plist") => Some(("<!-- ", " -->")),
+        (_, "css") => Some(("/* ", " */")),
+        _ if content.starts_with(b"#!") => Some(hash),
+        _ => None,
+    }
+}
+
+// Where the header goes: after a #! line or an XML declaration, which have
+// to stay first
+fn insert_at(content: &[u8]) -> usize {
+    if content.starts_with(b"#!") || content.starts_with(b"<?xml") {
+        bytes::find(content, b'\n').map_or(content.len(), |end| end + 1)
+    } else {
+        0
+    }
+}
+
+// Writes the header into the copy at dest of src, and into every file of
+// a copied directory
+pub(crate) fn stamp(src: &Path, dest: &Path) -> io::Result<()> {
+    if dest.is_dir() && !dest.is_symlink() {
+        for entry in fs::read_dir(dest)? {
+            let name = entry?.file_name();
+            stamp(&src.join(&name), &dest.join(&name))?;
+        }
+        return Ok(());
+    }
+    if !dest.is_file() {
+        return Ok(());
+    }
+    let content = fs::read(dest)?;
+    let name = dest.file_name().unwrap_or_default().to_string_lossy();
+    let Some((open, close)) = syntax(&name, &content) else {
+        return Ok(());
+    };
+    let at = insert_at(&content);
+    let header = format!(
+        "{open}{}{}; local edits will be overwritten{close}\n",
+        String::from_utf8_lossy(MARKER),
+        src.display()
+    );
+    let mut stamped = Vec::with_capacity(content.len() + header.len());
+    stamped.extend_from_slice(&content[..at]);
+    stamped.extend_from_slice(header.as_bytes());
+    stamped.extend_from_slice(&content[at..]);
+    fs::write(dest, stamped)
+}
+
+// The content of a copy as it was before stamp, to compare with its source
+pub(crate) fn strip(content: &[u8]) -> Cow<'_, [u8]> {
+    let at = insert_at(content);
+    let rest = &content[at..];
+    let end = bytes::find(rest, b'\n').map_or(rest.len(), |end| end + 1);
+    if !rest[..end].windows(MARKER.len()).any(|w| w == MARKER) {
+        return Cow::Borrowed(content);
+    }
+    let mut out = content[..at].to_vec();
+    out.extend_from_slice(&rest[end..]);
+    Cow::Owned(out)
+}
@@ -0,0 +1,74 @@
+// Every link a run makes is recorded along with the file that was run, so
+// links whose lines were since renamed or removed can be found again:
+// `neostow prune` removes those the file no longer plans, as long as they
+// are still what neostow made.
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{self, PathBuf};
+
+use crate::expand::normalize;
+use crate::{Config, LogLevel, Operation, journal, state, status};
+
+/// Removes the links an earlier run of cfg's file made that none of its
+/// entries plan anymore. ops is the plan of the whole file.
+pub fn prune(ops: &[(usize, Operation)], cfg: &Config, operations: &mut i32) -> io::Result<()> {
+    let file = fs::canonicalize(&cfg.file)?;
+    let planned: HashSet<PathBuf> = ops
+        .iter()
+        .filter_map(|(_, op)| path::absolute(&op.dest).ok())
+        .map(|dest| normalize(&dest))
+        .collect();
+
+    let mut links = state::load_links()?;
+    let mut orphans: Vec<(PathBuf, PathBuf)> = links
+        .iter()
+        .filter(|(dest, (from, _))| *from == file && !planned.contains(&normalize(dest)))
+        .map(|(dest, (_, src))| (dest.clone(), src.clone()))
+        .collect();
+    orphans.sort();
+
+    let mut kept = 0;
+    for (dest, src) in orphans {
+        // Gone already, nothing left to do
+        if dest.symlink_metadata().is_err() {
+            links.remove(&dest);
+            continue;
+        }
+        if !cfg.force && !status::owned(&src, &dest, cfg)? {
+            printfc!(
+                LogLevel::Warn,
+                "{} changed since neostow made it, leaving it alone (--force removes it)",
+                dest.display()
+            );
+            kept += 1;
+            continue;
+        }
+        if cfg.dry {
+            printfc!(LogLevel::Info, "Would remove {}", dest.display());
+            continue;
+        }
+        match journal::remove(&*cfg.fs, &dest) {
+            Ok(()) => {
+                links.remove(&dest);
+                *operations += 1;
+                if cfg.verbose {
+                    printfc!(LogLevel::Info, "Removed {}", dest.display());
+                }
+            }
+            Err(err) => {
+                printfc!(LogLevel::Error, "{}: {err}", dest.display());
+            }
+        }
+    }
+
+    if !cfg.dry {
+        state::save_links(&links)?;
+        journal::save(&journal::take())?;
+    }
+    if kept > 0 {
+        printfc!(LogLevel::Info, "{kept} links were left in place");
+    }
+    Ok(())
+}
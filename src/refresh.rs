@@ -0,0 +1,97 @@
+#[cfg(unix)]
+use std::process::Command;
+
+#[cfg(all(unix, not(target_os = "macos")))]
+use crate::platform;
+use crate::{Config, LogLevel, shell};
+
+// Built-in post-actions for `!refresh:KIND`, so entries don't need to spell
+// out each platform's cache-refresh incantation
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Fonts,
+    Desktop,
+    Mime,
+}
+
+impl Kind {
+    pub fn parse(name: &[u8]) -> Option<Kind> {
+        match name {
+            b"fonts" => Some(Kind::Fonts),
+            b"desktop" => Some(Kind::Desktop),
+            b"mime" => Some(Kind::Mime),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Fonts => "fonts",
+            Kind::Desktop => "desktop",
+            Kind::Mime => "mime",
+        }
+    }
+
+    // None when the platform keeps no such cache
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn command(self) -> Option<Command> {
+        let (program, arg) = match self {
+            Kind::Fonts => ("fc-cache", None),
+            Kind::Desktop => (
+                "update-desktop-database",
+                Some(platform::data_home().join("applications")),
+            ),
+            Kind::Mime => (
+                "update-mime-database",
+                Some(platform::data_home().join("mime")),
+            ),
+        };
+        let mut command = Command::new(program);
+        command.args(arg);
+        Some(command)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn command(self) -> Option<Command> {
+        match self {
+            Kind::Fonts => {
+                let mut command = Command::new("atsutil");
+                command.args(["databases", "-removeUser"]);
+                Some(command)
+            }
+            Kind::Desktop | Kind::Mime => None,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn command(self) -> Option<std::process::Command> {
+        None
+    }
+}
+
+pub fn run(kinds: &[Kind], cfg: &Config) {
+    for kind in kinds {
+        let Some(mut command) = kind.command() else {
+            continue;
+        };
+        let what = format!("the refresh of the {} cache", kind.name());
+        match shell::status(&mut command, &what, cfg) {
+            Ok(None) => {}
+            Ok(Some(status)) if status.success() => {}
+            Ok(Some(status)) => {
+                printfc!(
+                    LogLevel::Error,
+                    "Refreshing {} cache exited with {status}",
+                    kind.name()
+                );
+            }
+            Err(err) => {
+                printfc!(
+                    LogLevel::Error,
+                    "Refreshing {} cache failed: {err}",
+                    kind.name()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// A small regular expression subset over bytes, enough for renaming files:
+// literals, `.`, `[a-z]`/`[^a-z]` classes, `\d` `\w` `\s`, the `*` `+` `?`
+// quantifiers, `^` and `$` anchors and `|` between alternatives. There are
+// no groups or {n} repetitions, so matching never has to look further back
+// than one atom.
+
+use std::io;
+
+use crate::glob;
+
+#[derive(Clone)]
+enum Atom {
+    Byte(u8),
+    Any,
+    Class(Vec<u8>),
+    Start,
+    End,
+}
+
+impl Atom {
+    fn matches(&self, b: u8) -> bool {
+        match self {
+            Atom::Byte(c) => *c == b,
+            Atom::Any => true,
+            Atom::Class(class) => glob::match_class(class, b),
+            Atom::Start | Atom::End => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Piece {
+    atom: Atom,
+    min: usize,
+    max: usize,
+}
+
+#[derive(Clone)]
+pub struct Regex {
+    alternatives: Vec<Vec<Piece>>,
+}
+
+fn invalid(pattern: &[u8], why: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!(
+            "invalid pattern {:?}: {why}",
+            String::from_utf8_lossy(pattern)
+        ),
+    )
+}
+
+impl Regex {
+    pub fn new(pattern: &[u8]) -> io::Result<Self> {
+        let mut alternatives = vec![Vec::new()];
+        let mut i = 0;
+        while i < pattern.len() {
+            let atom = match pattern[i] {
+                b'|' => {
+                    alternatives.push(Vec::new());
+                    i += 1;
+                    continue;
+                }
+                b'^' => Atom::Start,
+                b'$' => Atom::End,
+                b'.' => Atom::Any,
+                b'(' | b')' => return Err(invalid(pattern, "groups are not supported")),
+                b'{' => {
+                    return Err(invalid(
+                        pattern,
+                        "repetition braces like {2} are not supported",
+                    ));
+                }
+                b'*' | b'+' | b'?' => return Err(invalid(pattern, "nothing to repeat")),
+                b'[' => {
+                    // A `]` right after the opening bracket is part of the class
+                    let end = pattern
+                        .get(i + 2..)
+                        .and_then(|rest| rest.iter().position(|&b| b == b']'))
+                        .map(|end| end + i + 2)
+                        .ok_or_else(|| invalid(pattern, "unclosed ["))?;
+                    let class = pattern[i + 1..end].to_vec();
+                    i = end;
+                    Atom::Class(class)
+                }
+                b'\\' => {
+                    i += 1;
+                    match pattern.get(i) {
+                        Some(b'd') => Atom::Class(b"0-9".to_vec()),
+                        Some(b'w') => Atom::Class(b"a-zA-Z0-9_".to_vec()),
+                        Some(b's') => Atom::Class(b" \t\r\n".to_vec()),
+                        Some(&c) => Atom::Byte(c),
+                        None => return Err(invalid(pattern, "trailing \\")),
+                    }
+                }
+                c => Atom::Byte(c),
+            };
+            i += 1;
+
+            let (min, max) = match pattern.get(i) {
+                Some(b'*') => (0, usize::MAX),
+                Some(b'+') => (1, usize::MAX),
+                Some(b'?') => (0, 1),
+                _ => {
+                    alternatives.last_mut().unwrap().push(Piece {
+                        atom,
+                        min: 1,
+                        max: 1,
+                    });
+                    continue;
+                }
+            };
+            if matches!(atom, Atom::Start | Atom::End) {
+                return Err(invalid(pattern, "anchors can't repeat"));
+            }
+            i += 1;
+            alternatives
+                .last_mut()
+                .unwrap()
+                .push(Piece { atom, min, max });
+        }
+        Ok(Regex { alternatives })
+    }
+
+    // The leftmost match as a byte range, preferring earlier alternatives
+    pub fn find(&self, text: &[u8]) -> Option<(usize, usize)> {
+        (0..=text.len()).find_map(|start| {
+            self.alternatives
+                .iter()
+                .find_map(|pieces| match_here(pieces, text, start))
+                .map(|end| (start, end))
+        })
+    }
+}
+
+// Repetitions are greedy, giving back one byte at a time until the rest matches
+fn match_here(pieces: &[Piece], text: &[u8], pos: usize) -> Option<usize> {
+    let Some((piece, rest)) = pieces.split_first() else {
+        return Some(pos);
+    };
+    match piece.atom {
+        Atom::Start => return (pos == 0).then(|| match_here(rest, text, pos))?,
+        Atom::End => return (pos == text.len()).then(|| match_here(rest, text, pos))?,
+        _ => {}
+    }
+    let available = text[pos..]
+        .iter()
+        .take(piece.max)
+        .take_while(|&&b| piece.atom.matches(b))
+        .count();
+    (piece.min..=available)
+        .rev()
+        .find_map(|n| match_here(rest, text, pos + n))
+}
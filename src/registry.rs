@@ -0,0 +1,274 @@
+// A [registry] section sets Windows registry values alongside the files, as
+// much of what Windows programs are configured with lives there:
+//   [registry]
+//   HKCU\Console\QuickEdit = dword:1
+//   "HKCU\Console\FaceName" = "Cascadia Mono"
+// The last part of the path names the value. Values are dword:N or qword:N
+// (decimal, or hex with 0x), "text" for a string or expand:"text" for one
+// with %VARIABLES% left for Windows to expand. Runs that select no package,
+// or the registry one, write the values that differ with reg.exe, and status
+// reports them. Elsewhere than Windows the section is read but not applied.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::reload::field;
+use crate::{Config, LogLevel, Mode, report, section_lines};
+
+const HIVES: [&str; 10] = [
+    "HKCU",
+    "HKLM",
+    "HKCR",
+    "HKU",
+    "HKCC",
+    "HKEY_CURRENT_USER",
+    "HKEY_LOCAL_MACHINE",
+    "HKEY_CLASSES_ROOT",
+    "HKEY_USERS",
+    "HKEY_CURRENT_CONFIG",
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Dword,
+    Qword,
+    Sz,
+    ExpandSz,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Dword => "REG_DWORD",
+            Kind::Qword => "REG_QWORD",
+            Kind::Sz => "REG_SZ",
+            Kind::ExpandSz => "REG_EXPAND_SZ",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Value {
+    key: String,
+    name: String,
+    kind: Kind,
+    data: String,
+}
+
+impl Value {
+    fn path(&self) -> String {
+        format!("{}\\{}", self.key, self.name)
+    }
+
+    // Numbers compare by value, whichever way they were written
+    fn matches(&self, kind: &str, data: &str) -> bool {
+        if kind != self.kind.name() {
+            return false;
+        }
+        match self.kind {
+            Kind::Dword | Kind::Qword => number(data) == number(&self.data),
+            Kind::Sz | Kind::ExpandSz => data == self.data,
+        }
+    }
+}
+
+fn number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn parse_value(line: &[u8]) -> io::Result<Option<Value>> {
+    let line = line.trim_ascii();
+    if line.is_empty() || line.starts_with(b"#") {
+        return Ok(None);
+    }
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg.to_string());
+    let usage = "expected KEY\\NAME = dword:N, qword:N, \"text\" or expand:\"text\"";
+    let (path, rest) = field(line, b'=').ok_or_else(|| invalid(usage))?;
+    let rest = rest
+        .trim_ascii_start()
+        .strip_prefix(b"=")
+        .ok_or_else(|| invalid(usage))?
+        .trim_ascii_start();
+
+    let path = String::from_utf8_lossy(path);
+    let Some((key, name)) = path.rsplit_once('\\') else {
+        return Err(invalid(usage));
+    };
+    let hive = key.split('\\').next().unwrap_or_default();
+    if !HIVES.iter().any(|h| h.eq_ignore_ascii_case(hive)) {
+        return Err(invalid(&format!(
+            "{hive} is not a registry hive, such as HKCU or HKLM"
+        )));
+    }
+    if name.is_empty() {
+        return Err(invalid("name the value after the last \\"));
+    }
+
+    let (kind, rest) = if let Some(rest) = rest.strip_prefix(b"dword:") {
+        (Kind::Dword, rest)
+    } else if let Some(rest) = rest.strip_prefix(b"qword:") {
+        (Kind::Qword, rest)
+    } else if let Some(rest) = rest.strip_prefix(b"expand:") {
+        (Kind::ExpandSz, rest)
+    } else {
+        (Kind::Sz, rest)
+    };
+    let (data, _) = field(rest, b'#').ok_or_else(|| invalid(usage))?;
+    let data = String::from_utf8_lossy(data).into_owned();
+    let limit = if kind == Kind::Dword {
+        u64::from(u32::MAX)
+    } else {
+        u64::MAX
+    };
+    if matches!(kind, Kind::Dword | Kind::Qword) && number(&data).is_none_or(|n| n > limit) {
+        return Err(invalid(&format!(
+            "{data} is not a {} number",
+            if kind == Kind::Dword {
+                "32-bit"
+            } else {
+                "64-bit"
+            }
+        )));
+    }
+    Ok(Some(Value {
+        key: key.to_string(),
+        name: name.to_string(),
+        kind,
+        data,
+    }))
+}
+
+// Whether a line of a [registry] section reads, for check
+pub(crate) fn check_line(line: &[u8]) -> io::Result<()> {
+    parse_value(line).map(|_| ())
+}
+
+// Reads the [registry] sections of content, the text of the file at path
+pub fn parse(content: &[u8], path: &Path) -> io::Result<Vec<Value>> {
+    let mut values = Vec::new();
+    section_lines(content, path, b"registry", |line| {
+        values.extend(parse_value(line)?);
+        Ok(())
+    })?;
+    Ok(values)
+}
+
+// The type and data the registry holds for value, if any. reg query lists
+// it indented under its key as NAME, type and data, four spaces apart.
+fn current(value: &Value) -> io::Result<Option<(String, String)>> {
+    let output = Command::new("reg")
+        .args(["query", &value.key, "/v", &value.name])
+        .output()?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let found = stdout.lines().find_map(|line| {
+        let mut fields = line.trim_start().splitn(3, "    ");
+        let name = fields.next()?;
+        if !name.eq_ignore_ascii_case(&value.name) {
+            return None;
+        }
+        Some((
+            fields.next()?.to_string(),
+            fields.next().unwrap_or("").to_string(),
+        ))
+    });
+    Ok(found)
+}
+
+fn set(value: &Value) -> io::Result<()> {
+    let output = Command::new("reg")
+        .args([
+            "add",
+            &value.key,
+            "/v",
+            &value.name,
+            "/t",
+            value.kind.name(),
+        ])
+        .args(["/d", &value.data, "/f"])
+        .output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(
+        String::from_utf8_lossy(&output.stderr).trim().to_string(),
+    ))
+}
+
+// Registry values apply to runs of the whole file or of the registry package
+fn selected(cfg: &Config) -> bool {
+    cfg!(windows)
+        && !cfg.registry.is_empty()
+        && (cfg.packages.is_empty() || cfg.packages.iter().any(|p| p == "registry"))
+}
+
+/// Sets the values of cfg's [registry] sections that differ, returning how
+/// many could not be.
+pub fn apply(cfg: &Config, operations: &mut i32) -> usize {
+    // Deleting leaves them, as there is no telling what they were before
+    if !selected(cfg) || matches!(cfg.mode, Mode::Delete) {
+        return 0;
+    }
+    let mut failed = 0;
+    for value in &cfg.registry {
+        let path = value.path();
+        let result = current(value).and_then(|current| match current {
+            Some((kind, data)) if value.matches(&kind, &data) => {
+                if cfg.dry {
+                    report::pending(report::Pending::UpToDate);
+                }
+                Ok(())
+            }
+            current if cfg.dry => {
+                printfc!(LogLevel::Info, "Would set {path} to {}", value.data);
+                report::pending(if current.is_some() {
+                    report::Pending::Overwrite
+                } else {
+                    report::Pending::Create
+                });
+                Ok(())
+            }
+            _ => set(value).map(|()| {
+                *operations += 1;
+                if cfg.verbose {
+                    println!("Set {path} to {}", value.data);
+                }
+            }),
+        });
+        if let Err(err) = result {
+            printfc!(LogLevel::Error, "{path}: {err}");
+            failed += 1;
+        }
+    }
+    failed
+}
+
+/// Prints whether each registry value is set, returning how many are not.
+pub fn status(cfg: &Config) -> usize {
+    if !selected(cfg) {
+        return 0;
+    }
+    let mut out_of_sync = 0;
+    for value in &cfg.registry {
+        let path = value.path();
+        match current(value) {
+            Ok(Some((kind, data))) if value.matches(&kind, &data) => {
+                println!("set       {path}");
+                continue;
+            }
+            Ok(Some((_, data))) => println!("differs   {path} (is {data})"),
+            Ok(None) => println!("missing   {path}"),
+            Err(err) => {
+                printfc!(LogLevel::Error, "{path}: {err}");
+            }
+        }
+        out_of_sync += 1;
+    }
+    out_of_sync
+}
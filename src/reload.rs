@@ -0,0 +1,106 @@
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::expand::{expand_path, normalize};
+use crate::vars::Vars;
+use crate::{Config, LogLevel, bytes, glob, section_lines, shell};
+
+// A [reload] line maps a destination glob to the command that makes its
+// consumers pick up changes: "~/.config/systemd/user/*" = "systemctl --user daemon-reload"
+#[derive(Clone)]
+pub struct Rule {
+    pattern: PathBuf,
+    command: OsString,
+}
+
+impl Rule {
+    pub fn matches(&self, dest: &Path) -> bool {
+        let Ok(dest) = std::path::absolute(dest) else {
+            return false;
+        };
+        glob::matches(
+            &bytes::from_os(self.pattern.as_os_str()),
+            &bytes::from_os(normalize(&dest).as_os_str()),
+        )
+    }
+}
+
+// Reads a possibly quoted field, returning it and the remaining input
+pub(crate) fn field(s: &[u8], delim: u8) -> Option<(&[u8], &[u8])> {
+    let s = s.trim_ascii_start();
+    match s.first() {
+        Some(&quote @ (b'"' | b'\'')) => {
+            let end = bytes::find(&s[1..], quote)?;
+            Some((&s[1..=end], &s[end + 2..]))
+        }
+        _ => {
+            let end = bytes::find(s, delim).unwrap_or(s.len());
+            Some((s[..end].trim_ascii(), &s[end..]))
+        }
+    }
+}
+
+fn parse_rule(line: &[u8], vars: &Vars) -> io::Result<Option<Rule>> {
+    let line = line.trim_ascii();
+    if line.is_empty() || line.starts_with(b"#") {
+        return Ok(None);
+    }
+
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected \"glob\" = \"command\"",
+        )
+    };
+    let (pattern, rest) = field(line, b'=').ok_or_else(invalid)?;
+    let rest = rest
+        .trim_ascii_start()
+        .strip_prefix(b"=")
+        .ok_or_else(invalid)?;
+    let (command, _) = field(rest, b'#').ok_or_else(invalid)?;
+    if pattern.is_empty() || command.is_empty() {
+        return Err(invalid());
+    }
+
+    let pattern = normalize(&std::path::absolute(expand_path(pattern, vars)?)?);
+    Ok(Some(Rule {
+        pattern,
+        command: bytes::to_os(command).into_owned(),
+    }))
+}
+
+// Reads the [reload] sections of content, the text of the file at path
+pub fn parse(content: &[u8], path: &Path, vars: &Vars) -> io::Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    section_lines(content, path, b"reload", |line| {
+        rules.extend(parse_rule(line, vars)?);
+        Ok(())
+    })?;
+    Ok(rules)
+}
+
+// Each command runs once, however many of its destinations changed
+pub fn run(rules: &[Rule], triggered: &[bool], cfg: &Config) {
+    for (rule, _) in rules.iter().zip(triggered).filter(|(_, t)| **t) {
+        let what = format!("reload `{}`", rule.command.display());
+        match shell::status(&mut shell::command(&rule.command), &what, cfg) {
+            Ok(None) => {}
+            Ok(Some(status)) if status.success() => {}
+            Ok(Some(status)) => {
+                printfc!(
+                    LogLevel::Error,
+                    "Reload `{}` exited with {status}",
+                    rule.command.display()
+                );
+            }
+            Err(err) => {
+                printfc!(
+                    LogLevel::Error,
+                    "Reload `{}` failed: {err}",
+                    rule.command.display()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,797 @@
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::{LogLevel, budget, bytes, include, log, platform, state, trace};
+
+// With --output json, results are collected and printed as one document at
+// the end, and log lines go to stderr so stdout stays parseable
+static JSON: AtomicBool = AtomicBool::new(false);
+// With --output ndjson, events are printed one per line as they happen
+static NDJSON: AtomicBool = AtomicBool::new(false);
+// With --open-report, the same results are rendered as HTML and opened
+static HTML: AtomicBool = AtomicBool::new(false);
+static RESULTS: Mutex<Vec<Row>> = Mutex::new(Vec::new());
+// What a dry run found each entry would need, counted by kind of Pending
+static DRY: AtomicBool = AtomicBool::new(false);
+static PENDING: Mutex<[usize; 5]> = Mutex::new([0; 5]);
+// And the bytes it would copy
+static COPYING: AtomicU64 = AtomicU64::new(0);
+// How entries went, counted by kind of Outcome whatever the output, for the
+// summary and the exit status
+static OUTCOMES: Mutex<[usize; 5]> = Mutex::new([0; 5]);
+// Which Conditions came up, for --fail-on
+static SEEN: [AtomicBool; 5] = [const { AtomicBool::new(false) }; 5];
+static STARTED: OnceLock<(Instant, PathBuf)> = OnceLock::new();
+// 0 for no notification, else the Notify plus one
+static NOTIFY: AtomicU8 = AtomicU8::new(0);
+// Runs shorter than this end without a notification
+const NOTIFY_AFTER: Duration = Duration::from_secs(10);
+// 0 to ask, 1 to answer every prompt yes and 2 no
+static ASSUME: AtomicU8 = AtomicU8::new(0);
+// Diffs longer than this many lines, where LINES doesn't tell the height of
+// the terminal, go through the pager
+const PAGE_LINES: usize = 24;
+thread_local! {
+    // The config file and line being executed on this thread, so results deep
+    // in execute() can name them, also from --jobs workers
+    static FILE: RefCell<Option<PathBuf>> = const { RefCell::new(None) };
+    static LINE: Cell<usize> = const { Cell::new(0) };
+    // What --group-by put the entries being executed under
+    static GROUP: RefCell<Option<String>> = const { RefCell::new(None) };
+    // What the entry a --jobs worker is applying writes, until it is its turn
+    static HELD: RefCell<Option<Vec<Piece>>> = const { RefCell::new(None) };
+}
+
+// A piece of held output: a line for stdout or stderr, or a result
+enum Piece {
+    Out(String),
+    Err(String),
+    Row(Row),
+}
+
+/// The output of one entry, held back to be written in file order.
+pub(crate) struct Held(Vec<Piece>);
+
+// Holds what this thread writes from now on, until held() is called
+pub(crate) fn hold() {
+    HELD.set(Some(Vec::new()));
+}
+
+// What this thread wrote since hold(), which is written as it comes again
+pub(crate) fn held() -> Held {
+    Held(HELD.take().unwrap_or_default())
+}
+
+// Keeps text for stdout, or stderr, if this thread is holding its output
+pub(crate) fn keep(to_stderr: bool, text: &str) -> bool {
+    HELD.with_borrow_mut(|held| match held {
+        Some(held) => {
+            let text = text.to_string();
+            held.push(if to_stderr {
+                Piece::Err(text)
+            } else {
+                Piece::Out(text)
+            });
+            true
+        }
+        None => false,
+    })
+}
+
+// Writes a line of output to stdout, or keeps it if held
+pub(crate) fn out(line: &str) {
+    if !keep(false, &format!("{line}\n")) {
+        println!("{line}");
+    }
+}
+
+// Writes out output held before, with its results in their place
+pub(crate) fn release(held: Held) {
+    let mut stdout = io::stdout().lock();
+    for piece in held.0 {
+        match piece {
+            Piece::Out(text) => {
+                let _ = stdout.write_all(text.as_bytes());
+            }
+            Piece::Err(text) => {
+                let _ = stdout.flush();
+                let _ = io::stderr().write_all(text.as_bytes());
+            }
+            Piece::Row(row) => RESULTS.lock().unwrap_or_else(|e| e.into_inner()).push(row),
+        }
+    }
+    let _ = stdout.flush();
+}
+
+pub fn set_json() {
+    JSON.store(true, Ordering::Relaxed);
+}
+
+pub fn set_ndjson() {
+    NDJSON.store(true, Ordering::Relaxed);
+}
+
+// Whether stdout carries JSON, as one document or as an event stream
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed) || ndjson()
+}
+
+fn ndjson() -> bool {
+    NDJSON.load(Ordering::Relaxed)
+}
+
+pub fn set_html() {
+    HTML.store(true, Ordering::Relaxed);
+}
+
+pub fn html() -> bool {
+    HTML.load(Ordering::Relaxed)
+}
+
+/// The terminal escape a long run ends with: OSC 9, as iTerm2, Windows
+/// Terminal and others show, or OSC 777, as VTE terminals and rxvt do.
+#[derive(Clone, Copy)]
+pub enum Notify {
+    Osc9,
+    Osc777,
+}
+
+impl Notify {
+    pub fn parse(name: &str) -> Option<Notify> {
+        match name {
+            "osc9" => Some(Notify::Osc9),
+            "osc777" => Some(Notify::Osc777),
+            _ => None,
+        }
+    }
+}
+
+pub fn set_notify(notify: Notify) {
+    NOTIFY.store(notify as u8 + 1, Ordering::Relaxed);
+}
+
+/// What a dry run found an entry would need.
+#[derive(Clone, Copy)]
+pub enum Pending {
+    Create,
+    Overwrite,
+    Remove,
+    /// Something else is in the way, which the run would fail on
+    Conflict,
+    UpToDate,
+}
+
+// Makes finish sum up what the dry run found instead of the operations
+pub(crate) fn summarize_plan() {
+    DRY.store(true, Ordering::Relaxed);
+}
+
+pub(crate) fn copying(bytes: u64) {
+    COPYING.store(bytes, Ordering::Relaxed);
+}
+
+pub(crate) fn pending(kind: Pending) {
+    if let Pending::Conflict = kind {
+        saw(Condition::Conflict);
+    }
+    PENDING.lock().unwrap_or_else(|e| e.into_inner())[kind as usize] += 1;
+}
+
+/// What `--fail-on` can make a run exit with 1 on.
+#[derive(Clone, Copy)]
+pub enum Condition {
+    /// An entry failed
+    Failed,
+    /// A destination was changed since neostow made it
+    Drift,
+    /// An entry's source doesn't exist, or its pattern matches nothing
+    MissingSource,
+    /// Something else is in the way of a destination
+    Conflict,
+    /// A dry run or status found anything not in place
+    OutOfSync,
+}
+
+impl Condition {
+    pub fn parse(name: &str) -> Option<Condition> {
+        match name {
+            "failed" => Some(Condition::Failed),
+            "drift" => Some(Condition::Drift),
+            "missing-source" => Some(Condition::MissingSource),
+            "conflict" => Some(Condition::Conflict),
+            "out-of-sync" => Some(Condition::OutOfSync),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn saw(condition: Condition) {
+    SEEN[condition as usize].store(true, Ordering::Relaxed);
+}
+
+/// Whether condition came up in this run.
+pub fn seen(condition: Condition) -> bool {
+    match condition {
+        Condition::Failed => failed() > 0,
+        Condition::OutOfSync if out_of_sync() => true,
+        _ => SEEN[condition as usize].load(Ordering::Relaxed),
+    }
+}
+
+/// Whether a dry run found anything that would change, or be in the way.
+pub fn out_of_sync() -> bool {
+    let counts = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    counts[..Pending::UpToDate as usize].iter().any(|&n| n > 0)
+}
+
+// The dry run's counts, by name
+fn plan_counts() -> [(&'static str, usize); 5] {
+    let counts = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+    [
+        ("create", counts[Pending::Create as usize]),
+        ("overwrite", counts[Pending::Overwrite as usize]),
+        ("remove", counts[Pending::Remove as usize]),
+        ("conflicts", counts[Pending::Conflict as usize]),
+        ("up_to_date", counts[Pending::UpToDate as usize]),
+    ]
+}
+
+#[derive(Clone, Copy)]
+enum Outcome {
+    Created,
+    Overwritten,
+    Deleted,
+    Skipped,
+    Failed,
+}
+
+// The counts of each Outcome, by name
+fn outcome_counts() -> [(&'static str, usize); 5] {
+    let counts = OUTCOMES.lock().unwrap_or_else(|e| e.into_inner());
+    [
+        ("created", counts[Outcome::Created as usize]),
+        ("overwritten", counts[Outcome::Overwritten as usize]),
+        ("deleted", counts[Outcome::Deleted as usize]),
+        ("skipped", counts[Outcome::Skipped as usize]),
+        ("failed", counts[Outcome::Failed as usize]),
+    ]
+}
+
+/// How many entries failed so far.
+pub fn failed() -> usize {
+    OUTCOMES.lock().unwrap_or_else(|e| e.into_inner())[Outcome::Failed as usize]
+}
+
+pub fn at_line(file: &Path, line: usize) {
+    FILE.with_borrow_mut(|current| {
+        if current.as_deref() != Some(file) {
+            *current = Some(file.to_path_buf());
+        }
+    });
+    LINE.set(line);
+}
+
+pub(crate) fn in_group(group: Option<&str>) {
+    GROUP.set(group.map(str::to_string));
+}
+
+// Held while a worker writes something that must not be interleaved with
+// another's output, like a diff and the prompt after it
+pub(crate) fn terminal() -> MutexGuard<'static, ()> {
+    static TERMINAL: Mutex<()> = Mutex::new(());
+    TERMINAL.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn set_assume(yes: bool) {
+    ASSUME.store(if yes { 1 } else { 2 }, Ordering::Relaxed);
+}
+
+// The answer a prompt takes without asking: the one given with --assume-yes
+// or --assume-no, or no where nobody is there to answer, as stdin is not a
+// terminal and no front-end replies on it as with --output ndjson
+pub(crate) fn assumed() -> Option<bool> {
+    match ASSUME.load(Ordering::Relaxed) {
+        1 => Some(true),
+        2 => Some(false),
+        _ if !ndjson() && !io::stdin().is_terminal() => Some(false),
+        _ => None,
+    }
+}
+
+// Shows text on stdout, through $PAGER (or less) when it is longer than the
+// terminal. Any trouble with the pager shows it without one.
+pub(crate) fn page(text: &[u8]) -> io::Result<()> {
+    let height = env::var("LINES")
+        .ok()
+        .and_then(|lines| lines.parse().ok())
+        .unwrap_or(PAGE_LINES);
+    if !io::stdout().is_terminal() || text.iter().filter(|&&b| b == b'\n').count() < height {
+        return io::stdout().write_all(text);
+    }
+    let pager = env::var_os("PAGER")
+        .filter(|pager| !pager.is_empty())
+        .unwrap_or_else(|| (if cfg!(windows) { "more" } else { "less -R" }).into());
+    let words = include::words(&bytes::from_os(&pager)).unwrap_or_default();
+    let paged = words.split_first().and_then(|(program, args)| {
+        let mut child = Command::new(bytes::to_os(program))
+            .args(args.iter().map(|arg| bytes::to_os(arg)))
+            .stdin(Stdio::piped())
+            .spawn()
+            .ok()?;
+        // A pager quit early closes its end, which is no error
+        let _ = child.stdin.take()?.write_all(text);
+        child.wait().ok()
+    });
+    match paged {
+        Some(_) => Ok(()),
+        None => io::stdout().write_all(text),
+    }
+}
+
+pub fn string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+pub fn path(p: Option<&Path>) -> String {
+    p.map_or_else(|| "null".to_string(), |p| string(&p.to_string_lossy()))
+}
+
+// One result per entry: what was (or would be) done, and how it went
+pub struct Result<'a> {
+    pub source: Option<&'a Path>,
+    pub dest: Option<&'a Path>,
+    pub action: &'a str,
+    pub result: &'a str,
+    pub error: Option<String>,
+}
+
+struct Row {
+    file: Option<PathBuf>,
+    line: usize,
+    group: Option<String>,
+    source: Option<PathBuf>,
+    dest: Option<PathBuf>,
+    action: String,
+    result: String,
+    error: Option<String>,
+}
+
+pub fn record(r: Result) {
+    let outcome = match (r.action, r.result) {
+        (_, "failed") => Some(Outcome::Failed),
+        (_, "skipped" | "no-target" | "guarded" | "busy") => Some(Outcome::Skipped),
+        ("create", "done") => Some(Outcome::Created),
+        ("overwrite", "done") => Some(Outcome::Overwritten),
+        ("delete", "done") => Some(Outcome::Deleted),
+        _ => None,
+    };
+    if let Some(outcome) = outcome {
+        OUTCOMES.lock().unwrap_or_else(|e| e.into_inner())[outcome as usize] += 1;
+    }
+    if !json() && !html() {
+        return;
+    }
+    let row = Row {
+        file: FILE.with_borrow(Clone::clone),
+        line: LINE.get(),
+        group: GROUP.with_borrow(Clone::clone),
+        source: r.source.map(Path::to_path_buf),
+        dest: r.dest.map(Path::to_path_buf),
+        action: r.action.to_string(),
+        result: r.result.to_string(),
+        error: r.error,
+    };
+    if ndjson() {
+        // An entry that status finds out of sync needs attention too
+        let severity = match (row.action.as_str(), row.result.as_str()) {
+            (_, "failed") => "error",
+            (_, "skipped")
+            | ("status", "drifted" | "elsewhere" | "shadow" | "conflict" | "missing") => "warning",
+            _ => "info",
+        };
+        let fields = json_row(&row);
+        event("entry-result", severity, &fields[1..fields.len() - 1]);
+    }
+    let row = HELD.with_borrow_mut(|held| match held {
+        Some(held) => {
+            held.push(Piece::Row(row));
+            None
+        }
+        None => Some(row),
+    });
+    if let Some(row) = row {
+        RESULTS.lock().unwrap_or_else(|e| e.into_inner()).push(row);
+    }
+}
+
+// Bumped whenever a field changes meaning or goes away; new fields may be
+// added to any event without a bump
+const SCHEMA_VERSION: u32 = 2;
+
+// Prints one NDJSON event. fields are the event's own members, already
+// encoded, without the surrounding braces.
+fn event(name: &str, severity: &str, fields: &str) {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let sep = if fields.is_empty() { "" } else { "," };
+    out(&format!(
+        "{{\"schema_version\":{SCHEMA_VERSION},\"event\":{},\"severity\":{},\"time\":{time},\"run_id\":{}{sep}{fields}}}",
+        string(name),
+        string(severity),
+        string(state::run_id())
+    ));
+}
+
+// Opens an NDJSON stream with the file being applied and how
+pub fn plan_start(file: &Path, action: &str, dry: bool) {
+    let _ = STARTED.set((Instant::now(), file.to_path_buf()));
+    if ndjson() {
+        let fields = format!(
+            "\"file\":{},\"action\":{},\"dry\":{dry}",
+            path(Some(file)),
+            string(action)
+        );
+        event("plan-start", "info", &fields);
+    }
+}
+
+// Reads the value of key from a one-line JSON object, if it is a string or
+// a number. Enough for replies to prompts, not a general parser.
+fn field(object: &str, key: &str) -> Option<String> {
+    let quoted = string(key);
+    let start = object.find(&quoted)? + quoted.len();
+    let value = object[start..].trim_start().strip_prefix(':')?.trim_start();
+    let Some(body) = value.strip_prefix('"') else {
+        let end = value
+            .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+            .unwrap_or(value.len());
+        return Some(value[..end].to_string()).filter(|n| !n.is_empty());
+    };
+    let mut out = String::new();
+    let mut chars = body.chars();
+    loop {
+        match chars.next()? {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                't' => out.push('\t'),
+                'r' => out.push('\r'),
+                'u' => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    out.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?)?);
+                }
+                c => out.push(c),
+            },
+            c => out.push(c),
+        }
+    }
+}
+
+// Asks question, with hint after it on a terminal, and returns the answer
+// or None once input has ended. With --output ndjson, the question is a
+// `prompt` event listing the choices, and a front-end replies on stdin with
+// the bare answer or with {"id": N, "answer": "..."}.
+pub fn ask(question: &str, hint: &str, choices: &[&str]) -> io::Result<Option<String>> {
+    static PROMPTS: AtomicUsize = AtomicUsize::new(0);
+
+    let id = PROMPTS.fetch_add(1, Ordering::Relaxed) + 1;
+    if ndjson() {
+        let choices: Vec<String> = choices.iter().map(|c| string(c)).collect();
+        let fields = format!(
+            "\"id\":{id},\"prompt\":{},\"choices\":[{}]",
+            string(question),
+            choices.join(",")
+        );
+        event("prompt", "warning", &fields);
+    } else {
+        // Prompts stay off stdout when it carries JSON
+        let mut out: Box<dyn Write> = if json() {
+            Box::new(io::stderr())
+        } else {
+            Box::new(io::stdout())
+        };
+        write!(out, "{question}{hint}")?;
+        out.flush()?;
+    }
+
+    loop {
+        let mut input = String::new();
+        if io::stdin().read_line(&mut input)? == 0 {
+            return Ok(None);
+        }
+        let input = input.trim();
+        if !ndjson() || !input.starts_with('{') {
+            return Ok(Some(input.to_string()));
+        }
+        match field(input, "id") {
+            Some(other) if other != id.to_string() => {
+                printfc!(
+                    LogLevel::Warn,
+                    "Ignoring an answer to prompt {other}, prompt {id} is waiting"
+                );
+            }
+            _ => return Ok(Some(field(input, "answer").unwrap_or_default())),
+        }
+    }
+}
+
+fn json_row(row: &Row) -> String {
+    let (file, line) = match row.line {
+        0 => ("null".to_string(), "null".to_string()),
+        line => (path(row.file.as_deref()), line.to_string()),
+    };
+    let error = row
+        .error
+        .as_deref()
+        .map_or_else(|| "null".to_string(), string);
+    // Only grouped runs have a group to give
+    let group = row
+        .group
+        .as_deref()
+        .map_or_else(String::new, |group| format!(",\"group\":{}", string(group)));
+    format!(
+        "{{\"file\":{file},\"line\":{line}{group},\"source\":{},\"dest\":{},\"action\":{},\"result\":{},\"error\":{error}}}",
+        path(row.source.as_deref()),
+        path(row.dest.as_deref()),
+        string(&row.action),
+        string(&row.result)
+    )
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn render_html(operations: i32, rows: &[Row]) -> String {
+    let cell = |p: &Option<PathBuf>| {
+        p.as_deref()
+            .map_or_else(String::new, |p| escape_html(&p.to_string_lossy()))
+    };
+    let mut out = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>neostow report</title>\n<style>\n\
+body { font-family: sans-serif; margin: 2em; }\n\
+table { border-collapse: collapse; }\n\
+th, td { border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }\n\
+td.path { font-family: monospace; }\n\
+th.group { background: #eee; }\n\
+tr.failed { background: #fdd; }\n\
+tr.skipped { background: #ffd; }\n\
+tr.done { background: #dfd; }\n\
+</style>\n</head>\n<body>\n<h1>neostow report</h1>\n",
+    );
+    out.push_str(&format!(
+        "<p>Run {}</p>\n<p>{operations} operations were performed.</p>\n<table>\n\
+<tr><th>Line</th><th>Source</th><th>Destination</th><th>Action</th><th>Result</th><th>Error</th></tr>\n",
+        state::run_id()
+    ));
+    let mut group = None;
+    for row in rows {
+        if row.group.is_some() && row.group != group {
+            group = row.group.clone();
+            out.push_str(&format!(
+                "<tr><th class=\"group\" colspan=\"6\">{}</th></tr>\n",
+                escape_html(group.as_deref().unwrap_or_default())
+            ));
+        }
+        let line = match row.line {
+            0 => String::new(),
+            line => line.to_string(),
+        };
+        out.push_str(&format!(
+            "<tr class=\"{}\"><td>{line}</td><td class=\"path\">{}</td><td class=\"path\">{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            escape_html(&row.result),
+            cell(&row.source),
+            cell(&row.dest),
+            escape_html(&row.action),
+            escape_html(&row.result),
+            escape_html(row.error.as_deref().unwrap_or_default()),
+        ));
+    }
+    out.push_str("</table>\n</body>\n</html>\n");
+    out
+}
+
+fn open(path: &Path) -> io::Result<()> {
+    #[cfg(target_os = "macos")]
+    let status = Command::new("open").arg(path).status()?;
+    #[cfg(windows)]
+    let status = Command::new("cmd")
+        .args(["/C", "start", ""])
+        .arg(path)
+        .status()?;
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let status = Command::new("xdg-open").arg(path).status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("opener exited with {status}")));
+    }
+    Ok(())
+}
+
+// Writes the HTML report into the state directory and hands it to the
+// default viewer. Failing to open it still leaves the file to look at.
+fn open_report(operations: i32, rows: &[Row]) {
+    let path = state::state_dir().join("report.html");
+    let written = fs::create_dir_all(state::state_dir())
+        .and_then(|_| fs::write(&path, render_html(operations, rows)));
+    if let Err(err) = written {
+        printfc!(LogLevel::Error, "Could not write {}: {err}", path.display());
+        return;
+    }
+    if let Err(err) = open(&path) {
+        printfc!(LogLevel::Warn, "Could not open {}: {err}", path.display());
+    }
+}
+
+// Closes a run with its operation count, as text or as the JSON document
+pub fn finish(operations: i32) {
+    let results = RESULTS.lock().unwrap_or_else(|e| e.into_inner());
+    let dry = DRY.load(Ordering::Relaxed);
+    let plan = dry.then(|| {
+        let mut fields: Vec<String> = plan_counts()
+            .iter()
+            .map(|(name, n)| format!("\"{name}\":{n}"))
+            .collect();
+        fields.push(format!(
+            "\"copy_bytes\":{}",
+            COPYING.load(Ordering::Relaxed)
+        ));
+        format!(",\"plan\":{{{}}}", fields.join(","))
+    });
+    let plan = plan.as_deref().unwrap_or_default();
+    let counts = outcome_counts();
+    let failed = failed();
+    if ndjson() {
+        let fields: Vec<String> = counts
+            .iter()
+            .map(|(name, n)| format!("\"{name}\":{n}"))
+            .collect();
+        let fields = format!(
+            "\"operations\":{operations},\"entries\":{},{}{plan}{}",
+            results.len(),
+            fields.join(","),
+            trace::json()
+        );
+        event(
+            "run-summary",
+            if failed > 0 { "error" } else { "info" },
+            &fields,
+        );
+    } else if json() {
+        let rows: Vec<String> = results.iter().map(json_row).collect();
+        let fields: Vec<String> = counts
+            .iter()
+            .map(|(name, n)| format!("\"{name}\":{n}"))
+            .collect();
+        println!(
+            "{{\"run_id\":{},\"operations\":{operations},\"counts\":{{{}}}{plan},\"results\":[{}]{}}}",
+            string(state::run_id()),
+            fields.join(","),
+            rows.join(","),
+            trace::json()
+        );
+    } else if dry {
+        let [create, overwrite, remove, conflicts, up_to_date] = plan_counts().map(|(_, n)| n);
+        let copying = match COPYING.load(Ordering::Relaxed) {
+            0 => String::new(),
+            bytes => format!(", {} to copy", budget::human(bytes)),
+        };
+        println!(
+            "{create} to create, {overwrite} to overwrite, {remove} to remove, \
+             {conflicts} conflicts, {up_to_date} up to date{copying}."
+        );
+    } else if log::shows(LogLevel::Info) {
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|(name, n)| format!("{n} {name}"))
+            .collect();
+        println!(
+            "{operations} operations were performed: {}.",
+            counts.join(", ")
+        );
+    }
+    if !json() {
+        trace::print();
+    }
+    if html() {
+        open_report(operations, &results);
+    }
+    if let Some((started, file)) = STARTED.get() {
+        let elapsed = started.elapsed();
+        if let Err(err) = write_summary(file, elapsed, operations, failed, dry, plan) {
+            printfc!(LogLevel::Warn, "Could not write the run summary: {err}");
+        }
+        if elapsed >= NOTIFY_AFTER {
+            notify(operations, failed, elapsed);
+        }
+    }
+}
+
+// The last run, summed up in $XDG_RUNTIME_DIR/neostow/last-summary.json for
+// shell prompts and status lines to read cheaply
+fn write_summary(
+    file: &Path,
+    elapsed: Duration,
+    operations: i32,
+    failed: usize,
+    dry: bool,
+    plan: &str,
+) -> io::Result<()> {
+    let Some(runtime) = platform::runtime_dir() else {
+        return Ok(());
+    };
+    let dir = runtime.join("neostow");
+    fs::create_dir_all(&dir)?;
+    let finished = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let summary = format!(
+        "{{\"run_id\":{},\"file\":{},\"finished\":{finished},\"duration_ms\":{},\
+         \"dry\":{dry},\"operations\":{operations},\"failed\":{failed}{plan}}}\n",
+        string(state::run_id()),
+        path(Some(file)),
+        elapsed.as_millis()
+    );
+    // Readers never see half of it
+    let tmp = dir.join(format!("last-summary.json.{}", std::process::id()));
+    fs::write(&tmp, summary)?;
+    fs::rename(tmp, dir.join("last-summary.json"))
+}
+
+fn notify(operations: i32, failed: usize, elapsed: Duration) {
+    let notify = match NOTIFY.load(Ordering::Relaxed) {
+        1 => Notify::Osc9,
+        2 => Notify::Osc777,
+        _ => return,
+    };
+    let mut stderr = io::stderr();
+    if !stderr.is_terminal() {
+        return;
+    }
+    let body = format!(
+        "{operations} operations, {failed} failed in {}s",
+        elapsed.as_secs()
+    );
+    let osc = match notify {
+        Notify::Osc9 => format!("\x1b]9;neostow: {body}\x07"),
+        Notify::Osc777 => format!("\x1b]777;notify;neostow;{body}\x07"),
+    };
+    // tmux passes escapes on to the terminal when wrapped, with ESC doubled
+    let osc = if env::var_os("TMUX").is_some() {
+        format!("\x1bPtmux;{}\x1b\\", osc.replace('\x1b', "\x1b\x1b"))
+    } else {
+        osc
+    };
+    let _ = stderr.write_all(osc.as_bytes());
+}
@@ -0,0 +1,150 @@
+// A run that isn't interactive can't ask what to do with a destination in
+// the way, and leaves it alone. Each one it left is queued in the state
+// directory, by the neostow file the run was of, and the run ends saying
+// how many there are. `neostow resolve` goes through the queue of the file
+// later, asking about each destination with the menu of --interactive, and
+// only about those. A destination stays queued until it is answered, so
+// skipping one keeps it for next time, and leaves the queue once it is put
+// in place or nothing is there any more.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glyphs::{self, Glyph};
+use crate::{
+    Config, LogLevel, Mode, Session, bytes, load_plan, report, report_error, state, status,
+};
+
+const CONFLICTS_FILE: &str = "conflicts";
+
+// Each destination queued, with the neostow file of the run that left it
+fn load() -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let Some(content) = state::read_file(&state::host_dir().join(CONFLICTS_FILE))? else {
+        return Ok(Vec::new());
+    };
+    let mut queued = Vec::new();
+    for line in bytes::lines(&content) {
+        let Some((file, dest)) = bytes::split_once(line, b'\t') else {
+            continue;
+        };
+        let path = |field: &[u8]| PathBuf::from(bytes::to_os(field).into_owned());
+        queued.push((path(file), path(dest)));
+    }
+    Ok(queued)
+}
+
+// An empty queue removes the file
+fn save(queued: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let path = state::host_dir().join(CONFLICTS_FILE);
+    if queued.is_empty() {
+        return state::remove_file(&path);
+    }
+    let mut out = Vec::new();
+    for (file, dest) in queued {
+        out.extend_from_slice(&bytes::from_os(file.as_os_str()));
+        out.push(b'\t');
+        out.extend_from_slice(&bytes::from_os(dest.as_os_str()));
+        out.push(b'\n');
+    }
+    state::write_file(&path, &out)
+}
+
+// Queues the destinations the run of file found in the way, and drops
+// those queued before that it put in place, or that nothing is at any
+// more. Says how many are queued for file.
+pub(crate) fn update(file: &Path, conflicted: &[PathBuf], placed: &[PathBuf]) -> io::Result<()> {
+    let before = load()?;
+    let mut queued = before.clone();
+    queued.retain(|(_, dest)| {
+        conflicted.contains(dest) || dest.symlink_metadata().is_ok() && !placed.contains(dest)
+    });
+    for dest in conflicted {
+        if !queued
+            .iter()
+            .any(|(from, queued)| from == file && queued == dest)
+        {
+            queued.push((file.to_path_buf(), dest.clone()));
+        }
+    }
+    if queued != before {
+        save(&queued)?;
+    }
+    let count = queued.iter().filter(|(from, _)| from == file).count();
+    if !conflicted.is_empty() {
+        printfc!(
+            LogLevel::Warn,
+            "{} destinations were in the way, {count} are queued; run neostow resolve to decide what to do with them",
+            conflicted.len()
+        );
+    }
+    Ok(())
+}
+
+/// Asks about each destination queued for the file of cfg, as --interactive
+/// would, and applies what is answered. With --dry they are only listed.
+pub fn resolve(cfg: &Config, operations: &mut i32) -> io::Result<()> {
+    let file = std::fs::canonicalize(&cfg.file).unwrap_or_else(|_| cfg.file.clone());
+    let mut queued = load()?;
+    let dests: Vec<PathBuf> = queued
+        .iter()
+        .filter(|(from, _)| *from == file)
+        .map(|(_, dest)| dest.clone())
+        .collect();
+    if dests.is_empty() {
+        printfc!(
+            LogLevel::Info,
+            "No conflicts queued for {}",
+            cfg.file.display()
+        );
+        return Ok(());
+    }
+
+    let ops: Vec<_> = load_plan(cfg)?
+        .into_iter()
+        .filter(|(_, op)| dests.contains(&op.dest))
+        .collect();
+    // Destinations no entry has any more, or put in place since, have
+    // nothing left to decide
+    let ops: Vec<_> = ops
+        .into_iter()
+        .filter(|(_, op)| !status::op_in_place(op, cfg).unwrap_or(false))
+        .collect();
+    let before = queued.len();
+    queued.retain(|(from, dest)| *from != file || ops.iter().any(|(_, op)| op.dest == *dest));
+    if !cfg.dry && queued.len() < before {
+        save(&queued)?;
+    }
+    if cfg.dry {
+        for (_, op) in &ops {
+            println!(
+                "{} {} {}",
+                op.src.display(),
+                glyphs::get(Glyph::Arrow),
+                op.dest.display()
+            );
+        }
+        return Ok(());
+    }
+
+    let cfg = Config {
+        mode: Mode::Create,
+        interactive: true,
+        skip_in_place: true,
+        ..cfg.clone()
+    };
+    report::plan_start(&cfg.file, "resolve", false);
+    let mut session = Session::new(&cfg)?;
+    for (linenum, op) in &ops {
+        report::at_line(op.file(&cfg), *linenum);
+        match session.execute(op, &cfg, operations) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                printfc!(LogLevel::Info, "Stopped, the rest stay queued");
+                break;
+            }
+            Err(err) => report_error(&cfg, op.file(&cfg), *linenum, Some(op), &err),
+        }
+    }
+    // The entries left to retry are the same as before
+    session.finish(&cfg, &state::load_failed()?)
+}
@@ -0,0 +1,81 @@
+// A sandbox rehearses a run in a directory standing in for / and the home:
+// every destination goes under it as with --target, after the file, link or
+// directory at its real destination is copied there. The run then changes
+// the copies, keeps its state beside them and starts no commands, so what it
+// did can be looked at before touching the real home.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{Config, LogLevel, age, fsops, journal, plan_lines, state};
+
+const STATE_DIR: &str = ".neostow-state";
+
+// Copies what is at real to copy, unless the sandbox has something there
+// already, as it does after an earlier rehearsal. Returns whether it did.
+fn seed(real: &Path, copy: &Path) -> io::Result<bool> {
+    if copy.symlink_metadata().is_ok() {
+        return Ok(false);
+    }
+    if let Some(parent) = real.parent()
+        && parent.is_dir()
+        && let Some(copy_parent) = copy.parent()
+    {
+        fs::create_dir_all(copy_parent)?;
+    }
+    let Ok(meta) = real.symlink_metadata() else {
+        return Ok(false);
+    };
+    if meta.is_symlink() {
+        // The link still points where the real one does, so links to
+        // sources are found unchanged
+        journal::make_link(&fs::read_link(real)?, copy, real.is_dir())?;
+    } else {
+        fsops::copy_tree(real, copy, false)?;
+    }
+    Ok(true)
+}
+
+/// Loads cfg like `Config::load`, with its destinations under dir, which is
+/// made if missing and filled with copies of what is at the real ones. State
+/// goes in dir too, and commands are only described.
+pub fn enter(cfg: &mut Config, dir: &Path) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let dir = fs::canonicalize(dir)?;
+    let mut real = cfg.clone();
+    real.load()?;
+    // Entries that can't be planned are reported by the run itself
+    let real_ops = plan_lines(&real, &age::read(&real.file)?, |_, _, _| {})?;
+
+    // The sandbox is the home too, even where HOME was given as a variable
+    cfg.vars
+        .insert("HOME".to_string(), dir.clone().into_os_string());
+    cfg.target = Some(dir.clone());
+    cfg.sandboxed = true;
+    cfg.load()?;
+    let ops = plan_lines(cfg, &age::read(&cfg.file)?, |_, _, _| {})?;
+    // Both plans hold the same entries, unless a condition depends on HOME,
+    // so only those of the same line are paired
+    if ops.len() != real_ops.len() {
+        printfc!(
+            LogLevel::Warn,
+            "The sandbox plans {} destinations where the real run plans {}, some are left empty",
+            ops.len(),
+            real_ops.len()
+        );
+    }
+    let mut copied = 0;
+    for ((real_line, real_op), (line, op)) in real_ops.iter().zip(&ops) {
+        if real_line == line && seed(&real_op.dest, &op.dest)? {
+            copied += 1;
+        }
+    }
+    state::set_state_dir(dir.join(STATE_DIR));
+    printfc!(
+        LogLevel::Info,
+        "Copied {copied} destinations into {}",
+        dir.display()
+    );
+    Ok(())
+}
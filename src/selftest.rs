@@ -0,0 +1,167 @@
+// `neostow selftest` tries the neostow file out on a throwaway home before it
+// touches the real one. The directory of the file is copied into a temporary
+// directory, version control metadata left out, beside an empty home that
+// every destination goes under, / included as with --target, and the state
+// directory. The copy is then applied, checked, applied again with
+// --overwrite and deleted, as a sandbox would, starting no commands, and
+// each phase is reported as passed or failed. The temporary directory is
+// removed when all of them pass, and kept to look into otherwise.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::status::{self, State};
+use crate::{
+    Config, LogLevel, Mode, Operation, journal, load_plan, platform, report, run, state, vcs,
+};
+
+// Copies the tree at from to to, without the metadata of version control
+fn copy_sources(from: &Path, to: &Path) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if vcs::METADATA.iter().any(|(dir, _)| name == *dir) {
+            continue;
+        }
+        let (from, to) = (entry.path(), to.join(&name));
+        if entry.file_type()?.is_dir() {
+            copy_sources(&from, &to)?;
+        } else if entry.file_type()?.is_symlink() {
+            journal::make_link(&fs::read_link(&from)?, &to, from.is_dir())?;
+        } else {
+            fs::copy(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+// Whether the run of op would make anything: !manual entries are left out
+// unless asked for, !require-target ones without their directory, and
+// !assert ones, which are about the real home
+fn expected(op: &Operation, cfg: &Config) -> bool {
+    if op.assert || (op.manual && !cfg.include_manual) {
+        return false;
+    }
+    !(op.require_target && op.dest.parent().is_some_and(|dir| !dir.is_dir()))
+}
+
+// The destinations of the plan not as wanted, by whether they should be in
+// place, each with what they are instead
+fn misplaced(cfg: &Config, linked: bool) -> io::Result<Vec<String>> {
+    let mut wrong = Vec::new();
+    for (_, op) in load_plan(cfg)? {
+        if !expected(&op, cfg) {
+            continue;
+        }
+        let found = if linked {
+            match status::classify(&op, cfg)? {
+                State::Linked => continue,
+                state => state.name(),
+            }
+        } else if op.dest.symlink_metadata().is_ok() {
+            "left behind"
+        } else {
+            continue;
+        };
+        wrong.push(format!("{} ({found})", op.dest.display()));
+    }
+    Ok(wrong)
+}
+
+// Reports how the phase called name went, and returns whether it passed
+fn phase(name: &str, result: io::Result<()>) -> bool {
+    match &result {
+        Ok(()) => {
+            printfc!(LogLevel::Info, "PASS {name}");
+        }
+        Err(err) => {
+            printfc!(LogLevel::Error, "FAIL {name}: {err}");
+        }
+    }
+    result.is_ok()
+}
+
+// Runs cfg in mode, failing when an entry does
+fn apply(cfg: &Config, mode: Mode) -> io::Result<()> {
+    let cfg = Config {
+        mode,
+        ..cfg.clone()
+    };
+    let failed = report::failed();
+    run(&cfg, &mut 0)?;
+    match report::failed() - failed {
+        0 => Ok(()),
+        n => Err(io::Error::other(format!("{n} entries failed"))),
+    }
+}
+
+// Fails naming the destinations not as wanted
+fn check(cfg: &Config, linked: bool) -> io::Result<()> {
+    let wrong = misplaced(cfg, linked)?;
+    if wrong.is_empty() {
+        return Ok(());
+    }
+    let what = if linked {
+        "not in place"
+    } else {
+        "not removed"
+    };
+    Err(io::Error::other(format!(
+        "{} destinations {what}: {}",
+        wrong.len(),
+        wrong.join(", ")
+    )))
+}
+
+/// Tries the file of cfg, not loaded yet, out on a temporary home, and
+/// returns whether every phase passed.
+pub fn selftest(cfg: &Config) -> io::Result<bool> {
+    let dir = env::temp_dir().join(format!("neostow-selftest-{}", std::process::id()));
+    let (home, dots) = (dir.join("home"), dir.join("dots"));
+    fs::create_dir_all(&home)?;
+    copy_sources(&cfg.basedir, &dots)?;
+    let file = cfg.file.strip_prefix(&cfg.basedir).unwrap_or(&cfg.file);
+    platform::set_home(home.clone());
+    state::set_state_dir(dir.join("state"));
+
+    let mut test = cfg.clone();
+    test.file = dots.join(file);
+    test.basedir = dots;
+    test.target = Some(home);
+    test.sandboxed = true;
+    test.dry = false;
+    test.interactive = false;
+    test.copy_budget = None;
+    printfc!(
+        LogLevel::Info,
+        "Testing {} in {}",
+        cfg.file.display(),
+        dir.display()
+    );
+
+    // Nothing after a failed load or create has anything to test
+    let passed = phase("load", test.load())
+        && phase("create", apply(&test, Mode::Create))
+        && [
+            phase("verify", check(&test, true)),
+            phase(
+                "overwrite",
+                apply(&test, Mode::Overwrite).and_then(|()| check(&test, true)),
+            ),
+            phase(
+                "delete",
+                apply(&test, Mode::Delete).and_then(|()| check(&test, false)),
+            ),
+        ]
+        .iter()
+        .all(|&passed| passed);
+    if passed {
+        fs::remove_dir_all(&dir)?;
+    } else {
+        printfc!(LogLevel::Info, "Kept {} to look into", dir.display());
+    }
+    Ok(passed)
+}
@@ -0,0 +1,208 @@
+// `neostow service install` keeps this machine in sync by applying the
+// neostow file every few minutes, with what the platform schedules jobs
+// with: a systemd user timer on Linux, a launchd user agent on macOS and a
+// Task Scheduler entry on Windows. The job runs this neostow binary with
+// the file, packages and --mode of the invocation, quietly, so only
+// warnings and errors reach the journal or log. `service uninstall`
+// removes it again.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::{Config, LinkMode, LogLevel, platform};
+
+const NAME: &str = "neostow";
+const LABEL: &str = "io.github.aocoronel.neostow";
+pub const DEFAULT_INTERVAL: u32 = 30;
+
+// The command line the job runs
+fn invocation(cfg: &Config) -> io::Result<Vec<OsString>> {
+    let mut args = vec![
+        env::current_exe()?.into_os_string(),
+        "--quiet".into(),
+        "-f".into(),
+        fs::canonicalize(&cfg.file)?.into_os_string(),
+    ];
+    match cfg.link_mode {
+        LinkMode::Symlink => {}
+        LinkMode::Copy => args.extend(["--mode".into(), "copy".into()]),
+        LinkMode::Hardlink => args.extend(["--mode".into(), "hardlink".into()]),
+    }
+    args.extend(cfg.packages.iter().map(OsString::from));
+    Ok(args)
+}
+
+// The files the job is made of, with their content
+fn files(cfg: &Config, interval: u32) -> io::Result<Vec<(PathBuf, String)>> {
+    let args = invocation(cfg)?;
+    if cfg!(target_os = "macos") {
+        let arguments: String = args
+            .iter()
+            .map(|arg| format!("    <string>{}</string>\n", xml(&arg.to_string_lossy())))
+            .collect();
+        let plist = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+             \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n<dict>\n\
+             \x20 <key>Label</key>\n  <string>{LABEL}</string>\n\
+             \x20 <key>ProgramArguments</key>\n  <array>\n{arguments}  </array>\n\
+             \x20 <key>StartInterval</key>\n  <integer>{}</integer>\n\
+             \x20 <key>RunAtLoad</key>\n  <true/>\n\
+             </dict>\n</plist>\n",
+            interval * 60
+        );
+        let path = platform::home().join(format!("Library/LaunchAgents/{LABEL}.plist"));
+        return Ok(vec![(path, plist)]);
+    }
+    let exec: Vec<String> = args
+        .iter()
+        .map(|arg| systemd_quote(&arg.to_string_lossy()))
+        .collect();
+    let dir = platform::config_home().join("systemd/user");
+    let service = format!(
+        "[Unit]\nDescription=Apply the neostow file\n\n\
+         [Service]\nType=oneshot\nExecStart={}\n",
+        exec.join(" ")
+    );
+    let timer = format!(
+        "[Unit]\nDescription=Apply the neostow file every {interval} minutes\n\n\
+         [Timer]\nOnBootSec=2min\nOnUnitActiveSec={interval}min\n\n\
+         [Install]\nWantedBy=timers.target\n"
+    );
+    Ok(vec![
+        (dir.join(format!("{NAME}.service")), service),
+        (dir.join(format!("{NAME}.timer")), timer),
+    ])
+}
+
+fn xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn systemd_quote(s: &str) -> String {
+    if s.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        s.replace('%', "%%")
+    }
+}
+
+// schtasks takes the whole command as one string
+fn windows_command(args: &[OsString]) -> String {
+    let quoted: Vec<String> = args
+        .iter()
+        .map(|arg| format!("\"{}\"", arg.to_string_lossy()))
+        .collect();
+    quoted.join(" ")
+}
+
+// What registers and unregisters the job once its files are in place
+fn commands(cfg: &Config, interval: u32, install: bool) -> io::Result<Vec<Vec<String>>> {
+    let strings = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    if cfg!(windows) {
+        return Ok(vec![if install {
+            let command = windows_command(&invocation(cfg)?);
+            let interval = interval.to_string();
+            strings(&[
+                "schtasks", "/Create", "/TN", NAME, "/SC", "MINUTE", "/MO", &interval, "/TR",
+                &command, "/F",
+            ])
+        } else {
+            strings(&["schtasks", "/Delete", "/TN", NAME, "/F"])
+        }]);
+    }
+    if cfg!(target_os = "macos") {
+        let plist = platform::home().join(format!("Library/LaunchAgents/{LABEL}.plist"));
+        let plist = plist.to_string_lossy();
+        let verb = if install { "load" } else { "unload" };
+        return Ok(vec![strings(&["launchctl", verb, "-w", &plist])]);
+    }
+    let timer = format!("{NAME}.timer");
+    Ok(if install {
+        vec![
+            strings(&["systemctl", "--user", "daemon-reload"]),
+            strings(&["systemctl", "--user", "enable", "--now", &timer]),
+        ]
+    } else {
+        vec![
+            strings(&["systemctl", "--user", "disable", "--now", &timer]),
+            strings(&["systemctl", "--user", "daemon-reload"]),
+        ]
+    })
+}
+
+fn run(command: &[String], cfg: &Config) -> io::Result<()> {
+    if cfg.dry {
+        printfc!(LogLevel::Info, "Would run {}", command.join(" "));
+        return Ok(());
+    }
+    let status = Command::new(&command[0]).args(&command[1..]).status()?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "{} exited with {status}",
+        command.join(" ")
+    )))
+}
+
+/// Installs a job applying cfg's file every interval minutes.
+pub fn install(cfg: &Config, interval: u32) -> io::Result<()> {
+    // Task Scheduler keeps the job itself
+    if !cfg!(windows) {
+        for (path, content) in files(cfg, interval)? {
+            if cfg.dry {
+                printfc!(LogLevel::Info, "Would write {}", path.display());
+                continue;
+            }
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(&path, content)?;
+            if cfg.verbose {
+                println!("Wrote {}", path.display());
+            }
+        }
+    }
+    for command in commands(cfg, interval, true)? {
+        run(&command, cfg)?;
+    }
+    if !cfg.dry {
+        printfc!(
+            LogLevel::Info,
+            "{} will be applied every {interval} minutes",
+            cfg.file.display()
+        );
+    }
+    Ok(())
+}
+
+/// Removes the job install set up.
+pub fn uninstall(cfg: &Config) -> io::Result<()> {
+    // A job that is half gone still has its files removed
+    for command in commands(cfg, DEFAULT_INTERVAL, false)? {
+        if let Err(err) = run(&command, cfg) {
+            printfc!(LogLevel::Warn, "{err}");
+        }
+    }
+    if cfg!(windows) {
+        return Ok(());
+    }
+    for (path, _) in files(cfg, DEFAULT_INTERVAL)? {
+        if cfg.dry {
+            printfc!(LogLevel::Info, "Would remove {}", path.display());
+        } else if let Err(err) = fs::remove_file(&path)
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            return Err(err);
+        }
+    }
+    Ok(())
+}
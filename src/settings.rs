@@ -0,0 +1,243 @@
+// The user's defaults live in $XDG_CONFIG_HOME/neostow/config.toml, or
+// ~/.config/neostow/config.toml (%APPDATA%\neostow\config.toml on Windows):
+//   file = "~/dots/.neostow"   # when no .neostow is found from here up
+//   mode = "copy"              # symlink, copy or hardlink
+//   color = "never"            # always, never or auto
+//   backup = "~/.cache/neostow" # or true for .bak beside each file
+//   notify = "osc777"          # or true for osc9, when long runs finish
+//   editor = "code --wait"     # instead of $VISUAL and $EDITOR
+//   copy_budget = "1G"         # or "none", before copies ask to go on
+//   ascii_only = true          # -> and |-- instead of → and ├──
+//   glyphs = "arrow=>>, ok=*"  # any glyph drawn otherwise
+//   mount_jobs = { "/mnt/nas" = 1 } # at most so many --jobs under a mount
+// Only top-level keys with string or boolean values are read, and inline
+// tables of numbers, which is all these need. Options on the command line
+// win over them.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::backup::Backup;
+use crate::glyphs::{self, Glyph};
+use crate::report::Notify;
+use crate::vars::Vars;
+use crate::{Color, LinkMode, budget, expand, platform, vcs};
+
+#[derive(Default)]
+pub struct Settings {
+    pub file: Option<PathBuf>,
+    pub mode: Option<LinkMode>,
+    pub color: Option<Color>,
+    pub backup: Option<Backup>,
+    pub notify: Option<Notify>,
+    pub editor: Option<String>,
+    pub vcs: Option<vcs::Kind>,
+    pub copy_budget: Option<Option<u64>>,
+    pub ascii_only: Option<bool>,
+    pub glyphs: Option<Vec<(Glyph, String)>>,
+    pub mount_jobs: Option<Vec<(PathBuf, usize)>>,
+}
+
+enum Value {
+    Str(String),
+    Bool(bool),
+    Table(Vec<(String, usize)>),
+}
+
+/// Where the user's config.toml is looked for.
+pub fn path() -> PathBuf {
+    platform::config_home().join("neostow").join("config.toml")
+}
+
+// A basic or literal string, true or false, or an inline table of quoted
+// keys to numbers, with an optional comment after
+fn parse_value(raw: &str) -> Option<Value> {
+    let raw = raw.trim();
+    let (value, rest) = if let Some(rest) = raw.strip_prefix('"') {
+        let mut out = String::new();
+        let mut chars = rest.char_indices();
+        loop {
+            match chars.next()? {
+                (i, '"') => break (Value::Str(out), &rest[i + 1..]),
+                (_, '\\') => out.push(match chars.next()?.1 {
+                    'n' => '\n',
+                    't' => '\t',
+                    c @ ('"' | '\\') => c,
+                    _ => return None,
+                }),
+                (_, c) => out.push(c),
+            }
+        }
+    } else if let Some(rest) = raw.strip_prefix('\'') {
+        let (value, rest) = rest.split_once('\'')?;
+        (Value::Str(value.to_string()), rest)
+    } else if let Some(rest) = raw.strip_prefix('{') {
+        let (table, rest) = rest.split_once('}')?;
+        let mut items = Vec::new();
+        for item in table.split(',').filter(|item| !item.trim().is_empty()) {
+            let (key, number) = item.rsplit_once('=')?;
+            let Value::Str(key) = parse_value(key)? else {
+                return None;
+            };
+            items.push((key, number.trim().parse().ok()?));
+        }
+        (Value::Table(items), rest)
+    } else {
+        let end = raw.find('#').unwrap_or(raw.len());
+        let value = match raw[..end].trim_end() {
+            "true" => Value::Bool(true),
+            "false" => Value::Bool(false),
+            _ => return None,
+        };
+        (value, &raw[end..])
+    };
+    let rest = rest.trim_start();
+    (rest.is_empty() || rest.starts_with('#')).then_some(value)
+}
+
+fn expand_path(value: &str) -> io::Result<PathBuf> {
+    expand::expand_path(value.as_bytes(), &Vars::new())
+}
+
+fn parse(content: &str, path: &Path) -> io::Result<Settings> {
+    let mut settings = Settings::default();
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = |msg: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}:{}: {msg}", path.display(), i + 1),
+            )
+        };
+        let Some((key, raw)) = line.split_once('=') else {
+            return Err(invalid(format!("expected key = value, found {line}")));
+        };
+        let key = key.trim();
+        let value = parse_value(raw).ok_or_else(|| match key {
+            "mount_jobs" => invalid(format!(
+                "{key} needs a table such as {{ \"/mnt/nas\" = 1 }}"
+            )),
+            _ => invalid(format!("{key} needs a quoted string, true or false")),
+        })?;
+        match (key, value) {
+            ("file", Value::Str(file)) => settings.file = Some(expand_path(&file)?),
+            ("mode", Value::Str(mode)) => {
+                settings.mode = Some(match mode.as_str() {
+                    "symlink" => LinkMode::Symlink,
+                    "copy" => LinkMode::Copy,
+                    "hardlink" => LinkMode::Hardlink,
+                    _ => {
+                        return Err(invalid(format!(
+                            "mode {mode} is not symlink, copy or hardlink"
+                        )));
+                    }
+                })
+            }
+            ("color", Value::Str(color)) => {
+                settings.color = Some(match color.as_str() {
+                    "always" => Color::Always,
+                    "never" => Color::Never,
+                    "auto" => Color::Auto,
+                    _ => {
+                        return Err(invalid(format!(
+                            "color {color} is not always, never or auto"
+                        )));
+                    }
+                })
+            }
+            ("backup", Value::Bool(backup)) => settings.backup = backup.then_some(Backup::Suffix),
+            ("backup", Value::Str(dir)) => {
+                let dir = expand_path(&dir)?;
+                settings.backup = Backup::parse(&format!("--backup={}", dir.display()));
+            }
+            ("notify", Value::Bool(notify)) => settings.notify = notify.then_some(Notify::Osc9),
+            ("notify", Value::Str(notify)) => {
+                settings.notify = Some(
+                    Notify::parse(&notify)
+                        .ok_or_else(|| invalid(format!("notify {notify} is not osc9 or osc777")))?,
+                )
+            }
+            ("editor", Value::Str(editor)) => settings.editor = Some(editor),
+            ("vcs", Value::Str(vcs)) => {
+                settings.vcs = Some(
+                    vcs::Kind::parse(&vcs)
+                        .ok_or_else(|| invalid(format!("vcs {vcs} is not git, jj or hg")))?,
+                )
+            }
+            ("copy_budget", Value::Str(size)) => {
+                settings.copy_budget = Some(
+                    budget::parse(&size)
+                        .ok_or_else(|| invalid(format!("copy_budget {size} is not a size")))?,
+                )
+            }
+            ("ascii_only", Value::Bool(ascii)) => settings.ascii_only = Some(ascii),
+            ("glyphs", Value::Str(list)) => {
+                settings.glyphs = Some(glyphs::parse(&list).ok_or_else(|| {
+                    invalid(format!(
+                        "glyphs {list} is not a list of arrow, back, dash, branch, last, ok, bad or warn = GLYPH"
+                    ))
+                })?)
+            }
+            ("mount_jobs", Value::Table(items)) => {
+                let mut limits = Vec::new();
+                for (dir, jobs) in items {
+                    if jobs == 0 {
+                        return Err(invalid(format!("mount_jobs of {dir} must be 1 or more")));
+                    }
+                    limits.push((expand_path(&dir)?, jobs));
+                }
+                settings.mount_jobs = Some(limits);
+            }
+            ("mount_jobs", _) => {
+                return Err(invalid(format!(
+                    "{key} takes a table such as {{ \"/mnt/nas\" = 1 }}"
+                )));
+            }
+            ("ascii_only", _) => return Err(invalid(format!("{key} takes true or false"))),
+            ("file" | "mode" | "color" | "editor" | "vcs" | "copy_budget" | "glyphs", _) => {
+                return Err(invalid(format!("{key} takes a string")));
+            }
+            _ => return Err(invalid(format!("unknown key {key}"))),
+        }
+    }
+    Ok(settings)
+}
+
+/// The user's settings, all unset when there is no config.toml.
+pub fn load() -> io::Result<Settings> {
+    let path = path();
+    match fs::read(&path) {
+        Ok(content) => parse(&String::from_utf8_lossy(&content), &path),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+        Err(err) => Err(err),
+    }
+}
+
+/// The nearest .neostow file (or its encrypted .neostow.age, or a
+/// .neostow.toml or .neostow.yaml) in dir or one of its parents, the way git
+/// finds its repository.
+pub fn find_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(".neostow"))
+        .find(|file| {
+            [
+                ".age",
+                ".toml",
+                ".toml.age",
+                ".yaml",
+                ".yaml.age",
+                ".yml",
+                ".yml.age",
+            ]
+            .iter()
+            .any(|ext| {
+                let mut other = file.clone().into_os_string();
+                other.push(ext);
+                Path::new(&other).exists()
+            }) || file.exists()
+        })
+}
@@ -0,0 +1,56 @@
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::{LogLevel, prompt_user, suggest};
+
+fn ask(prompt: &str, default: &str) -> io::Result<String> {
+    print!("{prompt} [{default}]: ");
+    io::stdout().flush()?;
+    let mut input = String::new();
+    io::stdin().read_line(&mut input)?;
+    let input = input.trim();
+    Ok(if input.is_empty() { default } else { input }.to_string())
+}
+
+// Walks through the items of a dotfiles directory, proposing a destination
+// for each, and writes the accepted mappings as its .neostow file
+pub fn setup() -> io::Result<()> {
+    let cwd = env::current_dir()?;
+    let dir = PathBuf::from(ask("Dotfiles directory", &cwd.to_string_lossy())?);
+    let file = dir.join(".neostow");
+    if file.exists() && !prompt_user(&format!("{} already exists. Replace it?", file.display()))? {
+        return Ok(());
+    }
+
+    println!("For each item, press Enter to accept, type another destination, or - to skip.");
+    let mut lines = Vec::new();
+    for (name, is_dir) in suggest::candidates(&dir)? {
+        let src = if is_dir {
+            format!("{name}/")
+        } else {
+            name.clone()
+        };
+        let dest = ask(&src, &suggest::propose(&name))?;
+        if dest != "-" {
+            lines.push(format!("{src}={dest}"));
+        }
+    }
+
+    if lines.is_empty() {
+        printfc!(LogLevel::Warn, "No mappings accepted, nothing written");
+        return Ok(());
+    }
+    let mut out = fs::File::create(&file)?;
+    for line in &lines {
+        writeln!(out, "{line}")?;
+    }
+    printfc!(
+        LogLevel::Info,
+        "Wrote {} mappings to {}",
+        lines.len(),
+        file.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,73 @@
+// Every command a neostow file has run for it, hooks, validators, reload and
+// refresh commands, is started by status, which never starts one in a dry
+// run: it only describes them, with --dry-run-hooks. `let` commands are the
+// exception, as destinations depend on what they print.
+
+use std::ffi::OsStr;
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus};
+
+use crate::{Config, LogLevel};
+
+pub fn command(cmd: &OsStr) -> Command {
+    #[cfg(not(windows))]
+    {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd);
+        command
+    }
+    #[cfg(windows)]
+    {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(cmd);
+        command
+    }
+}
+
+// Runs command, described by what as in "post-run hook `make`", and returns
+// how it exited, or None in a dry or sandboxed run, which starts nothing
+pub fn status(command: &mut Command, what: &str, cfg: &Config) -> io::Result<Option<ExitStatus>> {
+    if cfg.dry || cfg.sandboxed {
+        if cfg.dry_run_hooks || cfg.sandboxed {
+            printfc!(LogLevel::Info, "Would run {what}");
+        }
+        return Ok(None);
+    }
+    if cfg.verbose {
+        printfc!(LogLevel::Info, "Running {what}");
+    }
+    command.status().map(Some)
+}
+
+// Runs cmd against path. The path is passed as a positional parameter rather
+// than spliced into the command line, so it never needs quoting; `{}` marks
+// where it goes, otherwise it is appended. A dry run counts as a pass.
+pub fn run_with_path(cmd: &OsStr, path: &Path, cfg: &Config) -> io::Result<bool> {
+    let what = format!("validator `{}` on {}", cmd.display(), path.display());
+    let cmd = cmd.to_string_lossy();
+    #[cfg(not(windows))]
+    let mut command = {
+        let script = if cmd.contains("{}") {
+            cmd.replace("{}", "\"$1\"")
+        } else {
+            format!("{cmd} \"$1\"")
+        };
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(script).arg("neostow").arg(path);
+        command
+    };
+    #[cfg(windows)]
+    let mut command = {
+        let quoted = format!("\"{}\"", path.display());
+        let script = if cmd.contains("{}") {
+            cmd.replace("{}", &quoted)
+        } else {
+            format!("{cmd} {quoted}")
+        };
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(script);
+        command
+    };
+    Ok(status(&mut command, &what, cfg)?.is_none_or(|status| status.success()))
+}
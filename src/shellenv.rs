@@ -0,0 +1,63 @@
+// `neostow env` prints what a shell rc evaluates to follow the active config:
+//   eval "$(neostow env)"
+// NEOSTOW_CONFIG and NEOSTOW_PACKAGES, which neostow reads back as its
+// defaults, and PATH with the bin directories entries link into in front.
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::{Config, Operation};
+
+// The directory a PATH lookup finds the entry in: a bin directory itself,
+// or the one a file is linked into
+fn bin_dir(op: &Operation) -> Option<PathBuf> {
+    let bin = Some(OsStr::new("bin"));
+    if op.is_dir && op.dest.file_name() == bin {
+        return Some(op.dest.clone());
+    }
+    let parent = op.dest.parent()?;
+    (parent.file_name() == bin).then(|| parent.to_path_buf())
+}
+
+// PowerShell on Windows, sh and its descendants elsewhere; fish takes the
+// export form too
+fn export(out: &mut impl Write, name: &str, value: &OsStr) -> io::Result<()> {
+    let value = value.to_string_lossy();
+    if cfg!(windows) {
+        writeln!(out, "$env:{name} = '{}'", value.replace('\'', "''"))
+    } else {
+        writeln!(out, "export {name}='{}'", value.replace('\'', r"'\''"))
+    }
+}
+
+pub fn print(ops: &[(usize, Operation)], cfg: &Config, out: &mut impl Write) -> io::Result<()> {
+    export(
+        out,
+        "NEOSTOW_CONFIG",
+        fs::canonicalize(&cfg.file)?.as_os_str(),
+    )?;
+    if !cfg.packages.is_empty() {
+        export(out, "NEOSTOW_PACKAGES", OsStr::new(&cfg.packages.join(" ")))?;
+    }
+
+    let path = env::var_os("PATH").unwrap_or_default();
+    let current: Vec<PathBuf> = if path.is_empty() {
+        Vec::new()
+    } else {
+        env::split_paths(&path).collect()
+    };
+    let mut dirs = Vec::new();
+    for dir in ops.iter().filter_map(|(_, op)| bin_dir(op)) {
+        if !current.contains(&dir) && !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    if dirs.is_empty() {
+        return Ok(());
+    }
+    let joined = env::join_paths(dirs.iter().chain(&current)).map_err(io::Error::other)?;
+    export(out, "PATH", &joined)
+}
@@ -0,0 +1,305 @@
+// `neostow snapshot DIR` records what is under a target root, each path with
+// its type and its content hash or link target, and `neostow snapshot diff`
+// compares the tree as it is now against that record. Where status only
+// looks at the destinations of entries, this catches everything around
+// them: files that appeared, went away or changed beside the managed links,
+// as other users and programs leave them on a shared server. Snapshots are
+// kept per machine in the state directory, one per root, and a new one of a
+// root replaces the last. The state directory is left out when it is under
+// the root, as it changes with every run.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::glyphs::{self, Glyph};
+use crate::{LogLevel, backup, bytes, state};
+
+const SNAPSHOTS_FILE: &str = "snapshots";
+
+// What is at a path: a directory, a file by its content, a link by its
+// target, or anything else, such as a socket
+#[derive(PartialEq)]
+enum Kind {
+    Dir,
+    File(String),
+    Link(Vec<u8>),
+    Other,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Dir => "directory",
+            Kind::File(_) => "file",
+            Kind::Link(_) => "link",
+            Kind::Other => "special file",
+        }
+    }
+}
+
+// A root as recorded: when, and what was under it by relative path
+struct Snapshot {
+    taken: String,
+    paths: BTreeMap<PathBuf, Kind>,
+}
+
+// The content of the file at path as its hash and length, read a piece at
+// a time so a large file is never held whole
+fn digest(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = DefaultHasher::new();
+    let mut buf = [0u8; 64 * 1024];
+    let mut len = 0u64;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.write(&buf[..n]);
+        len += n as u64;
+    }
+    Ok(format!("{:016x}-{len}", hasher.finish()))
+}
+
+// Adds what is under dir to paths, relative to root. What can't be read is
+// warned about and left out, rather than failing the whole tree.
+fn walk(root: &Path, dir: &Path, skip: &Path, paths: &mut BTreeMap<PathBuf, Kind>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            printfc!(LogLevel::Warn, "Left out {}: {err}", dir.display());
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path == skip {
+            continue;
+        }
+        let kind = match entry.file_type() {
+            Ok(kind) if kind.is_dir() => Kind::Dir,
+            Ok(kind) if kind.is_symlink() => match fs::read_link(&path) {
+                Ok(target) => Kind::Link(bytes::from_os(target.as_os_str()).into_owned()),
+                Err(err) => {
+                    printfc!(LogLevel::Warn, "Left out {}: {err}", path.display());
+                    continue;
+                }
+            },
+            Ok(kind) if kind.is_file() => match digest(&path) {
+                Ok(digest) => Kind::File(digest),
+                Err(err) => {
+                    printfc!(LogLevel::Warn, "Left out {}: {err}", path.display());
+                    continue;
+                }
+            },
+            Ok(_) => Kind::Other,
+            Err(err) => {
+                printfc!(LogLevel::Warn, "Left out {}: {err}", path.display());
+                continue;
+            }
+        };
+        if kind == Kind::Dir {
+            walk(root, &path, skip, paths);
+        }
+        let rel = path.strip_prefix(root).unwrap_or(&path).to_path_buf();
+        paths.insert(rel, kind);
+    }
+}
+
+fn scan(root: &Path) -> BTreeMap<PathBuf, Kind> {
+    let mut paths = BTreeMap::new();
+    let state = state::state_dir();
+    walk(
+        root,
+        root,
+        &fs::canonicalize(&state).unwrap_or(state),
+        &mut paths,
+    );
+    paths
+}
+
+// Every snapshot recorded, by root. Each line is the root, a kind, what
+// stands for the content and the relative path, separated by tabs, with
+// one line of kind @ giving when the root was taken.
+fn load() -> io::Result<BTreeMap<PathBuf, Snapshot>> {
+    let mut snapshots: BTreeMap<PathBuf, Snapshot> = BTreeMap::new();
+    let Some(content) = state::read_file(&state::host_dir().join(SNAPSHOTS_FILE))? else {
+        return Ok(snapshots);
+    };
+    for line in bytes::lines(&content) {
+        let mut fields = line.splitn(4, |&b| b == b'\t');
+        let (Some(root), Some(kind), Some(data), Some(rel)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let snapshot = snapshots
+            .entry(PathBuf::from(bytes::to_os(root).into_owned()))
+            .or_insert_with(|| Snapshot {
+                taken: String::new(),
+                paths: BTreeMap::new(),
+            });
+        let kind = match kind {
+            b"@" => {
+                snapshot.taken = String::from_utf8_lossy(data).into_owned();
+                continue;
+            }
+            b"d" => Kind::Dir,
+            b"f" => Kind::File(String::from_utf8_lossy(data).into_owned()),
+            b"l" => Kind::Link(data.to_vec()),
+            _ => Kind::Other,
+        };
+        let rel = PathBuf::from(bytes::to_os(rel).into_owned());
+        snapshot.paths.insert(rel, kind);
+    }
+    Ok(snapshots)
+}
+
+fn save(snapshots: &BTreeMap<PathBuf, Snapshot>) -> io::Result<()> {
+    let mut out = Vec::new();
+    for (root, snapshot) in snapshots {
+        let root = bytes::from_os(root.as_os_str());
+        let mut line = |kind: &[u8], data: &[u8], rel: &[u8]| {
+            for field in [&root[..], kind, data] {
+                out.extend_from_slice(field);
+                out.push(b'\t');
+            }
+            out.extend_from_slice(rel);
+            out.push(b'\n');
+        };
+        line(b"@", snapshot.taken.as_bytes(), b"");
+        for (rel, kind) in &snapshot.paths {
+            let rel = bytes::from_os(rel.as_os_str());
+            match kind {
+                Kind::Dir => line(b"d", b"-", &rel),
+                Kind::File(digest) => line(b"f", digest.as_bytes(), &rel),
+                Kind::Link(target) => line(b"l", target, &rel),
+                Kind::Other => line(b"o", b"-", &rel),
+            }
+        }
+    }
+    state::write_file(&state::host_dir().join(SNAPSHOTS_FILE), &out)
+}
+
+// The absolute form of root, the same however it was written
+fn resolve(root: &Path) -> io::Result<PathBuf> {
+    fs::canonicalize(root).or_else(|_| std::path::absolute(root))
+}
+
+/// Records what is under root now, in place of any snapshot of it before.
+pub fn take(root: &Path) -> io::Result<()> {
+    let root = fs::canonicalize(root)?;
+    if !root.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a directory", root.display()),
+        ));
+    }
+    let paths = scan(&root);
+    let count = paths.len();
+    let mut snapshots = load()?;
+    snapshots.insert(
+        root.clone(),
+        Snapshot {
+            taken: backup::timestamp(),
+            paths,
+        },
+    );
+    save(&snapshots)?;
+    printfc!(
+        LogLevel::Info,
+        "Recorded {count} paths under {}",
+        root.display()
+    );
+    Ok(())
+}
+
+// How a path differs from what was recorded, if it does: whether it
+// appeared, went away or changed, and what it is
+fn change(was: Option<&Kind>, now: Option<&Kind>) -> Option<(char, String)> {
+    let arrow = glyphs::get(Glyph::Arrow);
+    match (was, now) {
+        (None, Some(kind)) => Some(('+', kind.name().to_string())),
+        (Some(kind), None) => Some(('-', kind.name().to_string())),
+        (Some(was), Some(now)) if was == now => None,
+        (Some(Kind::File(_)), Some(Kind::File(_))) => Some(('~', "content changed".to_string())),
+        (Some(Kind::Link(was)), Some(Kind::Link(now))) => Some((
+            '~',
+            format!(
+                "link {} {arrow} {}",
+                bytes::to_os(was).display(),
+                bytes::to_os(now).display()
+            ),
+        )),
+        (Some(was), Some(now)) => Some(('~', format!("{} {arrow} {}", was.name(), now.name()))),
+        (None, None) => None,
+    }
+}
+
+/// Compares root, or every root with a snapshot, against its snapshot,
+/// printing each path that appeared, went away or changed, and returns how
+/// many did.
+pub fn diff(root: Option<&Path>) -> io::Result<usize> {
+    let mut snapshots = load()?;
+    if let Some(root) = root {
+        let root = resolve(root)?;
+        let Some(snapshot) = snapshots.remove(&root) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!(
+                    "no snapshot of {}, take one with neostow snapshot {}",
+                    root.display(),
+                    root.display()
+                ),
+            ));
+        };
+        snapshots = BTreeMap::from([(root, snapshot)]);
+    }
+    if snapshots.is_empty() {
+        printfc!(
+            LogLevel::Info,
+            "No snapshots yet, take one with neostow snapshot DIR"
+        );
+        return Ok(0);
+    }
+
+    let mut total = 0;
+    for (root, snapshot) in &snapshots {
+        let now = if root.is_dir() {
+            scan(root)
+        } else {
+            BTreeMap::new()
+        };
+        let mut rels: Vec<&PathBuf> = snapshot.paths.keys().chain(now.keys()).collect();
+        rels.sort();
+        rels.dedup();
+        let mut changes = 0;
+        for rel in rels {
+            let Some((sign, what)) = change(snapshot.paths.get(rel), now.get(rel)) else {
+                continue;
+            };
+            println!("{sign} {} ({what})", root.join(rel).display());
+            changes += 1;
+        }
+        if changes == 0 {
+            printfc!(
+                LogLevel::Info,
+                "{} matches its snapshot of {}",
+                root.display(),
+                snapshot.taken
+            );
+        } else {
+            printfc!(
+                LogLevel::Info,
+                "{changes} paths under {} differ from its snapshot of {}",
+                root.display(),
+                snapshot.taken
+            );
+        }
+        total += changes;
+    }
+    Ok(total)
+}
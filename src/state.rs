@@ -0,0 +1,413 @@
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{BuildHasher, DefaultHasher, Hasher, RandomState};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::vfs::Fs;
+use crate::{LogLevel, bytes, platform};
+
+const FAILED_FILE: &str = "failed";
+const APPLIED_FILE: &str = "applied";
+const BACKUPS_FILE: &str = "backups";
+const LINKS_FILE: &str = "links";
+const DIRS_FILE: &str = "dirs";
+
+// Content hash of each linked source at the time it was applied, by destination
+pub type Applied = HashMap<PathBuf, u64>;
+
+// What neostow made at each destination, as the neostow file that was run
+// and the source, for prune
+pub type Links = HashMap<PathBuf, (PathBuf, PathBuf)>;
+
+#[derive(Clone)]
+pub struct FailedEntry {
+    pub file: PathBuf,
+    pub linenum: usize,
+    pub line: Vec<u8>,
+}
+
+// Where a sandboxed run keeps its state instead
+static OVERRIDE: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+pub fn set_state_dir(dir: PathBuf) {
+    *OVERRIDE.write().unwrap_or_else(|e| e.into_inner()) = Some(dir);
+}
+
+// Follows the XDG base directory spec, falling back to ~/.local/state, or
+// on Windows to %LOCALAPPDATA%
+pub fn state_dir() -> PathBuf {
+    if let Some(dir) = &*OVERRIDE.read().unwrap_or_else(|e| e.into_inner()) {
+        return dir.clone();
+    }
+    platform::state_home().join("neostow")
+}
+
+// The OS machine id where there is one, else the host name. Only its hash is
+// ever written to disk, since the machine id is meant to stay private.
+fn machine_id() -> String {
+    let raw = [
+        "/etc/machine-id",
+        "/var/lib/dbus/machine-id",
+        "/etc/hostname",
+    ]
+    .iter()
+    .find_map(|path| fs::read(path).ok().filter(|id| !id.trim_ascii().is_empty()))
+    .unwrap_or_else(|| platform::hostname().into_bytes());
+
+    let mut hasher = DefaultHasher::new();
+    hasher.write(raw.trim_ascii());
+    format!("{:016x}", hasher.finish())
+}
+
+// A random UUID naming this run, written to the journal and to every JSON
+// output, so what one run did can be traced across them
+pub fn run_id() -> &'static str {
+    static ID: OnceLock<String> = OnceLock::new();
+    ID.get_or_init(|| {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        // RandomState is seeded from the OS, which is all the randomness std
+        // offers
+        let mut raw = [0u8; 16];
+        for (i, half) in raw.chunks_mut(8).enumerate() {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(nanos);
+            hasher.write_u32(std::process::id());
+            hasher.write_usize(i);
+            half.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+        // Version 4, variant 1
+        raw[6] = (raw[6] & 0x0f) | 0x40;
+        raw[8] = (raw[8] & 0x3f) | 0x80;
+        let hex: String = raw.iter().map(|b| format!("{b:02x}")).collect();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    })
+}
+
+// Records are kept per machine, so a state directory synced between hosts
+// never has one host act on links that only exist on another
+pub fn host_dir() -> PathBuf {
+    state_dir().join("hosts").join(machine_id())
+}
+
+// State files begin with HEADER and end with a line counting the lines in
+// between, so one cut short by a crash or power loss is told apart from a
+// complete one. Files from before they did are read as they are.
+const HEADER: &[u8] = b"#neostow-state\n";
+const FOOTER: &[u8] = b"#end ";
+
+fn previous(path: &Path) -> PathBuf {
+    let mut prev = path.as_os_str().to_owned();
+    prev.push(".prev");
+    PathBuf::from(prev)
+}
+
+// The lines of a state file, or where it was cut short, the lines of it
+// that are whole
+fn verify(content: &[u8]) -> Result<&[u8], &[u8]> {
+    let whole = content
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let Some(body) = content[..whole].strip_prefix(HEADER) else {
+        // Cut short in the header, nothing is left
+        if content.starts_with(b"#neostow") {
+            return Err(&[]);
+        }
+        return if whole == content.len() {
+            Ok(content)
+        } else {
+            Err(&content[..whole])
+        };
+    };
+    let last = body[..body.len().saturating_sub(1)]
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    let (lines, footer) = body.split_at(last);
+    let count = footer
+        .strip_prefix(FOOTER)
+        .and_then(|n| std::str::from_utf8(n.trim_ascii()).ok())
+        .and_then(|n| n.parse::<usize>().ok());
+    let counted = lines.iter().filter(|&&b| b == b'\n').count();
+    match count {
+        Some(count) if whole == content.len() && count == counted => Ok(lines),
+        Some(_) => Err(lines),
+        None => Err(body),
+    }
+}
+
+// Writes lines to path through a file beside it, synced before it replaces
+// path, so path is either the old file or the new one whole. The old one is
+// kept as path.prev to fall back on.
+pub fn write_file(path: &Path, lines: &[u8]) -> io::Result<()> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::create_dir_all(dir)?;
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut out = fs::File::create(&tmp)?;
+    out.write_all(HEADER)?;
+    out.write_all(lines)?;
+    let count = lines.iter().filter(|&&b| b == b'\n').count();
+    out.write_all(FOOTER)?;
+    writeln!(out, "{count}")?;
+    out.sync_all()?;
+    let prev = previous(path);
+    let _ = fs::remove_file(&prev);
+    let _ = fs::hard_link(path, &prev);
+    fs::rename(&tmp, path)?;
+    // The rename itself only lasts once the directory is synced, which
+    // Windows has no way to do
+    #[cfg(unix)]
+    fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+// The lines of the state file at path, or None without one. A file cut
+// short is put back from the copy kept by the write before, or else cut
+// down to its whole lines, and written again.
+pub fn read_file(path: &Path) -> io::Result<Option<Vec<u8>>> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let whole = match verify(&content) {
+        Ok(lines) => return Ok(Some(lines.to_vec())),
+        Err(whole) => whole,
+    };
+    let prev = fs::read(previous(path)).unwrap_or_default();
+    let lines = match verify(&prev) {
+        Ok(lines) if !prev.is_empty() => {
+            printfc!(
+                LogLevel::Warn,
+                "{} was cut short, going back to the copy kept before it",
+                path.display()
+            );
+            lines
+        }
+        _ => {
+            printfc!(
+                LogLevel::Warn,
+                "{} was cut short, keeping the {} lines of it that are whole",
+                path.display(),
+                whole.iter().filter(|&&b| b == b'\n').count()
+            );
+            whole
+        }
+    };
+    write_file(path, lines)?;
+    Ok(Some(lines.to_vec()))
+}
+
+// Removes the state file at path, and the copy kept of it
+pub fn remove_file(path: &Path) -> io::Result<()> {
+    for path in [previous(path), path.to_path_buf()] {
+        match fs::remove_file(path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+pub fn load_failed() -> io::Result<Vec<FailedEntry>> {
+    let Some(content) = read_file(&host_dir().join(FAILED_FILE))? else {
+        return Ok(Vec::new());
+    };
+    let mut entries = Vec::new();
+    for line in bytes::lines(&content) {
+        let mut fields = line.splitn(3, |&b| b == b'\t');
+        let (Some(file), Some(linenum), Some(entry)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let Some(linenum) = std::str::from_utf8(linenum)
+            .ok()
+            .and_then(|n| n.parse().ok())
+        else {
+            continue;
+        };
+        entries.push(FailedEntry {
+            file: PathBuf::from(bytes::to_os(file).into_owned()),
+            linenum,
+            line: entry.to_vec(),
+        });
+    }
+
+    Ok(entries)
+}
+
+// An empty set removes the file, so a clean run leaves nothing to retry
+pub fn save_failed(entries: &[FailedEntry]) -> io::Result<()> {
+    let path = host_dir().join(FAILED_FILE);
+    if entries.is_empty() {
+        return remove_file(&path);
+    }
+
+    let mut out = Vec::new();
+    for entry in entries {
+        out.write_all(&bytes::from_os(entry.file.as_os_str()))?;
+        write!(out, "\t{}\t", entry.linenum)?;
+        out.write_all(&entry.line)?;
+        out.write_all(b"\n")?;
+    }
+    write_file(&path, &out)
+}
+
+pub fn load_applied() -> io::Result<Applied> {
+    let Some(content) = read_file(&host_dir().join(APPLIED_FILE))? else {
+        return Ok(Applied::new());
+    };
+    let mut applied = Applied::new();
+    for line in bytes::lines(&content) {
+        let Some((hash, dest)) = bytes::split_once(line, b'\t') else {
+            continue;
+        };
+        let Some(hash) = std::str::from_utf8(hash)
+            .ok()
+            .and_then(|h| u64::from_str_radix(h, 16).ok())
+        else {
+            continue;
+        };
+        applied.insert(PathBuf::from(bytes::to_os(dest).into_owned()), hash);
+    }
+
+    Ok(applied)
+}
+
+pub fn save_applied(applied: &Applied) -> io::Result<()> {
+    let mut out = Vec::new();
+    for (dest, hash) in applied {
+        write!(out, "{hash:016x}\t")?;
+        out.write_all(&bytes::from_os(dest.as_os_str()))?;
+        out.write_all(b"\n")?;
+    }
+    write_file(&host_dir().join(APPLIED_FILE), &out)
+}
+
+// Backups as (destination, backup) pairs, oldest first
+pub fn load_backups() -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let Some(content) = read_file(&host_dir().join(BACKUPS_FILE))? else {
+        return Ok(Vec::new());
+    };
+    Ok(bytes::lines(&content)
+        .filter_map(|line| bytes::split_once(line, b'\t'))
+        .map(|(dest, backup)| {
+            (
+                PathBuf::from(bytes::to_os(dest).into_owned()),
+                PathBuf::from(bytes::to_os(backup).into_owned()),
+            )
+        })
+        .collect())
+}
+
+pub fn save_backups(backups: &[(PathBuf, PathBuf)]) -> io::Result<()> {
+    let path = host_dir().join(BACKUPS_FILE);
+    if backups.is_empty() {
+        return remove_file(&path);
+    }
+
+    let mut out = Vec::new();
+    for (dest, backup) in backups {
+        out.write_all(&bytes::from_os(dest.as_os_str()))?;
+        out.write_all(b"\t")?;
+        out.write_all(&bytes::from_os(backup.as_os_str()))?;
+        out.write_all(b"\n")?;
+    }
+    write_file(&path, &out)
+}
+
+pub fn load_links() -> io::Result<Links> {
+    let Some(content) = read_file(&host_dir().join(LINKS_FILE))? else {
+        return Ok(Links::new());
+    };
+    let mut links = Links::new();
+    for line in bytes::lines(&content) {
+        let mut fields = line.splitn(3, |&b| b == b'\t');
+        let (Some(file), Some(src), Some(dest)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let path = |field: &[u8]| PathBuf::from(bytes::to_os(field).into_owned());
+        links.insert(path(dest), (path(file), path(src)));
+    }
+
+    Ok(links)
+}
+
+pub fn save_links(links: &Links) -> io::Result<()> {
+    let mut out = Vec::new();
+    for (dest, (file, src)) in links {
+        for field in [file, src] {
+            out.write_all(&bytes::from_os(field.as_os_str()))?;
+            out.write_all(b"\t")?;
+        }
+        out.write_all(&bytes::from_os(dest.as_os_str()))?;
+        out.write_all(b"\n")?;
+    }
+    write_file(&host_dir().join(LINKS_FILE), &out)
+}
+
+// Creates dir and its missing parents, recording the ones it made so that
+// decommission can take them away again, and returns them
+pub fn make_dirs(fs: &dyn Fs, dir: &Path) -> io::Result<Vec<PathBuf>> {
+    // Parallel runs make directories at once, and each rewrites the file
+    static RECORDING: Mutex<()> = Mutex::new(());
+
+    let missing: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|d| !d.as_os_str().is_empty() && fs.metadata(d).is_err())
+        .collect();
+    fs.create_dir_all(dir)?;
+    if missing.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let _recording = RECORDING.lock().unwrap_or_else(|e| e.into_inner());
+    let mut dirs = load_dirs()?;
+    for dir in &missing {
+        dirs.push(std::path::absolute(dir)?);
+    }
+    save_dirs(&dirs)?;
+    Ok(missing.into_iter().map(Path::to_path_buf).collect())
+}
+
+pub fn load_dirs() -> io::Result<Vec<PathBuf>> {
+    let Some(content) = read_file(&host_dir().join(DIRS_FILE))? else {
+        return Ok(Vec::new());
+    };
+    let mut dirs: Vec<PathBuf> = bytes::lines(&content)
+        .map(|line| PathBuf::from(bytes::to_os(line).into_owned()))
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    Ok(dirs)
+}
+
+pub fn save_dirs(dirs: &[PathBuf]) -> io::Result<()> {
+    let path = host_dir().join(DIRS_FILE);
+    if dirs.is_empty() {
+        return remove_file(&path);
+    }
+
+    let mut out = Vec::new();
+    for dir in dirs {
+        out.write_all(&bytes::from_os(dir.as_os_str()))?;
+        out.write_all(b"\n")?;
+    }
+    write_file(&path, &out)
+}
@@ -0,0 +1,162 @@
+// Trends over the runs on this machine. Every run that isn't dry appends
+// what it saw to a history next to the journal:
+//   run    SECS  MODE  ENTRIES  FAILED
+//   drift  DEST      a destination found changed and put right
+//   conflict DEST    a destination in the way that the run left alone
+//   failed LINE      an entry that failed, as written in its file
+// and `neostow stats` sums these up per week. Runs recorded before FAILED
+// was have four fields. `neostow log` lists the runs one by one.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{LogLevel, Mode, backup, bytes, state};
+use state::FailedEntry;
+
+pub(crate) const HISTORY_FILE: &str = "history";
+
+// How many weeks and destinations the summary shows
+const WEEKS: usize = 12;
+const TOP: usize = 10;
+
+pub(crate) fn record(
+    mode: &Mode,
+    entries: usize,
+    drifted: &[PathBuf],
+    conflicted: &[PathBuf],
+    failed: &[FailedEntry],
+) -> io::Result<()> {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let dir = state::host_dir();
+    fs::create_dir_all(&dir)?;
+    let mut out = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(dir.join(HISTORY_FILE))?;
+    let mut lines = format!(
+        "run\t{secs}\t{}\t{entries}\t{}\n",
+        mode.name(),
+        failed.len()
+    )
+    .into_bytes();
+    for (kind, dests) in [("drift", drifted), ("conflict", conflicted)] {
+        for dest in dests {
+            lines.extend_from_slice(kind.as_bytes());
+            lines.push(b'\t');
+            lines.extend_from_slice(&bytes::from_os(dest.as_os_str()));
+            lines.push(b'\n');
+        }
+    }
+    for entry in failed {
+        lines.extend_from_slice(b"failed\t");
+        lines.extend_from_slice(entry.line.trim_ascii());
+        lines.push(b'\n');
+    }
+    out.write_all(&lines)
+}
+
+#[derive(Default)]
+struct Week {
+    runs: usize,
+    // Entries of the last run that applied the file, rather than deleting it
+    entries: Option<usize>,
+    drift: usize,
+    conflicts: usize,
+}
+
+// Weeks start on Monday, and the epoch was a Thursday
+fn week_of(secs: u64) -> i64 {
+    (secs as i64 / 86400 + 3).div_euclid(7)
+}
+
+fn monday(week: i64) -> String {
+    let (year, month, day) = backup::civil(week * 7 - 3);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+pub fn stats() -> io::Result<()> {
+    let path = state::host_dir().join(HISTORY_FILE);
+    if !path.exists() {
+        printfc!(LogLevel::Info, "No runs recorded yet");
+        return Ok(());
+    }
+    let content = fs::read(path)?;
+
+    let mut weeks: Vec<(i64, Week)> = Vec::new();
+    // Per destination, how often it drifted and how often it was in the way
+    let mut dests: HashMap<&[u8], (usize, usize)> = HashMap::new();
+    for line in bytes::lines(&content) {
+        let mut fields = line.split(|&b| b == b'\t');
+        match fields.next() {
+            Some(b"run") => {
+                let mut text = || fields.next().and_then(|f| std::str::from_utf8(f).ok());
+                let (Some(secs), Some(mode), Some(entries)) = (
+                    text().and_then(|n| n.parse::<u64>().ok()),
+                    text(),
+                    text().and_then(|n| n.parse::<usize>().ok()),
+                ) else {
+                    continue;
+                };
+                let week = week_of(secs);
+                if weeks.last().is_none_or(|(w, _)| *w != week) {
+                    weeks.push((week, Week::default()));
+                }
+                let (_, current) = weeks.last_mut().expect("pushed above");
+                current.runs += 1;
+                if mode != Mode::Delete.name() {
+                    current.entries = Some(entries);
+                }
+            }
+            Some(kind @ (b"drift" | b"conflict")) => {
+                let (Some(dest), Some((_, week))) = (fields.next(), weeks.last_mut()) else {
+                    continue;
+                };
+                let counts = dests.entry(dest).or_default();
+                if kind == b"drift" {
+                    week.drift += 1;
+                    counts.0 += 1;
+                } else {
+                    week.conflicts += 1;
+                    counts.1 += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    println!(
+        "{:<12} {:>6} {:>8} {:>12} {:>10}",
+        "Week of", "Runs", "Entries", "Drift fixed", "Conflicts"
+    );
+    let start = weeks.len().saturating_sub(WEEKS);
+    for (week, stats) in &weeks[start..] {
+        let entries = stats.entries.map_or("-".to_string(), |n| n.to_string());
+        println!(
+            "{:<12} {:>6} {entries:>8} {:>12} {:>10}",
+            monday(*week),
+            stats.runs,
+            stats.drift,
+            stats.conflicts
+        );
+    }
+
+    if dests.is_empty() {
+        return Ok(());
+    }
+    let mut dests: Vec<_> = dests.into_iter().collect();
+    dests.sort_by(|(a, (ad, ac)), (b, (bd, bc))| (bc + bd, bc).cmp(&(ac + ad, ac)).then(a.cmp(b)));
+    println!("\nMost often changed outside neostow:");
+    println!("{:>10} {:>12}  Destination", "Conflicts", "Drift fixed");
+    for (dest, (drift, conflicts)) in dests.into_iter().take(TOP) {
+        println!(
+            "{conflicts:>10} {drift:>12}  {}",
+            Path::new(&*bytes::to_os(dest)).display()
+        );
+    }
+    Ok(())
+}
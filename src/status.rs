@@ -0,0 +1,403 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::expand::normalize;
+use crate::glyphs::{self, Glyph};
+use crate::vfs::Node;
+use crate::{
+    Config, LinkMode, LogLevel, Mode, Operation, attrs, cooldown, defaults, execute_op,
+    guarded_lines, ignore, meta, provenance, registry, report, state, template, transform,
+    unmanage,
+};
+
+// Where a destination stands relative to its entry. Of the real files sitting
+// where a link should go, a shadow is a copy identical to its source, usually
+// left by an earlier manual `cp`, and can be replaced without losing
+// anything; a conflict holds changes of its own. A drifted copy has the
+// source's content but not the permissions or times !inherit asks for. The
+// destination of an !assert entry holds when it exists with the mode and
+// owner asked for, and is broken otherwise.
+#[derive(PartialEq)]
+pub(crate) enum State {
+    Linked,
+    Drifted(String),
+    Elsewhere(PathBuf),
+    Shadow,
+    Conflict,
+    Missing,
+    Holds,
+    Broken(String),
+}
+
+impl State {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            State::Linked => "linked",
+            State::Drifted(_) => "drifted",
+            State::Elsewhere(_) => "elsewhere",
+            State::Shadow => "shadow",
+            State::Conflict => "conflict",
+            State::Missing => "missing",
+            State::Holds => "holds",
+            State::Broken(_) => "broken",
+        }
+    }
+}
+
+fn sorted_names(dir: &Path) -> io::Result<Vec<std::ffi::OsString>> {
+    let mut names = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.file_name()))
+        .collect::<io::Result<Vec<_>>>()?;
+    names.sort();
+    Ok(names)
+}
+
+// With hardlink, files only count as the same when they are one file
+fn same_content(a: &Path, b: &Path, hardlink: bool) -> io::Result<bool> {
+    let (meta_a, meta_b) = (a.symlink_metadata()?, b.symlink_metadata()?);
+    let (type_a, type_b) = (meta_a.file_type(), meta_b.file_type());
+    if type_a.is_symlink() || type_b.is_symlink() {
+        return Ok(type_a.is_symlink()
+            && type_b.is_symlink()
+            && fs::read_link(a)? == fs::read_link(b)?);
+    }
+    if type_a.is_dir() && type_b.is_dir() {
+        let names = sorted_names(a)?;
+        if names != sorted_names(b)? {
+            return Ok(false);
+        }
+        for name in names {
+            if !same_content(&a.join(&name), &b.join(&name), hardlink)? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+    if hardlink {
+        return Ok(type_a.is_file() && type_b.is_file() && same_file(&meta_a, &meta_b));
+    }
+    if !(type_a.is_file() && type_b.is_file()) {
+        return Ok(false);
+    }
+    if meta_a.len() == meta_b.len() {
+        return Ok(fs::read(a)? == fs::read(b)?);
+    }
+    // A copy may carry a provenance header its source doesn't
+    Ok(meta_b.len() > meta_a.len() && *provenance::strip(&fs::read(b)?) == *fs::read(a)?)
+}
+
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+// Without inode numbers, hard links still share their size and modification time
+#[cfg(not(unix))]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    a.len() == b.len() && a.modified().ok() == b.modified().ok()
+}
+
+// Relative link targets are resolved against the link's own directory
+fn points_to(link: &Path, target: &Path, src: &Path) -> bool {
+    let parent = link.parent().unwrap_or(Path::new(""));
+    normalize(&parent.join(target)) == normalize(src)
+}
+
+// Whether dest is what linking src with the configured link mode makes
+pub(crate) fn in_place(src: &Path, dest: &Path, cfg: &Config) -> io::Result<bool> {
+    let Ok(node) = cfg.fs.metadata(dest) else {
+        return Ok(false);
+    };
+    let is_link = matches!(node, Node::Link(_));
+    match (cfg.link_mode, node) {
+        (LinkMode::Symlink, Node::Link(target)) => Ok(points_to(dest, &target, src)),
+        // Where file symlinks need privileges, a hard link stands in
+        (LinkMode::Symlink, node) => {
+            Ok(cfg!(windows) && node == Node::File && same_content(src, dest, true)?)
+        }
+        (LinkMode::Copy, _) => Ok(!is_link && same_content(src, dest, false)?),
+        (LinkMode::Hardlink, _) => Ok(!is_link && same_content(src, dest, true)?),
+    }
+}
+
+// in_place for op, whose destination is in place as its source rendered when
+// it is a template
+pub(crate) fn op_in_place(op: &Operation, cfg: &Config) -> io::Result<bool> {
+    match &op.template {
+        Some(vars) => template::in_place(&op.src, &op.dest, vars),
+        None => in_place(&op.src, &op.dest, cfg),
+    }
+}
+
+// Whether dest is something neostow made, and may remove: a link into the
+// repo, or a copy still matching its source. Nothing at all counts too.
+pub(crate) fn owned(src: &Path, dest: &Path, cfg: &Config) -> io::Result<bool> {
+    let Ok(node) = cfg.fs.metadata(dest) else {
+        return Ok(true);
+    };
+    let Node::Link(target) = node else {
+        return in_place(src, dest, cfg);
+    };
+    let absolute =
+        |p: &Path| normalize(&std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf()));
+    let parent = dest.parent().unwrap_or(Path::new(""));
+    let target = absolute(&parent.join(target));
+    Ok(target == absolute(src) || target.starts_with(absolute(&cfg.basedir)))
+}
+
+// owned for op, where a rendered template is only neostow's while unedited
+pub(crate) fn op_owned(op: &Operation, cfg: &Config) -> io::Result<bool> {
+    match &op.template {
+        Some(vars) => {
+            Ok(cfg.fs.metadata(&op.dest).is_err() || template::in_place(&op.src, &op.dest, vars)?)
+        }
+        None => owned(&op.src, &op.dest, cfg),
+    }
+}
+
+// A managed directory is in sync once every child of its source is linked,
+// an unfolded one once every file in the tree is
+// Only copies have metadata of their own to drift
+fn drift(
+    src: &Path,
+    dest: &Path,
+    preserve: meta::Preserve,
+    cfg: &Config,
+) -> io::Result<Option<String>> {
+    if cfg.link_mode != LinkMode::Copy {
+        return Ok(None);
+    }
+    meta::drift(src, dest, preserve)
+}
+
+fn classify_tree(
+    src: &Path,
+    dest: &Path,
+    unfolded: bool,
+    preserve: meta::Preserve,
+    cfg: &Config,
+) -> io::Result<State> {
+    if !dest.symlink_metadata().is_ok_and(|meta| meta.is_dir()) {
+        return Ok(State::Missing);
+    }
+    for child in fs::read_dir(src)? {
+        let child = child?;
+        let link = dest.join(transform::apply(&cfg.transforms, &child.file_name())?);
+        if unmanage::covers(&cfg.unmanaged, &link)
+            || ignore::ignored(cfg, &child.path(), child.file_type()?.is_dir())
+        {
+            continue;
+        }
+        if unfolded && child.file_type()?.is_dir() {
+            match classify_tree(&child.path(), &link, unfolded, preserve, cfg)? {
+                State::Linked => {}
+                State::Drifted(drift) => return Ok(State::Drifted(drift)),
+                _ => return Ok(State::Missing),
+            }
+        } else if !in_place(&child.path(), &link, cfg)? {
+            return Ok(State::Missing);
+        } else if let Some(drift) = drift(&child.path(), &link, preserve, cfg)? {
+            return Ok(State::Drifted(drift));
+        }
+    }
+    Ok(State::Linked)
+}
+
+pub(crate) fn classify(op: &Operation, cfg: &Config) -> io::Result<State> {
+    if op.assert {
+        if !op.dest.exists() {
+            return Ok(State::Missing);
+        }
+        return Ok(match attrs::mismatch(&op.attrs, &op.dest)? {
+            Some(why) => State::Broken(format!("{} has {why}", op.dest.display())),
+            None => State::Holds,
+        });
+    }
+    if op.managed || (op.is_dir && cfg.no_folding) {
+        return classify_tree(&op.src, &op.dest, !op.managed, op.preserve, cfg);
+    }
+    let meta = match op.dest.symlink_metadata() {
+        Ok(meta) => meta,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(State::Missing),
+        Err(err) => return Err(err),
+    };
+    if op_in_place(op, cfg)? {
+        if let Some(drift) = drift(&op.src, &op.dest, op.preserve, cfg)? {
+            return Ok(State::Drifted(drift));
+        }
+        return Ok(State::Linked);
+    }
+    if meta.file_type().is_symlink() {
+        return Ok(State::Elsewhere(fs::read_link(&op.dest)?));
+    }
+    if same_content(&op.src, &op.dest, false)? {
+        Ok(State::Shadow)
+    } else {
+        Ok(State::Conflict)
+    }
+}
+
+fn convert(op: &Operation, cfg: &Config, operations: &mut i32) -> io::Result<()> {
+    if cfg.dry {
+        printfc!(
+            LogLevel::Info,
+            "Would replace {} with a link",
+            op.dest.display()
+        );
+        return Ok(());
+    }
+    if op.dest.is_dir() {
+        fs::remove_dir_all(&op.dest)?;
+    } else {
+        fs::remove_file(&op.dest)?;
+    }
+    let cfg = Config {
+        mode: Mode::Create,
+        ..cfg.clone()
+    };
+    execute_op(op, &cfg, operations)?;
+    Ok(())
+}
+
+// Prints the state of every entry and returns how many are out of sync
+pub fn status(
+    ops: &[(usize, Operation)],
+    cfg: &Config,
+    convert_shadows: bool,
+    operations: &mut i32,
+) -> usize {
+    report::plan_start(&cfg.file, "status", cfg.dry);
+    let mut out_of_sync = 0;
+    let mut linked = Vec::new();
+    // What was applied before, to tell a destination changed since from one
+    // in the way all along
+    let applied = state::load_applied().unwrap_or_default();
+    for (linenum, op) in ops {
+        let state = match classify(op, cfg) {
+            Ok(state) => state,
+            Err(err) => {
+                report::at_line(op.file(cfg), *linenum);
+                report::record(report::Result {
+                    source: Some(&op.src),
+                    dest: Some(&op.dest),
+                    action: "status",
+                    result: "failed",
+                    error: Some(err.to_string()),
+                });
+                printfc!(
+                    LogLevel::Error,
+                    "{}:{}: {err}",
+                    op.file(cfg).display(),
+                    linenum
+                );
+                out_of_sync += 1;
+                continue;
+            }
+        };
+        match state {
+            State::Drifted(_) | State::Elsewhere(_) => report::saw(report::Condition::Drift),
+            State::Conflict | State::Shadow | State::Missing if applied.contains_key(&op.dest) => {
+                report::saw(report::Condition::Drift)
+            }
+            State::Conflict => report::saw(report::Condition::Conflict),
+            _ => {}
+        }
+        let dest = op.dest.display();
+        report::at_line(op.file(cfg), *linenum);
+        report::record(report::Result {
+            source: Some(&op.src),
+            dest: Some(&op.dest),
+            action: "status",
+            result: state.name(),
+            error: None,
+        });
+        if !report::json() {
+            let line = match &state {
+                State::Linked => format!("linked    {dest}"),
+                State::Drifted(drift) => format!("drifted   {drift}"),
+                State::Elsewhere(target) => format!(
+                    "elsewhere {dest} {} {}",
+                    glyphs::get(Glyph::Arrow),
+                    target.display()
+                ),
+                State::Shadow => format!("shadow    {dest} (copy of its source)"),
+                State::Conflict => format!("conflict  {dest}"),
+                State::Missing => format!("missing   {dest}"),
+                State::Holds => format!("holds     {dest}"),
+                State::Broken(why) => format!("broken    {why}"),
+            };
+            let mark = glyphs::get(match state {
+                State::Linked | State::Holds => Glyph::Ok,
+                State::Conflict | State::Missing | State::Broken(_) => Glyph::Bad,
+                _ => Glyph::Warn,
+            });
+            match &op.desc {
+                Some(desc) => println!("{mark} {line} {} {desc}", glyphs::get(Glyph::Dash)),
+                None => println!("{mark} {line}"),
+            }
+        }
+        if state == State::Holds {
+            continue;
+        }
+        if state == State::Linked {
+            linked.push(&op.dest);
+            continue;
+        }
+        if convert_shadows && state == State::Shadow {
+            match convert(op, cfg, operations) {
+                Ok(()) if !cfg.dry => continue,
+                Ok(()) => {}
+                Err(err) => {
+                    printfc!(LogLevel::Error, "{dest}: {err}");
+                }
+            }
+        }
+        out_of_sync += 1;
+    }
+    // Guarded entries are as wanted on this machine, so not out of sync
+    for (file, linenum, reason) in guarded_lines(cfg).unwrap_or_default() {
+        report::at_line(&file, linenum);
+        report::record(report::Result {
+            source: None,
+            dest: None,
+            action: "status",
+            result: "guarded",
+            error: Some(reason.clone()),
+        });
+        if !report::json() {
+            println!("guarded   {}:{linenum}, {reason}", file.display());
+        }
+    }
+    // What watch gave up putting back, while it is still out of place
+    for flapping in cooldown::load().unwrap_or_default() {
+        if linked.contains(&&flapping.dest) {
+            continue;
+        }
+        report::record(report::Result {
+            source: None,
+            dest: Some(&flapping.dest),
+            action: "status",
+            result: "flapping",
+            error: None,
+        });
+        if !report::json() {
+            println!(
+                "flapping  {} (watch put it back {} times, something else keeps changing it)",
+                flapping.dest.display(),
+                flapping.fixes
+            );
+        }
+        out_of_sync += 1;
+    }
+    out_of_sync += registry::status(cfg);
+    out_of_sync += defaults::status(cfg);
+
+    if out_of_sync == 0 {
+        printfc!(LogLevel::Info, "All entries are linked");
+    } else {
+        report::saw(report::Condition::OutOfSync);
+    }
+    out_of_sync
+}
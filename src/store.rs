@@ -0,0 +1,90 @@
+// Copies of one asset at many destinations, a company's CA certificate in
+// the trust store of each program, cost its size each time. Entries marked
+// !dedup are copied through a store in the state directory instead: each
+// file is kept there once, named by its content, and hard linked to every
+// destination, so they all share one blob on disk. Where the store is on
+// another filesystem than a destination, the blob is reflinked, or copied,
+// to it. A changed source makes a new blob, and blobs no destination links
+// any more are removed at the end of the run. Modes and owners set on one
+// destination are set on all of them, as they are one file.
+
+use std::fs;
+use std::hash::{DefaultHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::{fsops, state};
+
+const STORE_DIR: &str = "store";
+
+fn dir() -> PathBuf {
+    state::state_dir().join(STORE_DIR)
+}
+
+// The blob holding the content of src, added to the store unless it is
+// there already. One edited through a destination no longer holds what its
+// name says, and is replaced, leaving the edited file to the destinations
+// that have it.
+fn blob(src: &Path) -> io::Result<PathBuf> {
+    let content = fs::read(src)?;
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&content);
+    let dir = dir();
+    let blob = dir.join(format!("{:016x}-{}", hasher.finish(), content.len()));
+    if fs::read(&blob).is_ok_and(|stored| stored == content) {
+        return Ok(blob);
+    }
+    fs::create_dir_all(&dir)?;
+    // Written aside first, so no destination ever links half a blob
+    let tmp = blob.with_extension(format!("tmp-{}", std::process::id()));
+    let _ = fs::remove_file(&tmp);
+    if let Err(err) = fsops::copy_file(src, &tmp).and_then(|()| fs::rename(&tmp, &blob)) {
+        let _ = fs::remove_file(&tmp);
+        return Err(err);
+    }
+    Ok(blob)
+}
+
+// Makes to a file of the store with the content of from, or a tree of them
+pub(crate) fn link_tree(from: &Path, to: &Path) -> io::Result<()> {
+    let meta = from.symlink_metadata()?;
+    if meta.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            link_tree(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        return Ok(());
+    }
+    // Links in a source tree are copied as they are
+    if !meta.is_file() {
+        return fsops::copy_tree(from, to, false);
+    }
+    let blob = blob(from)?;
+    if fs::hard_link(&blob, to).is_err() {
+        fsops::copy_file(&blob, to)?;
+    }
+    Ok(())
+}
+
+// Removes the blobs only the store still links. Elsewhere than on unix the
+// number of links isn't known, and blobs are kept.
+pub(crate) fn collect() -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let entries = match fs::read_dir(dir()) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.metadata()?.nlink() == 1 {
+                fs::remove_file(entry.path())?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,235 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::{bytes, parse_line, platform, raw};
+
+// Well-known programs and where they read their configuration from. Items
+// are linked under their own name, so files the program expects under
+// another name (gitconfig as ~/.gitconfig) carry the name to rename to.
+const KNOWN: &[(&str, &str)] = &[
+    (".bash_profile", "$HOME/"),
+    (".bashrc", "$HOME/"),
+    (".gitconfig", "$HOME/"),
+    (".inputrc", "$HOME/"),
+    (".profile", "$HOME/"),
+    (".tmux.conf", "$HOME/"),
+    (".vimrc", "$HOME/"),
+    (".xinitrc", "$HOME/"),
+    (".Xresources", "$HOME/"),
+    (".zprofile", "$HOME/"),
+    (".zshrc", "$HOME/"),
+    ("alacritty", "$HOME/.config/"),
+    ("bashrc", "rename to .bashrc"),
+    ("btop", "$HOME/.config/"),
+    ("dunst", "$HOME/.config/"),
+    ("fish", "$HOME/.config/"),
+    ("git", "$HOME/.config/"),
+    ("gitconfig", "rename to .gitconfig"),
+    ("helix", "$HOME/.config/"),
+    ("htop", "$HOME/.config/"),
+    ("hypr", "$HOME/.config/"),
+    ("i3", "$HOME/.config/"),
+    ("inputrc", "rename to .inputrc"),
+    ("kitty", "$HOME/.config/"),
+    ("lf", "$HOME/.config/"),
+    ("mpv", "$HOME/.config/"),
+    ("nvim", "$HOME/.config/"),
+    ("picom", "$HOME/.config/"),
+    ("polybar", "$HOME/.config/"),
+    ("ranger", "$HOME/.config/"),
+    ("rofi", "$HOME/.config/"),
+    ("starship.toml", "$HOME/.config/"),
+    ("sway", "$HOME/.config/"),
+    ("tmux", "$HOME/.config/"),
+    ("tmux.conf", "$HOME/.config/tmux/"),
+    ("vimrc", "rename to .vimrc"),
+    ("waybar", "$HOME/.config/"),
+    ("wezterm", "$HOME/.config/"),
+    ("yazi", "$HOME/.config/"),
+    ("zellij", "$HOME/.config/"),
+    ("zshrc", "rename to .zshrc"),
+];
+
+// A destination for a known item, or the hint when it can't be linked as is
+pub fn known(name: &str) -> Option<Result<&'static str, &'static str>> {
+    KNOWN
+        .iter()
+        .find(|(item, _)| *item == name)
+        .map(|(_, dest)| {
+            if dest.starts_with("rename") {
+                Err(*dest)
+            } else {
+                Ok(*dest)
+            }
+        })
+}
+
+// Where programs keep their configuration on the systems neostow runs on
+const SYSTEM_DIRS: &[&str] = &[".config/", "Library/Application Support/", ".local/share/"];
+
+// The directory under $HOME where an item of that name is already installed
+fn installed(name: &str) -> Option<&'static str> {
+    let home = platform::home();
+    SYSTEM_DIRS
+        .iter()
+        .find(|base| home.join(base).join(name).exists())
+        .copied()
+}
+
+// Where the item is already installed wins; otherwise dotfiles go straight
+// into $HOME and everything else into ~/.config, where most programs look
+pub fn propose(name: &str) -> String {
+    if let Some(base) = installed(name) {
+        return format!("$HOME/{base}");
+    }
+    if platform::home().join(name).exists() {
+        return "$HOME/".to_string();
+    }
+    if let Some(Ok(dest)) = known(name) {
+        return dest.to_string();
+    }
+    if name.starts_with('.') {
+        "$HOME/".to_string()
+    } else {
+        "$HOME/.config/".to_string()
+    }
+}
+
+// Lowercase alphanumerics only, so "Code", ".code" and "code-oss" line up
+fn normalized(name: &str) -> Vec<char> {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+pub(crate) fn distance(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + usize::from(ca != cb));
+            diagonal = above;
+        }
+    }
+    row[b.len()]
+}
+
+// 1.0 for names that only differ in case and punctuation, falling with the
+// edit distance; one name containing the other counts as a close match
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (normalized(a), normalized(b));
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+    let longest = a.len().max(b.len());
+    let score = 1.0 - distance(&a, &b) as f64 / longest as f64;
+    let contains = |x: &[char], y: &[char]| x.windows(y.len()).any(|w| w == y);
+    if contains(&a, &b) || contains(&b, &a) {
+        score.max(0.8)
+    } else {
+        score
+    }
+}
+
+// Installed configuration directories whose names resemble name, best first
+fn similar(name: &str) -> Vec<String> {
+    const THRESHOLD: f64 = 0.6;
+    let home = platform::home();
+    let mut found = Vec::new();
+    for base in SYSTEM_DIRS {
+        let Ok(entries) = fs::read_dir(home.join(base)) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(installed) = entry.file_name().into_string() else {
+                continue;
+            };
+            let score = similarity(name, &installed);
+            if score >= THRESHOLD {
+                found.push((score, format!("$HOME/{base}{installed}")));
+            }
+        }
+    }
+    found.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+    found.into_iter().take(3).map(|(_, path)| path).collect()
+}
+
+// Top-level items worth linking, skipping VCS metadata and neostow's own files
+pub fn candidates(dir: &Path) -> io::Result<Vec<(String, bool)>> {
+    let mut items = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if matches!(name.as_str(), ".git" | ".hg" | ".svn") || name.starts_with(".neostow") {
+            continue;
+        }
+        items.push((name, entry.path().is_dir()));
+    }
+    items.sort();
+    Ok(items)
+}
+
+// First path component of every source already in the file
+fn mapped(file: &Path) -> Vec<Vec<u8>> {
+    let Ok(content) = fs::read(file) else {
+        return Vec::new();
+    };
+    bytes::lines(&content)
+        .filter_map(parse_line)
+        .map(|entry| {
+            let src = raw(entry.src).unwrap_or(entry.src);
+            let src = src.strip_prefix(b"./").unwrap_or(src);
+            src.split(|&b| b == b'/').next().unwrap_or(src).to_vec()
+        })
+        .collect()
+}
+
+// Prints ready-to-paste lines for the known or installed items of basedir
+// that aren't mapped yet, listing the rest in a comment
+pub fn suggest(basedir: &Path, file: &Path) -> io::Result<()> {
+    let mapped = mapped(file);
+    let mut unknown = Vec::new();
+    for (name, is_dir) in candidates(basedir)? {
+        if mapped.iter().any(|m| m == name.as_bytes()) {
+            continue;
+        }
+        let src = if is_dir {
+            format!("{name}/")
+        } else {
+            name.clone()
+        };
+        match known(&name) {
+            Some(Ok(dest)) => println!("{src}={dest}"),
+            Some(Err(hint)) => println!("# {src}: {hint}, then map it to $HOME/"),
+            None => {
+                // Unknown programs may still be installed, under this name
+                // or one close to it
+                if let Some(base) = installed(&name) {
+                    println!("{src}=$HOME/{base}");
+                    continue;
+                }
+                let similar = similar(&name);
+                if similar.is_empty() {
+                    unknown.push(src);
+                } else {
+                    println!(
+                        "# {src}: similar to {}, rename to match",
+                        similar.join(", ")
+                    );
+                }
+            }
+        }
+    }
+    if !unknown.is_empty() {
+        println!("# no suggestion: {}", unknown.join(", "));
+    }
+    Ok(())
+}
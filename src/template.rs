@@ -0,0 +1,79 @@
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::reload::field;
+use crate::vars::{Vars, as_ident as ident};
+use crate::{bytes, section_lines};
+
+// A [vars] line gives templates a value: email = "me@example.org". Values are
+// taken as written, so per-machine ones go under @host conditions.
+fn parse_var(line: &[u8]) -> io::Result<Option<(String, OsString)>> {
+    let line = line.trim_ascii();
+    if line.is_empty() || line.starts_with(b"#") {
+        return Ok(None);
+    }
+
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "expected NAME = \"value\"");
+    let (name, rest) = bytes::split_once(line, b'=').ok_or_else(invalid)?;
+    let name = ident(name.trim_ascii()).ok_or_else(invalid)?;
+    let (value, _) = field(rest, b'#').ok_or_else(invalid)?;
+    Ok(Some((name.to_string(), bytes::to_os(value).into_owned())))
+}
+
+pub(crate) fn check_line(line: &[u8]) -> io::Result<()> {
+    parse_var(line).map(|_| ())
+}
+
+// Adds the [vars] sections of content, the text of the file at path, to vars
+pub fn parse(content: &[u8], path: &Path, vars: &mut Vars) -> io::Result<()> {
+    section_lines(content, path, b"vars", |line| {
+        vars.extend(parse_var(line)?);
+        Ok(())
+    })
+}
+
+// The text of src with each {{ NAME }} replaced by its value, from [vars] and
+// `let` first, then the environment. Braces around anything but a name are
+// left as they are.
+pub fn render(src: &Path, vars: &Vars) -> io::Result<Vec<u8>> {
+    let content = fs::read(src)?;
+    let mut out = Vec::with_capacity(content.len());
+    let mut rest = content.as_slice();
+    while let Some(start) = bytes::find(rest, b'{') {
+        let placeholder = rest[start..].strip_prefix(b"{{").and_then(|inner| {
+            let end = inner.windows(2).position(|w| w == b"}}")?;
+            Some((ident(inner[..end].trim_ascii())?, end + 4))
+        });
+        let Some((name, len)) = placeholder else {
+            out.extend_from_slice(&rest[..=start]);
+            rest = &rest[start + 1..];
+            continue;
+        };
+        let value = vars
+            .get(name)
+            .cloned()
+            .or_else(|| env::var_os(name))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{}: {{{{ {name} }}}} is not set", src.display()),
+                )
+            })?;
+        out.extend_from_slice(&rest[..start]);
+        out.extend_from_slice(&bytes::from_os(&value));
+        rest = &rest[start + len..];
+    }
+    out.extend_from_slice(rest);
+    Ok(out)
+}
+
+// Whether dest is a file holding what the template src renders to
+pub(crate) fn in_place(src: &Path, dest: &Path, vars: &Vars) -> io::Result<bool> {
+    let Ok(meta) = dest.symlink_metadata() else {
+        return Ok(false);
+    };
+    Ok(meta.is_file() && fs::read(dest)? == render(src, vars)?)
+}
@@ -0,0 +1,132 @@
+// With --trace, the time each entry takes is kept, split into looking at its
+// destination, changing it (fetching its source included) and running its
+// hooks, and the slowest entries are listed when the run ends. On a network
+// filesystem it tells apart a slow stat from a slow hook or copy.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::{Operation, report};
+
+#[derive(Clone, Copy)]
+pub(crate) enum Phase {
+    Stat,
+    Change,
+    Hooks,
+}
+
+const PHASES: [&str; 3] = ["stat", "change", "hooks"];
+
+struct Timing {
+    source: PathBuf,
+    spent: [Duration; 3],
+}
+
+impl Timing {
+    fn total(&self) -> Duration {
+        self.spent.iter().sum()
+    }
+}
+
+// How many of the slowest entries to list, none unless tracing
+static SLOWEST: AtomicUsize = AtomicUsize::new(0);
+static TIMINGS: Mutex<Option<HashMap<PathBuf, Timing>>> = Mutex::new(None);
+
+pub fn set_trace(slowest: usize) {
+    SLOWEST.store(slowest, Ordering::Relaxed);
+}
+
+fn tracing() -> bool {
+    SLOWEST.load(Ordering::Relaxed) > 0
+}
+
+// Runs f, adding the time it took to op's phase
+pub(crate) fn time<T>(op: &Operation, phase: Phase, f: impl FnOnce() -> T) -> T {
+    if !tracing() {
+        return f();
+    }
+    let started = Instant::now();
+    let result = f();
+    let spent = started.elapsed();
+    let mut timings = TIMINGS.lock().unwrap_or_else(|e| e.into_inner());
+    let timing = timings
+        .get_or_insert_default()
+        .entry(op.dest.clone())
+        .or_insert_with(|| Timing {
+            source: op.src.clone(),
+            spent: [Duration::ZERO; 3],
+        });
+    timing.spent[phase as usize] += spent;
+    result
+}
+
+// Every entry timed, slowest first
+fn by_time() -> Vec<(PathBuf, Timing)> {
+    let mut timings: Vec<_> = TIMINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    timings.sort_by(|a, b| b.1.total().cmp(&a.1.total()).then_with(|| a.0.cmp(&b.0)));
+    timings
+}
+
+fn ms(spent: Duration) -> String {
+    format!("{:.3}", spent.as_secs_f64() * 1000.0)
+}
+
+// The timings as a member of the JSON report, or nothing when not tracing
+pub(crate) fn json() -> String {
+    if !tracing() {
+        return String::new();
+    }
+    let entries: Vec<String> = by_time()
+        .iter()
+        .map(|(dest, timing)| {
+            let phases: Vec<String> = PHASES
+                .iter()
+                .zip(timing.spent)
+                .map(|(name, spent)| format!("\"{name}_ms\":{}", ms(spent)))
+                .collect();
+            format!(
+                "{{\"source\":{},\"dest\":{},\"total_ms\":{},{}}}",
+                report::path(Some(&timing.source)),
+                report::path(Some(dest)),
+                ms(timing.total()),
+                phases.join(",")
+            )
+        })
+        .collect();
+    format!(",\"trace\":[{}]", entries.join(","))
+}
+
+// Lists the slowest entries after the run's summary
+pub(crate) fn print() {
+    if !tracing() {
+        return;
+    }
+    let timings = by_time();
+    if timings.is_empty() {
+        return;
+    }
+    let slowest = SLOWEST.load(Ordering::Relaxed).min(timings.len());
+    println!("Slowest {slowest} of {} entries:", timings.len());
+    for (dest, timing) in &timings[..slowest] {
+        let phases: Vec<String> = PHASES
+            .iter()
+            .zip(timing.spent)
+            .map(|(name, spent)| format!("{name} {}ms", ms(spent)))
+            .collect();
+        println!(
+            "  {:>10}ms  {} ({})",
+            ms(timing.total()),
+            dest.display(),
+            phases.join(", ")
+        );
+    }
+}
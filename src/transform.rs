@@ -0,0 +1,195 @@
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::Path;
+
+use crate::regex::Regex;
+use crate::reload::field;
+use crate::{bytes, cond, hooks, section_header};
+
+// A [transform] line renames source files and directories on their way to
+// the destination, for repos that store dotfiles as `_bashrc`: "^_" = "."
+// The unquoted keys prefix and suffix add to every name: prefix = "."
+// Rules can also be listed on one line, outside the section:
+//   transform = [{ match = "^_", replace = "." }, { match = "^dot-", replace = "." }]
+#[derive(Clone)]
+pub struct Rule {
+    pattern: Regex,
+    replacement: Vec<u8>,
+}
+
+fn parse_rule(line: &[u8]) -> io::Result<Option<Rule>> {
+    let line = line.trim_ascii();
+    if line.is_empty() || line.starts_with(b"#") {
+        return Ok(None);
+    }
+
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected \"pattern\" = \"replacement\"",
+        )
+    };
+    let quoted = line.starts_with(b"\"") || line.starts_with(b"'");
+    let (pattern, rest) = field(line, b'=').ok_or_else(invalid)?;
+    let rest = rest
+        .trim_ascii_start()
+        .strip_prefix(b"=")
+        .ok_or_else(invalid)?;
+    // The replacement may be empty to strip what matched
+    let (replacement, _) = field(rest, b'#').ok_or_else(invalid)?;
+    if pattern.is_empty() {
+        return Err(invalid());
+    }
+
+    let pattern: &[u8] = match pattern {
+        b"prefix" if !quoted => b"^",
+        b"suffix" if !quoted => b"$",
+        _ => pattern,
+    };
+    Ok(Some(Rule {
+        pattern: Regex::new(pattern)?,
+        replacement: replacement.to_vec(),
+    }))
+}
+
+// What follows the [ of a `transform = [...]` line
+pub(crate) fn list(line: &[u8]) -> Option<&[u8]> {
+    line.trim_ascii()
+        .strip_prefix(b"transform")?
+        .trim_ascii_start()
+        .strip_prefix(b"=")?
+        .trim_ascii_start()
+        .strip_prefix(b"[")
+}
+
+// The rules of a transform = [...] line, given what list returned
+fn parse_list(mut rest: &[u8]) -> io::Result<Vec<Rule>> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected transform = [{ match = \"pattern\", replace = \"replacement\" }, ...]",
+        )
+    };
+    let mut rules = Vec::new();
+    loop {
+        rest = rest.trim_ascii_start();
+        if let Some(after) = rest.strip_prefix(b"]") {
+            let after = after.trim_ascii_start();
+            if !after.is_empty() && !after.starts_with(b"#") {
+                return Err(invalid());
+            }
+            return Ok(rules);
+        }
+        rest = rest.strip_prefix(b"{").ok_or_else(invalid)?;
+        let (mut pattern, mut replacement) = (None, None);
+        loop {
+            rest = rest.trim_ascii_start();
+            if let Some(after) = rest.strip_prefix(b"}") {
+                rest = after;
+                break;
+            }
+            let (key, value) = bytes::split_once(rest, b'=').ok_or_else(invalid)?;
+            let value = value.trim_ascii_start();
+            if !value.starts_with(b"\"") && !value.starts_with(b"'") {
+                return Err(invalid());
+            }
+            let (value, after) = field(value, b',').ok_or_else(invalid)?;
+            match key.trim_ascii() {
+                b"match" => pattern = Some(value),
+                b"replace" => replacement = Some(value),
+                key => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "unknown key {} in a transform rule, expected match or replace",
+                            String::from_utf8_lossy(key)
+                        ),
+                    ));
+                }
+            }
+            rest = after.trim_ascii_start();
+            if let Some(after) = rest.strip_prefix(b",") {
+                rest = after;
+            } else if !rest.starts_with(b"}") {
+                return Err(invalid());
+            }
+        }
+        let (Some(pattern), Some(replacement)) = (pattern, replacement) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "a transform rule needs a match and a replace",
+            ));
+        };
+        if pattern.is_empty() {
+            return Err(invalid());
+        }
+        rules.push(Rule {
+            pattern: Regex::new(pattern)?,
+            replacement: replacement.to_vec(),
+        });
+        rest = rest.trim_ascii_start();
+        if let Some(after) = rest.strip_prefix(b",") {
+            rest = after;
+        } else if !rest.starts_with(b"]") {
+            return Err(invalid());
+        }
+    }
+}
+
+// Reads the [transform] sections and transform = [...] lines of content, the
+// text of the file at path, in file order
+pub fn parse(content: &[u8], path: &Path) -> io::Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    let mut inside = false;
+    let mut unmet = false;
+    for (i, line) in bytes::lines(content).enumerate() {
+        let result = if let Some(header) = section_header(line) {
+            inside = header.trim_ascii() == b"transform";
+            unmet = false;
+            Ok(())
+        } else if cond::is_directive(line) {
+            cond::evaluate(line).map(|reason| unmet = reason.is_some())
+        } else if unmet {
+            Ok(())
+        } else if let Some(rest) = list(line) {
+            parse_list(rest).map(|list| rules.extend(list))
+        } else if inside && !hooks::is_hook(line) {
+            parse_rule(line).map(|rule| rules.extend(rule))
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{}:{}: {err}", path.display(), i + 1),
+            ));
+        }
+    }
+    Ok(rules)
+}
+
+pub fn affix(name: &OsStr, prefix: &[u8], suffix: &[u8]) -> OsString {
+    bytes::to_os_string([prefix, &bytes::from_os(name), suffix].concat())
+}
+
+// The destination name of a source named name. Rules apply in file order,
+// each replacing its first match in what the previous ones left.
+pub fn apply(rules: &[Rule], name: &OsStr) -> io::Result<OsString> {
+    let mut out = bytes::from_os(name).into_owned();
+    for rule in rules {
+        if let Some((start, end)) = rule.pattern.find(&out) {
+            out.splice(start..end, rule.replacement.iter().copied());
+        }
+    }
+    if out.is_empty() || out.contains(&b'/') || out == b"." || out == b".." {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "transform renames {} to {:?}, which is not a file name",
+                name.display(),
+                String::from_utf8_lossy(&out)
+            ),
+        ));
+    }
+    Ok(bytes::to_os_string(out))
+}
@@ -0,0 +1,43 @@
+use std::fs;
+use std::hash::{DefaultHasher, Hasher};
+use std::io;
+use std::path::Path;
+
+use crate::{Config, Mode, Operation, provenance};
+
+pub fn content_hash(path: &Path) -> io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&fs::read(path)?);
+    Ok(hasher.finish())
+}
+
+// A link replaced by a real file (editors saving through a rename do this)
+// may have been edited there while the source moved on too. Overwriting would
+// then silently drop the destination's changes, so unless --force makes the
+// source authoritative, both sides have to be reconciled by hand first.
+pub fn check(op: &Operation, cfg: &Config, recorded: Option<u64>) -> io::Result<()> {
+    // A rendered template never matches its source to begin with
+    if !matches!(cfg.mode, Mode::Overwrite) || cfg.force || op.is_dir || op.template.is_some() {
+        return Ok(());
+    }
+    let Some(recorded) = recorded else {
+        return Ok(());
+    };
+    if !op.dest.symlink_metadata().is_ok_and(|meta| meta.is_file()) {
+        return Ok(());
+    }
+
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&provenance::strip(&fs::read(&op.dest)?));
+    if content_hash(&op.src)? != recorded && hasher.finish() != recorded {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!(
+                "two-way conflict: {} and {} both changed since the last apply (reconcile them, or use --force to keep the source)",
+                op.src.display(),
+                op.dest.display()
+            ),
+        ));
+    }
+    Ok(())
+}
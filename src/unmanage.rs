@@ -0,0 +1,75 @@
+// `unmanage PATH` marks a destination neostow never touches, even where a
+// glob or a managed directory would otherwise reach it:
+//   unmanage ~/.config/some-app   # keeps its own state there
+// Entries whose destination is PATH or under it are left out of the plan,
+// and managed and unfolded directories neither link nor collect anything
+// there. Like entries, these lines follow @host/@os/@env conditions.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::expand::{expand_path, normalize};
+use crate::reload::field;
+use crate::vars::Vars;
+use crate::{bytes, cond, section_header};
+
+// The raw path of an unmanage line
+pub fn parse_line(line: &[u8]) -> Option<&[u8]> {
+    let rest = line.trim_ascii().strip_prefix(b"unmanage")?;
+    if !rest.first().is_some_and(u8::is_ascii_whitespace) {
+        return None;
+    }
+    field(rest, b'#').map(|(path, _)| path)
+}
+
+fn resolve(raw: &[u8], vars: &Vars) -> io::Result<PathBuf> {
+    if raw.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "expected unmanage PATH",
+        ));
+    }
+    Ok(absolute(&expand_path(raw, vars)?))
+}
+
+// Where there is no current directory (wasm32), paths are compared as written
+fn absolute(path: &Path) -> PathBuf {
+    normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()))
+}
+
+pub fn parse(content: &[u8], path: &Path, vars: &Vars) -> io::Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let mut unmet = false;
+    for (i, line) in bytes::lines(content).enumerate() {
+        let result = if section_header(line).is_some() {
+            unmet = false;
+            Ok(())
+        } else if let Some(raw) = parse_line(line) {
+            if unmet {
+                Ok(())
+            } else {
+                resolve(raw, vars).map(|dest| paths.push(dest))
+            }
+        } else if cond::is_directive(line) {
+            cond::evaluate(line).map(|reason| unmet = reason.is_some())
+        } else {
+            Ok(())
+        };
+        if let Err(err) = result {
+            return Err(io::Error::new(
+                err.kind(),
+                format!("{}:{}: {err}", path.display(), i + 1),
+            ));
+        }
+    }
+    Ok(paths)
+}
+
+// Whether dest is one of the unmanaged paths or inside one
+pub fn covers(unmanaged: &[PathBuf], dest: &Path) -> bool {
+    if unmanaged.is_empty() {
+        return false;
+    }
+    let dest = absolute(dest);
+    unmanaged.iter().any(|path| dest.starts_with(path))
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::expand::expand_path;
+use crate::{age, bytes, cond, overlay, section_header, selects, shell};
+
+pub type Vars = HashMap<String, OsString>;
+
+pub(crate) fn as_ident(name: &[u8]) -> Option<&str> {
+    let (first, rest) = name.split_first()?;
+    if !(first.is_ascii_alphabetic() || *first == b'_')
+        || !rest.iter().all(|c| c.is_ascii_alphanumeric() || *c == b'_')
+    {
+        return None;
+    }
+    std::str::from_utf8(name).ok()
+}
+
+// Matches `let NAME = VALUE`, returning the name and the raw value
+pub fn parse_let(line: &[u8]) -> Option<(&str, &[u8])> {
+    let rest = line.trim_ascii().strip_prefix(b"let ")?;
+    let (name, value) = bytes::split_once(rest, b'=')?;
+    let name = as_ident(name.trim_ascii())?;
+
+    let value = value.trim_ascii();
+    if value.starts_with(b"$(") {
+        let end = value.iter().rposition(|&b| b == b')')?;
+        return Some((name, &value[..=end]));
+    }
+    match bytes::find(value, b'#') {
+        Some(comment_start) => Some((name, value[..comment_start].trim_ascii())),
+        None => Some((name, value)),
+    }
+}
+
+// What each command run by a `let` printed, so loading the file again, as
+// watch does on every change, doesn't run it again
+static CAPTURED: Mutex<Option<HashMap<Vec<u8>, OsString>>> = Mutex::new(None);
+
+fn capture(name: &str, cmd: &[u8]) -> io::Result<OsString> {
+    let mut captured = CAPTURED.lock().unwrap_or_else(|e| e.into_inner());
+    let captured = captured.get_or_insert_default();
+    if let Some(value) = captured.get(cmd) {
+        return Ok(value.clone());
+    }
+    let key = cmd.to_vec();
+    let cmd = bytes::to_os(cmd);
+    let output = shell::command(&cmd).output()?;
+
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "let {name}: `{}` exited with {}",
+            cmd.display(),
+            output.status
+        )));
+    }
+    let mut stdout = output.stdout;
+    while stdout.last().is_some_and(|b| matches!(b, b'\n' | b'\r')) {
+        stdout.pop();
+    }
+    let value = bytes::to_os_string(stdout);
+    captured.insert(key, value.clone());
+    Ok(value)
+}
+
+pub fn load(path: &Path) -> io::Result<Vars> {
+    parse(&age::read(path)?, Vars::new(), &[], &[])
+}
+
+// Evaluates every `let` directive once, in file order, so later values and
+// all entries can refer to earlier ones. vars holds values set beforehand.
+// A `let` under an unmet condition, or in the section of a package left out
+// by packages or disabled, is passed over like the entries around it.
+pub fn parse(
+    content: &[u8],
+    mut vars: Vars,
+    packages: &[String],
+    disabled: &[String],
+) -> io::Result<Vars> {
+    let mut section: Option<&[u8]> = None;
+    let mut unmet = false;
+    for line in bytes::lines(content) {
+        if let Some(name) = section_header(line) {
+            section = Some(name.trim_ascii());
+            unmet = false;
+            continue;
+        }
+        // Conditions that can't be read are reported by the run
+        if cond::is_directive(line) {
+            unmet = !matches!(cond::evaluate(line), Ok(None));
+            continue;
+        }
+        let Some((name, value)) = parse_let(line) else {
+            continue;
+        };
+        let left_out = section.is_some_and(|name| {
+            !overlay::RESERVED.contains(&name)
+                && if packages.is_empty() {
+                    selects(disabled, name)
+                } else {
+                    !selects(packages, name)
+                }
+        });
+        if unmet || left_out {
+            continue;
+        }
+        let value = match value.strip_prefix(b"$(") {
+            Some(cmd) => capture(name, &cmd[..cmd.len() - 1])?,
+            None => expand_path(value, &vars)?.into_os_string(),
+        };
+        vars.insert(name.to_string(), value);
+    }
+    Ok(vars)
+}
@@ -0,0 +1,163 @@
+// The version control the dotfiles are kept in, which plan-diff --rev reads
+// old versions of the neostow file from. The repository the file is in
+// decides, by the .jj, .hg or .git directory found from its directory up, a
+// colocated jj repository being jj; vcs = "jj" in config.toml names one
+// instead. Git is what is assumed outside any repository.
+
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// A version control system a neostow file can be kept in.
+pub trait Vcs {
+    /// Its name, as vcs in config.toml takes it.
+    fn name(&self) -> &'static str;
+    /// The revision the working copy stands on, what an old revision left
+    /// out defaults to.
+    fn base(&self) -> &'static str;
+    /// The content of the file at path as of the revision rev.
+    fn show(&self, path: &Path, rev: &str) -> io::Result<Vec<u8>>;
+}
+
+/// Git.
+pub struct Git;
+
+/// Jujutsu, whose working copy is a commit of its own, @.
+pub struct Jj;
+
+/// Mercurial.
+pub struct Hg;
+
+// The metadata directory each keeps at the top of a repository, in the
+// order they are looked for
+pub(crate) const METADATA: [(&str, Kind); 3] =
+    [(".jj", Kind::Jj), (".hg", Kind::Hg), (".git", Kind::Git)];
+
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Kind {
+    Git = 1,
+    Jj,
+    Hg,
+}
+
+impl Kind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "git" => Some(Kind::Git),
+            "jj" => Some(Kind::Jj),
+            "hg" => Some(Kind::Hg),
+            _ => None,
+        }
+    }
+}
+
+// What config.toml named, 0 when the repository decides
+static CHOSEN: AtomicU8 = AtomicU8::new(0);
+
+/// Uses kind for every repository instead of finding out which it is.
+pub fn set_vcs(kind: Kind) {
+    CHOSEN.store(kind as u8, Ordering::Relaxed);
+}
+
+fn kind(dir: &Path) -> Kind {
+    match CHOSEN.load(Ordering::Relaxed) {
+        1 => return Kind::Git,
+        2 => return Kind::Jj,
+        3 => return Kind::Hg,
+        _ => {}
+    }
+    let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+    dir.ancestors()
+        .find_map(|dir| {
+            METADATA
+                .iter()
+                .find(|(name, _)| dir.join(name).exists())
+                .map(|&(_, kind)| kind)
+        })
+        .unwrap_or(Kind::Git)
+}
+
+/// The version control the repository holding dir is kept in.
+pub fn of(dir: &Path) -> &'static dyn Vcs {
+    match kind(dir) {
+        Kind::Git => &Git,
+        Kind::Jj => &Jj,
+        Kind::Hg => &Hg,
+    }
+}
+
+// What program prints run with args in dir, which an error names the
+// command of
+fn output(program: &str, args: &[&str], dir: &Path) -> io::Result<Vec<u8>> {
+    let output = Command::new(program)
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .map_err(|err| io::Error::new(err.kind(), format!("{program}: {err}")))?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{program} {}: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+// The directory of path, to run in, and its name, relative to that
+fn split(path: &Path) -> (&Path, String) {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty());
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    (dir.unwrap_or(Path::new(".")), name.into_owned())
+}
+
+impl Vcs for Git {
+    fn name(&self) -> &'static str {
+        "git"
+    }
+
+    fn base(&self) -> &'static str {
+        "HEAD"
+    }
+
+    fn show(&self, path: &Path, rev: &str) -> io::Result<Vec<u8>> {
+        let (dir, name) = split(path);
+        output("git", &["show", &format!("{rev}:./{name}")], dir)
+    }
+}
+
+impl Vcs for Jj {
+    fn name(&self) -> &'static str {
+        "jj"
+    }
+
+    // @ is the working copy itself, so the last change is its parent
+    fn base(&self) -> &'static str {
+        "@-"
+    }
+
+    fn show(&self, path: &Path, rev: &str) -> io::Result<Vec<u8>> {
+        let (dir, name) = split(path);
+        // A fileset, whose file: is relative to the directory it runs in
+        let name = format!("file:{name:?}");
+        output("jj", &["file", "show", "-r", rev, "--", &name], dir)
+    }
+}
+
+impl Vcs for Hg {
+    fn name(&self) -> &'static str {
+        "hg"
+    }
+
+    fn base(&self) -> &'static str {
+        "."
+    }
+
+    fn show(&self, path: &Path, rev: &str) -> io::Result<Vec<u8>> {
+        let (dir, name) = split(path);
+        let name = format!("relpath:{name}");
+        output("hg", &["cat", "-r", rev, "--", &name], dir)
+    }
+}
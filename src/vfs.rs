@@ -0,0 +1,356 @@
+// The filesystem as neostow sees it, behind a trait so a plan can be
+// computed against a description of another machine, e.g. by a previewer
+// compiled to wasm32 that has no filesystem of its own, and executed against
+// a tree in memory, where a dry run can be shown to change nothing. What an
+// entry makes and removes, and the checks that decide it, go through the
+// trait; what only a real filesystem has, like metadata to preserve, hard
+// links and the content store, is left to std.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::expand::normalize;
+use crate::{fsops, journal, meta};
+
+/// What is at a path, not following a link there.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Node {
+    File,
+    Dir,
+    /// A symlink, with its target as written
+    Link(PathBuf),
+}
+
+pub trait Fs: Send + Sync {
+    /// Whether anything is at path, following links.
+    fn exists(&self, path: &Path) -> bool;
+
+    /// Whether path is a directory, following links.
+    fn is_dir(&self, path: &Path) -> bool;
+
+    /// The names in a directory, each with whether it is a directory itself
+    /// (not following links, so a walk can't loop).
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(OsString, bool)>>;
+
+    fn metadata(&self, path: &Path) -> io::Result<Node>;
+
+    /// Makes the directory path and any parents missing.
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+
+    /// Writes content to a new file at path, failing if something is there.
+    fn create(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+
+    /// Makes a link at link to target. is_dir only matters on Windows.
+    fn symlink(&self, target: &Path, link: &Path, is_dir: bool) -> io::Result<()>;
+
+    /// Removes a file or link, or a directory with everything in it.
+    fn remove(&self, path: &Path) -> io::Result<()>;
+
+    /// Moves from to to, whose parent must exist.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Copies the file or tree at from to to, or with hardlink links every
+    /// file of it instead.
+    fn copy(&self, from: &Path, to: &Path, hardlink: bool) -> io::Result<()>;
+}
+
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir()
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(OsString, bool)>> {
+        let mut children = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            children.push((entry.file_name(), entry.file_type()?.is_dir()));
+        }
+        Ok(children)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Node> {
+        let file_type = path.symlink_metadata()?.file_type();
+        Ok(if file_type.is_symlink() {
+            Node::Link(fs::read_link(path)?)
+        } else if file_type.is_dir() {
+            Node::Dir
+        } else {
+            Node::File
+        })
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+
+    fn create(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        let mut file = fs::File::create_new(path)?;
+        if let Err(err) = file.write_all(content) {
+            let _ = fs::remove_file(path);
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn symlink(&self, target: &Path, link: &Path, is_dir: bool) -> io::Result<()> {
+        journal::make_link(target, link, is_dir)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }
+    }
+
+    // Copies when the target is on another filesystem, with everything a
+    // rename would have kept
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        match fs::rename(from, to) {
+            Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+                fsops::copy_tree(from, to, false)?;
+                // Filesystems without extended attributes simply lose them
+                match meta::apply(from, to, meta::Preserve::everything()) {
+                    Err(err) if err.kind() == io::ErrorKind::Unsupported => {}
+                    result => result?,
+                }
+                self.remove(from)
+            }
+            result => result,
+        }
+    }
+
+    fn copy(&self, from: &Path, to: &Path, hardlink: bool) -> io::Result<()> {
+        fsops::copy_tree(from, to, hardlink)
+    }
+}
+
+/// A tree of files, directories and links held in memory.
+#[derive(Default)]
+pub struct MemoryFs {
+    // Every path with what is there; parents are always present
+    entries: Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+// Links followed before giving up, as the kernel does
+const MAX_LINKS: usize = 40;
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} does not exist", path.display()),
+    )
+}
+
+impl MemoryFs {
+    pub fn new() -> Self {
+        MemoryFs::default()
+    }
+
+    fn entries(&self) -> MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn add(&mut self, path: &Path, node: Node) {
+        let entries = self.entries.get_mut().unwrap_or_else(|e| e.into_inner());
+        for ancestor in path.ancestors().skip(1) {
+            if ancestor.as_os_str().is_empty() {
+                break;
+            }
+            entries.insert(ancestor.to_path_buf(), Node::Dir);
+        }
+        entries.insert(path.to_path_buf(), node);
+    }
+
+    pub fn add_file(&mut self, path: impl AsRef<Path>) {
+        self.add(path.as_ref(), Node::File);
+    }
+
+    pub fn add_dir(&mut self, path: impl AsRef<Path>) {
+        self.add(path.as_ref(), Node::Dir);
+    }
+
+    pub fn add_link(&mut self, path: impl AsRef<Path>, target: impl AsRef<Path>) {
+        self.add(path.as_ref(), Node::Link(target.as_ref().to_path_buf()));
+    }
+
+    /// Every path in the tree, with what is there.
+    pub fn nodes(&self) -> Vec<(PathBuf, Node)> {
+        self.entries()
+            .iter()
+            .map(|(path, node)| (path.clone(), node.clone()))
+            .collect()
+    }
+
+    // What path leads to once links are followed
+    fn resolve(entries: &BTreeMap<PathBuf, Node>, path: &Path) -> Option<Node> {
+        let mut path = path.to_path_buf();
+        for _ in 0..MAX_LINKS {
+            match entries.get(&path)? {
+                Node::Link(target) => {
+                    path = normalize(&path.parent().unwrap_or(Path::new("")).join(target));
+                }
+                node => return Some(node.clone()),
+            }
+        }
+        None
+    }
+
+    // Fails unless path is free and its parent a directory
+    fn vacant(entries: &BTreeMap<PathBuf, Node>, path: &Path) -> io::Result<()> {
+        if entries.contains_key(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        match path.parent() {
+            Some(parent)
+                if !parent.as_os_str().is_empty()
+                    && Self::resolve(entries, parent) != Some(Node::Dir) =>
+            {
+                Err(not_found(parent))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Where path, under from, goes when from goes to to
+    fn moved(path: &Path, from: &Path, to: &Path) -> PathBuf {
+        match path.strip_prefix(from) {
+            Ok(rest) if !rest.as_os_str().is_empty() => to.join(rest),
+            _ => to.to_path_buf(),
+        }
+    }
+
+    // path and everything under it
+    fn subtree(entries: &BTreeMap<PathBuf, Node>, path: &Path) -> Vec<(PathBuf, Node)> {
+        entries
+            .range(path.to_path_buf()..)
+            .take_while(|(child, _)| child.starts_with(path))
+            .map(|(child, node)| (child.clone(), node.clone()))
+            .collect()
+    }
+}
+
+impl Fs for MemoryFs {
+    fn exists(&self, path: &Path) -> bool {
+        Self::resolve(&self.entries(), path).is_some()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        Self::resolve(&self.entries(), path) == Some(Node::Dir)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<(OsString, bool)>> {
+        let entries = self.entries();
+        if entries.get(path) != Some(&Node::Dir) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not a directory", path.display()),
+            ));
+        }
+        Ok(entries
+            .range(path.to_path_buf()..)
+            .skip(1)
+            .take_while(|(child, _)| child.starts_with(path))
+            .filter(|(child, _)| child.parent() == Some(path))
+            .filter_map(|(child, node)| {
+                Some((child.file_name()?.to_os_string(), *node == Node::Dir))
+            })
+            .collect())
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Node> {
+        self.entries()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let mut entries = self.entries();
+        let mut missing = Vec::new();
+        for dir in path.ancestors() {
+            if dir.as_os_str().is_empty() {
+                break;
+            }
+            match Self::resolve(&entries, dir) {
+                Some(Node::Dir) => break,
+                Some(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!("{} is not a directory", dir.display()),
+                    ));
+                }
+                None => missing.push(dir.to_path_buf()),
+            }
+        }
+        for dir in missing {
+            entries.insert(dir, Node::Dir);
+        }
+        Ok(())
+    }
+
+    fn create(&self, path: &Path, _content: &[u8]) -> io::Result<()> {
+        let mut entries = self.entries();
+        Self::vacant(&entries, path)?;
+        entries.insert(path.to_path_buf(), Node::File);
+        Ok(())
+    }
+
+    fn symlink(&self, target: &Path, link: &Path, _is_dir: bool) -> io::Result<()> {
+        let mut entries = self.entries();
+        Self::vacant(&entries, link)?;
+        entries.insert(link.to_path_buf(), Node::Link(target.to_path_buf()));
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut entries = self.entries();
+        if !entries.contains_key(path) {
+            return Err(not_found(path));
+        }
+        for (child, _) in Self::subtree(&entries, path) {
+            entries.remove(&child);
+        }
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut entries = self.entries();
+        if !entries.contains_key(from) {
+            return Err(not_found(from));
+        }
+        Self::vacant(&entries, to)?;
+        for (child, node) in Self::subtree(&entries, from) {
+            entries.remove(&child);
+            entries.insert(Self::moved(&child, from, to), node);
+        }
+        Ok(())
+    }
+
+    // Hard links are copies here, having no content to share
+    fn copy(&self, from: &Path, to: &Path, _hardlink: bool) -> io::Result<()> {
+        let mut entries = self.entries();
+        if Self::resolve(&entries, from).is_none() {
+            return Err(not_found(from));
+        }
+        Self::vacant(&entries, to)?;
+        for (child, node) in Self::subtree(&entries, from) {
+            entries.insert(Self::moved(&child, from, to), node);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+// Entries a run passes over until something on the machine changes: those
+// under a condition that doesn't hold, and !require-target ones whose
+// directory doesn't exist yet. Each run records them in the state directory,
+// so the run that finally applies one, from `watch` or the service, says
+// what changed, and `watch` applies again as soon as a directory shows up.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::{Config, LogLevel, Mode, bytes, state};
+
+const WAITING_FILE: &str = "waiting";
+
+enum Wait {
+    // The condition line the entry is under
+    Cond(String),
+    // The directory the destination goes in
+    Dir(PathBuf),
+}
+
+// An entry, by its line or its destination, and what it waits on
+struct Waiting {
+    entry: String,
+    wait: Wait,
+}
+
+// What this run found waiting, and what the last one did
+static NOW: Mutex<Vec<Waiting>> = Mutex::new(Vec::new());
+static BEFORE: Mutex<Vec<Waiting>> = Mutex::new(Vec::new());
+
+fn load() -> io::Result<Vec<Waiting>> {
+    let Some(content) = state::read_file(&state::host_dir().join(WAITING_FILE))? else {
+        return Ok(Vec::new());
+    };
+    let mut found = Vec::new();
+    for line in bytes::lines(&content) {
+        let mut fields = line.splitn(3, |&b| b == b'\t');
+        let (Some(kind), Some(on), Some(entry)) = (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let wait = match kind {
+            b"cond" => Wait::Cond(String::from_utf8_lossy(on).into_owned()),
+            b"dir" => Wait::Dir(PathBuf::from(bytes::to_os(on).into_owned())),
+            _ => continue,
+        };
+        found.push(Waiting {
+            entry: String::from_utf8_lossy(entry).into_owned(),
+            wait,
+        });
+    }
+    Ok(found)
+}
+
+// Only runs that link look at what waits, and dry ones keep it to themselves
+fn tracked(cfg: &Config) -> bool {
+    !cfg.dry && !matches!(cfg.mode, Mode::Delete)
+}
+
+// Takes in what the last run left waiting, before a run starts
+pub(crate) fn start(cfg: &Config) {
+    NOW.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    let before = if tracked(cfg) {
+        load().unwrap_or_else(|err| {
+            printfc!(LogLevel::Warn, "Could not read what waits: {err}");
+            Vec::new()
+        })
+    } else {
+        Vec::new()
+    };
+    *BEFORE.lock().unwrap_or_else(|e| e.into_inner()) = before;
+}
+
+fn wait(cfg: &Config, entry: String, wait: Wait) {
+    if tracked(cfg) {
+        NOW.lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(Waiting { entry, wait });
+    }
+}
+
+// Notes that the entry on line is skipped while the condition on gate
+// doesn't hold
+pub(crate) fn wait_for_condition(cfg: &Config, line: &[u8], gate: &[u8]) {
+    let cond = String::from_utf8_lossy(gate.trim_ascii()).into_owned();
+    wait(
+        cfg,
+        String::from_utf8_lossy(line.trim_ascii()).into_owned(),
+        Wait::Cond(cond),
+    );
+}
+
+pub(crate) fn wait_for_dir(cfg: &Config, dest: &Path, dir: &Path) {
+    wait(
+        cfg,
+        dest.display().to_string(),
+        Wait::Dir(dir.to_path_buf()),
+    );
+}
+
+// Says what changed when entry, a line or a destination the last run left
+// waiting, is applied now
+pub(crate) fn resume(cfg: &Config, entry: &str) {
+    if !tracked(cfg) {
+        return;
+    }
+    let mut before = BEFORE.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(k) = before.iter().position(|waiting| waiting.entry == entry) else {
+        return;
+    };
+    match &before.remove(k).wait {
+        Wait::Cond(cond) => {
+            printfc!(LogLevel::Info, "{cond} holds now, applying {entry}");
+        }
+        Wait::Dir(dir) => {
+            printfc!(
+                LogLevel::Info,
+                "{} exists now, applying {entry}",
+                dir.display()
+            );
+        }
+    }
+}
+
+// Records what still waits after a run. One of some packages only replaces
+// the entries it came across, and leaves the rest waiting.
+pub(crate) fn save(cfg: &Config) -> io::Result<()> {
+    if !tracked(cfg) {
+        return Ok(());
+    }
+    let mut now = NOW.lock().unwrap_or_else(|e| e.into_inner());
+    if !cfg.packages.is_empty() {
+        let before = std::mem::take(&mut *BEFORE.lock().unwrap_or_else(|e| e.into_inner()));
+        for waiting in before {
+            if !now.iter().any(|seen| seen.entry == waiting.entry) {
+                now.push(waiting);
+            }
+        }
+    }
+
+    let path = state::host_dir().join(WAITING_FILE);
+    if now.is_empty() {
+        return state::remove_file(&path);
+    }
+    let mut out = Vec::new();
+    for waiting in now.iter() {
+        match &waiting.wait {
+            Wait::Cond(cond) => write!(out, "cond\t{cond}\t")?,
+            Wait::Dir(dir) => {
+                out.write_all(b"dir\t")?;
+                out.write_all(&bytes::from_os(dir.as_os_str()))?;
+                out.write_all(b"\t")?;
+            }
+        }
+        writeln!(out, "{}", waiting.entry)?;
+    }
+    state::write_file(&path, &out)
+}
+
+// A directory some entry of the last run waits on that exists now. The
+// conditions of entries can't change while neostow runs, so only those are
+// looked at.
+pub(crate) fn ready() -> Option<PathBuf> {
+    NOW.lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find_map(|waiting| match &waiting.wait {
+            Wait::Dir(dir) if dir.is_dir() => Some(dir.clone()),
+            _ => None,
+        })
+}
@@ -0,0 +1,339 @@
+// `neostow watch` applies the file, then polls it, the files it includes and
+// everything under its directory, and applies again whenever any of them is
+// added, removed or modified, or when the directory an !require-target entry
+// waits on is made, as installing its program does. Polling needs nothing beyond std and works the
+// same on every platform, at the cost of a walk of the repo each interval.
+//
+// When the file or one it includes changes, or on SIGHUP, it is parsed and
+// planned again and only the difference from the last plan is applied, so
+// entries dropped from it are unlinked and the others are left alone. A
+// file that no longer loads or plans keeps the last one in effect.
+//
+// Destinations are polled too, and one that something else changed is put
+// back, holding off more and more if it keeps changing (see cooldown).
+
+use std::collections::BTreeMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use crate::cooldown::{self, Verdict};
+use crate::plan_diff::PlanDiff;
+use crate::{Config, LogLevel, Operation, age, plan_lines, run, status, vars, vcs, waiting};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// What is seen of a path: its modification time and size
+type Snapshot = BTreeMap<PathBuf, (Option<SystemTime>, u64)>;
+
+fn walk(path: &Path, snapshot: &mut Snapshot) {
+    let Ok(meta) = path.symlink_metadata() else {
+        return;
+    };
+    snapshot.insert(path.to_path_buf(), (meta.modified().ok(), meta.len()));
+    let metadata = |name: &OsStr| vcs::METADATA.iter().any(|(dir, _)| name == *dir);
+    if !meta.is_dir() || path.file_name().is_some_and(metadata) {
+        return;
+    }
+    let Ok(entries) = fs::read_dir(path) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        walk(&entry.path(), snapshot);
+    }
+}
+
+fn files(cfg: &Config, out: &mut Vec<PathBuf>) {
+    out.push(cfg.file.clone());
+    for (_, child) in &cfg.includes {
+        files(child, out);
+    }
+}
+
+fn snapshot(cfg: &Config, planned: &[(usize, Operation)]) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    walk(&cfg.basedir, &mut snapshot);
+    let mut included = Vec::new();
+    files(cfg, &mut included);
+    for file in included {
+        walk(&file, &mut snapshot);
+    }
+    for (_, op) in planned {
+        walk(&op.dest, &mut snapshot);
+    }
+    snapshot
+}
+
+// The paths that differ between two snapshots
+fn changes(before: &Snapshot, after: &Snapshot) -> Vec<PathBuf> {
+    before
+        .iter()
+        .filter(|(path, seen)| after.get(*path) != Some(seen))
+        .map(|(path, _)| path)
+        .chain(after.keys().filter(|path| !before.contains_key(*path)))
+        .cloned()
+        .collect()
+}
+
+static HANGUP: AtomicBool = AtomicBool::new(false);
+
+// SIGHUP asks for the file to be reloaded, as it does of most daemons
+#[cfg(unix)]
+fn on_hangup() {
+    use std::ffi::c_int;
+
+    unsafe extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+    const SIGHUP: c_int = 1;
+
+    extern "C" fn hangup(_: c_int) {
+        HANGUP.store(true, Ordering::Relaxed);
+    }
+    unsafe { signal(SIGHUP, hangup) };
+}
+
+#[cfg(not(unix))]
+fn on_hangup() {}
+
+// The file loaded afresh, so edits to its variables and rules take effect.
+// Entries linked by an earlier sync are passed over.
+fn reload(base: &Config) -> io::Result<Config> {
+    let mut cfg = Config {
+        vars: vars::Vars::new(),
+        skip_in_place: true,
+        ..base.clone()
+    };
+    cfg.load()?;
+    Ok(cfg)
+}
+
+// What the file of cfg asks for, with the entries that can't be planned
+// reported when loud
+fn plan(cfg: &Config, loud: bool) -> io::Result<Vec<(usize, Operation)>> {
+    plan_lines(cfg, &age::read(&cfg.file)?, |file_cfg, linenum, err| {
+        if loud {
+            printfc!(
+                LogLevel::Warn,
+                "{}:{linenum}: {err}",
+                file_cfg.file.display()
+            );
+        }
+    })
+}
+
+// Reloads the file and applies what changed in its plan, keeping cfg and
+// planned as they were if it no longer loads or plans
+fn reconfigure(base: &Config, cfg: &mut Config, planned: &mut Vec<(usize, Operation)>) {
+    let reloaded = reload(base).and_then(|fresh| Ok((plan(&fresh, true)?, fresh)));
+    let (ops, fresh) = match reloaded {
+        Ok(reloaded) => reloaded,
+        Err(err) => {
+            printfc!(
+                LogLevel::Error,
+                "{err}, the file as last loaded stays in effect"
+            );
+            return;
+        }
+    };
+    let diff = PlanDiff::new(std::mem::take(planned), ops);
+    *cfg = fresh;
+    // Planned again since the diff took the operations
+    *planned = plan(cfg, false).unwrap_or_default();
+    if diff.is_empty() {
+        printfc!(LogLevel::Info, "Reloaded, the plan is unchanged");
+        return;
+    }
+    printfc!(LogLevel::Info, "Reloaded, the plan changed:");
+    diff.print();
+    let mut operations = 0;
+    match diff.apply(cfg, &mut operations) {
+        Ok(()) => {
+            printfc!(
+                LogLevel::Info,
+                "Synced, {operations} operations were performed"
+            );
+        }
+        Err(err) => {
+            printfc!(LogLevel::Error, "{err}");
+        }
+    }
+}
+
+// Applies cfg, leaving alone the destinations cooldown holds back
+fn sync(cfg: &Config, pending: &[PathBuf]) {
+    let mut cfg = cfg.clone();
+    cfg.unmanaged.extend(cooldown::held(pending));
+    let mut operations = 0;
+    match run(&cfg, &mut operations) {
+        Ok(()) => {
+            printfc!(
+                LogLevel::Info,
+                "Synced, {operations} operations were performed"
+            );
+        }
+        Err(err) => {
+            printfc!(LogLevel::Error, "{err}");
+        }
+    }
+}
+
+// Decides about each destination of ops found changed: one in place again
+// is settled, and one that isn't is put back now, held in pending for later,
+// or left alone as flapping. Returns those to be put back now.
+fn drifted<'a>(
+    ops: impl Iterator<Item = &'a Operation>,
+    cfg: &Config,
+    pending: &mut Vec<PathBuf>,
+) -> Vec<&'a Operation> {
+    let mut fix = Vec::new();
+    for op in ops {
+        if status::op_in_place(op, cfg).unwrap_or(false) {
+            cooldown::settled(&op.dest);
+            continue;
+        }
+        match cooldown::drifted(&op.dest) {
+            Verdict::Fix => {
+                printfc!(LogLevel::Info, "Putting back {}", op.dest.display());
+                fix.push(op);
+            }
+            Verdict::Hold(wait) => {
+                if !pending.contains(&op.dest) {
+                    printfc!(
+                        LogLevel::Info,
+                        "{} changed again, putting it back in {}s",
+                        op.dest.display(),
+                        wait.as_secs().max(1)
+                    );
+                    pending.push(op.dest.clone());
+                }
+            }
+            Verdict::Flapping(true) => {
+                printfc!(
+                    LogLevel::Warn,
+                    "{} keeps changing after being put back, something else writes it. Leaving it alone until it is in place again, see neostow status",
+                    op.dest.display()
+                );
+            }
+            Verdict::Flapping(false) => {}
+        }
+    }
+    fix
+}
+
+// Snapshots after putting back fixed. One changed again before the snapshot
+// is left out of it, so the next poll still finds it changed.
+fn resnapshot(cfg: &Config, planned: &[(usize, Operation)], fixed: &[&Operation]) -> Snapshot {
+    let mut seen = snapshot(cfg, planned);
+    for op in fixed {
+        if !status::op_in_place(op, cfg).unwrap_or(false) {
+            seen.remove(&op.dest);
+        }
+    }
+    seen
+}
+
+/// Applies the file at cfg, then again each time a source changes, until
+/// interrupted. A change to the file itself, or SIGHUP, reloads it and
+/// applies only what changed in its plan; a file that no longer loads is
+/// reported and the one last loaded kept. A destination changed by
+/// something else is put back, less and less eagerly if it keeps changing.
+pub fn watch(base: &Config) -> io::Result<()> {
+    let mut cfg = reload(base)?;
+    let mut planned = plan(&cfg, false)?;
+    let mut pending = Vec::new();
+    cooldown::start();
+    // Flapping destinations put in place since are settled
+    for (_, op) in &planned {
+        if status::op_in_place(op, &cfg).unwrap_or(false) {
+            cooldown::settled(&op.dest);
+        }
+    }
+    sync(&cfg, &pending);
+    on_hangup();
+    printfc!(
+        LogLevel::Info,
+        "Watching {} for changes, Ctrl-C to stop",
+        cfg.basedir.display()
+    );
+    let mut seen = snapshot(&cfg, &planned);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let now = snapshot(&cfg, &planned);
+        let changed = changes(&seen, &now);
+        if HANGUP.swap(false, Ordering::Relaxed) {
+            printfc!(LogLevel::Info, "Got SIGHUP");
+            reconfigure(base, &mut cfg, &mut planned);
+            seen = snapshot(&cfg, &planned);
+            continue;
+        }
+        let Some(path) = changed.first() else {
+            let expired = cooldown::expired(&mut pending);
+            let again = planned
+                .iter()
+                .map(|(_, op)| op)
+                .filter(|op| expired.contains(&op.dest));
+            let fixed = drifted(again, &cfg, &mut pending);
+            if !fixed.is_empty() {
+                sync(&cfg, &pending);
+                seen = resnapshot(&cfg, &planned, &fixed);
+            } else if let Some(dir) = waiting::ready() {
+                printfc!(LogLevel::Info, "{} was made", dir.display());
+                sync(&cfg, &pending);
+                seen = snapshot(&cfg, &planned);
+            }
+            continue;
+        };
+        seen = now;
+        if changed.len() == 1 {
+            printfc!(LogLevel::Info, "{} changed", path.display());
+        } else {
+            printfc!(
+                LogLevel::Info,
+                "{} and {} other paths changed",
+                path.display(),
+                changed.len() - 1
+            );
+        }
+        let mut included = Vec::new();
+        files(&cfg, &mut included);
+        let at_dest = |path: &PathBuf| {
+            planned
+                .iter()
+                .any(|(_, op)| path.starts_with(&op.dest) && !path.starts_with(&cfg.basedir))
+        };
+        if changed.iter().any(|path| included.contains(path)) {
+            reconfigure(base, &mut cfg, &mut planned);
+        } else if changed.iter().all(at_dest) {
+            let ops = planned
+                .iter()
+                .map(|(_, op)| op)
+                .filter(|op| changed.iter().any(|path| path.starts_with(&op.dest)));
+            let fixed = drifted(ops, &cfg, &mut pending);
+            if !fixed.is_empty() {
+                sync(&cfg, &pending);
+                seen = resnapshot(&cfg, &planned, &fixed);
+            }
+            continue;
+        } else {
+            cfg = match reload(base) {
+                Ok(cfg) => cfg,
+                Err(err) => {
+                    printfc!(LogLevel::Error, "{err}");
+                    continue;
+                }
+            };
+            sync(&cfg, &pending);
+            // A source added under a glob is planned now
+            if let Ok(ops) = plan(&cfg, false) {
+                planned = ops;
+            }
+        }
+        // What the sync itself did is no change to act on
+        seen = snapshot(&cfg, &planned);
+    }
+}
@@ -0,0 +1,207 @@
+// Extended attributes through the C library std already links against.
+// Only Linux and macOS are covered; elsewhere asking for them is an error.
+
+use std::io;
+use std::path::Path;
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+mod sys {
+    use std::ffi::{CString, c_char, c_void};
+    use std::io;
+    use std::path::Path;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe extern "C" {
+        fn llistxattr(path: *const c_char, list: *mut c_char, size: usize) -> isize;
+        fn lgetxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+        ) -> isize;
+        fn lsetxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+            flags: i32,
+        ) -> i32;
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe extern "C" {
+        fn listxattr(path: *const c_char, list: *mut c_char, size: usize, options: i32) -> isize;
+        fn getxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *mut c_void,
+            size: usize,
+            position: u32,
+            options: i32,
+        ) -> isize;
+        fn setxattr(
+            path: *const c_char,
+            name: *const c_char,
+            value: *const c_void,
+            size: usize,
+            position: u32,
+            options: i32,
+        ) -> i32;
+    }
+
+    // The same calls on both, never following a final symlink
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn list_raw(path: *const c_char, buf: *mut c_char, size: usize) -> isize {
+        unsafe { llistxattr(path, buf, size) }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn get_raw(
+        path: *const c_char,
+        name: *const c_char,
+        buf: *mut c_void,
+        size: usize,
+    ) -> isize {
+        unsafe { lgetxattr(path, name, buf, size) }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe fn set_raw(
+        path: *const c_char,
+        name: *const c_char,
+        value: *const c_void,
+        size: usize,
+    ) -> i32 {
+        unsafe { lsetxattr(path, name, value, size, 0) }
+    }
+
+    #[cfg(target_os = "macos")]
+    const XATTR_NOFOLLOW: i32 = 1;
+
+    #[cfg(target_os = "macos")]
+    unsafe fn list_raw(path: *const c_char, buf: *mut c_char, size: usize) -> isize {
+        unsafe { listxattr(path, buf, size, XATTR_NOFOLLOW) }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn get_raw(
+        path: *const c_char,
+        name: *const c_char,
+        buf: *mut c_void,
+        size: usize,
+    ) -> isize {
+        unsafe { getxattr(path, name, buf, size, 0, XATTR_NOFOLLOW) }
+    }
+
+    #[cfg(target_os = "macos")]
+    unsafe fn set_raw(
+        path: *const c_char,
+        name: *const c_char,
+        value: *const c_void,
+        size: usize,
+    ) -> i32 {
+        unsafe { setxattr(path, name, value, size, 0, XATTR_NOFOLLOW) }
+    }
+
+    pub fn c_path(path: &Path) -> io::Result<CString> {
+        use std::os::unix::ffi::OsStrExt;
+        CString::new(path.as_os_str().as_bytes()).map_err(io::Error::other)
+    }
+
+    // Calls fill with a buffer until it is large enough, the size having been
+    // asked for with an empty one first; attributes may grow in between
+    fn read(mut fill: impl FnMut(*mut c_void, usize) -> isize) -> io::Result<Vec<u8>> {
+        loop {
+            let size = fill(std::ptr::null_mut(), 0);
+            if size < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut buf = vec![0u8; size as usize];
+            let len = fill(buf.as_mut_ptr().cast(), buf.len());
+            if len >= 0 {
+                buf.truncate(len as usize);
+                return Ok(buf);
+            }
+            // ERANGE means the buffer fell short, anything else is a failure
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(34) {
+                return Err(err);
+            }
+        }
+    }
+
+    pub fn names(path: &CString) -> io::Result<Vec<u8>> {
+        read(|buf, size| unsafe { list_raw(path.as_ptr(), buf.cast(), size) })
+    }
+
+    pub fn get(path: &CString, name: &CString) -> io::Result<Vec<u8>> {
+        read(|buf, size| unsafe { get_raw(path.as_ptr(), name.as_ptr(), buf, size) })
+    }
+
+    pub fn set(path: &CString, name: &CString, value: &[u8]) -> io::Result<()> {
+        let result = unsafe {
+            set_raw(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr().cast(),
+                value.len(),
+            )
+        };
+        if result < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+// The extended attributes of path, sorted by name
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub fn list(path: &Path) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let c_path = sys::c_path(path)?;
+    let mut attrs = Vec::new();
+    for name in sys::names(&c_path)?
+        .split(|&b| b == 0)
+        .filter(|n| !n.is_empty())
+    {
+        let c_name = std::ffi::CString::new(name).map_err(io::Error::other)?;
+        attrs.push((name.to_vec(), sys::get(&c_path, &c_name)?));
+    }
+    attrs.sort();
+    Ok(attrs)
+}
+
+// Attributes outside the user namespace (security labels, ACLs) usually
+// take privileges to set, so being refused those isn't an error
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    let c_path = sys::c_path(to)?;
+    for (name, value) in list(from)? {
+        let c_name = std::ffi::CString::new(name.clone()).map_err(io::Error::other)?;
+        match sys::set(&c_path, &c_name, &value) {
+            Err(err)
+                if err.kind() == io::ErrorKind::PermissionDenied
+                    && cfg!(not(target_os = "macos"))
+                    && !name.starts_with(b"user.") => {}
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "extended attributes are not supported on this platform",
+    )
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub fn list(_path: &Path) -> io::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    Err(unsupported())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos")))]
+pub fn copy(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(unsupported())
+}
@@ -0,0 +1,91 @@
+// Calls the C ABI as a C program would, through the declarations of
+// include/neostow.h, against a neostow file under the temporary directory.
+// Only a dry run is executed, so nothing outside it changes.
+
+#![cfg(feature = "ffi")]
+
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::fs;
+use std::ptr;
+
+// Linked for the symbols it exports
+use neostow as _;
+
+#[repr(C)]
+struct Plan {
+    _private: [u8; 0],
+}
+
+const NEOSTOW_CREATE: c_int = 0;
+const NEOSTOW_DRY: c_int = 1;
+
+unsafe extern "C" {
+    fn neostow_last_error() -> *const c_char;
+    fn neostow_plan(path: *const c_char, mode: c_int) -> *mut Plan;
+    fn neostow_plan_json(plan: *const Plan) -> *mut c_char;
+    fn neostow_execute(plan: *const Plan, flags: c_int) -> *mut c_char;
+    fn neostow_status(plan: *const Plan) -> *mut c_char;
+    fn neostow_plan_free(plan: *mut Plan);
+    fn neostow_string_free(s: *mut c_char);
+}
+
+// Copies a string the library returned and releases it
+fn take(s: *mut c_char) -> String {
+    assert!(!s.is_null(), "{}", last_error());
+    // SAFETY: s is a live string from the library, freed once here
+    unsafe {
+        let copy = CStr::from_ptr(s).to_string_lossy().into_owned();
+        neostow_string_free(s);
+        copy
+    }
+}
+
+fn last_error() -> String {
+    // SAFETY: the error is a valid C string until the next failing call
+    unsafe {
+        let err = neostow_last_error();
+        if err.is_null() {
+            return String::new();
+        }
+        CStr::from_ptr(err).to_string_lossy().into_owned()
+    }
+}
+
+#[test]
+fn plans_and_dry_runs_through_the_c_abi() {
+    let root = std::env::temp_dir().join(format!("neostow-ffi-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("home")).unwrap();
+    fs::write(root.join("zshrc"), "# zshrc\n").unwrap();
+    fs::write(
+        root.join(".neostow"),
+        format!("zshrc={} !system\n", root.join("home").display()),
+    )
+    .unwrap();
+    let path = CString::new(root.join(".neostow").to_string_lossy().as_bytes()).unwrap();
+
+    // SAFETY: path is a C string, and the plan is freed once, after its last use
+    unsafe {
+        let plan = neostow_plan(path.as_ptr(), NEOSTOW_CREATE);
+        assert!(!plan.is_null(), "{}", last_error());
+
+        let json = take(neostow_plan_json(plan));
+        assert!(json.contains("home/zshrc"), "{json}");
+        let status = take(neostow_status(plan));
+        assert!(status.contains("\"state\":\"missing\""), "{status}");
+        let run = take(neostow_execute(plan, NEOSTOW_DRY));
+        assert!(run.starts_with('{'), "{run}");
+        assert!(!root.join("home/zshrc").exists());
+
+        neostow_plan_free(plan);
+    }
+    let _ = fs::remove_dir_all(&root);
+}
+
+#[test]
+fn failures_return_null_with_a_reason() {
+    // SAFETY: a null path is refused before it is read
+    let plan = unsafe { neostow_plan(ptr::null(), NEOSTOW_CREATE) };
+    assert!(plan.is_null());
+    assert_eq!(last_error(), "path is null");
+}
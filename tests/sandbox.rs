@@ -0,0 +1,232 @@
+// Runs neostow files against a sandbox under the temporary directory, with a
+// fake home standing in for the real one, so no test touches the machine, or
+// against a tree in memory, where a dry run can be seen to change nothing.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use neostow::vfs::{Fs, MemoryFs, Node};
+use neostow::{
+    Config, ExecuteOptions, Mode, PlanOptions, apply, execute, plan_from_source, report, sandbox,
+};
+
+// The state directory and the report are process-wide, so runs take turns
+static RUNS: Mutex<()> = Mutex::new(());
+
+struct Fixture {
+    root: PathBuf,
+    _turn: MutexGuard<'static, ()>,
+}
+
+impl Fixture {
+    // A dotfiles directory with the given neostow file, where {root} stands
+    // for the fixture's directory, and a zshrc, and an empty home
+    fn new(name: &str, neostow: &str) -> Self {
+        let turn = RUNS.lock().unwrap_or_else(|e| e.into_inner());
+        let root = std::env::temp_dir().join(format!("neostow-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dots")).unwrap();
+        fs::create_dir_all(root.join("home")).unwrap();
+        fs::write(
+            root.join("dots/.neostow"),
+            neostow.replace("{root}", &root.to_string_lossy()),
+        )
+        .unwrap();
+        fs::write(root.join("dots/zshrc"), "# zshrc\n").unwrap();
+        Fixture { root, _turn: turn }
+    }
+
+    fn home(&self) -> PathBuf {
+        self.root.join("home")
+    }
+
+    fn sandbox(&self) -> PathBuf {
+        self.root.join("sandbox")
+    }
+
+    fn run(&self, mode: Mode, dry: bool) -> i32 {
+        let mut cfg = Config::new(self.root.join("dots/.neostow"));
+        cfg.vars
+            .insert("HOME".to_string(), self.home().into_os_string());
+        cfg.mode = mode;
+        cfg.dry = dry;
+        cfg.force = true;
+        sandbox::enter(&mut cfg, &self.sandbox()).unwrap();
+        apply(&cfg).unwrap()
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+fn is_link_to(path: &Path, src: &Path) -> bool {
+    fs::read_link(path).is_ok_and(|target| target == src)
+}
+
+#[test]
+fn creates_links_in_the_sandbox() {
+    let fx = Fixture::new("create", "zshrc -> ~/.zshrc\n");
+    assert_eq!(fx.run(Mode::Create, false), 1);
+    assert!(is_link_to(
+        &fx.sandbox().join(".zshrc"),
+        &fx.root.join("dots/zshrc")
+    ));
+    assert!(fx.home().join(".zshrc").symlink_metadata().is_err());
+    assert_eq!(fx.run(Mode::Create, false), 0);
+}
+
+#[test]
+fn rerun_finds_links_in_place() {
+    let fx = Fixture::new("rerun", "zshrc -> ~/.zshrc\n");
+    fx.run(Mode::Create, false);
+    let failed = report::failed();
+    assert_eq!(fx.run(Mode::Create, false), 0);
+    assert_eq!(report::failed(), failed);
+}
+
+#[test]
+fn dry_run_changes_nothing() {
+    let fx = Fixture::new("dry", "@post-run = touch {root}/ran\nzshrc -> ~/.zshrc\n");
+    fx.run(Mode::Create, true);
+    assert!(fx.sandbox().join(".zshrc").symlink_metadata().is_err());
+    assert!(!fx.root.join("ran").exists());
+}
+
+#[test]
+fn create_leaves_a_copied_file_in_place() {
+    let fx = Fixture::new("conflict", "zshrc -> ~/.zshrc\n");
+    fs::write(fx.home().join(".zshrc"), "mine\n").unwrap();
+    assert_eq!(fx.run(Mode::Create, false), 0);
+    let copy = fx.sandbox().join(".zshrc");
+    assert_eq!(fs::read_to_string(&copy).unwrap(), "mine\n");
+    assert!(!copy.is_symlink());
+}
+
+#[test]
+fn overwrite_replaces_only_the_copy() {
+    let fx = Fixture::new("overwrite", "zshrc -> ~/.zshrc\n");
+    fs::write(fx.home().join(".zshrc"), "mine\n").unwrap();
+    assert_eq!(fx.run(Mode::Overwrite, false), 1);
+    assert!(is_link_to(
+        &fx.sandbox().join(".zshrc"),
+        &fx.root.join("dots/zshrc")
+    ));
+    assert_eq!(
+        fs::read_to_string(fx.home().join(".zshrc")).unwrap(),
+        "mine\n"
+    );
+}
+
+#[test]
+fn delete_removes_links() {
+    let fx = Fixture::new("delete", "zshrc -> ~/.zshrc\n");
+    fx.run(Mode::Create, false);
+    assert_eq!(fx.run(Mode::Delete, false), 1);
+    assert!(fx.sandbox().join(".zshrc").symlink_metadata().is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn existing_links_are_found_unchanged() {
+    let fx = Fixture::new("relink", "zshrc -> ~/.zshrc\n");
+    let src = fx.root.join("dots/zshrc");
+    std::os::unix::fs::symlink(&src, fx.home().join(".zshrc")).unwrap();
+    assert_eq!(fx.run(Mode::Create, false), 0);
+    assert!(is_link_to(&fx.sandbox().join(".zshrc"), &src));
+}
+
+#[test]
+fn plans_against_memory() {
+    let mut fs = MemoryFs::new();
+    fs.add_file("/dots/zshrc");
+    fs.add_dir("/dots/nvim");
+    fs.add_dir("/home/me");
+    let mut options = PlanOptions::default();
+    options.env.insert("HOME".to_string(), "/home/me".into());
+    let plan = plan_from_source(
+        b"zshrc -> ~/.zshrc\nnvim -> ~/.config/nvim\nmissing -> ~/.missing\n",
+        Path::new("/dots/.neostow"),
+        &options,
+        Arc::new(fs),
+    )
+    .unwrap();
+    // A missing source is passed over rather than planned
+    let dests: Vec<_> = plan.operations.iter().map(|(_, op)| &op.dest).collect();
+    assert_eq!(
+        dests,
+        [
+            Path::new("/home/me/.zshrc"),
+            Path::new("/home/me/.config/nvim")
+        ]
+    );
+    assert!(plan.errors.is_empty());
+}
+
+const MEMORY_ENTRIES: &[u8] = b"zshrc -> ~/.zshrc\nnvim -> ~/.config/nvim\nvimrc -> ~/.vimrc\ngitconfig -> ~/.config/git/config\n";
+
+// A home in memory with zshrc linked already, someone else's link where nvim
+// goes, and nothing yet for vimrc and gitconfig
+fn memory() -> Arc<MemoryFs> {
+    let mut fs = MemoryFs::new();
+    for source in ["/dots/zshrc", "/dots/vimrc", "/dots/gitconfig"] {
+        fs.add_file(source);
+    }
+    fs.add_dir("/dots/nvim");
+    fs.add_link("/home/me/.zshrc", "/dots/zshrc");
+    fs.add_link("/home/me/.config/nvim", "/elsewhere/nvim");
+    Arc::new(fs)
+}
+
+fn run_in_memory(fs: &Arc<MemoryFs>, mode: Mode, dry: bool) {
+    let mut options = PlanOptions::default();
+    options.mode = mode;
+    options.env.insert("HOME".to_string(), "/home/me".into());
+    let plan = plan_from_source(
+        MEMORY_ENTRIES,
+        Path::new("/dots/.neostow"),
+        &options,
+        fs.clone(),
+    )
+    .unwrap();
+    let mut execute_options = ExecuteOptions::default();
+    execute_options.dry = dry;
+    execute_options.force = true;
+    execute(&plan, &execute_options).unwrap();
+}
+
+#[test]
+fn dry_runs_change_nothing_in_memory() {
+    let _turn = RUNS.lock().unwrap_or_else(|e| e.into_inner());
+    for mode in [Mode::Create, Mode::Overwrite, Mode::Delete] {
+        let fs = memory();
+        let before = fs.nodes();
+        run_in_memory(&fs, mode, true);
+        assert_eq!(fs.nodes(), before);
+    }
+    assert!(Path::new("/home/me").symlink_metadata().is_err());
+}
+
+#[test]
+fn runs_in_memory() {
+    // A sandbox keeps the state the run writes out of the real one
+    let fx = Fixture::new("memory", "");
+    fx.run(Mode::Create, false);
+    let fs = memory();
+    run_in_memory(&fs, Mode::Overwrite, false);
+    let link = |path| fs.metadata(Path::new(path)).unwrap();
+    assert_eq!(link("/home/me/.zshrc"), Node::Link("/dots/zshrc".into()));
+    assert_eq!(
+        link("/home/me/.config/nvim"),
+        Node::Link("/dots/nvim".into())
+    );
+    assert_eq!(link("/home/me/.vimrc"), Node::Link("/dots/vimrc".into()));
+    assert_eq!(
+        link("/home/me/.config/git/config"),
+        Node::Link("/dots/gitconfig".into())
+    );
+    assert!(Path::new("/home/me").symlink_metadata().is_err());
+}